@@ -0,0 +1,473 @@
+//! Answers editor queries from a document's tokens and analyzed program.
+
+use std::collections::HashSet;
+
+use crust::diagnostics::Severity;
+use crust::semantics::context::ScopeContext;
+use crust::semantics::procedure::AnalyzedProcedure;
+use crust::semantics::value::{ProcedureSignature, Type};
+use crust::semantics::AnalyzedProgram;
+use crust::span::Span;
+use crust::tokens::Token;
+use lsp_types::{
+    CompletionItem, CompletionItemKind, Diagnostic, DiagnosticRelatedInformation,
+    DiagnosticSeverity, DocumentSymbol, Hover, HoverContents, Location, MarkupContent, MarkupKind,
+    Range, SymbolKind, Url,
+};
+
+use crate::document::Document;
+
+/// A declaration that a name refers to
+enum Symbol<'a> {
+    Variable(&'a str, &'a Type, Option<Span>),
+    /// Builtin procedures aren't declared anywhere, so have no span
+    Procedure(&'a str, &'a ProcedureSignature, Option<Span>),
+}
+
+impl Symbol<'_> {
+    /// The declaration, as it would be written in source
+    fn describe(&self) -> String {
+        match self {
+            Symbol::Variable(name, value_type, _) => variable_detail(name, value_type),
+            Symbol::Procedure(name, signature, _) => procedure_detail(name, signature),
+        }
+    }
+
+    fn span(&self) -> Option<Span> {
+        match self {
+            Symbol::Variable(_, _, span) | Symbol::Procedure(_, _, span) => *span,
+        }
+    }
+}
+
+fn variable_detail(name: &str, value_type: &Type) -> String {
+    format!("variable {} : {}", name, value_type)
+}
+
+fn procedure_detail(name: &str, signature: &ProcedureSignature) -> String {
+    let params: Vec<String> = signature
+        .0
+        .iter()
+        .map(|param| variable_detail(&param.0, &param.1))
+        .collect();
+    format!(
+        "procedure {} : {}({})",
+        name,
+        signature.1,
+        params.join(", ")
+    )
+}
+
+/// Spans are inclusive of their end here, so a name is found with the cursor just after it
+fn contains(span: Span, offset: usize) -> bool {
+    span.start <= offset && offset <= span.end
+}
+
+pub fn diagnostics(document: &Document, uri: &Url) -> Vec<Diagnostic> {
+    let sink = document.session().diagnostics();
+    sink.diagnostics()
+        .iter()
+        .map(|diagnostic| {
+            let range = diagnostic
+                .primary_label()
+                .map_or(Range::default(), |label| document.range(label.span));
+            let related: Vec<DiagnosticRelatedInformation> = diagnostic
+                .labels
+                .iter()
+                .filter(|label| !label.primary)
+                .map(|label| DiagnosticRelatedInformation {
+                    location: Location::new(uri.clone(), document.range(label.span)),
+                    message: label.message.clone(),
+                })
+                .collect();
+            let mut message = diagnostic.message.clone();
+            for note in diagnostic.notes.iter() {
+                message.push_str("\nnote: ");
+                message.push_str(note);
+            }
+            let severity = match diagnostic.severity {
+                Severity::Error => DiagnosticSeverity::ERROR,
+                Severity::Warning => DiagnosticSeverity::WARNING,
+            };
+            Diagnostic {
+                range,
+                severity: Some(severity),
+                source: Some(String::from("crust")),
+                message,
+                related_information: (!related.is_empty()).then_some(related),
+                ..Default::default()
+            }
+        })
+        .collect()
+}
+
+/// Finds the identifier at `offset`, and whether it is called
+fn identifier_at(document: &Document, offset: usize) -> Option<(&str, Span, bool)> {
+    let tokens = document.session().tokens()?;
+    let position = tokens.iter().position(|token| {
+        matches!(token.token, Token::Identifier(_)) && contains(token.span, offset)
+    })?;
+    let Token::Identifier(name) = &tokens[position].token else {
+        return None;
+    };
+    let is_call = tokens
+        .get(position + 1)
+        .is_some_and(|token| token.token == Token::LParen);
+    Some((name, tokens[position].span, is_call))
+}
+
+/// Procedures enclosing `offset`, outermost first
+fn enclosing_procedures(program: &AnalyzedProgram, offset: usize) -> Vec<&AnalyzedProcedure> {
+    let mut enclosing = Vec::new();
+    let mut procedures: Vec<&AnalyzedProcedure> = program.procedures.iter().collect();
+    while let Some(procedure) = procedures
+        .into_iter()
+        .find(|procedure| contains(procedure.span, offset))
+    {
+        enclosing.push(procedure);
+        procedures = procedure.procedures.iter().map(Box::as_ref).collect();
+    }
+    enclosing
+}
+
+/// The scopes names at `offset` are looked up in, innermost first.
+/// Like the analyzer, that is each enclosing procedure out to the first one declared in the global scope,
+/// then the global scope.
+fn scopes_at(program: &AnalyzedProgram, offset: usize) -> Vec<&ScopeContext> {
+    let mut scopes = Vec::new();
+    for procedure in enclosing_procedures(program, offset).into_iter().rev() {
+        scopes.push(&procedure.declarations);
+        if procedure.is_global {
+            break;
+        }
+    }
+    scopes.push(&program.declarations);
+    scopes
+}
+
+fn procedure_scopes<'a>(procedure: &'a AnalyzedProcedure, scopes: &mut Vec<&'a ScopeContext>) {
+    scopes.push(&procedure.declarations);
+    for nested in procedure.procedures.iter() {
+        procedure_scopes(nested, scopes);
+    }
+}
+
+/// Finds the variable or procedure `name` refers to in `scopes`.
+/// Variables and procedures have separate namespaces, so calls look for procedures first.
+fn lookup<'a>(scopes: &[&'a ScopeContext], name: &'a str, is_call: bool) -> Option<Symbol<'a>> {
+    let variable = || {
+        scopes.iter().find_map(|scope| {
+            let value_type = scope.variables.get(name)?;
+            let span = scope.variable_spans.get(name).copied();
+            Some(Symbol::Variable(name, value_type, span))
+        })
+    };
+    let procedure = || {
+        scopes.iter().find_map(|scope| {
+            let signature = scope.procedures.get(name)?;
+            let span = scope.procedure_spans.get(name).copied();
+            Some(Symbol::Procedure(name, signature, span))
+        })
+    };
+    match is_call {
+        true => procedure().or_else(variable),
+        false => variable().or_else(procedure),
+    }
+}
+
+/// Resolves the identifier at `offset` to its declaration, returning the identifier's span too
+fn symbol_at(document: &Document, offset: usize) -> Option<(Symbol<'_>, Span)> {
+    let program = document.session().partial_analysis()?;
+    let (name, span, is_call) = identifier_at(document, offset)?;
+
+    // A name being declared refers to its own declaration, whichever scope it is in
+    let mut all_scopes = vec![&program.declarations];
+    for procedure in program.procedures.iter() {
+        procedure_scopes(procedure, &mut all_scopes);
+    }
+    for scope in all_scopes {
+        if let Some(&declaration) = scope.variable_spans.get(name) {
+            if contains(declaration, span.start) {
+                return Some((
+                    Symbol::Variable(name, &scope.variables[name], Some(declaration)),
+                    span,
+                ));
+            }
+        }
+        if let Some(&declaration) = scope.procedure_spans.get(name) {
+            if contains(declaration, span.start) {
+                return Some((
+                    Symbol::Procedure(name, &scope.procedures[name], Some(declaration)),
+                    span,
+                ));
+            }
+        }
+    }
+
+    let symbol = lookup(&scopes_at(program, span.start), name, is_call)?;
+    Some((symbol, span))
+}
+
+pub fn hover(document: &Document, offset: usize) -> Option<Hover> {
+    let (symbol, span) = symbol_at(document, offset)?;
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value: format!("```crust\n{}\n```", symbol.describe()),
+        }),
+        range: Some(document.range(span)),
+    })
+}
+
+/// The range of the declaration the identifier at `offset` refers to
+pub fn definition(document: &Document, offset: usize) -> Option<Range> {
+    let (symbol, _) = symbol_at(document, offset)?;
+    symbol.span().map(|span| document.range(span))
+}
+
+/// Global variables and procedures, with nested procedures as children of their procedure
+pub fn document_symbols(document: &Document) -> Vec<DocumentSymbol> {
+    let Some(program) = document.session().partial_analysis() else {
+        return Vec::new();
+    };
+    let mut symbols: Vec<DocumentSymbol> = program
+        .declarations
+        .variables
+        .iter()
+        .filter_map(|(name, value_type)| {
+            let span = *program.declarations.variable_spans.get(name)?;
+            let range = document.range(span);
+            Some(symbol(
+                name,
+                value_type.to_string(),
+                SymbolKind::VARIABLE,
+                range,
+                range,
+                None,
+            ))
+        })
+        .collect();
+    symbols.extend(
+        program
+            .procedures
+            .iter()
+            .map(|procedure| procedure_symbol(document, program, procedure, &program.declarations)),
+    );
+    symbols.sort_by_key(|symbol| symbol.range.start);
+    symbols
+}
+
+/// `declared_in` is the scope the procedure was declared in, which has the span of its header
+fn procedure_symbol(
+    document: &Document,
+    program: &AnalyzedProgram,
+    procedure: &AnalyzedProcedure,
+    declared_in: &ScopeContext,
+) -> DocumentSymbol {
+    let range = document.range(procedure.span);
+    let selection_range = declared_in
+        .procedure_spans
+        .get(&procedure.identifier)
+        .map_or(range, |span| document.range(*span));
+    let children = procedure
+        .procedures
+        .iter()
+        .map(|nested| {
+            let declared_in = match nested.is_global {
+                true => &program.declarations,
+                false => &procedure.declarations,
+            };
+            procedure_symbol(document, program, nested, declared_in)
+        })
+        .collect();
+    let signature = ProcedureSignature(
+        procedure.arg_list.clone(),
+        procedure.declarations.return_type.clone(),
+    );
+    symbol(
+        &procedure.identifier,
+        procedure_detail(&procedure.identifier, &signature),
+        SymbolKind::FUNCTION,
+        range,
+        selection_range,
+        Some(children),
+    )
+}
+
+// `DocumentSymbol::deprecated` is deprecated, but still has to be set
+#[allow(deprecated)]
+fn symbol(
+    name: &str,
+    detail: String,
+    kind: SymbolKind,
+    range: Range,
+    selection_range: Range,
+    children: Option<Vec<DocumentSymbol>>,
+) -> DocumentSymbol {
+    DocumentSymbol {
+        name: String::from(name),
+        detail: Some(detail),
+        kind,
+        tags: None,
+        deprecated: None,
+        range,
+        selection_range,
+        children,
+    }
+}
+
+/// The variables and procedures in scope at `offset`
+pub fn completions(document: &Document, offset: usize) -> Vec<CompletionItem> {
+    let Some(program) = document.session().partial_analysis() else {
+        return Vec::new();
+    };
+    let mut seen = HashSet::new();
+    let mut items = Vec::new();
+    for scope in scopes_at(program, offset) {
+        let mut variables: Vec<_> = scope.variables.iter().collect();
+        variables.sort_by_key(|(name, _)| *name);
+        for (name, value_type) in variables {
+            if seen.insert((name, true)) {
+                items.push(CompletionItem {
+                    label: name.clone(),
+                    kind: Some(CompletionItemKind::VARIABLE),
+                    detail: Some(variable_detail(name, value_type)),
+                    ..Default::default()
+                });
+            }
+        }
+        let mut procedures: Vec<_> = scope.procedures.iter().collect();
+        procedures.sort_by_key(|(name, _)| *name);
+        for (name, signature) in procedures {
+            if seen.insert((name, false)) {
+                items.push(CompletionItem {
+                    label: name.clone(),
+                    kind: Some(CompletionItemKind::FUNCTION),
+                    detail: Some(procedure_detail(name, signature)),
+                    ..Default::default()
+                });
+            }
+        }
+    }
+    items
+}
+
+#[cfg(test)]
+use lsp_types::Position;
+#[cfg(test)]
+use rstest::rstest;
+
+#[cfg(test)]
+const SOURCE: &str = "program example is
+global variable total : integer;
+variable out : bool;
+
+procedure add : integer(variable value : integer)
+    variable doubled : integer[2];
+    procedure half : float(variable value : integer)
+        begin
+            return value / 2 + doubled[1];
+        end procedure;
+    begin
+        total := total + value;
+        return half(total);
+    end procedure;
+
+begin
+    out := putinteger(add(3));
+end program.
+";
+
+/// Finds the offset of the `occurrence`th match of `needle`, plus `delta`
+#[cfg(test)]
+fn offset_of(needle: &str, occurrence: usize, delta: usize) -> usize {
+    SOURCE.match_indices(needle).nth(occurrence).unwrap().0 + delta
+}
+
+#[cfg(test)]
+fn test_document() -> Document {
+    Document::new(String::from("example.src"), String::from(SOURCE))
+}
+
+#[cfg(test)]
+#[rstest]
+#[case(offset_of("total", 1, 2), Some("variable total : integer"))]
+#[case(offset_of("value", 3, 0), Some("variable value : integer"))]
+#[case(
+    offset_of("half(", 0, 0),
+    Some("procedure half : float(variable value : integer)")
+)]
+#[case(
+    offset_of("putinteger", 0, 3),
+    Some("procedure putinteger : bool(variable value : integer)")
+)]
+#[case(offset_of("doubled", 0, 7), Some("variable doubled : integer[2]"))]
+#[case(offset_of("begin", 1, 1), None)]
+fn hover_shows_declarations(#[case] offset: usize, #[case] expected: Option<&str>) {
+    let hover = hover(&test_document(), offset);
+    let value = hover.map(|hover| match hover.contents {
+        HoverContents::Markup(markup) => markup.value,
+        contents => panic!("unexpected hover contents {:?}", contents),
+    });
+    assert_eq!(
+        value,
+        expected.map(|expected| format!("```crust\n{}\n```", expected))
+    );
+}
+
+#[cfg(test)]
+#[rstest]
+#[case(offset_of("total", 2, 0), Some(1))]
+#[case(offset_of("value", 3, 0), Some(4))]
+#[case(offset_of("value", 2, 0), Some(6))]
+#[case(offset_of("add(", 0, 0), Some(4))]
+#[case(offset_of("doubled", 1, 0), Some(5))]
+#[case(offset_of("putinteger", 0, 0), None)]
+fn definition_finds_declaration_line(#[case] offset: usize, #[case] line: Option<u32>) {
+    let range = definition(&test_document(), offset);
+    assert_eq!(range.map(|range| range.start.line), line);
+}
+
+#[cfg(test)]
+#[test]
+fn document_symbols_nest_procedures() {
+    let symbols = document_symbols(&test_document());
+    let names: Vec<&str> = symbols.iter().map(|symbol| symbol.name.as_str()).collect();
+    assert_eq!(names, ["total", "out", "add"]);
+    let add = &symbols[2];
+    assert_eq!(add.selection_range.start, Position::new(4, 0));
+    let children = add.children.as_ref().unwrap();
+    assert_eq!(children.len(), 1);
+    assert_eq!(children[0].name, "half");
+}
+
+#[cfg(test)]
+#[test]
+fn completions_include_names_in_scope() {
+    let document = test_document();
+    let labels = |offset| -> Vec<String> {
+        completions(&document, offset)
+            .into_iter()
+            .map(|item| item.label)
+            .collect()
+    };
+    let in_add = labels(offset_of("total :=", 0, 0));
+    for name in ["doubled", "value", "half", "total", "add", "getinteger"] {
+        assert!(in_add.iter().any(|label| label == name), "{}", name);
+    }
+    let in_body = labels(offset_of("out :=", 0, 0));
+    assert!(in_body.iter().any(|label| label == "out"));
+    assert!(!in_body.iter().any(|label| label == "doubled"));
+}
+
+#[cfg(test)]
+#[test]
+fn diagnostics_point_at_errors() {
+    let source = "program broken is\nbegin\n    x := 1;\nend program.\n";
+    let document = Document::new(String::from("broken.src"), String::from(source));
+    let uri = Url::parse("file:///broken.src").unwrap();
+    let diagnostics = diagnostics(&document, &uri);
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].severity, Some(DiagnosticSeverity::ERROR));
+    assert_eq!(diagnostics[0].range.start, Position::new(2, 4));
+}
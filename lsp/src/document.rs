@@ -0,0 +1,74 @@
+use crust::span::Span;
+use crust::Session;
+use lsp_types::{Position, Range};
+
+/// An open document, analyzed as far as its errors allow.
+pub struct Document {
+    session: Session,
+}
+
+impl Document {
+    pub fn new(name: String, text: String) -> Self {
+        let mut session = Session::from_text(name, text);
+        // Errors are published as diagnostics
+        let _ = session.analyze();
+        Document { session }
+    }
+
+    pub fn session(&self) -> &Session {
+        &self.session
+    }
+
+    pub fn text(&self) -> &str {
+        &self.session.source().text
+    }
+
+    /// Converts a byte offset to a position, where characters are counted in UTF-16 code units
+    pub fn position(&self, offset: usize) -> Position {
+        let source = self.session.source();
+        let offset = offset.min(source.text.len());
+        let line = source.line_of(offset);
+        let line_start = source.line_start(line);
+        let character = source
+            .text
+            .get(line_start..offset)
+            .map_or(0, |prefix| prefix.encode_utf16().count());
+        // Positions count lines from 0
+        Position::new(line as u32 - 1, character as u32)
+    }
+
+    /// Converts a position to a byte offset, clamping it to the end of its line
+    pub fn offset(&self, position: Position) -> usize {
+        // Lines past the end start at the end of the text
+        let line_start = self.session.source().line_start(position.line as usize + 1);
+        let mut units = 0;
+        for (index, character) in self.text()[line_start..].char_indices() {
+            if units >= position.character as usize || character == '\n' {
+                return line_start + index;
+            }
+            units += character.len_utf16();
+        }
+        self.text().len()
+    }
+
+    pub fn range(&self, span: Span) -> Range {
+        Range::new(self.position(span.start), self.position(span.end))
+    }
+}
+
+#[cfg(test)]
+use rstest::rstest;
+
+#[cfg(test)]
+#[rstest]
+#[case(0, Position::new(0, 0))]
+#[case(4, Position::new(0, 4))]
+#[case(6, Position::new(1, 0))]
+// `é` is two bytes, but one UTF-16 code unit
+#[case(9, Position::new(1, 2))]
+#[case(14, Position::new(2, 0))]
+fn convert_positions(#[case] offset: usize, #[case] position: Position) {
+    let document = Document::new(String::from("test.src"), String::from("a := \né := 1\n\n"));
+    assert_eq!(document.position(offset), position);
+    assert_eq!(document.offset(position), offset);
+}
@@ -0,0 +1,142 @@
+//! A language server for crust source files, which speaks the Language Server Protocol over stdio.
+//!
+//! It publishes diagnostics whenever a document changes, and answers hover, go to definition,
+//! document symbol and completion requests.
+
+use std::error::Error;
+use std::process::ExitCode;
+
+use lsp_server::{Connection, Message};
+use lsp_types::{
+    CompletionOptions, HoverProviderCapability, OneOf, ServerCapabilities,
+    TextDocumentSyncCapability, TextDocumentSyncKind,
+};
+
+use crate::server::Server;
+
+mod analysis;
+mod document;
+mod server;
+
+fn capabilities() -> ServerCapabilities {
+    ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        definition_provider: Some(OneOf::Left(true)),
+        document_symbol_provider: Some(OneOf::Left(true)),
+        completion_provider: Some(CompletionOptions::default()),
+        ..Default::default()
+    }
+}
+
+/// Serves requests on `connection` until the client shuts the server down
+fn run(connection: &Connection) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let capabilities = serde_json::to_value(capabilities()).unwrap_or_default();
+    connection.initialize(capabilities)?;
+
+    let mut server = Server::default();
+    for message in &connection.receiver {
+        match message {
+            Message::Request(request) => {
+                if connection.handle_shutdown(&request)? {
+                    return Ok(());
+                }
+                let response = server.handle_request(request);
+                connection.sender.send(Message::Response(response))?;
+            }
+            Message::Notification(notification) => {
+                for reply in server.handle_notification(notification) {
+                    connection.sender.send(Message::Notification(reply))?;
+                }
+            }
+            Message::Response(_) => {}
+        }
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let (connection, io_threads) = Connection::stdio();
+    let result = run(&connection);
+    // The writer thread only stops once every sender is dropped
+    drop(connection);
+    match result.and_then(|_| Ok(io_threads.join()?)) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("crust-lsp: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+use lsp_server::{Notification, Request, RequestId};
+#[cfg(test)]
+use serde_json::{json, Value};
+
+/// Receives messages from the server until a response to `id`
+#[cfg(test)]
+fn response_to(client: &Connection, id: i32) -> Value {
+    loop {
+        match client.receiver.recv().unwrap() {
+            Message::Response(response) if response.id == RequestId::from(id) => {
+                assert!(response.error.is_none(), "{:?}", response.error);
+                return response.result.unwrap_or_default();
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn serve_a_session() {
+    let (server, client) = Connection::memory();
+    let thread = std::thread::spawn(move || run(&server));
+    let send = |message: Message| client.sender.send(message).unwrap();
+    let request = |id: i32, method: &str, params: Value| {
+        send(Request::new(id.into(), method.to_string(), params).into())
+    };
+    let notify =
+        |method: &str, params: Value| send(Notification::new(method.to_string(), params).into());
+
+    request(1, "initialize", json!({ "capabilities": {} }));
+    let initialized = response_to(&client, 1);
+    assert_eq!(initialized["capabilities"]["hoverProvider"], json!(true));
+    notify("initialized", json!({}));
+
+    let uri = "file:///example.src";
+    let text = "program example is\nvariable x : integer;\nbegin\n    x := y;\nend program.\n";
+    let document = json!({ "uri": uri, "languageId": "crust", "version": 1, "text": text });
+    notify("textDocument/didOpen", json!({ "textDocument": document }));
+    let Message::Notification(published) = client.receiver.recv().unwrap() else {
+        panic!("expected diagnostics to be published");
+    };
+    assert_eq!(published.method, "textDocument/publishDiagnostics");
+    let diagnostics = published.params["diagnostics"].as_array().unwrap();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(
+        diagnostics[0]["range"]["start"],
+        json!({ "line": 3, "character": 9 })
+    );
+
+    let position =
+        json!({ "textDocument": { "uri": uri }, "position": { "line": 3, "character": 4 } });
+    request(2, "textDocument/hover", position.clone());
+    let hover = response_to(&client, 2);
+    assert_eq!(
+        hover["contents"]["value"],
+        json!("```crust\nvariable x : integer\n```")
+    );
+    request(3, "textDocument/definition", position);
+    let definition = response_to(&client, 3);
+    assert_eq!(
+        definition["range"]["start"],
+        json!({ "line": 1, "character": 0 })
+    );
+
+    request(4, "shutdown", Value::Null);
+    response_to(&client, 4);
+    notify("exit", Value::Null);
+    assert!(thread.join().unwrap().is_ok());
+}
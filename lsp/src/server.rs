@@ -0,0 +1,129 @@
+use std::collections::HashMap;
+
+use lsp_server::{ErrorCode, Notification, Request, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument,
+    Notification as NotificationTrait, PublishDiagnostics,
+};
+use lsp_types::request::{
+    Completion, DocumentSymbolRequest, GotoDefinition, HoverRequest, Request as RequestTrait,
+};
+use lsp_types::{
+    CompletionResponse, DocumentSymbolResponse, GotoDefinitionResponse, Location,
+    PublishDiagnosticsParams, TextDocumentPositionParams, Url,
+};
+use serde_json::Value;
+
+use crate::analysis;
+use crate::document::Document;
+
+/// The state of the server, which is every open document
+#[derive(Default)]
+pub struct Server {
+    documents: HashMap<Url, Document>,
+}
+
+impl Server {
+    pub fn handle_request(&self, request: Request) -> Response {
+        let id = request.id.clone();
+        let result = match request.method.as_str() {
+            HoverRequest::METHOD => self.dispatch::<HoverRequest>(request, |server, params| {
+                let (document, offset) = server.locate(&params.text_document_position_params)?;
+                analysis::hover(document, offset)
+            }),
+            GotoDefinition::METHOD => self.dispatch::<GotoDefinition>(request, |server, params| {
+                let position = &params.text_document_position_params;
+                let (document, offset) = server.locate(position)?;
+                let range = analysis::definition(document, offset)?;
+                let uri = position.text_document.uri.clone();
+                Some(GotoDefinitionResponse::Scalar(Location::new(uri, range)))
+            }),
+            DocumentSymbolRequest::METHOD => {
+                self.dispatch::<DocumentSymbolRequest>(request, |server, params| {
+                    let document = server.documents.get(&params.text_document.uri)?;
+                    let symbols = analysis::document_symbols(document);
+                    Some(DocumentSymbolResponse::Nested(symbols))
+                })
+            }
+            Completion::METHOD => self.dispatch::<Completion>(request, |server, params| {
+                let (document, offset) = server.locate(&params.text_document_position)?;
+                let items = analysis::completions(document, offset);
+                Some(CompletionResponse::Array(items))
+            }),
+            method => Err((
+                ErrorCode::MethodNotFound,
+                format!("Unsupported request {}", method),
+            )),
+        };
+        match result {
+            Ok(result) => Response::new_ok(id, result),
+            Err((code, message)) => Response::new_err(id, code as i32, message),
+        }
+    }
+
+    /// Handles a notification, returning the notifications to send in reply
+    pub fn handle_notification(&mut self, notification: Notification) -> Vec<Notification> {
+        match notification.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let Ok(params) = parse_params::<DidOpenTextDocument>(notification.params) else {
+                    return Vec::new();
+                };
+                let document = params.text_document;
+                vec![self.update(document.uri, document.text)]
+            }
+            DidChangeTextDocument::METHOD => {
+                let Ok(params) = parse_params::<DidChangeTextDocument>(notification.params) else {
+                    return Vec::new();
+                };
+                // Documents are synced in full, so the last change has the whole text
+                match params.content_changes.into_iter().last() {
+                    Some(change) => vec![self.update(params.text_document.uri, change.text)],
+                    None => Vec::new(),
+                }
+            }
+            DidCloseTextDocument::METHOD => {
+                let Ok(params) = parse_params::<DidCloseTextDocument>(notification.params) else {
+                    return Vec::new();
+                };
+                let uri = params.text_document.uri;
+                self.documents.remove(&uri);
+                // Clears the closed document's diagnostics
+                vec![publish_diagnostics(uri, Vec::new())]
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Analyzes a document's new text, returning its diagnostics
+    fn update(&mut self, uri: Url, text: String) -> Notification {
+        let document = Document::new(uri.path().to_string(), text);
+        let diagnostics = analysis::diagnostics(&document, &uri);
+        self.documents.insert(uri.clone(), document);
+        publish_diagnostics(uri, diagnostics)
+    }
+
+    fn locate(&self, position: &TextDocumentPositionParams) -> Option<(&Document, usize)> {
+        let document = self.documents.get(&position.text_document.uri)?;
+        Some((document, document.offset(position.position)))
+    }
+
+    /// Answers a request of type `R` with the result of `handler`
+    fn dispatch<R: RequestTrait>(
+        &self,
+        request: Request,
+        handler: impl FnOnce(&Self, R::Params) -> R::Result,
+    ) -> Result<Value, (ErrorCode, String)> {
+        let params = serde_json::from_value::<R::Params>(request.params)
+            .map_err(|err| (ErrorCode::InvalidParams, err.to_string()))?;
+        Ok(serde_json::to_value(handler(self, params)).unwrap_or_default())
+    }
+}
+
+fn parse_params<N: NotificationTrait>(params: Value) -> serde_json::Result<N::Params> {
+    serde_json::from_value(params)
+}
+
+fn publish_diagnostics(uri: Url, diagnostics: Vec<lsp_types::Diagnostic>) -> Notification {
+    let params = PublishDiagnosticsParams::new(uri, diagnostics, None);
+    Notification::new(PublishDiagnostics::METHOD.to_string(), params)
+}
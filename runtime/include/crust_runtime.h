@@ -0,0 +1,43 @@
+/*
+ * The crust runtime: the builtin procedures every crust program can call.
+ * Link against libcrust_runtime.a, built from the crust-runtime crate.
+ *
+ * Strings are NUL-terminated. Strings passed to the runtime are borrowed for the
+ * duration of the call, and NULL is treated as the empty string. Strings returned
+ * by crust_getstring are owned by the caller, and may be released with
+ * crust_string_free.
+ *
+ * The get procedures flush stdout, then read a line from stdin. On malformed
+ * input, or at the end of input, they print an error to stderr and exit with
+ * status 1, except for crust_getstring, which returns an empty string at the end
+ * of input. The put procedures write their value and a newline to stdout, and
+ * return false if writing failed.
+ */
+#ifndef CRUST_RUNTIME_H
+#define CRUST_RUNTIME_H
+
+#include <stdbool.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+bool crust_getbool(void);
+int64_t crust_getinteger(void);
+double crust_getfloat(void);
+char *crust_getstring(void);
+void crust_string_free(char *value);
+
+bool crust_putbool(bool value);
+bool crust_putinteger(int64_t value);
+bool crust_putfloat(double value);
+bool crust_putstring(const char *value);
+
+double crust_sqrt(int64_t value);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
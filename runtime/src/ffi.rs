@@ -0,0 +1,104 @@
+//! The C ABI compiled programs link against, declared in `include/crust_runtime.h`.
+//!
+//! Programs read from stdin and write to stdout, which is flushed before each read so prompts are shown.
+//!
+//! Strings are NUL-terminated. Strings passed to the runtime are borrowed for the duration of the call,
+//! and a null pointer is treated as the empty string. Strings returned by `crust_getstring` are owned
+//! by the caller, and may be released with `crust_string_free`. A line of input containing a NUL byte
+//! is cut short at the first NUL.
+
+use std::ffi::{c_char, CStr, CString};
+use std::io::{self, Write};
+use std::process;
+
+use crate::INPUT_ERROR_EXIT_CODE;
+
+/// Reads from stdin with `read`, exiting the program if it fails
+fn read_stdin<T>(
+    expected: &str,
+    read: impl FnOnce(&mut io::StdinLock<'static>) -> io::Result<Option<T>>,
+) -> T {
+    // Nothing can be done if stdout is closed, and reading doesn't depend on it
+    let _ = io::stdout().flush();
+    match read(&mut io::stdin().lock()) {
+        Ok(Some(value)) => value,
+        Ok(None) => input_error(&format!("Could not read a {} from input.", expected)),
+        Err(err) => input_error(&err.to_string()),
+    }
+}
+
+fn input_error(message: &str) -> ! {
+    eprintln!("error: {}", message);
+    process::exit(INPUT_ERROR_EXIT_CODE)
+}
+
+#[no_mangle]
+pub extern "C" fn crust_getbool() -> bool {
+    read_stdin("bool", crate::read_bool)
+}
+
+#[no_mangle]
+pub extern "C" fn crust_getinteger() -> i64 {
+    read_stdin("integer", crate::read_integer)
+}
+
+#[no_mangle]
+pub extern "C" fn crust_getfloat() -> f64 {
+    read_stdin("float", crate::read_float)
+}
+
+#[no_mangle]
+pub extern "C" fn crust_getstring() -> *mut c_char {
+    let line = read_stdin("string", |input| crate::read_string(input).map(Some));
+    let line = match line.find('\0') {
+        Some(position) => &line[..position],
+        None => &line,
+    };
+    // Can't fail, since there are no NUL bytes left
+    CString::new(line).unwrap().into_raw()
+}
+
+/// Releases a string returned by `crust_getstring`.
+///
+/// # Safety
+/// `value` must be null, or a string returned by `crust_getstring` that hasn't been released yet.
+#[no_mangle]
+pub unsafe extern "C" fn crust_string_free(value: *mut c_char) {
+    if !value.is_null() {
+        drop(CString::from_raw(value));
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn crust_putbool(value: bool) -> bool {
+    crate::write_bool(&mut io::stdout(), value).is_ok()
+}
+
+#[no_mangle]
+pub extern "C" fn crust_putinteger(value: i64) -> bool {
+    crate::write_integer(&mut io::stdout(), value).is_ok()
+}
+
+#[no_mangle]
+pub extern "C" fn crust_putfloat(value: f64) -> bool {
+    crate::write_float(&mut io::stdout(), value).is_ok()
+}
+
+/// Writes a string, replacing any invalid UTF-8 with U+FFFD.
+///
+/// # Safety
+/// `value` must be null, or point to a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn crust_putstring(value: *const c_char) -> bool {
+    let value = if value.is_null() {
+        Default::default()
+    } else {
+        CStr::from_ptr(value).to_string_lossy()
+    };
+    crate::write_string(&mut io::stdout(), &value).is_ok()
+}
+
+#[no_mangle]
+pub extern "C" fn crust_sqrt(value: i64) -> f64 {
+    crate::sqrt(value)
+}
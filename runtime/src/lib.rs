@@ -0,0 +1,133 @@
+//! Runtime support for crust programs: the builtin procedures declared in `ScopeContext::new_global_ctx`.
+//!
+//! Compiled programs link against the C ABI in [`ffi`], declared in `include/crust_runtime.h`.
+//! The interpreter calls the functions here directly, on its own input and output streams.
+//!
+//! Input is read a line at a time, and the line terminator (`\n` or `\r\n`) is not part of the line.
+//! - `getbool` accepts `true`, `false`, `1` or `0`, ignoring case and surrounding whitespace.
+//! - `getinteger` accepts a decimal 64-bit integer with an optional sign, ignoring surrounding whitespace.
+//! - `getfloat` accepts anything Rust's `f64::from_str` does, such as `1.5`, `-2`, `1e10` or `inf`,
+//!   ignoring surrounding whitespace.
+//! - `getstring` gives the whole line, including any whitespace, and an empty string at the end of input.
+//!
+//! Malformed input, or the end of input, is an error for every builtin but `getstring`.
+//! Compiled programs report it on stderr and exit with [`INPUT_ERROR_EXIT_CODE`].
+//!
+//! Output is written a line at a time: each `put` procedure writes its value followed by `\n`.
+//! - Bools are written as `true` or `false`.
+//! - Floats are written in the shortest form that reads back as the same value, without an exponent.
+//!   Whole numbers have no fractional part, so `3.0` is written as `3`.
+//! - The `put` procedures return `true`, or `false` if writing failed.
+//!
+//! `sqrt` takes an integer and returns a float. The square root of a negative number is NaN.
+
+use std::io::{self, BufRead, Write};
+
+pub mod ffi;
+
+/// Status compiled programs exit with after failing to read a value
+pub const INPUT_ERROR_EXIT_CODE: i32 = 1;
+
+/// Reads a line of input without its line terminator, or `None` at the end of input.
+pub fn read_line(input: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut line = String::new();
+    if input.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    line.truncate(line.trim_end_matches(['\n', '\r']).len());
+    Ok(Some(line))
+}
+
+/// Reads a value for `getbool`, or `None` if the line is malformed or there is no more input.
+pub fn read_bool(input: &mut impl BufRead) -> io::Result<Option<bool>> {
+    Ok(read_line(input)?.and_then(|line| parse_bool(&line)))
+}
+
+pub fn read_integer(input: &mut impl BufRead) -> io::Result<Option<i64>> {
+    Ok(read_line(input)?.and_then(|line| line.trim().parse().ok()))
+}
+
+pub fn read_float(input: &mut impl BufRead) -> io::Result<Option<f64>> {
+    Ok(read_line(input)?.and_then(|line| line.trim().parse().ok()))
+}
+
+/// Reads a value for `getstring`, which is empty at the end of input.
+pub fn read_string(input: &mut impl BufRead) -> io::Result<String> {
+    Ok(read_line(input)?.unwrap_or_default())
+}
+
+pub fn parse_bool(line: &str) -> Option<bool> {
+    match line.trim().to_lowercase().as_str() {
+        "true" | "1" => Some(true),
+        "false" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+pub fn format_bool(value: bool) -> &'static str {
+    if value {
+        "true"
+    } else {
+        "false"
+    }
+}
+
+pub fn format_float(value: f64) -> String {
+    value.to_string()
+}
+
+pub fn write_bool(output: &mut impl Write, value: bool) -> io::Result<()> {
+    writeln!(output, "{}", format_bool(value))
+}
+
+pub fn write_integer(output: &mut impl Write, value: i64) -> io::Result<()> {
+    writeln!(output, "{}", value)
+}
+
+pub fn write_float(output: &mut impl Write, value: f64) -> io::Result<()> {
+    writeln!(output, "{}", format_float(value))
+}
+
+pub fn write_string(output: &mut impl Write, value: &str) -> io::Result<()> {
+    writeln!(output, "{}", value)
+}
+
+pub fn sqrt(value: i64) -> f64 {
+    (value as f64).sqrt()
+}
+
+#[cfg(test)]
+#[test]
+fn reads_values_a_line_at_a_time() {
+    let mut input = "TRUE\n 42 \r\n1e3\n  padded  \n".as_bytes();
+    assert_eq!(read_bool(&mut input).unwrap(), Some(true));
+    assert_eq!(read_integer(&mut input).unwrap(), Some(42));
+    assert_eq!(read_float(&mut input).unwrap(), Some(1000.0));
+    assert_eq!(read_string(&mut input).unwrap(), "  padded  ");
+    assert_eq!(read_string(&mut input).unwrap(), "");
+    assert_eq!(read_integer(&mut input).unwrap(), None);
+}
+
+#[cfg(test)]
+#[test]
+fn rejects_malformed_input() {
+    assert_eq!(read_integer(&mut "4.5\n".as_bytes()).unwrap(), None);
+    assert_eq!(read_bool(&mut "yes\n".as_bytes()).unwrap(), None);
+    assert_eq!(read_float(&mut "\n".as_bytes()).unwrap(), None);
+}
+
+#[cfg(test)]
+#[test]
+fn formats_output() {
+    let mut output = Vec::new();
+    write_bool(&mut output, false).unwrap();
+    write_integer(&mut output, -7).unwrap();
+    write_float(&mut output, 3.0).unwrap();
+    write_float(&mut output, 0.1).unwrap();
+    write_float(&mut output, 1e21).unwrap();
+    write_string(&mut output, "text").unwrap();
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "false\n-7\n3\n0.1\n1000000000000000000000\ntext\n"
+    );
+}
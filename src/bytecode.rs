@@ -0,0 +1,160 @@
+//! A compact bytecode for a stack-based virtual machine.
+//!
+//! Programs are compiled from the IR, where each procedure compiles to a function whose instructions
+//! push and pop `Value`s on a shared operand stack.
+//! Locals live in the function's call frame, with the parameters first and the IR's temporaries last,
+//! and globals live in the VM.
+//! A nested procedure's frame links to its parent's frame, which is how it reaches the parent's locals.
+//! Whole arrays can be on the stack, so array arithmetic and array arguments need no special instructions.
+//! Programs can be written to a binary file with `encoding`, and run with `vm::Vm`.
+
+use std::fmt::Display;
+
+use crate::interpreter::value::{ArithOperator, Comparison};
+use crate::ir::Builtin;
+use crate::semantics::value::Type;
+use crate::span::Span;
+
+pub mod compile;
+pub mod encoding;
+pub mod vm;
+
+/// The program body, which is always the first function
+pub const BODY: usize = 0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Program {
+    pub name: String,
+    /// String constants, indexed by `Op::PushString`
+    pub strings: Vec<String>,
+    pub globals: Vec<Type>,
+    /// The body, then every procedure including nested ones
+    pub functions: Vec<Function>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    /// Nested procedures are named by their path, like `outer.inner`
+    pub name: String,
+    /// The function this one is nested in, if it can use that function's locals.
+    /// Its frames are linked to a frame of the parent, so it has to be called with `Op::CallNested`.
+    pub parent: Option<usize>,
+    /// The first `param_count` locals are the parameters, in order
+    pub param_count: usize,
+    pub locals: Vec<Type>,
+    /// `Type::Void` for the program body
+    pub return_type: Type,
+    pub code: Vec<Op>,
+    /// The source each instruction was compiled from, for runtime errors
+    pub spans: Vec<Span>,
+}
+
+/// Where a variable is stored
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Slot {
+    /// Index into the current frame's locals
+    Local(usize),
+    /// Index into the program's globals
+    Global(usize),
+    /// Index into the locals of the frame that is the given number of static links out
+    Outer(usize, usize),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Op {
+    PushBool(bool),
+    PushInt(i64),
+    PushFloat(f64),
+    /// Pushes a string constant
+    PushString(usize),
+    /// Pushes a copy of a variable's value
+    Load(Slot),
+    /// Pops a value into a variable
+    Store(Slot),
+    /// Pops an index, then pushes that element of an array variable
+    LoadElement(Slot),
+    /// Pops a value, then an index, and stores the value into that element of an array variable
+    StoreElement(Slot),
+    /// Pops the right operand, then the left, and pushes the result.
+    /// Arrays are handled element-wise, as in `Value::arith`.
+    Arith(ArithOperator),
+    Compare(Comparison),
+    BitwiseAnd,
+    BitwiseOr,
+    BitwiseNot,
+    LogicalAnd,
+    LogicalOr,
+    LogicalNot,
+    Negate,
+    Cast(Type),
+    /// Jumps to an instruction of the current function
+    Jump(usize),
+    /// Pops a condition, and jumps if it is false
+    JumpIfFalse(usize),
+    /// Pops the arguments of a function, with the last on top, and calls it
+    Call(usize),
+    /// Calls a function like `Call`, linking its frame to the frame the given number of static links out,
+    /// where 0 is the calling frame
+    CallNested(usize, usize),
+    CallBuiltin(Builtin),
+    /// Returns from the current function, with the value on top of the stack unless it is the body
+    Return,
+}
+
+impl Display for Slot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Slot::Local(index) => write!(f, "local {}", index),
+            Slot::Global(index) => write!(f, "global {}", index),
+            Slot::Outer(depth, index) => write!(f, "local {}^{}", index, depth),
+        }
+    }
+}
+
+impl Display for Op {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Op::PushBool(value) => write!(f, "push {}", value),
+            Op::PushInt(value) => write!(f, "push {}", value),
+            Op::PushFloat(value) => write!(f, "push {:?}", value),
+            Op::PushString(index) => write!(f, "push string {}", index),
+            Op::Load(slot) => write!(f, "load {}", slot),
+            Op::Store(slot) => write!(f, "store {}", slot),
+            Op::LoadElement(slot) => write!(f, "load element {}", slot),
+            Op::StoreElement(slot) => write!(f, "store element {}", slot),
+            Op::Arith(operator) => match operator {
+                ArithOperator::Add => write!(f, "add"),
+                ArithOperator::Subtract => write!(f, "subtract"),
+                ArithOperator::Multiply => write!(f, "multiply"),
+                ArithOperator::Divide => write!(f, "divide"),
+            },
+            Op::Compare(comparison) => match comparison {
+                Comparison::LessThan => write!(f, "compare <"),
+                Comparison::LessThanEq => write!(f, "compare <="),
+                Comparison::GreaterThan => write!(f, "compare >"),
+                Comparison::GreaterThanEq => write!(f, "compare >="),
+                Comparison::Equals => write!(f, "compare =="),
+                Comparison::NotEquals => write!(f, "compare !="),
+            },
+            Op::BitwiseAnd => write!(f, "bitwise and"),
+            Op::BitwiseOr => write!(f, "bitwise or"),
+            Op::BitwiseNot => write!(f, "bitwise not"),
+            Op::LogicalAnd => write!(f, "and"),
+            Op::LogicalOr => write!(f, "or"),
+            Op::LogicalNot => write!(f, "not"),
+            Op::Negate => write!(f, "negate"),
+            Op::Cast(value_type) => write!(f, "cast {}", value_type),
+            Op::Jump(target) => write!(f, "jump {}", target),
+            Op::JumpIfFalse(target) => write!(f, "jump if false {}", target),
+            Op::Call(function) => write!(f, "call {}", function),
+            Op::CallNested(function, depth) => write!(f, "call {}^{}", function, depth),
+            Op::CallBuiltin(builtin) => write!(f, "call {}", builtin),
+            Op::Return => write!(f, "return"),
+        }
+    }
+}
+
+#[cfg(test)]
+fn compile_test_program(path: &str) -> Program {
+    crate::session::with_test_program(path, |program| compile::compile(program).unwrap())
+}
@@ -0,0 +1,260 @@
+use crate::codegen::CodegenError;
+use crate::interpreter::value::{ArithOperator, Comparison};
+use crate::ir::lower::lower;
+use crate::ir::{
+    self, Argument, BinaryOp, Callee, Constant, FunctionId, Instruction, Module, Operand, Place,
+    Temp, Terminator, UnaryOp, Variable,
+};
+use crate::semantics::value::Type;
+use crate::semantics::AnalyzedProgram;
+use crate::span::Span;
+
+use super::{Function, Op, Program, Slot};
+
+/// Compiles an analyzed program to bytecode, by lowering it to the IR first.
+pub fn compile(program: &AnalyzedProgram) -> Result<Program, CodegenError> {
+    Ok(compile_module(&lower(program)?))
+}
+
+/// Compiles a module to bytecode.
+/// The body is the first function, followed by each procedure in the order of their `FunctionId`s.
+pub fn compile_module(module: &Module) -> Program {
+    let mut strings = Vec::new();
+    let functions = module
+        .functions()
+        .map(|function| {
+            FunctionCompiler {
+                strings: &mut strings,
+                function,
+                code: Vec::new(),
+                spans: Vec::new(),
+                jumps: Vec::new(),
+            }
+            .compile()
+        })
+        .collect();
+    Program {
+        name: module.name.clone(),
+        strings,
+        globals: module
+            .globals
+            .iter()
+            .map(|global| global.value_type.clone())
+            .collect(),
+        functions,
+    }
+}
+
+/// The bytecode function a procedure compiles to, after the body
+fn function_index(id: FunctionId) -> usize {
+    id.0 + 1
+}
+
+fn slot(variable: Variable) -> Slot {
+    match variable {
+        Variable::Local(index) => Slot::Local(index),
+        Variable::Outer(depth, index) => Slot::Outer(depth, index),
+        Variable::Global(index) => Slot::Global(index),
+    }
+}
+
+struct FunctionCompiler<'c> {
+    strings: &'c mut Vec<String>,
+    function: &'c ir::Function,
+    code: Vec<Op>,
+    spans: Vec<Span>,
+    /// Jumps whose target is still a block id, to be pointed at the start of the block
+    jumps: Vec<usize>,
+}
+
+impl FunctionCompiler<'_> {
+    fn compile(mut self) -> Function {
+        let function = self.function;
+        let mut block_starts = Vec::new();
+        for (id, block) in function.blocks.iter().enumerate() {
+            block_starts.push(self.code.len());
+            for (instruction, span) in block.instructions.iter().zip(block.spans.iter()) {
+                self.instruction(instruction, *span);
+            }
+            self.terminator(&block.terminator, id + 1, block.terminator_span);
+        }
+        for position in self.jumps.iter() {
+            match &mut self.code[*position] {
+                Op::Jump(target) | Op::JumpIfFalse(target) => *target = block_starts[*target],
+                op => unreachable!("patched {} instead of a jump", op),
+            }
+        }
+
+        // Temporaries are kept in locals after the variables
+        let locals = function
+            .locals
+            .iter()
+            .map(|local| &local.value_type)
+            .chain(function.temps.iter())
+            .cloned()
+            .collect();
+        Function {
+            name: function.name.clone(),
+            parent: function.parent.map(function_index),
+            param_count: function.param_count,
+            locals,
+            return_type: function.return_type.clone(),
+            code: self.code,
+            spans: self.spans,
+        }
+    }
+
+    fn emit(&mut self, op: Op, span: Span) {
+        self.code.push(op);
+        self.spans.push(span);
+    }
+
+    /// Emits a jump to the start of a block
+    fn jump(&mut self, op: Op, span: Span) {
+        self.jumps.push(self.code.len());
+        self.emit(op, span);
+    }
+
+    fn string(&mut self, value: &str) -> usize {
+        match self.strings.iter().position(|string| string == value) {
+            Some(index) => index,
+            None => {
+                self.strings.push(String::from(value));
+                self.strings.len() - 1
+            }
+        }
+    }
+
+    fn temp_slot(&self, temp: Temp) -> Slot {
+        Slot::Local(self.function.locals.len() + temp.0)
+    }
+
+    fn push(&mut self, operand: &Operand, span: Span) {
+        let op = match operand {
+            Operand::Temp(temp) => Op::Load(self.temp_slot(*temp)),
+            Operand::Const(Constant::Bool(value)) => Op::PushBool(*value),
+            Operand::Const(Constant::Int(value)) => Op::PushInt(*value),
+            Operand::Const(Constant::Float(value)) => Op::PushFloat(*value),
+            Operand::Const(Constant::String(value)) => Op::PushString(self.string(value)),
+        };
+        self.emit(op, span);
+    }
+
+    /// Pops the value on top of the stack into a temporary
+    fn store(&mut self, temp: Temp, span: Span) {
+        self.emit(Op::Store(self.temp_slot(temp)), span);
+    }
+
+    fn instruction(&mut self, instruction: &Instruction, span: Span) {
+        match instruction {
+            Instruction::Load { dest, source } => {
+                match source {
+                    Place::Variable(variable) => self.emit(Op::Load(slot(*variable)), span),
+                    Place::Element(variable, index) => {
+                        self.push(index, span);
+                        self.emit(Op::LoadElement(slot(*variable)), span);
+                    }
+                }
+                self.store(*dest, span);
+            }
+            Instruction::Store { dest, value } => match dest {
+                Place::Variable(variable) => {
+                    self.push(value, span);
+                    self.emit(Op::Store(slot(*variable)), span);
+                }
+                Place::Element(variable, index) => {
+                    self.push(index, span);
+                    self.push(value, span);
+                    self.emit(Op::StoreElement(slot(*variable)), span);
+                }
+            },
+            // Arrays are values on the stack, so copying one is loading it
+            Instruction::CopyArray { dest, source } => {
+                self.emit(Op::Load(slot(*source)), span);
+                self.emit(Op::Store(slot(*dest)), span);
+            }
+            Instruction::Unary { dest, op, operand } => {
+                self.push(operand, span);
+                let op = match (op, self.function.operand_type(operand)) {
+                    (UnaryOp::Negate, _) => Op::Negate,
+                    (UnaryOp::Not, Type::Bool) => Op::LogicalNot,
+                    (UnaryOp::Not, _) => Op::BitwiseNot,
+                };
+                self.emit(op, span);
+                self.store(*dest, span);
+            }
+            Instruction::Binary { dest, op, lhs, rhs } => {
+                self.push(lhs, span);
+                self.push(rhs, span);
+                let is_bool = self.function.operand_type(lhs) == Type::Bool;
+                let op = match op {
+                    BinaryOp::Add => Op::Arith(ArithOperator::Add),
+                    BinaryOp::Subtract => Op::Arith(ArithOperator::Subtract),
+                    BinaryOp::Multiply => Op::Arith(ArithOperator::Multiply),
+                    BinaryOp::Divide => Op::Arith(ArithOperator::Divide),
+                    BinaryOp::And if is_bool => Op::LogicalAnd,
+                    BinaryOp::And => Op::BitwiseAnd,
+                    BinaryOp::Or if is_bool => Op::LogicalOr,
+                    BinaryOp::Or => Op::BitwiseOr,
+                    BinaryOp::LessThan => Op::Compare(Comparison::LessThan),
+                    BinaryOp::LessThanEq => Op::Compare(Comparison::LessThanEq),
+                    BinaryOp::GreaterThan => Op::Compare(Comparison::GreaterThan),
+                    BinaryOp::GreaterThanEq => Op::Compare(Comparison::GreaterThanEq),
+                    BinaryOp::Equals => Op::Compare(Comparison::Equals),
+                    BinaryOp::NotEquals => Op::Compare(Comparison::NotEquals),
+                };
+                self.emit(op, span);
+                self.store(*dest, span);
+            }
+            Instruction::Cast { dest, operand } => {
+                self.push(operand, span);
+                let value_type = self.function.temp_type(*dest).clone();
+                self.emit(Op::Cast(value_type), span);
+                self.store(*dest, span);
+            }
+            Instruction::Call { dest, callee, args } => {
+                for arg in args.iter() {
+                    match arg {
+                        Argument::Scalar(operand) => self.push(operand, span),
+                        Argument::Array(variable) => self.emit(Op::Load(slot(*variable)), span),
+                    }
+                }
+                let op = match callee {
+                    Callee::Procedure(id) => Op::Call(function_index(*id)),
+                    Callee::Nested(id, depth) => Op::CallNested(function_index(*id), *depth),
+                    Callee::Builtin(builtin) => Op::CallBuiltin(*builtin),
+                };
+                self.emit(op, span);
+                self.store(*dest, span);
+            }
+        }
+    }
+
+    /// Ends a block, falling through to the block after it where possible
+    fn terminator(&mut self, terminator: &Terminator, next: usize, span: Span) {
+        match terminator {
+            Terminator::Jump(target) => {
+                if target.0 != next {
+                    self.jump(Op::Jump(target.0), span);
+                }
+            }
+            Terminator::Branch {
+                condition,
+                then_block,
+                else_block,
+            } => {
+                self.push(condition, span);
+                self.jump(Op::JumpIfFalse(else_block.0), span);
+                if then_block.0 != next {
+                    self.jump(Op::Jump(then_block.0), span);
+                }
+            }
+            Terminator::Return(value) => {
+                if let Some(value) = value {
+                    self.push(value, span);
+                }
+                self.emit(Op::Return, span);
+            }
+        }
+    }
+}
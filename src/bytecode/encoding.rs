@@ -0,0 +1,539 @@
+//! The binary file format for bytecode programs.
+//!
+//! Files start with `MAGIC` and a little endian `u16` version, followed by the program.
+//! Integers are little endian, counts and indices are `u32`, and strings are a count of bytes then UTF-8.
+//! Optional indices are a byte that is 1 if the index follows.
+//! Each instruction is an opcode byte followed by its operands, then the span it was compiled from.
+
+use thiserror::Error;
+
+use crate::interpreter::value::{ArithOperator, Comparison};
+use crate::ir::Builtin;
+use crate::semantics::value::Type;
+use crate::span::Span;
+
+use super::{Function, Op, Program, Slot, BODY};
+
+pub const MAGIC: &[u8; 4] = b"CRBC";
+pub const VERSION: u16 = 2;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum DecodeError {
+    #[error("Not a bytecode file")]
+    NotBytecode,
+    #[error("Unsupported bytecode version {0}. Expected version {}.", VERSION)]
+    UnsupportedVersion(u16),
+    #[error("Unexpected end of file at byte {0}")]
+    UnexpectedEnd(usize),
+    #[error("Invalid {0} tag {1} at byte {2}")]
+    InvalidTag(&'static str, u8, usize),
+    #[error("Invalid UTF-8 in a string ending at byte {0}")]
+    InvalidUtf8(usize),
+    #[error("In {0}: {1} refers to something that does not exist")]
+    InvalidReference(String, String),
+}
+
+/// Whether `bytes` look like a bytecode file, rather than source
+pub fn is_bytecode(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+impl Program {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = Writer(Vec::new());
+        writer.0.extend_from_slice(MAGIC);
+        writer.0.extend_from_slice(&VERSION.to_le_bytes());
+        writer.string(&self.name);
+        writer.count(self.strings.len());
+        for string in self.strings.iter() {
+            writer.string(string);
+        }
+        writer.count(self.globals.len());
+        for global in self.globals.iter() {
+            writer.value_type(global);
+        }
+        writer.count(self.functions.len());
+        for function in self.functions.iter() {
+            writer.function(function);
+        }
+        writer.0
+    }
+
+    /// Decodes a program, checking that everything it refers to exists
+    pub fn from_bytes(bytes: &[u8]) -> Result<Program, DecodeError> {
+        if !is_bytecode(bytes) {
+            return Err(DecodeError::NotBytecode);
+        }
+        let mut reader = Reader {
+            bytes,
+            position: MAGIC.len(),
+        };
+        let version = u16::from_le_bytes(reader.array()?);
+        if version != VERSION {
+            return Err(DecodeError::UnsupportedVersion(version));
+        }
+
+        let name = reader.string()?;
+        let strings = reader.list(Reader::string)?;
+        let globals = reader.list(Reader::value_type)?;
+        let functions = reader.list(Reader::function)?;
+        let program = Program {
+            name,
+            strings,
+            globals,
+            functions,
+        };
+        program.check_references()?;
+        Ok(program)
+    }
+
+    fn check_references(&self) -> Result<(), DecodeError> {
+        if self.functions.get(BODY).is_none() {
+            return Err(DecodeError::InvalidReference(
+                self.name.clone(),
+                String::from("the program body"),
+            ));
+        }
+        for function in self.functions.iter() {
+            let invalid = |what: String| DecodeError::InvalidReference(function.name.clone(), what);
+            if function.param_count > function.locals.len() {
+                return Err(invalid(format!("parameter {}", function.param_count)));
+            }
+            if let Some(parent) = function.parent {
+                if parent >= self.functions.len() || parent == BODY {
+                    return Err(invalid(format!("parent function {}", parent)));
+                }
+            }
+            for op in function.code.iter() {
+                let valid = match op {
+                    Op::PushString(index) => *index < self.strings.len(),
+                    Op::Load(slot)
+                    | Op::Store(slot)
+                    | Op::LoadElement(slot)
+                    | Op::StoreElement(slot) => match slot {
+                        Slot::Local(index) => *index < function.locals.len(),
+                        Slot::Global(index) => *index < self.globals.len(),
+                        Slot::Outer(depth, index) => self
+                            .enclosing(function, *depth)
+                            .is_some_and(|enclosing| *index < enclosing.locals.len()),
+                    },
+                    // Jumping to the end is running off it, which is reported when run
+                    Op::Jump(target) | Op::JumpIfFalse(target) => *target <= function.code.len(),
+                    Op::Call(index) => self
+                        .callable(*index)
+                        .is_some_and(|callee| callee.parent.is_none()),
+                    Op::CallNested(index, depth) => {
+                        let parent = self.callable(*index).and_then(|callee| callee.parent);
+                        // Linked frames are always frames of the callee's parent
+                        parent.is_some_and(|parent| {
+                            self.enclosing(function, *depth).is_some_and(|enclosing| {
+                                std::ptr::eq(enclosing, &self.functions[parent])
+                            })
+                        })
+                    }
+                    _ => true,
+                };
+                if !valid {
+                    return Err(invalid(format!("`{}`", op)));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// A function that can be called, which is any but the body
+    fn callable(&self, index: usize) -> Option<&Function> {
+        self.functions.get(index).filter(|_| index != BODY)
+    }
+
+    /// The function whose frame is `depth` static links out from a frame of `function`.
+    /// Parents have been checked to exist.
+    fn enclosing<'f>(&'f self, function: &'f Function, depth: usize) -> Option<&'f Function> {
+        let mut enclosing = function;
+        for _ in 0..depth {
+            enclosing = &self.functions[enclosing.parent?];
+        }
+        Some(enclosing)
+    }
+}
+
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn count(&mut self, count: usize) {
+        self.0.extend_from_slice(&(count as u32).to_le_bytes());
+    }
+
+    fn string(&mut self, value: &str) {
+        self.count(value.len());
+        self.0.extend_from_slice(value.as_bytes());
+    }
+
+    fn optional(&mut self, value: Option<usize>) {
+        match value {
+            Some(value) => {
+                self.0.push(1);
+                self.count(value);
+            }
+            None => self.0.push(0),
+        }
+    }
+
+    fn value_type(&mut self, value_type: &Type) {
+        match value_type {
+            Type::Bool => self.0.push(0),
+            Type::Int => self.0.push(1),
+            Type::Float => self.0.push(2),
+            Type::String => self.0.push(3),
+            Type::Array(element_type, bound) => {
+                self.0.push(4);
+                self.value_type(element_type);
+                self.count(*bound);
+            }
+            Type::Void => self.0.push(5),
+        }
+    }
+
+    fn function(&mut self, function: &Function) {
+        self.string(&function.name);
+        self.optional(function.parent);
+        self.count(function.param_count);
+        self.count(function.locals.len());
+        for local in function.locals.iter() {
+            self.value_type(local);
+        }
+        self.value_type(&function.return_type);
+        self.count(function.code.len());
+        for (op, span) in function.code.iter().zip(function.spans.iter()) {
+            self.op(op);
+            for position in [span.start, span.end, span.line, span.column] {
+                self.count(position);
+            }
+        }
+    }
+
+    /// Writes a slot instruction, whose opcode is `opcode` for locals, the next for globals,
+    /// and `outer_opcode` for locals of an enclosing frame
+    fn slot(&mut self, opcode: u8, outer_opcode: u8, slot: &Slot) {
+        match slot {
+            Slot::Local(index) => {
+                self.0.push(opcode);
+                self.count(*index);
+            }
+            Slot::Global(index) => {
+                self.0.push(opcode + 1);
+                self.count(*index);
+            }
+            Slot::Outer(depth, index) => {
+                self.0.push(outer_opcode);
+                self.count(*depth);
+                self.count(*index);
+            }
+        }
+    }
+
+    fn op(&mut self, op: &Op) {
+        match op {
+            Op::PushBool(value) => self.0.extend_from_slice(&[0, *value as u8]),
+            Op::PushInt(value) => {
+                self.0.push(1);
+                self.0.extend_from_slice(&value.to_le_bytes());
+            }
+            Op::PushFloat(value) => {
+                self.0.push(2);
+                self.0.extend_from_slice(&value.to_bits().to_le_bytes());
+            }
+            Op::PushString(index) => {
+                self.0.push(3);
+                self.count(*index);
+            }
+            Op::Load(slot) => self.slot(4, 35, slot),
+            Op::Store(slot) => self.slot(6, 36, slot),
+            Op::LoadElement(slot) => self.slot(8, 37, slot),
+            Op::StoreElement(slot) => self.slot(10, 38, slot),
+            Op::Arith(operator) => self.0.push(match operator {
+                ArithOperator::Add => 12,
+                ArithOperator::Subtract => 13,
+                ArithOperator::Multiply => 14,
+                ArithOperator::Divide => 15,
+            }),
+            Op::Compare(comparison) => self.0.push(match comparison {
+                Comparison::LessThan => 16,
+                Comparison::LessThanEq => 17,
+                Comparison::GreaterThan => 18,
+                Comparison::GreaterThanEq => 19,
+                Comparison::Equals => 20,
+                Comparison::NotEquals => 21,
+            }),
+            Op::BitwiseAnd => self.0.push(22),
+            Op::BitwiseOr => self.0.push(23),
+            Op::BitwiseNot => self.0.push(24),
+            Op::LogicalAnd => self.0.push(25),
+            Op::LogicalOr => self.0.push(26),
+            Op::LogicalNot => self.0.push(27),
+            Op::Negate => self.0.push(28),
+            Op::Cast(value_type) => {
+                self.0.push(29);
+                self.value_type(value_type);
+            }
+            Op::Jump(target) => {
+                self.0.push(30);
+                self.count(*target);
+            }
+            Op::JumpIfFalse(target) => {
+                self.0.push(31);
+                self.count(*target);
+            }
+            Op::Call(function) => {
+                self.0.push(32);
+                self.count(*function);
+            }
+            Op::CallBuiltin(builtin) => {
+                let position = Builtin::ALL.iter().position(|other| other == builtin);
+                // Every builtin is in `ALL`
+                self.0.extend_from_slice(&[33, position.unwrap() as u8]);
+            }
+            Op::Return => self.0.push(34),
+            Op::CallNested(function, depth) => {
+                self.0.push(39);
+                self.count(*function);
+                self.count(*depth);
+            }
+        }
+    }
+}
+
+struct Reader<'b> {
+    bytes: &'b [u8],
+    position: usize,
+}
+
+impl<'b> Reader<'b> {
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], DecodeError> {
+        let bytes = self
+            .bytes
+            .get(self.position..self.position + N)
+            .ok_or(DecodeError::UnexpectedEnd(self.bytes.len()))?;
+        self.position += N;
+        // The slice has exactly N bytes
+        Ok(bytes.try_into().unwrap())
+    }
+
+    fn byte(&mut self) -> Result<u8, DecodeError> {
+        Ok(self.array::<1>()?[0])
+    }
+
+    fn count(&mut self) -> Result<usize, DecodeError> {
+        Ok(u32::from_le_bytes(self.array()?) as usize)
+    }
+
+    fn list<T>(
+        &mut self,
+        read: impl Fn(&mut Self) -> Result<T, DecodeError>,
+    ) -> Result<Vec<T>, DecodeError> {
+        let count = self.count()?;
+        // Don't trust the count for the allocation, in case the file is corrupt
+        let mut values = Vec::with_capacity(count.min(1024));
+        for _ in 0..count {
+            values.push(read(self)?);
+        }
+        Ok(values)
+    }
+
+    fn string(&mut self) -> Result<String, DecodeError> {
+        let length = self.count()?;
+        let bytes = self
+            .bytes
+            .get(self.position..self.position.saturating_add(length))
+            .ok_or(DecodeError::UnexpectedEnd(self.bytes.len()))?;
+        self.position += length;
+        String::from_utf8(bytes.to_vec()).map_err(|_| DecodeError::InvalidUtf8(self.position))
+    }
+
+    fn optional(&mut self) -> Result<Option<usize>, DecodeError> {
+        let position = self.position;
+        match self.byte()? {
+            0 => Ok(None),
+            1 => Ok(Some(self.count()?)),
+            tag => Err(DecodeError::InvalidTag("optional", tag, position)),
+        }
+    }
+
+    fn value_type(&mut self) -> Result<Type, DecodeError> {
+        let position = self.position;
+        match self.byte()? {
+            0 => Ok(Type::Bool),
+            1 => Ok(Type::Int),
+            2 => Ok(Type::Float),
+            3 => Ok(Type::String),
+            4 => {
+                let element_type = self.value_type()?;
+                Ok(Type::Array(Box::new(element_type), self.count()?))
+            }
+            5 => Ok(Type::Void),
+            tag => Err(DecodeError::InvalidTag("type", tag, position)),
+        }
+    }
+
+    fn function(&mut self) -> Result<Function, DecodeError> {
+        let name = self.string()?;
+        let parent = self.optional()?;
+        let param_count = self.count()?;
+        let locals = self.list(Reader::value_type)?;
+        let return_type = self.value_type()?;
+        let instructions = self.list(|reader| {
+            let op = reader.op()?;
+            let [start, end, line, column] = [(); 4].map(|_| reader.count());
+            Ok((op, Span::new(start?, end?, line?, column?)))
+        })?;
+        let (code, spans) = instructions.into_iter().unzip();
+        Ok(Function {
+            name,
+            parent,
+            param_count,
+            locals,
+            return_type,
+            code,
+            spans,
+        })
+    }
+
+    fn op(&mut self) -> Result<Op, DecodeError> {
+        let position = self.position;
+        let opcode = self.byte()?;
+        let slot = |index| match opcode % 2 {
+            0 => Slot::Local(index),
+            _ => Slot::Global(index),
+        };
+        let op = match opcode {
+            0 => match self.byte()? {
+                0 => Op::PushBool(false),
+                1 => Op::PushBool(true),
+                value => return Err(DecodeError::InvalidTag("bool", value, position + 1)),
+            },
+            1 => Op::PushInt(i64::from_le_bytes(self.array()?)),
+            2 => Op::PushFloat(f64::from_bits(u64::from_le_bytes(self.array()?))),
+            3 => Op::PushString(self.count()?),
+            4 | 5 => Op::Load(slot(self.count()?)),
+            6 | 7 => Op::Store(slot(self.count()?)),
+            8 | 9 => Op::LoadElement(slot(self.count()?)),
+            10 | 11 => Op::StoreElement(slot(self.count()?)),
+            12 => Op::Arith(ArithOperator::Add),
+            13 => Op::Arith(ArithOperator::Subtract),
+            14 => Op::Arith(ArithOperator::Multiply),
+            15 => Op::Arith(ArithOperator::Divide),
+            16 => Op::Compare(Comparison::LessThan),
+            17 => Op::Compare(Comparison::LessThanEq),
+            18 => Op::Compare(Comparison::GreaterThan),
+            19 => Op::Compare(Comparison::GreaterThanEq),
+            20 => Op::Compare(Comparison::Equals),
+            21 => Op::Compare(Comparison::NotEquals),
+            22 => Op::BitwiseAnd,
+            23 => Op::BitwiseOr,
+            24 => Op::BitwiseNot,
+            25 => Op::LogicalAnd,
+            26 => Op::LogicalOr,
+            27 => Op::LogicalNot,
+            28 => Op::Negate,
+            29 => Op::Cast(self.value_type()?),
+            30 => Op::Jump(self.count()?),
+            31 => Op::JumpIfFalse(self.count()?),
+            32 => Op::Call(self.count()?),
+            33 => {
+                let index = self.byte()?;
+                let builtin = Builtin::ALL
+                    .get(index as usize)
+                    .ok_or(DecodeError::InvalidTag("builtin", index, position + 1))?;
+                Op::CallBuiltin(*builtin)
+            }
+            34 => Op::Return,
+            35..=38 => {
+                let slot = Slot::Outer(self.count()?, self.count()?);
+                match opcode {
+                    35 => Op::Load(slot),
+                    36 => Op::Store(slot),
+                    37 => Op::LoadElement(slot),
+                    _ => Op::StoreElement(slot),
+                }
+            }
+            39 => Op::CallNested(self.count()?, self.count()?),
+            opcode => return Err(DecodeError::InvalidTag("opcode", opcode, position)),
+        };
+        Ok(op)
+    }
+}
+
+#[cfg(test)]
+use rstest::rstest;
+#[cfg(test)]
+use std::path::PathBuf;
+
+#[cfg(test)]
+#[rstest]
+fn roundtrip_test_programs(#[files("tests/correct/*.src")] source_file: PathBuf) {
+    let program = super::compile_test_program(source_file.to_str().unwrap());
+    let bytes = program.to_bytes();
+    assert!(is_bytecode(&bytes));
+    assert_eq!(Program::from_bytes(&bytes), Ok(program));
+}
+
+#[cfg(test)]
+#[test]
+fn roundtrip_static_links() {
+    let program = super::compile_test_program(crate::session::NESTED_RUN.0);
+    assert!(program
+        .functions
+        .iter()
+        .any(|function| function.parent.is_some()));
+    assert_eq!(Program::from_bytes(&program.to_bytes()), Ok(program));
+}
+
+#[cfg(test)]
+#[test]
+fn decode_rejects_invalid_files() {
+    let bytes = super::compile_test_program("tests/correct/math.src").to_bytes();
+    assert_eq!(
+        Program::from_bytes(b"program math is"),
+        Err(DecodeError::NotBytecode)
+    );
+    assert_eq!(
+        Program::from_bytes(&bytes[..bytes.len() - 1]),
+        Err(DecodeError::UnexpectedEnd(bytes.len() - 1))
+    );
+
+    let mut wrong_version = bytes.clone();
+    wrong_version[MAGIC.len()] = 99;
+    assert_eq!(
+        Program::from_bytes(&wrong_version),
+        Err(DecodeError::UnsupportedVersion(99))
+    );
+}
+
+#[cfg(test)]
+#[test]
+fn decode_rejects_invalid_references() {
+    let mut program = super::compile_test_program("tests/correct/math.src");
+    program.functions[BODY].code[0] = Op::Load(Slot::Global(program.globals.len()));
+    assert!(matches!(
+        Program::from_bytes(&program.to_bytes()),
+        Err(DecodeError::InvalidReference(..))
+    ));
+
+    // The body has no frame to link to a nested procedure's parent
+    let mut program = super::compile_test_program(crate::session::NESTED_RUN.0);
+    let nested = program
+        .functions
+        .iter()
+        .position(|function| function.parent.is_some())
+        .unwrap();
+    program.functions[BODY].code[0] = Op::CallNested(nested, 0);
+    assert!(matches!(
+        Program::from_bytes(&program.to_bytes()),
+        Err(DecodeError::InvalidReference(..))
+    ));
+    program.functions[BODY].code[0] = Op::Load(Slot::Outer(1, 0));
+    assert!(matches!(
+        Program::from_bytes(&program.to_bytes()),
+        Err(DecodeError::InvalidReference(..))
+    ));
+}
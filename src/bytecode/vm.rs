@@ -0,0 +1,395 @@
+use std::io::{BufRead, Write};
+
+use crate::interpreter::builtins::call_builtin;
+use crate::interpreter::value::Value;
+use crate::interpreter::{RuntimeError, MAX_CALL_DEPTH};
+use crate::semantics::value::Type;
+use crate::span::Span;
+
+use super::{Function, Op, Program, Slot, BODY};
+
+/// The state of a single function call
+struct Frame {
+    function: usize,
+    /// The next instruction to run
+    pc: usize,
+    locals: Vec<Value>,
+    /// The position in the call stack of the frame of the function's parent, if it is nested
+    link: Option<usize>,
+}
+
+impl Frame {
+    fn new(function: usize, definition: &Function, args: Vec<Value>, link: Option<usize>) -> Self {
+        let mut locals: Vec<Value> = definition.locals.iter().map(Value::default_for).collect();
+        for (local, value) in locals.iter_mut().zip(args) {
+            *local = value;
+        }
+        Frame {
+            function,
+            pc: 0,
+            locals,
+            link,
+        }
+    }
+}
+
+/// Runs a bytecode `Program` on an operand stack.
+pub struct Vm<'p, 'a> {
+    program: &'p Program,
+    globals: Vec<Value>,
+    stack: Vec<Value>,
+    frames: Vec<Frame>,
+    input: Box<dyn BufRead + 'a>,
+    output: Box<dyn Write + 'a>,
+    /// Where every instruction is logged before it runs, if tracing
+    trace: Option<Box<dyn Write + 'a>>,
+}
+
+impl<'p, 'a> Vm<'p, 'a> {
+    pub fn new(
+        program: &'p Program,
+        input: Box<dyn BufRead + 'a>,
+        output: Box<dyn Write + 'a>,
+    ) -> Self {
+        Vm {
+            program,
+            globals: program.globals.iter().map(Value::default_for).collect(),
+            stack: Vec::new(),
+            frames: vec![Frame::new(BODY, &program.functions[BODY], Vec::new(), None)],
+            input,
+            output,
+            trace: None,
+        }
+    }
+
+    /// Logs each instruction, with the top of the stack, to `trace`
+    pub fn with_trace(mut self, trace: Box<dyn Write + 'a>) -> Self {
+        self.trace = Some(trace);
+        self
+    }
+
+    pub fn run(mut self) -> Result<(), RuntimeError> {
+        let program = self.program;
+        while let Some(frame) = self.frames.last() {
+            let (function, pc) = (&program.functions[frame.function], frame.pc);
+            let Some(op) = function.code.get(pc) else {
+                return Err(RuntimeError::InvalidBytecode(
+                    format!("Ran off the end of {}", function.name),
+                    function.spans.last().copied().unwrap_or_default(),
+                ));
+            };
+            let span = function.spans[pc];
+            if self.trace.is_some() {
+                self.trace(function, pc, op, span)?;
+            }
+            self.frames.last_mut().unwrap().pc += 1;
+            self.step(op, span)?;
+        }
+
+        let span = program.functions[BODY].spans.last().copied();
+        self.output
+            .flush()
+            .map_err(|err| RuntimeError::IoError(err, span.unwrap_or_default()))
+    }
+
+    fn trace(
+        &mut self,
+        function: &Function,
+        pc: usize,
+        op: &Op,
+        span: Span,
+    ) -> Result<(), RuntimeError> {
+        let trace = self.trace.as_mut().unwrap();
+        let top = self
+            .stack
+            .iter()
+            .rev()
+            .take(3)
+            .map(|value| format!("{:?}", value));
+        writeln!(
+            trace,
+            "{}@{:04}  {:<24} [{}]",
+            function.name,
+            pc,
+            op.to_string(),
+            top.collect::<Vec<String>>().join(", ")
+        )
+        .map_err(|err| RuntimeError::IoError(err, span))
+    }
+
+    fn step(&mut self, op: &Op, span: Span) -> Result<(), RuntimeError> {
+        match op {
+            Op::PushBool(value) => self.stack.push(Value::Bool(*value)),
+            Op::PushInt(value) => self.stack.push(Value::Int(*value)),
+            Op::PushFloat(value) => self.stack.push(Value::Float(*value)),
+            Op::PushString(index) => {
+                let value = self.program.strings[*index].clone();
+                self.stack.push(Value::String(value));
+            }
+            Op::Load(slot) => {
+                let value = self.slot(*slot).clone();
+                self.stack.push(value);
+            }
+            Op::Store(slot) => {
+                let value = self.pop(span)?;
+                *self.slot(*slot) = value;
+            }
+            Op::LoadElement(slot) => {
+                let index = self.pop(span)?;
+                let value = self.slot(*slot).index(&index, span)?.clone();
+                self.stack.push(value);
+            }
+            Op::StoreElement(slot) => {
+                let value = self.pop(span)?;
+                let index = self.pop(span)?;
+                *self.slot(*slot).index_mut(&index, span)? = value;
+            }
+            Op::Arith(operator) => {
+                let (lhs, rhs) = self.pop_pair(span)?;
+                self.stack.push(lhs.arith(*operator, rhs, span)?);
+            }
+            Op::Compare(comparison) => {
+                let (lhs, rhs) = self.pop_pair(span)?;
+                self.stack.push(lhs.compare(*comparison, rhs, span)?);
+            }
+            Op::BitwiseAnd | Op::BitwiseOr | Op::LogicalAnd | Op::LogicalOr => {
+                let result = match (op, self.pop_pair(span)?) {
+                    (Op::BitwiseAnd, (Value::Int(lhs), Value::Int(rhs))) => Value::Int(lhs & rhs),
+                    (Op::BitwiseOr, (Value::Int(lhs), Value::Int(rhs))) => Value::Int(lhs | rhs),
+                    (Op::LogicalAnd, (Value::Bool(lhs), Value::Bool(rhs))) => {
+                        Value::Bool(lhs && rhs)
+                    }
+                    (Op::LogicalOr, (Value::Bool(lhs), Value::Bool(rhs))) => {
+                        Value::Bool(lhs || rhs)
+                    }
+                    (_, (lhs, rhs)) => return Err(RuntimeError::InvalidOperands(lhs, rhs, span)),
+                };
+                self.stack.push(result);
+            }
+            Op::BitwiseNot | Op::LogicalNot | Op::Negate => {
+                let result = match (op, self.pop(span)?) {
+                    (Op::BitwiseNot, Value::Int(value)) => Value::Int(!value),
+                    (Op::LogicalNot, Value::Bool(value)) => Value::Bool(!value),
+                    (Op::Negate, value) => value.negate(span)?,
+                    (_, value) => return Err(RuntimeError::InvalidOperand(value, span)),
+                };
+                self.stack.push(result);
+            }
+            Op::Cast(value_type) => {
+                let value = self.pop(span)?;
+                self.stack.push(value.cast(value_type));
+            }
+            Op::Jump(target) => self.frames.last_mut().unwrap().pc = *target,
+            Op::JumpIfFalse(target) => {
+                if !self.pop(span)?.is_true() {
+                    self.frames.last_mut().unwrap().pc = *target;
+                }
+            }
+            Op::Call(function) => self.call(*function, None, span)?,
+            Op::CallNested(function, depth) => {
+                let link = self.frame_position(*depth);
+                self.call(*function, Some(link), span)?;
+            }
+            Op::CallBuiltin(builtin) => {
+                let args = self.pop_args(builtin.param_types().len(), span)?;
+                let result = call_builtin(
+                    &mut self.input,
+                    &mut self.output,
+                    builtin.name(),
+                    &args,
+                    span,
+                )
+                .unwrap_or_else(|| {
+                    Err(RuntimeError::UndefinedRef(
+                        String::from(builtin.name()),
+                        span,
+                    ))
+                })?;
+                self.stack.push(result);
+            }
+            Op::Return => {
+                // There is always a frame while running
+                let frame = self.frames.pop().unwrap();
+                // The result is left on the stack for the caller
+                let returns_value =
+                    self.program.functions[frame.function].return_type != Type::Void;
+                if returns_value && self.stack.is_empty() {
+                    return Err(RuntimeError::InvalidBytecode(
+                        String::from("Stack underflow"),
+                        span,
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn call(
+        &mut self,
+        function: usize,
+        link: Option<usize>,
+        span: Span,
+    ) -> Result<(), RuntimeError> {
+        if self.frames.len() > MAX_CALL_DEPTH {
+            return Err(RuntimeError::StackOverflow(MAX_CALL_DEPTH, span));
+        }
+        let definition = &self.program.functions[function];
+        let args = self.pop_args(definition.param_count, span)?;
+        self.frames
+            .push(Frame::new(function, definition, args, link));
+        Ok(())
+    }
+
+    /// The position in the call stack of the frame `depth` static links out from the current one
+    fn frame_position(&self, depth: usize) -> usize {
+        let mut position = self.frames.len() - 1;
+        for _ in 0..depth {
+            // Static links are checked when the program is compiled or decoded
+            position = self.frames[position].link.unwrap();
+        }
+        position
+    }
+
+    fn slot(&mut self, slot: Slot) -> &mut Value {
+        // Slots are checked when the program is compiled or decoded
+        match slot {
+            Slot::Local(index) => &mut self.frames.last_mut().unwrap().locals[index],
+            Slot::Global(index) => &mut self.globals[index],
+            Slot::Outer(depth, index) => {
+                let position = self.frame_position(depth);
+                &mut self.frames[position].locals[index]
+            }
+        }
+    }
+
+    fn pop(&mut self, span: Span) -> Result<Value, RuntimeError> {
+        self.stack
+            .pop()
+            .ok_or_else(|| RuntimeError::InvalidBytecode(String::from("Stack underflow"), span))
+    }
+
+    /// Pops the right operand, then the left
+    fn pop_pair(&mut self, span: Span) -> Result<(Value, Value), RuntimeError> {
+        let rhs = self.pop(span)?;
+        Ok((self.pop(span)?, rhs))
+    }
+
+    fn pop_args(&mut self, count: usize, span: Span) -> Result<Vec<Value>, RuntimeError> {
+        let start =
+            self.stack.len().checked_sub(count).ok_or_else(|| {
+                RuntimeError::InvalidBytecode(String::from("Stack underflow"), span)
+            })?;
+        Ok(self.stack.split_off(start))
+    }
+}
+
+#[cfg(test)]
+use crate::interpreter::value::ArithOperator;
+#[cfg(test)]
+use crate::session::{NESTED_RUN, TEST_RUNS};
+
+/// Runs a program, returning what it wrote to stdout and the trace if `trace` is set
+#[cfg(test)]
+fn run_test_program(
+    program: &Program,
+    input: &str,
+    trace: bool,
+) -> Result<(String, String), RuntimeError> {
+    let mut output = Vec::new();
+    let mut trace_output = Vec::new();
+    let mut vm = Vm::new(program, Box::new(input.as_bytes()), Box::new(&mut output));
+    if trace {
+        vm = vm.with_trace(Box::new(&mut trace_output));
+    }
+    vm.run()?;
+    Ok((
+        String::from_utf8(output).unwrap(),
+        String::from_utf8(trace_output).unwrap(),
+    ))
+}
+
+#[cfg(test)]
+#[test]
+fn run_compiled_programs() {
+    for &(path, input, expected) in TEST_RUNS.iter().chain([&NESTED_RUN]) {
+        let program = super::compile_test_program(path);
+        let (output, _) = run_test_program(&program, input, false).unwrap();
+        assert_eq!(output, expected, "{}", path);
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn run_traces_every_instruction() {
+    let program = super::compile_test_program("tests/correct/math.src");
+    let (output, trace) = run_test_program(&program, "", true).unwrap();
+    assert_eq!(output, "610\n");
+    let first = format!("{}@0000  ", program.functions[BODY].name);
+    assert!(trace.lines().next().unwrap().starts_with(&first));
+    assert!(trace.lines().any(|line| line.contains("call putinteger")));
+}
+
+#[cfg(test)]
+#[test]
+fn run_traces_static_links() {
+    let (path, input, expected) = NESTED_RUN;
+    let program = super::compile_test_program(path);
+    let (output, trace) = run_test_program(&program, input, true).unwrap();
+    assert_eq!(output, expected);
+    assert!(trace.lines().any(|line| line.contains("store local 1^2")));
+}
+
+#[cfg(test)]
+#[test]
+fn run_reports_where_errors_happen() {
+    let source = "program spans is
+    variable n : integer;
+begin
+    n := getinteger();
+    n := 12 / n;
+end program.
+";
+    let program = crate::session::with_test_source(source, |program| {
+        super::compile::compile(program).unwrap()
+    });
+    let result = run_test_program(&program, "0\n", false);
+    let Err(RuntimeError::DivisionByZero(span)) = result else {
+        panic!("expected a division by zero, got {:?}", result);
+    };
+    assert_eq!(&source[span.start..span.end], "12 / n");
+}
+
+#[cfg(test)]
+#[test]
+fn run_reports_runtime_errors() {
+    let program = super::compile_test_program("tests/correct/recursiveFib.src");
+    let result = run_test_program(&program, "five\n", false);
+    assert!(matches!(result, Err(RuntimeError::InvalidInput(..))));
+
+    let span = Span::new(0, 1, 1, 1);
+    let mut program = Program {
+        name: String::from("divide"),
+        strings: Vec::new(),
+        globals: Vec::new(),
+        functions: vec![Function {
+            name: String::from("divide"),
+            parent: None,
+            param_count: 0,
+            locals: Vec::new(),
+            return_type: Type::Void,
+            code: vec![
+                Op::PushInt(1),
+                Op::PushInt(0),
+                Op::Arith(ArithOperator::Divide),
+                Op::Return,
+            ],
+            spans: vec![span; 4],
+        }],
+    };
+    let result = run_test_program(&program, "", false);
+    assert!(matches!(result, Err(RuntimeError::DivisionByZero(_))));
+
+    program.functions[BODY].code.remove(0);
+    let result = run_test_program(&program, "", false);
+    assert!(matches!(result, Err(RuntimeError::InvalidBytecode(..))));
+}
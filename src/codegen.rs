@@ -0,0 +1,193 @@
+use std::collections::HashMap;
+
+use thiserror::Error;
+
+use crate::semantics::context::Binding;
+use crate::semantics::procedure::AnalyzedProcedure;
+use crate::semantics::AnalyzedProgram;
+use crate::span::Span;
+
+pub mod c;
+pub mod llvm;
+pub mod traits;
+pub mod wasm;
+pub mod x86_64;
+
+/// Errors generating code for an analyzed program.
+/// These indicate a bug in the analyzer, since analyzed programs are well-formed.
+#[derive(Debug, Error)]
+pub enum CodegenError {
+    #[error("Undeclared reference {0}")]
+    UndefinedRef(String, Span),
+    #[error("Cannot generate code for a cast from {0} to {1}")]
+    InvalidCast(String, String, Span),
+}
+
+impl CodegenError {
+    pub fn span(&self) -> Span {
+        match self {
+            CodegenError::UndefinedRef(_, span) | CodegenError::InvalidCast(_, _, span) => *span,
+        }
+    }
+}
+
+/// Iterates a scope's declarations by identifier, so the output is deterministic
+pub(crate) fn sorted<T>(declarations: &HashMap<String, T>) -> Vec<(&String, &T)> {
+    let mut declarations: Vec<_> = declarations.iter().collect();
+    declarations.sort_by_key(|(identifier, _)| *identifier);
+    declarations
+}
+
+/// A procedure, and the symbol a backend generates it as
+#[derive(Debug, Clone)]
+pub(crate) struct ProcedureSymbol<'a> {
+    pub symbol: String,
+    pub procedure: &'a AnalyzedProcedure,
+}
+
+/// Resolves the procedures a call can refer to, for the backends that walk the analyzed program.
+/// Symbols are the backend's prefix followed by the identifiers of the enclosing procedures,
+/// since nested procedures may share identifiers.
+pub(crate) struct ProcedureScopes<'a> {
+    prefix: &'static str,
+    separator: &'static str,
+    global_procedures: HashMap<&'a str, ProcedureSymbol<'a>>,
+    /// Nested procedures of each procedure being generated that aren't global, innermost last
+    scopes: Vec<HashMap<&'a str, ProcedureSymbol<'a>>>,
+    /// The procedure being generated and those enclosing it, innermost last
+    generating: Vec<ProcedureSymbol<'a>>,
+}
+
+impl<'a> ProcedureScopes<'a> {
+    pub fn new(
+        program: &'a AnalyzedProgram,
+        prefix: &'static str,
+        separator: &'static str,
+    ) -> Self {
+        let mut scopes = ProcedureScopes {
+            prefix,
+            separator,
+            global_procedures: HashMap::new(),
+            scopes: Vec::new(),
+            generating: Vec::new(),
+        };
+        for procedure in program.procedures.iter() {
+            scopes.collect_global_procedures(procedure, scopes.top_level_symbol(procedure));
+        }
+        scopes
+    }
+
+    /// Adds every procedure declared in the global scope, including nested ones declared with `global`
+    fn collect_global_procedures(&mut self, procedure: &'a AnalyzedProcedure, symbol: String) {
+        for nested in procedure.procedures.iter() {
+            self.collect_global_procedures(nested, self.nested_symbol(&symbol, nested));
+        }
+        if procedure.is_global {
+            self.global_procedures
+                .insert(&procedure.identifier, ProcedureSymbol { symbol, procedure });
+        }
+    }
+
+    pub fn top_level_symbol(&self, procedure: &AnalyzedProcedure) -> String {
+        format!("{}{}", self.prefix, procedure.identifier)
+    }
+
+    pub fn nested_symbol(&self, parent: &str, procedure: &AnalyzedProcedure) -> String {
+        format!("{}{}{}", parent, self.separator, procedure.identifier)
+    }
+
+    /// Starts generating `procedure`, making its nested procedures visible to calls
+    pub fn enter(&mut self, procedure: &'a AnalyzedProcedure, symbol: &str) {
+        let procedures = procedure
+            .procedures
+            .iter()
+            .filter(|nested| !nested.is_global)
+            .map(|nested| {
+                let symbol = self.nested_symbol(symbol, nested);
+                let procedure = nested.as_ref();
+                (
+                    nested.identifier.as_str(),
+                    ProcedureSymbol { symbol, procedure },
+                )
+            })
+            .collect();
+        self.scopes.push(procedures);
+        self.generating.push(ProcedureSymbol {
+            symbol: symbol.to_string(),
+            procedure,
+        });
+    }
+
+    /// Finishes generating the innermost procedure
+    pub fn exit(&mut self) {
+        self.scopes.pop();
+        self.generating.pop();
+    }
+
+    /// The procedure being generated for `depth` 0, or the one declaring it `depth` levels out
+    pub fn enclosing(&self, depth: usize) -> Option<&ProcedureSymbol<'a>> {
+        self.generating.iter().rev().nth(depth)
+    }
+
+    /// Finds the procedure a call refers to, where the analyzer found it.
+    /// Builtins aren't procedures of the program, so they aren't found.
+    pub fn resolve(&self, identifier: &str, binding: Binding) -> Option<&ProcedureSymbol<'a>> {
+        match binding {
+            Binding::Global => self.global_procedures.get(identifier),
+            Binding::Local(depth) => self
+                .scopes
+                .iter()
+                .rev()
+                .nth(depth)
+                .and_then(|procedures| procedures.get(identifier)),
+        }
+    }
+}
+
+/// Finds a build of the runtime library next to the test executable, or in its parent directory.
+/// The file is named `<prefix>crust_runtime<suffix>`, like `libcrust_runtime.a`.
+#[cfg(test)]
+fn runtime_library(prefix: &str, suffix: &str) -> Option<std::path::PathBuf> {
+    let file_name = format!("{}crust_runtime{}", prefix, suffix);
+    let deps = std::env::current_exe().ok()?.parent()?.to_path_buf();
+    [deps.join(&file_name), deps.parent()?.join(&file_name)]
+        .into_iter()
+        .find(|path| path.is_file())
+}
+
+/// Generates a test program with a backend's `generate`, which must succeed
+#[cfg(test)]
+fn generate_test_program(
+    path: &str,
+    generate: fn(&AnalyzedProgram) -> Result<String, CodegenError>,
+) -> String {
+    crate::session::with_test_program(path, |program| generate(program).unwrap())
+}
+
+/// Runs a command with `input` on stdin, returning its stdout, or `None` if it isn't installed.
+/// Panics with its stderr if it fails.
+#[cfg(test)]
+fn run_tool(mut command: std::process::Command, input: &str) -> Option<String> {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .ok()?;
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    Some(String::from_utf8(output.stdout).unwrap())
+}
@@ -0,0 +1,904 @@
+use std::collections::HashMap;
+
+pub mod expression;
+pub mod statement;
+
+use crate::interpreter::value::{ArithOperator, Comparison};
+use crate::semantics::context::{Binding, ScopeContext};
+use crate::semantics::procedure::AnalyzedProcedure;
+use crate::semantics::value::{ProcedureSignature, Type};
+use crate::semantics::AnalyzedProgram;
+use crate::span::Span;
+
+use super::traits::Generate;
+use super::{sorted, CodegenError, ProcedureScopes};
+
+/// The header declaring the builtin procedures, which generated C includes.
+/// It is written next to the generated file, so only the runtime library is needed to build it.
+pub const RUNTIME_HEADER_NAME: &str = "crust_runtime.h";
+pub const RUNTIME_HEADER: &str = include_str!("../../runtime/include/crust_runtime.h");
+
+/// Helpers for the operations C leaves undefined, matching the wrapping semantics of the interpreter
+const PRELUDE: &str = r#"#include <stdbool.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#include "crust_runtime.h"
+
+static int64_t wrapping_add(int64_t lhs, int64_t rhs) {
+    return (int64_t)((uint64_t)lhs + (uint64_t)rhs);
+}
+
+static int64_t wrapping_sub(int64_t lhs, int64_t rhs) {
+    return (int64_t)((uint64_t)lhs - (uint64_t)rhs);
+}
+
+static int64_t wrapping_mul(int64_t lhs, int64_t rhs) {
+    return (int64_t)((uint64_t)lhs * (uint64_t)rhs);
+}
+
+static int64_t wrapping_neg(int64_t value) {
+    return (int64_t)(0 - (uint64_t)value);
+}
+
+static int64_t wrapping_div(int64_t lhs, int64_t rhs) {
+    if (rhs == 0) {
+        fputs("error: Attempted to divide by zero.\n", stderr);
+        exit(1);
+    }
+    return rhs == -1 ? wrapping_neg(lhs) : lhs / rhs;
+}
+"#;
+
+/// Translates an analyzed program to a single C99 file, which includes `RUNTIME_HEADER`.
+///
+/// Every procedure becomes a static function, with nested procedures lifted to the top level
+/// and named after their enclosing procedures.
+/// Globals are file scope variables. A procedure with nested procedures keeps its locals
+/// in a frame struct, and passes a pointer to it as the `link` parameter of those it calls,
+/// so they can reach the locals of every procedure enclosing them.
+/// Arrays are fixed size C arrays. Array parameters are copied on entry, since arrays are values.
+pub fn generate(program: &AnalyzedProgram) -> Result<String, CodegenError> {
+    let mut module = Module::new(program);
+
+    let mut main = FunctionBuilder::new(&mut module, Type::Void);
+    // String arrays are the only globals whose default can't be written as an initializer
+    for (identifier, value_type) in sorted(&program.declarations.variables) {
+        if let Type::Array(box Type::String, _) = value_type {
+            main.initialize(&global_symbol(identifier), value_type);
+        }
+    }
+    program.block.generate(&mut main)?;
+    let main = main.finish("int main(void)");
+
+    for procedure in program.procedures.iter() {
+        let symbol = module.procedures.top_level_symbol(procedure);
+        module.generate_procedure(procedure, symbol)?;
+    }
+    module.functions.push(main);
+
+    Ok(module.finish())
+}
+
+/// A C expression of `value_type`. Arrays are the name of an array.
+#[derive(Debug, Clone)]
+pub struct Operand {
+    pub value_type: Type,
+    pub value: String,
+    /// Whether evaluating the expression later gives the same value, without side effects.
+    /// Expressions that aren't stable are saved to a temporary before anything with side effects runs.
+    pub stable: bool,
+}
+
+impl Operand {
+    fn new(value_type: Type, value: impl Into<String>) -> Self {
+        Operand {
+            value_type,
+            value: value.into(),
+            stable: false,
+        }
+    }
+
+    fn constant(value_type: Type, value: impl Into<String>) -> Self {
+        Operand {
+            value_type,
+            value: value.into(),
+            stable: true,
+        }
+    }
+}
+
+/// The C type of a scalar of `value_type`, or of the elements of an array
+fn type_name(value_type: &Type) -> &'static str {
+    match value_type {
+        Type::Bool => "bool",
+        Type::Int => "int64_t",
+        Type::Float => "double",
+        Type::String => "const char *",
+        Type::Array(element_type, _) => type_name(element_type),
+        Type::Void => "void",
+    }
+}
+
+/// Declares `name` as a variable of `value_type`, like `int64_t name[10]`
+fn declaration(value_type: &Type, name: &str) -> String {
+    let type_name = type_name(value_type);
+    // Pointer types are written without a space before the name
+    let separator = if type_name.ends_with('*') { "" } else { " " };
+    match value_type {
+        Type::Array(_, bound) => format!("{}{}{}[{}]", type_name, separator, name, bound),
+        _ => format!("{}{}{}", type_name, separator, name),
+    }
+}
+
+/// The constant a scalar of `value_type` holds before it is first assigned
+fn default_constant(value_type: &Type) -> &'static str {
+    match value_type {
+        Type::Bool => "false",
+        Type::Int => "0",
+        Type::Float => "0.0",
+        Type::String => "\"\"",
+        Type::Array(..) => "{0}",
+        Type::Void => "",
+    }
+}
+
+/// Floats are written as Rust writes them, which is the shortest form that round trips
+fn float_constant(value: f64) -> String {
+    if value < 0.0 {
+        format!("({:?})", value)
+    } else {
+        format!("{:?}", value)
+    }
+}
+
+fn integer_constant(value: i64) -> String {
+    match value {
+        // The literal 9223372036854775808 doesn't fit in an int64_t, so can't be negated
+        i64::MIN => String::from("INT64_MIN"),
+        value if value < 0 => format!("({})", value),
+        value => value.to_string(),
+    }
+}
+
+fn global_symbol(identifier: &str) -> String {
+    format!("g_{}", identifier)
+}
+
+fn local_symbol(identifier: &str) -> String {
+    format!("l_{}", identifier)
+}
+
+/// The struct holding the locals of the procedure generated as `symbol`
+fn frame_type(symbol: &str) -> String {
+    format!("struct f_{}", symbol)
+}
+
+struct Module<'a> {
+    program: &'a AnalyzedProgram,
+    procedures: ProcedureScopes<'a>,
+    builtins: HashMap<String, ProcedureSignature>,
+    /// Definitions of the frame structs, which every prototype comes after
+    frame_types: Vec<String>,
+    /// Declarations of every function, so they can call each other in any order
+    prototypes: Vec<String>,
+    functions: Vec<String>,
+}
+
+impl<'a> Module<'a> {
+    fn new(program: &'a AnalyzedProgram) -> Self {
+        Module {
+            program,
+            procedures: ProcedureScopes::new(program, "p_", "__"),
+            builtins: ScopeContext::new_global_ctx().procedures,
+            frame_types: Vec::new(),
+            prototypes: Vec::new(),
+            functions: Vec::new(),
+        }
+    }
+
+    fn generate_procedure(
+        &mut self,
+        procedure: &'a AnalyzedProcedure,
+        symbol: String,
+    ) -> Result<(), CodegenError> {
+        self.procedures.enter(procedure, &symbol);
+        let link_type = self
+            .has_link(0)
+            .then(|| self.procedures.enclosing(1))
+            .flatten()
+            .map(|parent| frame_type(&parent.symbol));
+        let has_frame = self.has_frame(0);
+
+        let mut params = Vec::new();
+        if let Some(link_type) = &link_type {
+            params.push(format!("{} *link", link_type));
+        }
+        if has_frame {
+            let mut fields = Vec::new();
+            if link_type.is_some() {
+                fields.push(params[0].clone());
+            }
+            for (identifier, value_type) in sorted(&procedure.declarations.variables) {
+                fields.push(declaration(value_type, &local_symbol(identifier)));
+            }
+            let fields: String = fields
+                .iter()
+                .map(|field| format!("    {};\n", field))
+                .collect();
+            self.frame_types
+                .push(format!("{} {{\n{}}};\n", frame_type(&symbol), fields));
+        }
+
+        let return_type = procedure.declarations.return_type.clone();
+        let mut function = FunctionBuilder::new(self, return_type.clone());
+        function.variables = procedure.declarations.variables.clone();
+        function.has_frame = has_frame;
+        if has_frame {
+            // Zeroes every local, and the link until it is set
+            function.line(format!("{} frame = {{0}};", frame_type(&symbol)));
+            if link_type.is_some() {
+                function.line("frame.link = link;");
+            }
+        }
+
+        for arg in procedure.arg_list.iter() {
+            let local = function.local(&arg.0);
+            match &arg.1 {
+                // Arrays are passed by pointer, but copied on entry since they have value semantics
+                Type::Array(..) => {
+                    let param = format!("a_{}", arg.0);
+                    params.push(format!("const {}", declaration(&arg.1, &param)));
+                    if !has_frame {
+                        function.line(format!("{};", declaration(&arg.1, &local)));
+                    }
+                    function.line(format!("memcpy({0}, {1}, sizeof {0});", local, param));
+                }
+                value_type if has_frame => {
+                    let param = format!("a_{}", arg.0);
+                    params.push(declaration(value_type, &param));
+                    function.line(format!("{} = {};", local, param));
+                }
+                value_type => params.push(declaration(value_type, &local)),
+            }
+        }
+        for (identifier, value_type) in sorted(&procedure.declarations.variables) {
+            if !procedure.arg_list.iter().any(|arg| &arg.0 == identifier) {
+                function.declare_local(identifier, value_type);
+            }
+        }
+
+        procedure.block.generate(&mut function)?;
+        let params = if params.is_empty() {
+            String::from("void")
+        } else {
+            params.join(", ")
+        };
+        let header = format!("static {} {}({})", type_name(&return_type), symbol, params);
+        let function = function.finish(&header);
+        self.prototypes.push(format!("{};", header));
+        self.functions.push(function);
+
+        for nested in procedure.procedures.iter() {
+            let nested_symbol = self.procedures.nested_symbol(&symbol, nested);
+            self.generate_procedure(nested, nested_symbol)?;
+        }
+        self.procedures.exit();
+        Ok(())
+    }
+
+    /// Whether the procedure `depth` levels out keeps its locals in a frame struct.
+    /// Only procedures with nested procedures need one, and only if it holds locals or a link to pass on.
+    fn has_frame(&self, depth: usize) -> bool {
+        self.procedures.enclosing(depth).is_some_and(|enclosing| {
+            let procedure = enclosing.procedure;
+            procedure.procedures.iter().any(|nested| !nested.is_global)
+                && (self.has_link(depth) || !procedure.declarations.variables.is_empty())
+        })
+    }
+
+    /// Whether the procedure `depth` levels out is passed the frame of the procedure it is declared in.
+    /// Global procedures can't use the locals of procedures enclosing them.
+    fn has_link(&self, depth: usize) -> bool {
+        self.procedures
+            .enclosing(depth)
+            .is_some_and(|enclosing| !enclosing.procedure.is_global)
+            && self.has_frame(depth + 1)
+    }
+
+    fn finish(self) -> String {
+        let mut output = format!(
+            "/* Generated by crust from program {} */\n",
+            self.program.name
+        );
+        output.push_str(PRELUDE);
+
+        if !self.program.declarations.variables.is_empty() {
+            output.push('\n');
+        }
+        for (identifier, value_type) in sorted(&self.program.declarations.variables) {
+            let symbol = global_symbol(identifier);
+            match value_type {
+                // Arrays of other types are zeroed, like every static variable
+                Type::Array(..) => {
+                    output.push_str(&format!("static {};\n", declaration(value_type, &symbol)))
+                }
+                value_type => output.push_str(&format!(
+                    "static {} = {};\n",
+                    declaration(value_type, &symbol),
+                    default_constant(value_type)
+                )),
+            }
+        }
+
+        for frame_type in self.frame_types.iter() {
+            output.push('\n');
+            output.push_str(frame_type);
+        }
+
+        if !self.prototypes.is_empty() {
+            output.push('\n');
+        }
+        for prototype in self.prototypes.iter() {
+            output.push_str(prototype);
+            output.push('\n');
+        }
+        for function in self.functions {
+            output.push('\n');
+            output.push_str(&function);
+        }
+        output
+    }
+}
+
+/// Escapes a string for a C string literal.
+/// Question marks are escaped, so they can't form trigraphs.
+fn escape_string(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'"' | b'\\' | b'?' => format!("\\{}", byte as char),
+            0x20..=0x7e => String::from(byte as char),
+            _ => format!("\\{:03o}", byte),
+        })
+        .collect()
+}
+
+/// Removes the parentheses around a whole expression, where it stands alone in a statement
+fn unparenthesized(value: &str) -> &str {
+    let Some(inner) = value
+        .strip_prefix('(')
+        .and_then(|value| value.strip_suffix(')'))
+    else {
+        return value;
+    };
+    // `(a) + (b)` starts and ends with parentheses, but they don't match each other
+    let mut depth = 0;
+    for character in inner.chars() {
+        match character {
+            '(' => depth += 1,
+            ')' if depth == 0 => return value,
+            ')' => depth -= 1,
+            _ => (),
+        }
+    }
+    inner
+}
+
+/// Builds the body of a single function, one statement at a time.
+pub struct FunctionBuilder<'m, 'a> {
+    module: &'m mut Module<'a>,
+    /// Types of the function's locals and parameters, each named by `local`
+    variables: HashMap<String, Type>,
+    /// Whether the locals are in the struct `frame`, for nested procedures to reach
+    has_frame: bool,
+    return_type: Type,
+    body: Vec<String>,
+    /// Nesting depth of the next statement
+    indent: usize,
+    next_temp: usize,
+}
+
+impl<'m, 'a> FunctionBuilder<'m, 'a> {
+    fn new(module: &'m mut Module<'a>, return_type: Type) -> Self {
+        FunctionBuilder {
+            module,
+            variables: HashMap::new(),
+            has_frame: false,
+            return_type,
+            body: Vec::new(),
+            indent: 1,
+            next_temp: 0,
+        }
+    }
+
+    fn finish(mut self, header: &str) -> String {
+        // Falling off the end returns the default value
+        let returns = self
+            .body
+            .last()
+            .is_some_and(|line| line.starts_with("    return "));
+        if !returns {
+            match &self.return_type {
+                Type::Void => self.line("return 0;"),
+                return_type => self.line(format!("return {};", default_constant(return_type))),
+            }
+        }
+
+        let mut output = format!("{} {{\n", header);
+        for line in self.body.iter() {
+            output.push_str(line);
+            output.push('\n');
+        }
+        output.push_str("}\n");
+        output
+    }
+
+    fn line(&mut self, statement: impl AsRef<str>) {
+        self.body.push(format!(
+            "{}{}",
+            "    ".repeat(self.indent),
+            statement.as_ref()
+        ));
+    }
+
+    /// Emits a statement that opens a block, like `if (x) {`
+    fn open(&mut self, statement: impl AsRef<str>) {
+        self.line(statement);
+        self.indent += 1;
+    }
+
+    /// Closes the innermost block, with `statement` continuing it, like `} else {`
+    fn close(&mut self, statement: &str) {
+        self.indent -= 1;
+        self.line(format!("}}{}", statement));
+    }
+
+    fn temp(&mut self) -> String {
+        self.next_temp += 1;
+        format!("t{}", self.next_temp)
+    }
+
+    /// Where the next statement goes, for `sequence`
+    fn mark(&self) -> usize {
+        self.body.len()
+    }
+
+    /// Makes sure `operand` is evaluated before any statements emitted since `mark`,
+    /// by saving it to a temporary before them if it isn't stable.
+    /// Operands are evaluated from left to right, which C doesn't guarantee by itself.
+    fn sequence(&mut self, operand: Operand, mark: usize) -> Operand {
+        if operand.stable || self.body.len() == mark {
+            return operand;
+        }
+        let temp = self.temp();
+        let indent = "    ".repeat(self.indent);
+        let statements = match &operand.value_type {
+            Type::Array(..) => vec![
+                format!("{}{};", indent, declaration(&operand.value_type, &temp)),
+                format!(
+                    "{}memcpy({}, {}, sizeof {});",
+                    indent, temp, operand.value, temp
+                ),
+            ],
+            value_type => vec![format!(
+                "{}{} = {};",
+                indent,
+                declaration(value_type, &temp),
+                unparenthesized(&operand.value)
+            )],
+        };
+        self.body.splice(mark..mark, statements);
+        Operand::constant(operand.value_type, temp)
+    }
+
+    /// Saves a scalar value to a temporary, so whatever side effects it has happen now
+    fn save(&mut self, operand: Operand) -> Operand {
+        let temp = self.temp();
+        self.line(format!(
+            "{} = {};",
+            declaration(&operand.value_type, &temp),
+            unparenthesized(&operand.value)
+        ));
+        Operand::constant(operand.value_type, temp)
+    }
+
+    /// Names one of the function's own locals
+    fn local(&self, identifier: &str) -> String {
+        match self.has_frame {
+            true => format!("frame.{}", local_symbol(identifier)),
+            false => local_symbol(identifier),
+        }
+    }
+
+    /// Declares a local variable, holding the default value for `value_type`
+    fn declare_local(&mut self, identifier: &str, value_type: &Type) {
+        let symbol = &self.local(identifier);
+        if self.has_frame {
+            // The frame starts out zeroed, which is the default for everything but strings
+            match value_type {
+                Type::String => self.line(format!("{} = \"\";", symbol)),
+                Type::Array(box Type::String, _) => self.initialize(symbol, value_type),
+                _ => (),
+            }
+            return;
+        }
+        match value_type {
+            Type::Array(box Type::String, _) => {
+                self.line(format!("{};", declaration(value_type, symbol)));
+                self.initialize(symbol, value_type);
+            }
+            value_type => self.line(format!(
+                "{} = {};",
+                declaration(value_type, symbol),
+                default_constant(value_type)
+            )),
+        }
+    }
+
+    /// Sets every element of a string array to the empty string
+    fn initialize(&mut self, symbol: &str, value_type: &Type) {
+        let Type::Array(_, bound) = value_type else {
+            unreachable!("only string arrays need initializing")
+        };
+        self.for_each_index(*bound, |function, index| {
+            function.line(format!("{}[{}] = \"\";", symbol, index));
+            Ok(())
+        })
+        // Storing constants can't fail
+        .unwrap()
+    }
+
+    /// Looks up a variable where the analyzer found it.
+    /// Locals of enclosing procedures are reached by following the links of their frames.
+    fn variable(
+        &self,
+        identifier: &str,
+        binding: Binding,
+        span: Span,
+    ) -> Result<Operand, CodegenError> {
+        if let Binding::Local(depth @ 1..) = binding {
+            return self
+                .module
+                .procedures
+                .enclosing(depth)
+                .and_then(|enclosing| enclosing.procedure.declarations.variables.get(identifier))
+                .map(|value_type| {
+                    let value = format!("{}{}", "link->".repeat(depth), local_symbol(identifier));
+                    Operand::new(value_type.clone(), value)
+                })
+                .ok_or_else(|| CodegenError::UndefinedRef(String::from(identifier), span));
+        }
+        if let Some(value_type) = self.variables.get(identifier) {
+            return Ok(Operand::new(value_type.clone(), self.local(identifier)));
+        }
+        self.module
+            .program
+            .declarations
+            .variables
+            .get(identifier)
+            .map(|value_type| Operand::new(value_type.clone(), global_symbol(identifier)))
+            .ok_or_else(|| CodegenError::UndefinedRef(String::from(identifier), span))
+    }
+
+    /// Assigns `value` to `destination`, copying the contents of arrays
+    fn store(&mut self, value: &Operand, destination: &str) {
+        match value.value_type {
+            // The source may be the destination itself
+            Type::Array(..) => self.line(format!(
+                "memmove({0}, {1}, sizeof {0});",
+                destination, value.value
+            )),
+            _ => self.line(format!(
+                "{} = {};",
+                destination,
+                unparenthesized(&value.value)
+            )),
+        }
+    }
+
+    /// Opens a C `for` loop with an `int64_t` counter from 0 up to `bound`, with `body` emitting its statements
+    fn for_each_index(
+        &mut self,
+        bound: usize,
+        mut body: impl FnMut(&mut Self, &str) -> Result<(), CodegenError>,
+    ) -> Result<(), CodegenError> {
+        let index = self.temp();
+        self.open(format!(
+            "for (int64_t {0} = 0; {0} < {1}; {0}++) {{",
+            index, bound
+        ));
+        body(self, &index)?;
+        self.close("");
+        Ok(())
+    }
+
+    /// Declares a temporary C array of `array_type` and assigns each element in a loop,
+    /// from the expression `element` gives for the index
+    fn map_array(
+        &mut self,
+        array_type: &Type,
+        mut element: impl FnMut(&mut Self, &str) -> Result<Operand, CodegenError>,
+    ) -> Result<Operand, CodegenError> {
+        let Type::Array(_, bound) = array_type else {
+            unreachable!("only arrays are mapped")
+        };
+        let array = self.temp();
+        self.line(format!("{};", declaration(array_type, &array)));
+        self.for_each_index(*bound, |function, index| {
+            let value = element(function, index)?;
+            function.line(format!("{}[{}] = {};", array, index, value.value));
+            Ok(())
+        })?;
+        Ok(Operand::constant(array_type.clone(), array))
+    }
+
+    /// Gives an element of an array operand, or a scalar operand as is
+    fn broadcast(&mut self, operand: &Operand, index: &str) -> Operand {
+        match &operand.value_type {
+            Type::Array(element_type, _) => Operand {
+                value_type: element_type.as_ref().clone(),
+                value: format!("{}[{}]", operand.value, index),
+                stable: operand.stable,
+            },
+            _ => operand.clone(),
+        }
+    }
+
+    fn cast(
+        &mut self,
+        operand: Operand,
+        value_type: &Type,
+        span: Span,
+    ) -> Result<Operand, CodegenError> {
+        match (&operand.value_type, value_type) {
+            (from, to) if from == to => Ok(operand),
+            (Type::Int, Type::Float)
+            | (Type::Float, Type::Int)
+            | (Type::Bool, Type::Int)
+            | (Type::Int, Type::Bool) => Ok(Operand {
+                value_type: value_type.clone(),
+                value: format!("(({}){})", type_name(value_type), operand.value),
+                stable: operand.stable,
+            }),
+            (Type::Array(..), Type::Array(element_type, _)) => {
+                let array_type = value_type.clone();
+                let element_type = element_type.as_ref().clone();
+                self.map_array(&array_type, |function, index| {
+                    let element = function.broadcast(&operand, index);
+                    function.cast(element, &element_type, span)
+                })
+            }
+            (from, to) => Err(CodegenError::InvalidCast(
+                format!("{:?}", from),
+                format!("{:?}", to),
+                span,
+            )),
+        }
+    }
+
+    /// Combines two operands into an expression, which is stable if they are
+    fn binary(&self, value_type: Type, value: String, lhs: &Operand, rhs: &Operand) -> Operand {
+        Operand {
+            value_type,
+            value,
+            stable: lhs.stable && rhs.stable,
+        }
+    }
+
+    /// Builds the expression for an arithmetic operator, using the prelude's `wrapping_*` helpers for integers.
+    /// Operators on arrays build a temporary array instead.
+    fn arith(
+        &mut self,
+        operator: ArithOperator,
+        lhs: Operand,
+        rhs: Operand,
+    ) -> Result<Operand, CodegenError> {
+        let array_type = match (&lhs.value_type, &rhs.value_type) {
+            (array_type @ Type::Array(..), _) | (_, array_type @ Type::Array(..)) => {
+                array_type.clone()
+            }
+            (Type::Float, _) => {
+                let symbol = match operator {
+                    ArithOperator::Add => "+",
+                    ArithOperator::Subtract => "-",
+                    ArithOperator::Multiply => "*",
+                    ArithOperator::Divide => "/",
+                };
+                let value = format!("({} {} {})", lhs.value, symbol, rhs.value);
+                return Ok(self.binary(Type::Float, value, &lhs, &rhs));
+            }
+            (value_type, _) => {
+                let helper = match operator {
+                    ArithOperator::Add => "wrapping_add",
+                    ArithOperator::Subtract => "wrapping_sub",
+                    ArithOperator::Multiply => "wrapping_mul",
+                    ArithOperator::Divide => "wrapping_div",
+                };
+                let value = format!("{}({}, {})", helper, lhs.value, rhs.value);
+                let mut result = self.binary(value_type.clone(), value, &lhs, &rhs);
+                // Dividing by zero exits, so must happen in order
+                result.stable &= operator != ArithOperator::Divide;
+                return Ok(result);
+            }
+        };
+        self.map_array(&array_type, |function, index| {
+            let lhs = function.broadcast(&lhs, index);
+            let rhs = function.broadcast(&rhs, index);
+            function.arith(operator, lhs, rhs)
+        })
+    }
+
+    fn negate(&mut self, operand: Operand) -> Result<Operand, CodegenError> {
+        match &operand.value_type {
+            Type::Float => Ok(Operand {
+                value_type: Type::Float,
+                value: format!("(-{})", operand.value),
+                stable: operand.stable,
+            }),
+            Type::Array(..) => self.map_array(&operand.value_type.clone(), |function, index| {
+                let element = function.broadcast(&operand, index);
+                function.negate(element)
+            }),
+            _ => Ok(Operand {
+                value_type: Type::Int,
+                value: format!("wrapping_neg({})", operand.value),
+                stable: operand.stable,
+            }),
+        }
+    }
+
+    fn compare(&self, comparison: Comparison, lhs: Operand, rhs: Operand) -> Operand {
+        // C's operators already give false for NaN, except for `!=`, so floats need no special case
+        let symbol = match comparison {
+            Comparison::LessThan => "<",
+            Comparison::LessThanEq => "<=",
+            Comparison::GreaterThan => ">",
+            Comparison::GreaterThanEq => ">=",
+            Comparison::Equals => "==",
+            Comparison::NotEquals => "!=",
+        };
+        let value = match lhs.value_type {
+            Type::String => format!("(strcmp({}, {}) {} 0)", lhs.value, rhs.value, symbol),
+            _ => format!("({} {} {})", lhs.value, symbol, rhs.value),
+        };
+        self.binary(Type::Bool, value, &lhs, &rhs)
+    }
+
+    /// Calls the C function for the procedure the analyzer bound the call to, or `crust_<name>` for a builtin.
+    /// The result is saved to a temporary, so calls happen in order.
+    fn call(
+        &mut self,
+        identifier: &str,
+        binding: Binding,
+        args: Vec<Operand>,
+        span: Span,
+    ) -> Result<Operand, CodegenError> {
+        let (symbol, return_type) = match self.module.procedures.resolve(identifier, binding) {
+            Some(procedure) => (
+                procedure.symbol.clone(),
+                procedure.procedure.declarations.return_type.clone(),
+            ),
+            None => match self.module.builtins.get(identifier) {
+                Some(signature) => (format!("crust_{}", identifier), signature.1.clone()),
+                None => return Err(CodegenError::UndefinedRef(String::from(identifier), span)),
+            },
+        };
+
+        let mut args: Vec<String> = args.into_iter().map(|arg| arg.value).collect();
+        // Procedures declared in one with a frame are passed a pointer to it
+        if let Binding::Local(depth) = binding {
+            if self.module.has_frame(depth) {
+                let link = match depth {
+                    0 => String::from("&frame"),
+                    depth => format!("link{}", "->link".repeat(depth - 1)),
+                };
+                args.insert(0, link);
+            }
+        }
+        let call = Operand::new(return_type, format!("{}({})", symbol, args.join(", ")));
+        Ok(self.save(call))
+    }
+}
+
+#[cfg(test)]
+use super::{generate_test_program, run_tool};
+#[cfg(test)]
+use crate::session::{NESTED_RUN, TEST_RUNS};
+#[cfg(test)]
+use rstest::rstest;
+#[cfg(test)]
+use std::path::{Path, PathBuf};
+#[cfg(test)]
+use std::process::{Command, Stdio};
+
+/// Compiles generated C with `cc`, along with `extra_args`.
+/// Returns `false` if `cc` isn't installed.
+#[cfg(test)]
+fn run_cc(source: &str, stem: &str, extra_args: &[&std::ffi::OsStr]) -> bool {
+    let directory = std::env::temp_dir().join(format!("crust-{}-{}", std::process::id(), stem));
+    std::fs::create_dir_all(&directory).unwrap();
+    let source_path = directory.join(format!("{}.c", stem));
+    std::fs::write(&source_path, source).unwrap();
+    std::fs::write(directory.join(RUNTIME_HEADER_NAME), RUNTIME_HEADER).unwrap();
+
+    let output = Command::new("cc")
+        .args(["-std=c99", "-pedantic-errors", "-Wall", "-Wno-unused"])
+        .arg(&source_path)
+        .args(extra_args)
+        .stderr(Stdio::piped())
+        .output();
+    std::fs::remove_dir_all(&directory).unwrap();
+    let Ok(output) = output else {
+        return false;
+    };
+    assert!(
+        output.status.success() && output.stderr.is_empty(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    true
+}
+
+#[cfg(test)]
+#[rstest]
+#[case("", "")]
+#[case("Enter a string:", "Enter a string:")]
+#[case("say \"hi\"\\", "say \\\"hi\\\"\\\\")]
+#[case("what??!", "what\\?\\?!")]
+#[case("tab\there\n", "tab\\011here\\012")]
+#[case("é", "\\303\\251")]
+fn escape_string_for_c(#[case] value: &str, #[case] expected: &str) {
+    assert_eq!(escape_string(value), expected);
+}
+
+#[cfg(test)]
+#[rstest]
+fn generate_valid_c(#[files("tests/correct/*.src")] source_file: PathBuf) {
+    let source = generate_test_program(source_file.to_str().unwrap(), generate);
+    let stem = source_file.file_stem().unwrap().to_str().unwrap();
+    if !run_cc(&source, stem, &["-fsyntax-only".as_ref()]) {
+        eprintln!("cc not found, skipping");
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn run_generated_programs() {
+    let Some(runtime) = super::runtime_library("lib", ".a") else {
+        eprintln!("the runtime library not found, skipping");
+        return;
+    };
+    for &(path, input, expected) in TEST_RUNS.iter().chain([&NESTED_RUN]) {
+        let source = generate_test_program(path, generate);
+
+        let stem = format!(
+            "{}-run",
+            Path::new(path).file_stem().unwrap().to_str().unwrap()
+        );
+        let executable_path =
+            std::env::temp_dir().join(format!("crust-{}-{}.out", std::process::id(), stem));
+        let linked = run_cc(
+            &source,
+            &stem,
+            &[
+                "-o".as_ref(),
+                executable_path.as_os_str(),
+                runtime.as_os_str(),
+                "-lpthread".as_ref(),
+                "-ldl".as_ref(),
+                "-lm".as_ref(),
+            ],
+        );
+        if !linked {
+            eprintln!("cc not found, skipping");
+            return;
+        }
+
+        let output = run_tool(Command::new(&executable_path), input).unwrap();
+        std::fs::remove_file(&executable_path).unwrap();
+        assert_eq!(output, expected, "{}", path);
+    }
+}
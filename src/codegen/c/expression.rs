@@ -0,0 +1,208 @@
+use crate::codegen::traits::GenerateExpression;
+use crate::codegen::CodegenError;
+use crate::interpreter::value::{ArithOperator, Comparison};
+use crate::semantics::expression::{
+    AnalyzedArithOp, AnalyzedExpression, AnalyzedFactor, AnalyzedName, AnalyzedNumber,
+    AnalyzedRelation, AnalyzedTerm,
+};
+use crate::semantics::procedure::AnalyzedProcedureCall;
+use crate::semantics::value::Type;
+
+use super::{escape_string, float_constant, integer_constant, FunctionBuilder, Operand};
+
+impl GenerateExpression<FunctionBuilder<'_, '_>, Operand> for AnalyzedExpression {
+    fn generate(&self, function: &mut FunctionBuilder) -> Result<Operand, CodegenError> {
+        // Calls are saved to temporaries as they are generated, so `&&` and `||` can't skip them
+        let (expression, arith_op, symbol) = match self {
+            AnalyzedExpression::BitwiseAnd(expression, arith_op) => (expression, arith_op, "&"),
+            AnalyzedExpression::BitwiseOr(expression, arith_op) => (expression, arith_op, "|"),
+            AnalyzedExpression::LogicalAnd(expression, arith_op) => (expression, arith_op, "&&"),
+            AnalyzedExpression::LogicalOr(expression, arith_op) => (expression, arith_op, "||"),
+            AnalyzedExpression::BitwiseNot(arith_op) | AnalyzedExpression::LogicalNot(arith_op) => {
+                let value = arith_op.generate(function)?;
+                let symbol = match self {
+                    AnalyzedExpression::BitwiseNot(_) => "~",
+                    _ => "!",
+                };
+                return Ok(Operand {
+                    value_type: value.value_type,
+                    value: format!("({}{})", symbol, value.value),
+                    stable: value.stable,
+                });
+            }
+            AnalyzedExpression::Cast(expression, value_type) => {
+                let value = expression.generate(function)?;
+                return function.cast(value, value_type, self.span());
+            }
+            AnalyzedExpression::ArithOp(arith_op) => return arith_op.generate(function),
+        };
+        let lhs = expression.generate(function)?;
+        let mark = function.mark();
+        let rhs = arith_op.generate(function)?;
+        let lhs = function.sequence(lhs, mark);
+        let value = format!("({} {} {})", lhs.value, symbol, rhs.value);
+        Ok(function.binary(lhs.value_type.clone(), value, &lhs, &rhs))
+    }
+}
+
+impl GenerateExpression<FunctionBuilder<'_, '_>, Operand> for AnalyzedArithOp {
+    fn generate(&self, function: &mut FunctionBuilder) -> Result<Operand, CodegenError> {
+        let (arith_op, relation, operator) = match self {
+            AnalyzedArithOp::Plus(arith_op, relation)
+            | AnalyzedArithOp::ArrayScalarPlus(arith_op, relation)
+            | AnalyzedArithOp::ScalarArrayPlus(arith_op, relation)
+            | AnalyzedArithOp::ArrayPlus(arith_op, relation) => {
+                (arith_op, relation, ArithOperator::Add)
+            }
+            AnalyzedArithOp::Minus(arith_op, relation)
+            | AnalyzedArithOp::ArrayScalarMinus(arith_op, relation)
+            | AnalyzedArithOp::ScalarArrayMinus(arith_op, relation)
+            | AnalyzedArithOp::ArrayMinus(arith_op, relation) => {
+                (arith_op, relation, ArithOperator::Subtract)
+            }
+            AnalyzedArithOp::Cast(arith_op, value_type) => {
+                let value = arith_op.generate(function)?;
+                return function.cast(value, value_type, self.span());
+            }
+            AnalyzedArithOp::Relation(relation) => return relation.generate(function),
+        };
+        let lhs = arith_op.generate(function)?;
+        let mark = function.mark();
+        let rhs = relation.generate(function)?;
+        let lhs = function.sequence(lhs, mark);
+        function.arith(operator, lhs, rhs)
+    }
+}
+
+impl GenerateExpression<FunctionBuilder<'_, '_>, Operand> for AnalyzedRelation {
+    fn generate(&self, function: &mut FunctionBuilder) -> Result<Operand, CodegenError> {
+        let (relation, term, comparison) = match self {
+            AnalyzedRelation::LessThan(relation, term) => (relation, term, Comparison::LessThan),
+            AnalyzedRelation::LessThanEq(relation, term) => {
+                (relation, term, Comparison::LessThanEq)
+            }
+            AnalyzedRelation::GreaterThan(relation, term) => {
+                (relation, term, Comparison::GreaterThan)
+            }
+            AnalyzedRelation::GreaterThanEq(relation, term) => {
+                (relation, term, Comparison::GreaterThanEq)
+            }
+            AnalyzedRelation::Equals(relation, term) => (relation, term, Comparison::Equals),
+            AnalyzedRelation::NotEquals(relation, term) => (relation, term, Comparison::NotEquals),
+            AnalyzedRelation::Cast(relation, value_type) => {
+                let value = relation.generate(function)?;
+                return function.cast(value, value_type, self.span());
+            }
+            AnalyzedRelation::Term(term) => return term.generate(function),
+        };
+        let lhs = relation.generate(function)?;
+        let mark = function.mark();
+        let rhs = term.generate(function)?;
+        let lhs = function.sequence(lhs, mark);
+        Ok(function.compare(comparison, lhs, rhs))
+    }
+}
+
+impl GenerateExpression<FunctionBuilder<'_, '_>, Operand> for AnalyzedTerm {
+    fn generate(&self, function: &mut FunctionBuilder) -> Result<Operand, CodegenError> {
+        let (term, factor, operator) = match self {
+            AnalyzedTerm::Multiply(term, factor)
+            | AnalyzedTerm::ArrayScalarMultiply(term, factor)
+            | AnalyzedTerm::ScalarArrayMultiply(term, factor)
+            | AnalyzedTerm::ArrayMultiply(term, factor) => (term, factor, ArithOperator::Multiply),
+            AnalyzedTerm::Divide(term, factor)
+            | AnalyzedTerm::ArrayScalarDivide(term, factor)
+            | AnalyzedTerm::ScalarArrayDivide(term, factor)
+            | AnalyzedTerm::ArrayDivide(term, factor) => (term, factor, ArithOperator::Divide),
+            AnalyzedTerm::Cast(term, value_type) => {
+                let value = term.generate(function)?;
+                return function.cast(value, value_type, self.span());
+            }
+            AnalyzedTerm::Factor(factor) => return factor.generate(function),
+        };
+        let lhs = term.generate(function)?;
+        let mark = function.mark();
+        let rhs = factor.generate(function)?;
+        let lhs = function.sequence(lhs, mark);
+        function.arith(operator, lhs, rhs)
+    }
+}
+
+impl GenerateExpression<FunctionBuilder<'_, '_>, Operand> for AnalyzedFactor {
+    fn generate(&self, function: &mut FunctionBuilder) -> Result<Operand, CodegenError> {
+        match self {
+            AnalyzedFactor::NestedExpression(expression) => expression.generate(function),
+            AnalyzedFactor::ProcedureCall(proc_call) => proc_call.generate(function),
+            AnalyzedFactor::Name(name) => name.generate(function),
+            AnalyzedFactor::NegatedName(name) => {
+                let value = name.generate(function)?;
+                function.negate(value)
+            }
+            AnalyzedFactor::Number(number, _) => Ok(number_constant(number, false)),
+            AnalyzedFactor::NegatedNumber(number, _) => Ok(number_constant(number, true)),
+            AnalyzedFactor::String(value, _) => Ok(Operand::constant(
+                Type::String,
+                format!("\"{}\"", escape_string(value)),
+            )),
+            AnalyzedFactor::True(_) => Ok(Operand::constant(Type::Bool, "true")),
+            AnalyzedFactor::False(_) => Ok(Operand::constant(Type::Bool, "false")),
+            AnalyzedFactor::Cast(factor, value_type) => {
+                let value = factor.generate(function)?;
+                function.cast(value, value_type, self.span())
+            }
+        }
+    }
+}
+
+fn number_constant(number: &AnalyzedNumber, negate: bool) -> Operand {
+    match (number, negate) {
+        (AnalyzedNumber::Integer(value), false) => {
+            Operand::constant(Type::Int, integer_constant(*value))
+        }
+        (AnalyzedNumber::Integer(value), true) => {
+            Operand::constant(Type::Int, integer_constant(value.wrapping_neg()))
+        }
+        (AnalyzedNumber::Float(value), false) => {
+            Operand::constant(Type::Float, float_constant(*value))
+        }
+        (AnalyzedNumber::Float(value), true) => {
+            Operand::constant(Type::Float, float_constant(-value))
+        }
+    }
+}
+
+impl GenerateExpression<FunctionBuilder<'_, '_>, Operand> for AnalyzedName {
+    fn generate(&self, function: &mut FunctionBuilder) -> Result<Operand, CodegenError> {
+        match self {
+            AnalyzedName::Name(identifier, binding, span) => {
+                function.variable(identifier, *binding, *span)
+            }
+            AnalyzedName::Indexed(identifier, binding, expression, span) => {
+                let index = expression.generate(function)?;
+                let array = function.variable(identifier, *binding, *span)?;
+                let element = function.broadcast(&array, &index.value);
+                Ok(Operand {
+                    stable: false,
+                    ..element
+                })
+            }
+        }
+    }
+}
+
+impl GenerateExpression<FunctionBuilder<'_, '_>, Operand> for AnalyzedProcedureCall {
+    fn generate(&self, function: &mut FunctionBuilder) -> Result<Operand, CodegenError> {
+        let mut args = Vec::new();
+        for arg in self.arg_list.iter() {
+            let mark = function.mark();
+            let value = arg.generate(function)?;
+            // Earlier arguments are evaluated before any calls in this one
+            args = args
+                .into_iter()
+                .map(|arg| function.sequence(arg, mark))
+                .collect();
+            args.push(value);
+        }
+        function.call(&self.identifier, self.binding, args, self.span)
+    }
+}
@@ -0,0 +1,101 @@
+use crate::codegen::traits::{Generate, GenerateExpression};
+use crate::codegen::CodegenError;
+use crate::semantics::statement::{
+    AnalyzedAssignment, AnalyzedBlock, AnalyzedIf, AnalyzedLoop, AnalyzedReturn, AnalyzedStatement,
+};
+
+use super::{unparenthesized, FunctionBuilder};
+
+impl Generate<FunctionBuilder<'_, '_>> for AnalyzedBlock {
+    fn generate(&self, function: &mut FunctionBuilder) -> Result<(), CodegenError> {
+        for statement in self.0.iter() {
+            statement.generate(function)?;
+        }
+        Ok(())
+    }
+}
+
+impl Generate<FunctionBuilder<'_, '_>> for AnalyzedStatement {
+    fn generate(&self, function: &mut FunctionBuilder) -> Result<(), CodegenError> {
+        match self {
+            AnalyzedStatement::Assignment(statement) => statement.generate(function),
+            AnalyzedStatement::If(statement) => statement.generate(function),
+            AnalyzedStatement::Loop(statement) => statement.generate(function),
+            AnalyzedStatement::Return(statement) => statement.generate(function),
+        }
+    }
+}
+
+impl Generate<FunctionBuilder<'_, '_>> for AnalyzedAssignment {
+    fn generate(&self, function: &mut FunctionBuilder) -> Result<(), CodegenError> {
+        let destination = &self.destination;
+        let index = destination
+            .expression
+            .as_ref()
+            .map(|expression| expression.generate(function))
+            .transpose()?;
+        let mark = function.mark();
+        let value = self.expression.generate(function)?;
+        let index = index.map(|index| function.sequence(index, mark));
+
+        let variable = function.variable(
+            &destination.identifier,
+            destination.binding,
+            destination.span,
+        )?;
+        match index {
+            Some(index) => {
+                let element = function.broadcast(&variable, &index.value);
+                function.store(&value, &element.value);
+            }
+            None => function.store(&value, &variable.value),
+        }
+        Ok(())
+    }
+}
+
+impl Generate<FunctionBuilder<'_, '_>> for AnalyzedIf {
+    fn generate(&self, function: &mut FunctionBuilder) -> Result<(), CodegenError> {
+        let condition = self.conditional_expr.generate(function)?;
+        function.open(format!("if ({}) {{", unparenthesized(&condition.value)));
+        self.then_block.generate(function)?;
+        if let Some(else_block) = &self.else_block {
+            function.close(" else {");
+            function.indent += 1;
+            else_block.generate(function)?;
+        }
+        function.close("");
+        Ok(())
+    }
+}
+
+impl Generate<FunctionBuilder<'_, '_>> for AnalyzedLoop {
+    fn generate(&self, function: &mut FunctionBuilder) -> Result<(), CodegenError> {
+        self.assignment.generate(function)?;
+
+        // The condition is generated inside the loop, in case it needs statements of its own
+        function.indent += 1;
+        let mark = function.mark();
+        let condition = self.condition.generate(function)?;
+        function.indent -= 1;
+        if function.mark() == mark {
+            function.open(format!("while ({}) {{", unparenthesized(&condition.value)));
+        } else {
+            let statements = function.body.split_off(mark);
+            function.open("for (;;) {");
+            function.body.extend(statements);
+            function.line(format!("if (!{}) break;", condition.value));
+        }
+        self.loop_body.generate(function)?;
+        function.close("");
+        Ok(())
+    }
+}
+
+impl Generate<FunctionBuilder<'_, '_>> for AnalyzedReturn {
+    fn generate(&self, function: &mut FunctionBuilder) -> Result<(), CodegenError> {
+        let value = self.expression.generate(function)?;
+        function.line(format!("return {};", unparenthesized(&value.value)));
+        Ok(())
+    }
+}
@@ -0,0 +1,823 @@
+use std::collections::HashMap;
+
+pub mod expression;
+pub mod statement;
+
+use crate::interpreter::value::{ArithOperator, Comparison};
+use crate::semantics::context::{Binding, ScopeContext};
+use crate::semantics::procedure::AnalyzedProcedure;
+use crate::semantics::value::{ProcedureSignature, Type};
+use crate::semantics::AnalyzedProgram;
+use crate::span::Span;
+
+use super::traits::Generate;
+use super::{sorted, CodegenError, ProcedureScopes};
+
+/// Declarations of the builtin procedures, which are defined by the `crust_runtime` library.
+/// Builtins are named `crust_<identifier>`, and bools are passed zero extended, as in C.
+const PRELUDE: &str = r#"@.str.empty = private unnamed_addr constant [1 x i8] zeroinitializer
+
+declare zeroext i1 @crust_getbool()
+declare i64 @crust_getinteger()
+declare double @crust_getfloat()
+declare ptr @crust_getstring()
+declare zeroext i1 @crust_putbool(i1 zeroext)
+declare zeroext i1 @crust_putinteger(i64)
+declare zeroext i1 @crust_putfloat(double)
+declare zeroext i1 @crust_putstring(ptr)
+declare double @crust_sqrt(i64)
+
+declare i32 @strcmp(ptr, ptr)
+declare void @llvm.memcpy.p0.p0.i64(ptr, ptr, i64, i1)
+declare void @llvm.memset.p0.i64(ptr, i8, i64, i1)
+"#;
+
+/// Lowers an analyzed program to a textual LLVM IR module.
+/// The module uses opaque pointers, and links against the `crust_runtime` static library.
+///
+/// A procedure with nested procedures also allocates `%frame`, an array of pointers to its locals,
+/// and passes it to the nested procedures it calls as `%link`.
+/// Nested procedures keep their `%link` in the first element of their own `%frame`,
+/// so the frames further out can be reached through it.
+pub fn generate(program: &AnalyzedProgram) -> Result<String, CodegenError> {
+    let mut module = Module::new(program);
+
+    let mut main = FunctionBuilder::new(&mut module, "ret i32 0");
+    // String arrays are the only globals whose default can't be written as a constant
+    for (identifier, value_type) in sorted(&program.declarations.variables) {
+        if let Type::Array(box Type::String, _) = value_type {
+            main.initialize(&global_symbol(identifier), value_type);
+        }
+    }
+    program.block.generate(&mut main)?;
+    let main = main.finish("define i32 @main()");
+    module.functions.push(main);
+
+    for procedure in program.procedures.iter() {
+        let symbol = module.procedures.top_level_symbol(procedure);
+        module.generate_procedure(procedure, symbol)?;
+    }
+
+    Ok(module.finish())
+}
+
+/// A constant or register holding a value of `value_type`.
+/// Strings and arrays are pointers, and array storage is never modified through an operand.
+#[derive(Debug, Clone)]
+pub struct Operand {
+    pub value_type: Type,
+    pub value: String,
+}
+
+impl Operand {
+    fn new(value_type: Type, value: impl Into<String>) -> Self {
+        Operand {
+            value_type,
+            value: value.into(),
+        }
+    }
+
+    /// The operand with its type, as written for arguments and stores
+    fn typed(&self) -> String {
+        format!("{} {}", value_type_name(&self.value_type), self.value)
+    }
+}
+
+/// The LLVM type values of `value_type` are passed around as
+fn value_type_name(value_type: &Type) -> String {
+    match value_type {
+        Type::Array(..) => String::from("ptr"),
+        value_type => storage_type_name(value_type),
+    }
+}
+
+/// The LLVM type variables of `value_type` are stored as
+fn storage_type_name(value_type: &Type) -> String {
+    match value_type {
+        Type::Bool => String::from("i1"),
+        Type::Int => String::from("i64"),
+        Type::Float => String::from("double"),
+        Type::String => String::from("ptr"),
+        Type::Array(element_type, bound) => {
+            format!("[{} x {}]", bound, storage_type_name(element_type))
+        }
+        Type::Void => String::from("void"),
+    }
+}
+
+/// Size in bytes of a variable of `value_type`
+fn size_of(value_type: &Type) -> usize {
+    match value_type {
+        Type::Bool => 1,
+        Type::Int | Type::Float | Type::String => 8,
+        Type::Array(element_type, bound) => bound * size_of(element_type),
+        Type::Void => 0,
+    }
+}
+
+/// The constant a scalar variable of `value_type` holds before it is first assigned
+fn default_constant(value_type: &Type) -> &'static str {
+    match value_type {
+        Type::Bool => "false",
+        Type::Int => "0",
+        Type::Float => "0.0",
+        Type::String => "@.str.empty",
+        Type::Array(..) => "zeroinitializer",
+        Type::Void => "",
+    }
+}
+
+/// Floats are written by their bits, so they round trip exactly
+fn float_constant(value: f64) -> String {
+    format!("0x{:016X}", value.to_bits())
+}
+
+fn global_symbol(identifier: &str) -> String {
+    format!("@var.{}", identifier)
+}
+
+fn local_symbol(identifier: &str) -> String {
+    format!("%var.{}", identifier)
+}
+
+/// Whether a procedure allocates a `%frame`, for its nested procedures to reach its locals through
+fn has_frame(procedure: &AnalyzedProcedure) -> bool {
+    procedure.procedures.iter().any(|nested| !nested.is_global)
+}
+
+/// The position of a local in the `%frame` of a procedure,
+/// after the `%link` that every procedure but the global ones is passed
+fn frame_index(procedure: &AnalyzedProcedure, identifier: &str) -> Option<usize> {
+    sorted(&procedure.declarations.variables)
+        .iter()
+        .position(|(local, _)| *local == identifier)
+        .map(|position| position + !procedure.is_global as usize)
+}
+
+struct Module<'a> {
+    program: &'a AnalyzedProgram,
+    procedures: ProcedureScopes<'a>,
+    builtins: HashMap<String, ProcedureSignature>,
+    /// Contents of each string literal, where literal `n` is named `@.str.<n>`
+    strings: Vec<String>,
+    functions: Vec<String>,
+}
+
+impl<'a> Module<'a> {
+    fn new(program: &'a AnalyzedProgram) -> Self {
+        Module {
+            program,
+            procedures: ProcedureScopes::new(program, "proc.", "."),
+            builtins: ScopeContext::new_global_ctx().procedures,
+            strings: Vec::new(),
+            functions: Vec::new(),
+        }
+    }
+
+    fn string_constant(&mut self, value: &str) -> String {
+        let position = match self.strings.iter().position(|string| string == value) {
+            Some(position) => position,
+            None => {
+                self.strings.push(String::from(value));
+                self.strings.len() - 1
+            }
+        };
+        format!("@.str.{}", position)
+    }
+
+    fn generate_procedure(
+        &mut self,
+        procedure: &'a AnalyzedProcedure,
+        symbol: String,
+    ) -> Result<(), CodegenError> {
+        self.procedures.enter(procedure, &symbol);
+        let (has_link, has_frame) = (!procedure.is_global, has_frame(procedure));
+
+        let return_type = &procedure.declarations.return_type;
+        let epilogue = format!(
+            "ret {} {}",
+            value_type_name(return_type),
+            default_constant(return_type)
+        );
+        let mut function = FunctionBuilder::new(self, &epilogue);
+
+        let mut params = Vec::new();
+        if has_link {
+            params.push(String::from("ptr %link"));
+        }
+        for (identifier, value_type) in sorted(&procedure.declarations.variables) {
+            let address = local_symbol(identifier);
+            function
+                .variables
+                .insert(identifier.clone(), value_type.clone());
+            function.allocas.push(format!(
+                "  {} = alloca {}",
+                address,
+                storage_type_name(value_type)
+            ));
+        }
+        if has_frame {
+            let mut slots = Vec::new();
+            if has_link {
+                slots.push(String::from("%link"));
+            }
+            slots.extend(
+                sorted(&procedure.declarations.variables)
+                    .into_iter()
+                    .map(|(identifier, _)| local_symbol(identifier)),
+            );
+            function
+                .allocas
+                .push(format!("  %frame = alloca [{} x ptr]", slots.len()));
+            for (index, slot) in slots.iter().enumerate() {
+                let address = function.frame_slot("%frame", index);
+                function.emit(format!("store ptr {}, ptr {}", slot, address));
+            }
+        }
+        for arg in procedure.arg_list.iter() {
+            let value = Operand::new(arg.1.clone(), format!("%arg.{}", arg.0));
+            params.push(value.typed());
+            // Arrays are passed by reference, but copied on entry since they have value semantics
+            function.store(&value, &local_symbol(&arg.0));
+        }
+        for (identifier, value_type) in sorted(&procedure.declarations.variables) {
+            if !procedure.arg_list.iter().any(|arg| &arg.0 == identifier) {
+                function.initialize(&local_symbol(identifier), value_type);
+            }
+        }
+
+        procedure.block.generate(&mut function)?;
+        let function = function.finish(&format!(
+            "define internal {} @{}({})",
+            value_type_name(return_type),
+            symbol,
+            params.join(", ")
+        ));
+        self.functions.push(function);
+
+        for nested in procedure.procedures.iter() {
+            let nested_symbol = self.procedures.nested_symbol(&symbol, nested);
+            self.generate_procedure(nested, nested_symbol)?;
+        }
+        self.procedures.exit();
+        Ok(())
+    }
+
+    fn finish(self) -> String {
+        let mut output = format!(
+            "; ModuleID = '{0}'\nsource_filename = \"{0}\"\n\n",
+            self.program.name
+        );
+
+        for (identifier, value_type) in sorted(&self.program.declarations.variables) {
+            output.push_str(&format!(
+                "{} = internal global {} {}\n",
+                global_symbol(identifier),
+                storage_type_name(value_type),
+                default_constant(value_type)
+            ));
+        }
+        for (position, value) in self.strings.iter().enumerate() {
+            output.push_str(&format!(
+                "@.str.{} = private unnamed_addr constant [{} x i8] c\"{}\\00\"\n",
+                position,
+                value.len() + 1,
+                escape_string(value)
+            ));
+        }
+        output.push('\n');
+        output.push_str(PRELUDE);
+
+        for function in self.functions {
+            output.push('\n');
+            output.push_str(&function);
+        }
+        output
+    }
+}
+
+/// Escapes a string for an LLVM `c"..."` constant
+fn escape_string(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'"' | b'\\' => format!("\\{:02X}", byte),
+            0x20..=0x7e => String::from(byte as char),
+            _ => format!("\\{:02X}", byte),
+        })
+        .collect()
+}
+
+/// Builds the body of a single function, one instruction at a time.
+pub struct FunctionBuilder<'m, 'a> {
+    module: &'m mut Module<'a>,
+    /// Types of the function's locals, each stored in an alloca named by `local_symbol`
+    variables: HashMap<String, Type>,
+    /// Returns the default value, for when control reaches the end of the function
+    epilogue: String,
+    /// Allocas are all hoisted into the entry block
+    allocas: Vec<String>,
+    body: Vec<String>,
+    next_register: usize,
+    next_label: usize,
+    /// Whether the current block has been terminated, by a branch or a return
+    terminated: bool,
+}
+
+impl<'m, 'a> FunctionBuilder<'m, 'a> {
+    fn new(module: &'m mut Module<'a>, epilogue: &str) -> Self {
+        FunctionBuilder {
+            module,
+            variables: HashMap::new(),
+            epilogue: String::from(epilogue),
+            allocas: Vec::new(),
+            body: Vec::new(),
+            next_register: 0,
+            next_label: 0,
+            terminated: false,
+        }
+    }
+
+    fn finish(mut self, header: &str) -> String {
+        if !self.terminated {
+            let epilogue = self.epilogue.clone();
+            self.terminate(epilogue);
+        }
+
+        let mut output = format!("{} {{\nentry:\n", header);
+        for line in self.allocas.iter().chain(self.body.iter()) {
+            output.push_str(line);
+            output.push('\n');
+        }
+        output.push_str("}\n");
+        output
+    }
+
+    fn register(&mut self) -> String {
+        self.next_register += 1;
+        format!("%r{}", self.next_register)
+    }
+
+    fn label(&mut self, name: &str) -> String {
+        self.next_label += 1;
+        format!("{}.{}", name, self.next_label)
+    }
+
+    fn emit(&mut self, instruction: impl AsRef<str>) {
+        // Code after a return is unreachable, but still needs a block to live in
+        if self.terminated {
+            let label = self.label("dead");
+            self.start_block(&label);
+        }
+        self.body.push(format!("  {}", instruction.as_ref()));
+    }
+
+    /// Emits an instruction that produces a value, returning the register holding it
+    fn assign(&mut self, instruction: impl AsRef<str>) -> String {
+        let register = self.register();
+        self.emit(format!("{} = {}", register, instruction.as_ref()));
+        register
+    }
+
+    fn terminate(&mut self, instruction: impl AsRef<str>) {
+        self.emit(instruction);
+        self.terminated = true;
+    }
+
+    /// Branches to `label`, unless the current block already ended with a return
+    fn branch(&mut self, label: &str) {
+        if !self.terminated {
+            self.terminate(format!("br label %{}", label));
+        }
+    }
+
+    /// Starts a new block, falling through to it from the current block
+    fn start_block(&mut self, label: &str) {
+        self.branch(label);
+        self.body.push(format!("{}:", label));
+        self.terminated = false;
+    }
+
+    fn alloca(&mut self, value_type: &Type) -> String {
+        let register = self.register();
+        self.allocas.push(format!(
+            "  {} = alloca {}",
+            register,
+            storage_type_name(value_type)
+        ));
+        register
+    }
+
+    fn string_constant(&mut self, value: &str) -> String {
+        self.module.string_constant(value)
+    }
+
+    /// Returns a pointer to an element of a `%frame`
+    fn frame_slot(&mut self, frame: &str, index: usize) -> String {
+        self.assign(format!(
+            "getelementptr inbounds ptr, ptr {}, i64 {}",
+            frame, index
+        ))
+    }
+
+    /// Returns a pointer to the `%frame` of the procedure `depth` levels out,
+    /// following the links saved in each frame in between
+    fn frame(&mut self, depth: usize) -> String {
+        if depth == 0 {
+            return String::from("%frame");
+        }
+        let mut frame = String::from("%link");
+        for _ in 1..depth {
+            frame = self.assign(format!("load ptr, ptr {}", frame));
+        }
+        frame
+    }
+
+    /// Looks up a variable where the analyzer found it, returning a pointer to it
+    fn variable(
+        &mut self,
+        identifier: &str,
+        binding: Binding,
+        span: Span,
+    ) -> Result<Operand, CodegenError> {
+        if let Binding::Local(depth @ 1..) = binding {
+            let undefined = || CodegenError::UndefinedRef(String::from(identifier), span);
+            let enclosing = self
+                .module
+                .procedures
+                .enclosing(depth)
+                .ok_or_else(undefined)?
+                .procedure;
+            let value_type = enclosing
+                .declarations
+                .variables
+                .get(identifier)
+                .ok_or_else(undefined)?
+                .clone();
+            let index = frame_index(enclosing, identifier).ok_or_else(undefined)?;
+            let frame = self.frame(depth);
+            let slot = self.frame_slot(&frame, index);
+            let address = self.assign(format!("load ptr, ptr {}", slot));
+            return Ok(Operand::new(value_type, address));
+        }
+        if let Some(value_type) = self.variables.get(identifier) {
+            return Ok(Operand::new(value_type.clone(), local_symbol(identifier)));
+        }
+        self.module
+            .program
+            .declarations
+            .variables
+            .get(identifier)
+            .map(|value_type| Operand::new(value_type.clone(), global_symbol(identifier)))
+            .ok_or_else(|| CodegenError::UndefinedRef(String::from(identifier), span))
+    }
+
+    /// Returns a pointer to the element of `array` at `index`, where `array` is a pointer
+    fn element(&mut self, array: &Operand, index: &str) -> Operand {
+        let Type::Array(element_type, _) = &array.value_type else {
+            unreachable!("only arrays are indexed")
+        };
+        let register = self.assign(format!(
+            "getelementptr inbounds {}, ptr {}, i64 0, i64 {}",
+            storage_type_name(&array.value_type),
+            array.value,
+            index
+        ));
+        Operand::new(element_type.as_ref().clone(), register)
+    }
+
+    /// Loads the value stored at `address`. Arrays are left in place.
+    fn load(&mut self, address: Operand) -> Operand {
+        if let Type::Array(..) = address.value_type {
+            return address;
+        }
+        let register = self.assign(format!(
+            "load {}, ptr {}",
+            storage_type_name(&address.value_type),
+            address.value
+        ));
+        Operand::new(address.value_type, register)
+    }
+
+    /// Stores `value` at `address`, copying the contents of arrays.
+    fn store(&mut self, value: &Operand, address: &str) {
+        match value.value_type {
+            Type::Array(..) => self.emit(format!(
+                "call void @llvm.memcpy.p0.p0.i64(ptr {}, ptr {}, i64 {}, i1 false)",
+                address,
+                value.value,
+                size_of(&value.value_type)
+            )),
+            _ => self.emit(format!("store {}, ptr {}", value.typed(), address)),
+        }
+    }
+
+    /// Stores the default value for `value_type` at `address`
+    fn initialize(&mut self, address: &str, value_type: &Type) {
+        match value_type {
+            Type::Array(box Type::String, bound) => {
+                let array = Operand::new(value_type.clone(), address);
+                self.for_each_index(*bound, |function, index| {
+                    let element = function.element(&array, index);
+                    function.emit(format!("store ptr @.str.empty, ptr {}", element.value));
+                    Ok(())
+                })
+                // Storing constants can't fail
+                .unwrap()
+            }
+            Type::Array(..) => self.emit(format!(
+                "call void @llvm.memset.p0.i64(ptr {}, i8 0, i64 {}, i1 false)",
+                address,
+                size_of(value_type)
+            )),
+            value_type => {
+                let value = Operand::new(value_type.clone(), default_constant(value_type));
+                self.store(&value, address);
+            }
+        }
+    }
+
+    /// Emits blocks counting an alloca'd `i64` from 0 up to `bound`, running `body` in the loop block.
+    /// Code after the loop goes in the `array.end` block.
+    fn for_each_index(
+        &mut self,
+        bound: usize,
+        mut body: impl FnMut(&mut Self, &str) -> Result<(), CodegenError>,
+    ) -> Result<(), CodegenError> {
+        let counter = self.alloca(&Type::Int);
+        let condition = self.label("array.cond");
+        let loop_body = self.label("array.body");
+        let end = self.label("array.end");
+
+        self.emit(format!("store i64 0, ptr {}", counter));
+        self.start_block(&condition);
+        let index = self.assign(format!("load i64, ptr {}", counter));
+        let in_bounds = self.assign(format!("icmp slt i64 {}, {}", index, bound));
+        self.terminate(format!(
+            "br i1 {}, label %{}, label %{}",
+            in_bounds, loop_body, end
+        ));
+
+        self.start_block(&loop_body);
+        body(self, &index)?;
+        let next = self.assign(format!("add i64 {}, 1", index));
+        self.emit(format!("store i64 {}, ptr {}", next, counter));
+        self.branch(&condition);
+
+        self.start_block(&end);
+        Ok(())
+    }
+
+    /// Allocates an array of `array_type` and stores the value `element` gives for each index in it
+    fn map_array(
+        &mut self,
+        array_type: &Type,
+        mut element: impl FnMut(&mut Self, &str) -> Result<Operand, CodegenError>,
+    ) -> Result<Operand, CodegenError> {
+        let Type::Array(_, bound) = array_type else {
+            unreachable!("only arrays are mapped")
+        };
+        let array = Operand::new(array_type.clone(), self.alloca(array_type));
+        self.for_each_index(*bound, |function, index| {
+            let value = element(function, index)?;
+            let address = function.element(&array, index);
+            function.store(&value, &address.value);
+            Ok(())
+        })?;
+        Ok(array)
+    }
+
+    /// Loads an element of an array operand, or gives a scalar operand as is
+    fn broadcast(&mut self, operand: &Operand, index: &str) -> Operand {
+        match operand.value_type {
+            Type::Array(..) => {
+                let address = self.element(operand, index);
+                self.load(address)
+            }
+            _ => operand.clone(),
+        }
+    }
+
+    fn cast(
+        &mut self,
+        operand: Operand,
+        value_type: &Type,
+        span: Span,
+    ) -> Result<Operand, CodegenError> {
+        let instruction = match (&operand.value_type, value_type) {
+            (from, to) if from == to => return Ok(operand),
+            (Type::Int, Type::Float) => "sitofp",
+            (Type::Float, Type::Int) => "fptosi",
+            (Type::Bool, Type::Int) => "zext",
+            (Type::Int, Type::Bool) => {
+                let register = self.assign(format!("icmp ne i64 {}, 0", operand.value));
+                return Ok(Operand::new(Type::Bool, register));
+            }
+            (from, to) => {
+                return Err(CodegenError::InvalidCast(
+                    format!("{:?}", from),
+                    format!("{:?}", to),
+                    span,
+                ))
+            }
+        };
+        let register = self.assign(format!(
+            "{} {} to {}",
+            instruction,
+            operand.typed(),
+            value_type_name(value_type)
+        ));
+        Ok(Operand::new(value_type.clone(), register))
+    }
+
+    /// Emits a binary instruction whose result has the type of its operands
+    fn binary(&mut self, opcode: &str, lhs: &Operand, rhs: &Operand) -> Operand {
+        let register = self.assign(format!("{} {}, {}", opcode, lhs.typed(), rhs.value));
+        Operand::new(lhs.value_type.clone(), register)
+    }
+
+    /// Emits the instruction for an arithmetic operator, which wraps for integers,
+    /// or a loop over the elements if either operand is an array
+    fn arith(
+        &mut self,
+        operator: ArithOperator,
+        lhs: Operand,
+        rhs: Operand,
+    ) -> Result<Operand, CodegenError> {
+        let array_type = match (&lhs.value_type, &rhs.value_type) {
+            (array_type @ Type::Array(..), _) | (_, array_type @ Type::Array(..)) => {
+                array_type.clone()
+            }
+            _ => {
+                let opcode = match (operator, &lhs.value_type) {
+                    (ArithOperator::Add, Type::Float) => "fadd",
+                    (ArithOperator::Subtract, Type::Float) => "fsub",
+                    (ArithOperator::Multiply, Type::Float) => "fmul",
+                    (ArithOperator::Divide, Type::Float) => "fdiv",
+                    (ArithOperator::Add, _) => "add",
+                    (ArithOperator::Subtract, _) => "sub",
+                    (ArithOperator::Multiply, _) => "mul",
+                    (ArithOperator::Divide, _) => "sdiv",
+                };
+                return Ok(self.binary(opcode, &lhs, &rhs));
+            }
+        };
+        self.map_array(&array_type, |function, index| {
+            let lhs = function.broadcast(&lhs, index);
+            let rhs = function.broadcast(&rhs, index);
+            function.arith(operator, lhs, rhs)
+        })
+    }
+
+    fn negate(&mut self, operand: Operand) -> Result<Operand, CodegenError> {
+        match &operand.value_type {
+            Type::Float => {
+                let register = self.assign(format!("fneg {}", operand.typed()));
+                Ok(Operand::new(Type::Float, register))
+            }
+            Type::Array(..) => self.map_array(&operand.value_type.clone(), |function, index| {
+                let element = function.broadcast(&operand, index);
+                function.negate(element)
+            }),
+            _ => {
+                let zero = Operand::new(operand.value_type.clone(), "0");
+                Ok(self.binary("sub", &zero, &operand))
+            }
+        }
+    }
+
+    fn compare(&mut self, comparison: Comparison, lhs: Operand, rhs: Operand) -> Operand {
+        let (float, integer) = match comparison {
+            Comparison::LessThan => ("olt", "slt"),
+            Comparison::LessThanEq => ("ole", "sle"),
+            Comparison::GreaterThan => ("ogt", "sgt"),
+            Comparison::GreaterThanEq => ("oge", "sge"),
+            Comparison::Equals => ("oeq", "eq"),
+            // The ordered comparisons are false for NaN, and unordered `une` is true
+            Comparison::NotEquals => ("une", "ne"),
+        };
+        let register = match lhs.value_type {
+            Type::Float => self.assign(format!("fcmp {} {}, {}", float, lhs.typed(), rhs.value)),
+            Type::String => {
+                let ordering = self.assign(format!(
+                    "call i32 @strcmp({}, {})",
+                    lhs.typed(),
+                    rhs.typed()
+                ));
+                self.assign(format!("icmp {} i32 {}, 0", integer, ordering))
+            }
+            _ => self.assign(format!("icmp {} {}, {}", integer, lhs.typed(), rhs.value)),
+        };
+        Operand::new(Type::Bool, register)
+    }
+
+    /// Emits a `call` to the procedure the analyzer bound the call to,
+    /// or to the runtime's `crust_<name>` if no procedure of the program is found
+    fn call(
+        &mut self,
+        identifier: &str,
+        binding: Binding,
+        args: Vec<Operand>,
+        span: Span,
+    ) -> Result<Operand, CodegenError> {
+        let (symbol, return_type) = match self.module.procedures.resolve(identifier, binding) {
+            Some(procedure) => (
+                procedure.symbol.clone(),
+                procedure.procedure.declarations.return_type.clone(),
+            ),
+            None => match self.module.builtins.get(identifier) {
+                Some(signature) => (format!("crust_{}", identifier), signature.1.clone()),
+                None => return Err(CodegenError::UndefinedRef(String::from(identifier), span)),
+            },
+        };
+
+        let mut args: Vec<String> = args.iter().map(Operand::typed).collect();
+        // Builtins are global, so a procedure found in a local scope is nested,
+        // and is passed the frame of the procedure it is declared in
+        if let Binding::Local(depth) = binding {
+            args.insert(0, format!("ptr {}", self.frame(depth)));
+        }
+        let register = self.assign(format!(
+            "call {} @{}({})",
+            value_type_name(&return_type),
+            symbol,
+            args.join(", ")
+        ));
+        Ok(Operand::new(return_type, register))
+    }
+}
+
+#[cfg(test)]
+use super::{generate_test_program, run_tool};
+#[cfg(test)]
+use crate::session::{NESTED_RUN, TEST_RUNS};
+#[cfg(test)]
+use rstest::rstest;
+#[cfg(test)]
+use std::path::PathBuf;
+#[cfg(test)]
+use std::process::Command;
+
+/// Builds a command for an LLVM tool, or `None` if it isn't installed.
+/// LLVM before 15 needs a flag to read opaque pointers.
+#[cfg(test)]
+fn llvm_tool(name: &str) -> Option<Command> {
+    let version = Command::new(name).arg("--version").output().ok()?;
+    let version = String::from_utf8_lossy(&version.stdout);
+    let major: u32 = version
+        .split("LLVM version ")
+        .nth(1)?
+        .split('.')
+        .next()?
+        .parse()
+        .ok()?;
+
+    let mut command = Command::new(name);
+    if major < 15 {
+        command.arg("-opaque-pointers");
+    }
+    Some(command)
+}
+
+#[cfg(test)]
+#[rstest]
+#[ignore = "needs llc from LLVM"]
+fn generate_valid_ir(#[files("tests/correct/*.src")] source_file: PathBuf) {
+    let module = generate_test_program(source_file.to_str().unwrap(), generate);
+    let mut llc = llvm_tool("llc").expect("llc not found");
+    llc.args(["-filetype=null", "-"]);
+    run_tool(llc, &module).unwrap();
+}
+
+/// Runs a test program with `lli`, returning what it wrote
+#[cfg(test)]
+fn run_generated_program(path: &str, input: &str) -> String {
+    use std::env::consts::{DLL_PREFIX, DLL_SUFFIX};
+
+    let module = generate_test_program(path, generate);
+    let mut lli = llvm_tool("lli").expect("lli not found");
+    let runtime =
+        super::runtime_library(DLL_PREFIX, DLL_SUFFIX).expect("the runtime library not found");
+
+    // lli reads the module from a file, so that stdin is left for the program
+    let module_path = std::env::temp_dir().join(format!(
+        "crust-{}-{}.ll",
+        std::process::id(),
+        PathBuf::from(path).file_stem().unwrap().to_str().unwrap()
+    ));
+    std::fs::write(&module_path, module).unwrap();
+    lli.arg(format!("-load={}", runtime.display()))
+        .arg(&module_path);
+    let output = run_tool(lli, input).unwrap();
+    std::fs::remove_file(&module_path).unwrap();
+    output
+}
+
+#[cfg(test)]
+#[test]
+#[ignore = "needs lli from LLVM"]
+fn run_generated_programs() {
+    for &(path, input, expected) in TEST_RUNS.iter().chain([&NESTED_RUN]) {
+        assert_eq!(run_generated_program(path, input), expected, "{}", path);
+    }
+}
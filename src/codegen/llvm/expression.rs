@@ -0,0 +1,185 @@
+use crate::codegen::traits::GenerateExpression;
+use crate::codegen::CodegenError;
+use crate::interpreter::value::{ArithOperator, Comparison};
+use crate::semantics::expression::{
+    AnalyzedArithOp, AnalyzedExpression, AnalyzedFactor, AnalyzedName, AnalyzedNumber,
+    AnalyzedRelation, AnalyzedTerm,
+};
+use crate::semantics::procedure::AnalyzedProcedureCall;
+use crate::semantics::value::Type;
+
+use super::{float_constant, FunctionBuilder, Operand};
+
+impl GenerateExpression<FunctionBuilder<'_, '_>, Operand> for AnalyzedExpression {
+    fn generate(&self, function: &mut FunctionBuilder) -> Result<Operand, CodegenError> {
+        // Both operands are always evaluated, since either may call a procedure
+        match self {
+            AnalyzedExpression::BitwiseAnd(expression, arith_op)
+            | AnalyzedExpression::LogicalAnd(expression, arith_op) => {
+                let lhs = expression.generate(function)?;
+                let rhs = arith_op.generate(function)?;
+                Ok(function.binary("and", &lhs, &rhs))
+            }
+            AnalyzedExpression::BitwiseOr(expression, arith_op)
+            | AnalyzedExpression::LogicalOr(expression, arith_op) => {
+                let lhs = expression.generate(function)?;
+                let rhs = arith_op.generate(function)?;
+                Ok(function.binary("or", &lhs, &rhs))
+            }
+            AnalyzedExpression::BitwiseNot(arith_op) => {
+                let value = arith_op.generate(function)?;
+                Ok(function.binary("xor", &value, &Operand::new(Type::Int, "-1")))
+            }
+            AnalyzedExpression::LogicalNot(arith_op) => {
+                let value = arith_op.generate(function)?;
+                Ok(function.binary("xor", &value, &Operand::new(Type::Bool, "true")))
+            }
+            AnalyzedExpression::Cast(expression, value_type) => {
+                let value = expression.generate(function)?;
+                function.cast(value, value_type, self.span())
+            }
+            AnalyzedExpression::ArithOp(arith_op) => arith_op.generate(function),
+        }
+    }
+}
+
+impl GenerateExpression<FunctionBuilder<'_, '_>, Operand> for AnalyzedArithOp {
+    fn generate(&self, function: &mut FunctionBuilder) -> Result<Operand, CodegenError> {
+        let (arith_op, relation, operator) = match self {
+            AnalyzedArithOp::Plus(arith_op, relation)
+            | AnalyzedArithOp::ArrayScalarPlus(arith_op, relation)
+            | AnalyzedArithOp::ScalarArrayPlus(arith_op, relation)
+            | AnalyzedArithOp::ArrayPlus(arith_op, relation) => {
+                (arith_op, relation, ArithOperator::Add)
+            }
+            AnalyzedArithOp::Minus(arith_op, relation)
+            | AnalyzedArithOp::ArrayScalarMinus(arith_op, relation)
+            | AnalyzedArithOp::ScalarArrayMinus(arith_op, relation)
+            | AnalyzedArithOp::ArrayMinus(arith_op, relation) => {
+                (arith_op, relation, ArithOperator::Subtract)
+            }
+            AnalyzedArithOp::Cast(arith_op, value_type) => {
+                let value = arith_op.generate(function)?;
+                return function.cast(value, value_type, self.span());
+            }
+            AnalyzedArithOp::Relation(relation) => return relation.generate(function),
+        };
+        let lhs = arith_op.generate(function)?;
+        let rhs = relation.generate(function)?;
+        function.arith(operator, lhs, rhs)
+    }
+}
+
+impl GenerateExpression<FunctionBuilder<'_, '_>, Operand> for AnalyzedRelation {
+    fn generate(&self, function: &mut FunctionBuilder) -> Result<Operand, CodegenError> {
+        let (relation, term, comparison) = match self {
+            AnalyzedRelation::LessThan(relation, term) => (relation, term, Comparison::LessThan),
+            AnalyzedRelation::LessThanEq(relation, term) => {
+                (relation, term, Comparison::LessThanEq)
+            }
+            AnalyzedRelation::GreaterThan(relation, term) => {
+                (relation, term, Comparison::GreaterThan)
+            }
+            AnalyzedRelation::GreaterThanEq(relation, term) => {
+                (relation, term, Comparison::GreaterThanEq)
+            }
+            AnalyzedRelation::Equals(relation, term) => (relation, term, Comparison::Equals),
+            AnalyzedRelation::NotEquals(relation, term) => (relation, term, Comparison::NotEquals),
+            AnalyzedRelation::Cast(relation, value_type) => {
+                let value = relation.generate(function)?;
+                return function.cast(value, value_type, self.span());
+            }
+            AnalyzedRelation::Term(term) => return term.generate(function),
+        };
+        let lhs = relation.generate(function)?;
+        let rhs = term.generate(function)?;
+        Ok(function.compare(comparison, lhs, rhs))
+    }
+}
+
+impl GenerateExpression<FunctionBuilder<'_, '_>, Operand> for AnalyzedTerm {
+    fn generate(&self, function: &mut FunctionBuilder) -> Result<Operand, CodegenError> {
+        let (term, factor, operator) = match self {
+            AnalyzedTerm::Multiply(term, factor)
+            | AnalyzedTerm::ArrayScalarMultiply(term, factor)
+            | AnalyzedTerm::ScalarArrayMultiply(term, factor)
+            | AnalyzedTerm::ArrayMultiply(term, factor) => (term, factor, ArithOperator::Multiply),
+            AnalyzedTerm::Divide(term, factor)
+            | AnalyzedTerm::ArrayScalarDivide(term, factor)
+            | AnalyzedTerm::ScalarArrayDivide(term, factor)
+            | AnalyzedTerm::ArrayDivide(term, factor) => (term, factor, ArithOperator::Divide),
+            AnalyzedTerm::Cast(term, value_type) => {
+                let value = term.generate(function)?;
+                return function.cast(value, value_type, self.span());
+            }
+            AnalyzedTerm::Factor(factor) => return factor.generate(function),
+        };
+        let lhs = term.generate(function)?;
+        let rhs = factor.generate(function)?;
+        function.arith(operator, lhs, rhs)
+    }
+}
+
+impl GenerateExpression<FunctionBuilder<'_, '_>, Operand> for AnalyzedFactor {
+    fn generate(&self, function: &mut FunctionBuilder) -> Result<Operand, CodegenError> {
+        match self {
+            AnalyzedFactor::NestedExpression(expression) => expression.generate(function),
+            AnalyzedFactor::ProcedureCall(proc_call) => proc_call.generate(function),
+            AnalyzedFactor::Name(name) => name.generate(function),
+            AnalyzedFactor::NegatedName(name) => {
+                let value = name.generate(function)?;
+                function.negate(value)
+            }
+            AnalyzedFactor::Number(number, _) => Ok(number_constant(number, false)),
+            AnalyzedFactor::NegatedNumber(number, _) => Ok(number_constant(number, true)),
+            AnalyzedFactor::String(value, _) => {
+                Ok(Operand::new(Type::String, function.string_constant(value)))
+            }
+            AnalyzedFactor::True(_) => Ok(Operand::new(Type::Bool, "true")),
+            AnalyzedFactor::False(_) => Ok(Operand::new(Type::Bool, "false")),
+            AnalyzedFactor::Cast(factor, value_type) => {
+                let value = factor.generate(function)?;
+                function.cast(value, value_type, self.span())
+            }
+        }
+    }
+}
+
+fn number_constant(number: &AnalyzedNumber, negate: bool) -> Operand {
+    match (number, negate) {
+        (AnalyzedNumber::Integer(value), false) => Operand::new(Type::Int, value.to_string()),
+        (AnalyzedNumber::Integer(value), true) => {
+            Operand::new(Type::Int, value.wrapping_neg().to_string())
+        }
+        (AnalyzedNumber::Float(value), false) => Operand::new(Type::Float, float_constant(*value)),
+        (AnalyzedNumber::Float(value), true) => Operand::new(Type::Float, float_constant(-value)),
+    }
+}
+
+impl GenerateExpression<FunctionBuilder<'_, '_>, Operand> for AnalyzedName {
+    fn generate(&self, function: &mut FunctionBuilder) -> Result<Operand, CodegenError> {
+        match self {
+            AnalyzedName::Name(identifier, binding, span) => {
+                let address = function.variable(identifier, *binding, *span)?;
+                Ok(function.load(address))
+            }
+            AnalyzedName::Indexed(identifier, binding, expression, span) => {
+                let index = expression.generate(function)?;
+                let array = function.variable(identifier, *binding, *span)?;
+                let address = function.element(&array, &index.value);
+                Ok(function.load(address))
+            }
+        }
+    }
+}
+
+impl GenerateExpression<FunctionBuilder<'_, '_>, Operand> for AnalyzedProcedureCall {
+    fn generate(&self, function: &mut FunctionBuilder) -> Result<Operand, CodegenError> {
+        let args = self
+            .arg_list
+            .iter()
+            .map(|arg| arg.generate(function))
+            .collect::<Result<Vec<Operand>, CodegenError>>()?;
+        function.call(&self.identifier, self.binding, args, self.span)
+    }
+}
@@ -0,0 +1,116 @@
+use crate::codegen::traits::{Generate, GenerateExpression};
+use crate::codegen::CodegenError;
+use crate::semantics::statement::{
+    AnalyzedAssignment, AnalyzedBlock, AnalyzedIf, AnalyzedLoop, AnalyzedReturn, AnalyzedStatement,
+};
+
+use super::FunctionBuilder;
+
+impl Generate<FunctionBuilder<'_, '_>> for AnalyzedBlock {
+    fn generate(&self, function: &mut FunctionBuilder) -> Result<(), CodegenError> {
+        for statement in self.0.iter() {
+            statement.generate(function)?;
+        }
+        Ok(())
+    }
+}
+
+impl Generate<FunctionBuilder<'_, '_>> for AnalyzedStatement {
+    fn generate(&self, function: &mut FunctionBuilder) -> Result<(), CodegenError> {
+        match self {
+            AnalyzedStatement::Assignment(statement) => statement.generate(function),
+            AnalyzedStatement::If(statement) => statement.generate(function),
+            AnalyzedStatement::Loop(statement) => statement.generate(function),
+            AnalyzedStatement::Return(statement) => statement.generate(function),
+        }
+    }
+}
+
+impl Generate<FunctionBuilder<'_, '_>> for AnalyzedAssignment {
+    fn generate(&self, function: &mut FunctionBuilder) -> Result<(), CodegenError> {
+        let destination = &self.destination;
+        let index = destination
+            .expression
+            .as_ref()
+            .map(|expression| expression.generate(function))
+            .transpose()?;
+        let value = self.expression.generate(function)?;
+
+        let mut address = function.variable(
+            &destination.identifier,
+            destination.binding,
+            destination.span,
+        )?;
+        if let Some(index) = index {
+            address = function.element(&address, &index.value);
+        }
+        function.store(&value, &address.value);
+        Ok(())
+    }
+}
+
+impl Generate<FunctionBuilder<'_, '_>> for AnalyzedIf {
+    fn generate(&self, function: &mut FunctionBuilder) -> Result<(), CodegenError> {
+        let condition = self.conditional_expr.generate(function)?;
+        let then_label = function.label("if.then");
+        let else_label = function.label("if.else");
+        let end_label = function.label("if.end");
+
+        let false_label = match self.else_block {
+            Some(_) => &else_label,
+            None => &end_label,
+        };
+        function.terminate(format!(
+            "br {}, label %{}, label %{}",
+            condition.typed(),
+            then_label,
+            false_label
+        ));
+
+        function.start_block(&then_label);
+        self.then_block.generate(function)?;
+        function.branch(&end_label);
+
+        if let Some(else_block) = &self.else_block {
+            function.start_block(&else_label);
+            else_block.generate(function)?;
+            function.branch(&end_label);
+        }
+
+        function.start_block(&end_label);
+        Ok(())
+    }
+}
+
+impl Generate<FunctionBuilder<'_, '_>> for AnalyzedLoop {
+    fn generate(&self, function: &mut FunctionBuilder) -> Result<(), CodegenError> {
+        let condition_label = function.label("for.cond");
+        let body_label = function.label("for.body");
+        let end_label = function.label("for.end");
+
+        self.assignment.generate(function)?;
+        function.start_block(&condition_label);
+        let condition = self.condition.generate(function)?;
+        function.terminate(format!(
+            "br {}, label %{}, label %{}",
+            condition.typed(),
+            body_label,
+            end_label
+        ));
+
+        function.start_block(&body_label);
+        self.loop_body.generate(function)?;
+        function.branch(&condition_label);
+
+        function.start_block(&end_label);
+        Ok(())
+    }
+}
+
+impl Generate<FunctionBuilder<'_, '_>> for AnalyzedReturn {
+    fn generate(&self, function: &mut FunctionBuilder) -> Result<(), CodegenError> {
+        let value = self.expression.generate(function)?;
+        function.terminate(format!("ret {}", value.typed()));
+        Ok(())
+    }
+}
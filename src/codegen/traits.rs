@@ -0,0 +1,11 @@
+use super::CodegenError;
+
+/// Generates a statement into the function being built by a backend that walks the analyzed program
+pub trait Generate<Function> {
+    fn generate(&self, function: &mut Function) -> Result<(), CodegenError>;
+}
+
+/// Generates an expression, giving the backend's operand for its value
+pub trait GenerateExpression<Function, Operand> {
+    fn generate(&self, function: &mut Function) -> Result<Operand, CodegenError>;
+}
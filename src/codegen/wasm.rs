@@ -0,0 +1,1040 @@
+//! A backend emitting a WebAssembly text module from the IR, to run programs in a sandbox.
+//!
+//! Bools and strings are `i32`s, integers are `i64`s and floats are `f64`s.
+//! Strings are pointers to NUL-terminated bytes in linear memory, where address 0 is the empty string.
+//! Arrays live in linear memory too, with every element taking 8 bytes,
+//! and are passed around as pointers to their first element.
+//!
+//! The module imports each builtin procedure from the `crust` module, with the same name,
+//! and exports `memory`, and the program body as `main`.
+//! Strings returned by `getstring` are written by the host at the address in the exported global `heap`,
+//! which it then moves past the string, growing `memory` as needed.
+//!
+//! Scalar variables and temporaries are WebAssembly locals.
+//! Local arrays are stored in a frame on a stack in linear memory, which calls push and pop.
+//! Running out of stack traps with `unreachable`.
+//! Procedures with nested procedures keep all of their locals in the frame, and pass its address
+//! to the nested procedures they call as `$link`. A nested procedure with nested procedures of its own
+//! saves `$link` at the start of its frame, so the frames further out can be reached through it.
+//!
+//! Basic blocks are nested WebAssembly blocks inside a loop, and jumping to one sets `$block`
+//! and branches back to the `br_table` at the top of the loop.
+
+use crate::ir::lower::lower;
+use crate::ir::{
+    self, Argument, BinaryOp, Builtin, Callee, Constant, FunctionId, Instruction, Operand, Place,
+    Terminator, UnaryOp, Variable,
+};
+use crate::semantics::value::Type;
+use crate::semantics::AnalyzedProgram;
+use crate::span::Span;
+
+use super::CodegenError;
+
+/// Size in bytes of every array element
+const ELEMENT_SIZE: u32 = 8;
+/// Size in bytes of the stack holding local arrays
+const STACK_SIZE: u32 = 1 << 20;
+const PAGE_SIZE: u32 = 1 << 16;
+/// The first byte of static data. Address 0 is the empty string.
+const DATA_START: u32 = 8;
+
+/// Compares two strings, like C's `strcmp`
+const STRCMP: &str = r#"  (func $strcmp (param $lhs i32) (param $rhs i32) (result i32)
+    (local $l i32)
+    (local $r i32)
+    loop $next
+      local.get $lhs
+      i32.load8_u
+      local.set $l
+      local.get $rhs
+      i32.load8_u
+      local.set $r
+      local.get $l
+      local.get $r
+      i32.eq
+      local.get $l
+      i32.const 0
+      i32.ne
+      i32.and
+      if
+        local.get $lhs
+        i32.const 1
+        i32.add
+        local.set $lhs
+        local.get $rhs
+        i32.const 1
+        i32.add
+        local.set $rhs
+        br $next
+      end
+    end
+    local.get $l
+    local.get $r
+    i32.sub)
+"#;
+
+/// Divides integers, wrapping on overflow like the interpreter, rather than trapping.
+/// Dividing by zero still traps.
+const DIV: &str = r#"  (func $div (param $lhs i64) (param $rhs i64) (result i64)
+    local.get $rhs
+    i64.const -1
+    i64.eq
+    if (result i64)
+      i64.const 0
+      local.get $lhs
+      i64.sub
+    else
+      local.get $lhs
+      local.get $rhs
+      i64.div_s
+    end)
+"#;
+
+/// Generates a WebAssembly text module for a program, by lowering it to the IR first.
+pub fn generate(program: &AnalyzedProgram) -> Result<String, CodegenError> {
+    let module = lower(program)?;
+    let mut generator = ModuleGenerator::new(&module);
+
+    let main = FunctionGenerator::new(&mut generator, &module.body, false)
+        .generate()?
+        .finish("$main (export \"main\")");
+    generator.functions.push(main);
+    for (id, procedure) in module.procedures.iter().enumerate() {
+        let has_nested = module
+            .procedures
+            .iter()
+            .any(|nested| nested.parent == Some(FunctionId(id)));
+        let function = FunctionGenerator::new(&mut generator, procedure, has_nested)
+            .generate()?
+            .finish(&procedure_symbol(procedure));
+        generator.functions.push(function);
+    }
+
+    Ok(generator.finish())
+}
+
+/// The WebAssembly type values of `value_type` are passed around as
+fn value_type_name(value_type: &Type) -> &'static str {
+    match value_type {
+        Type::Int => "i64",
+        Type::Float => "f64",
+        // Void is never stored or passed
+        Type::Bool | Type::String | Type::Array(..) | Type::Void => "i32",
+    }
+}
+
+/// Size in bytes of a variable of `value_type` in linear memory
+fn size_of(value_type: &Type) -> u32 {
+    match value_type {
+        Type::Array(_, bound) => *bound as u32 * ELEMENT_SIZE,
+        _ => ELEMENT_SIZE,
+    }
+}
+
+fn load_instruction(value_type: &Type) -> String {
+    format!("{}.load", value_type_name(value_type))
+}
+
+fn store_instruction(value_type: &Type) -> String {
+    format!("{}.store", value_type_name(value_type))
+}
+
+/// `{:?}` always writes a decimal point or exponent, which `f64.const` accepts as a float literal
+fn float_constant(value: f64) -> String {
+    format!("f64.const {:?}", value)
+}
+
+fn variable_symbol(name: &str) -> String {
+    format!("$var.{}", name)
+}
+
+/// Nested procedures are named by their path, so they can't clash with each other
+fn procedure_symbol(function: &ir::Function) -> String {
+    format!("$proc.{}", function.name)
+}
+
+fn align(address: u32) -> u32 {
+    address.next_multiple_of(ELEMENT_SIZE)
+}
+
+/// Offsets of the locals of a procedure with nested procedures, which are all in its frame,
+/// and the size of the frame. The saved `$link` comes first, if the procedure is nested itself.
+fn shared_frame_offsets(function: &ir::Function) -> (Vec<u32>, u32) {
+    let mut frame_size = match function.parent {
+        Some(_) => ELEMENT_SIZE,
+        None => 0,
+    };
+    let offsets = function
+        .locals
+        .iter()
+        .map(|local| {
+            let offset = frame_size;
+            frame_size += size_of(&local.value_type);
+            offset
+        })
+        .collect();
+    (offsets, frame_size)
+}
+
+/// Instructions pushing the address of an offset into the current frame
+fn frame_offset(offset: u32) -> [String; 3] {
+    [
+        String::from("local.get $frame"),
+        format!("i32.const {}", offset),
+        String::from("i32.add"),
+    ]
+}
+
+/// Where a variable is stored
+#[derive(Debug, Clone)]
+enum Storage {
+    /// A scalar in a WebAssembly local
+    Local(String),
+    /// A scalar in a WebAssembly global
+    Global(String),
+    /// At an offset into the frame of the procedure `depth` levels out, where 0 is the current one
+    Frame(usize, u32),
+    /// A global array at a fixed address
+    Static(u32),
+}
+
+struct ModuleGenerator<'m> {
+    module: &'m ir::Module,
+    /// Where each of the module's globals is stored
+    globals: Vec<Storage>,
+    /// Contents and addresses of each string constant
+    strings: Vec<(String, u32)>,
+    /// The end of static data, which global arrays and then strings are added to
+    data_end: u32,
+    functions: Vec<String>,
+}
+
+impl<'m> ModuleGenerator<'m> {
+    fn new(module: &'m ir::Module) -> Self {
+        let mut data_end = DATA_START;
+        let globals = module
+            .globals
+            .iter()
+            .map(|global| match global.value_type {
+                Type::Array(..) => {
+                    let address = data_end;
+                    data_end += size_of(&global.value_type);
+                    Storage::Static(address)
+                }
+                _ => Storage::Global(variable_symbol(&global.name)),
+            })
+            .collect();
+        ModuleGenerator {
+            module,
+            globals,
+            strings: Vec::new(),
+            data_end,
+            functions: Vec::new(),
+        }
+    }
+
+    fn string_constant(&mut self, value: &str) -> u32 {
+        if value.is_empty() {
+            return 0;
+        }
+        if let Some((_, address)) = self.strings.iter().find(|(string, _)| string == value) {
+            return *address;
+        }
+        let address = self.data_end;
+        self.data_end += value.len() as u32 + 1;
+        self.strings.push((String::from(value), address));
+        address
+    }
+
+    fn finish(self) -> String {
+        let stack_start = align(self.data_end);
+        let stack_end = stack_start + STACK_SIZE;
+        // Leave at least a page for the heap
+        let pages = stack_end / PAGE_SIZE + 1;
+
+        let mut output = String::from("(module\n");
+        for builtin in Builtin::ALL {
+            let params: String = builtin
+                .param_types()
+                .iter()
+                .map(|param| format!(" (param {})", value_type_name(param)))
+                .collect();
+            output.push_str(&format!(
+                "  (import \"crust\" \"{0}\" (func $builtin.{0}{1} (result {2})))\n",
+                builtin,
+                params,
+                value_type_name(&builtin.return_type())
+            ));
+        }
+        output.push('\n');
+
+        output.push_str(&format!("  (memory (export \"memory\") {})\n", pages));
+        output.push_str(&format!(
+            "  (global $sp (mut i32) (i32.const {}))\n",
+            stack_start
+        ));
+        output.push_str(&format!(
+            "  (global $stack.end i32 (i32.const {}))\n",
+            stack_end
+        ));
+        output.push_str(&format!(
+            "  (global $heap (export \"heap\") (mut i32) (i32.const {}))\n",
+            stack_end
+        ));
+        for (global, storage) in self.module.globals.iter().zip(self.globals.iter()) {
+            if let Storage::Global(symbol) = storage {
+                output.push_str(&format!(
+                    "  (global {} (mut {1}) ({1}.const 0))\n",
+                    symbol,
+                    value_type_name(&global.value_type)
+                ));
+            }
+        }
+        for (value, address) in self.strings.iter() {
+            output.push_str(&format!(
+                "  (data (i32.const {}) \"{}\\00\")\n",
+                address,
+                escape_string(value)
+            ));
+        }
+
+        output.push('\n');
+        output.push_str(STRCMP);
+        output.push('\n');
+        output.push_str(DIV);
+        for function in self.functions {
+            output.push('\n');
+            output.push_str(&function);
+        }
+        output.push_str(")\n");
+        output
+    }
+}
+
+/// Escapes a string for a WebAssembly string literal
+fn escape_string(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'"' | b'\\' => format!("\\{:02x}", byte),
+            0x20..=0x7e => String::from(byte as char),
+            _ => format!("\\{:02x}", byte),
+        })
+        .collect()
+}
+
+/// Generates the body of a single function, one instruction at a time.
+struct FunctionGenerator<'g, 'm> {
+    module: &'g mut ModuleGenerator<'m>,
+    function: &'m ir::Function,
+    /// Where each of the function's locals is stored
+    storage: Vec<Storage>,
+    /// Declarations of the parameters
+    params: Vec<String>,
+    /// Declarations of locals, other than the parameters
+    locals: Vec<String>,
+    /// Zeroes locals in the frame, and copies arguments into it
+    prologue: Vec<String>,
+    /// Bytes of the frame used by locals in linear memory
+    frame_size: u32,
+    /// Whether the function pushes a frame, which those with nested procedures always do
+    has_frame: bool,
+    body: Vec<String>,
+    /// Nesting depth of the next instruction
+    indent: usize,
+}
+
+impl<'g, 'm> FunctionGenerator<'g, 'm> {
+    fn new(
+        module: &'g mut ModuleGenerator<'m>,
+        function: &'m ir::Function,
+        has_nested: bool,
+    ) -> Self {
+        let mut generator = FunctionGenerator {
+            module,
+            function,
+            storage: Vec::new(),
+            params: Vec::new(),
+            locals: Vec::new(),
+            prologue: Vec::new(),
+            frame_size: 0,
+            has_frame: has_nested,
+            body: Vec::new(),
+            indent: 2,
+        };
+        if function.parent.is_some() {
+            generator.params.push(String::from("(param $link i32)"));
+        }
+        let shared_offsets = has_nested.then(|| {
+            let (offsets, frame_size) = shared_frame_offsets(function);
+            generator.frame_size = frame_size;
+            // Zero is the default value of every type
+            generator.prologue.extend([
+                String::from("local.get $frame"),
+                String::from("i32.const 0"),
+                format!("i32.const {}", frame_size),
+                String::from("memory.fill"),
+            ]);
+            if function.parent.is_some() {
+                generator
+                    .prologue
+                    .extend(["local.get $frame", "local.get $link", "i32.store"].map(String::from));
+            }
+            offsets
+        });
+
+        for (index, local) in function.locals.iter().enumerate() {
+            let is_param = index < function.param_count;
+            let value_type = &local.value_type;
+            let is_array = matches!(value_type, Type::Array(..));
+            if !is_array && shared_offsets.is_none() {
+                let symbol = variable_symbol(&local.name);
+                let declaration = format!("{} {}", symbol, value_type_name(value_type));
+                match is_param {
+                    true => generator.params.push(format!("(param {})", declaration)),
+                    false => generator.locals.push(format!("(local {})", declaration)),
+                }
+                generator.storage.push(Storage::Local(symbol));
+                continue;
+            }
+
+            let offset = match &shared_offsets {
+                Some(offsets) => offsets[index],
+                None => {
+                    let offset = generator.frame_size;
+                    generator.frame_size += size_of(value_type);
+                    offset
+                }
+            };
+            let param = format!("$arg.{}", local.name);
+            if is_param {
+                generator
+                    .params
+                    .push(format!("(param {} {})", param, value_type_name(value_type)));
+            }
+            let copy = match (is_array, is_param) {
+                // The caller passes the address of its array, which is copied into this frame
+                (true, true) => vec![
+                    format!("local.get {}", param),
+                    format!("i32.const {}", size_of(value_type)),
+                    String::from("memory.copy"),
+                ],
+                (false, true) => vec![
+                    format!("local.get {}", param),
+                    store_instruction(value_type),
+                ],
+                (true, false) if shared_offsets.is_none() => vec![
+                    String::from("i32.const 0"),
+                    format!("i32.const {}", size_of(value_type)),
+                    String::from("memory.fill"),
+                ],
+                // Already zeroed with the rest of the frame
+                _ => Vec::new(),
+            };
+            if !copy.is_empty() {
+                generator.prologue.extend(frame_offset(offset));
+                generator.prologue.extend(copy);
+            }
+            generator.storage.push(Storage::Frame(0, offset));
+        }
+        generator.has_frame |= generator.frame_size > 0;
+        for (index, value_type) in function.temps.iter().enumerate() {
+            generator.locals.push(format!(
+                "(local $t{} {})",
+                index,
+                value_type_name(value_type)
+            ));
+        }
+        generator
+    }
+
+    /// Generates every block, inside a loop that dispatches on `$block` unless there is only one
+    fn generate(mut self) -> Result<Self, CodegenError> {
+        let function = self.function;
+        let dispatch = function.blocks.len() > 1
+            || !matches!(function.blocks[0].terminator, Terminator::Return(_));
+        if dispatch {
+            self.locals.push(String::from("(local $block i32)"));
+            self.open("loop $dispatch");
+            for id in (0..function.blocks.len()).rev() {
+                self.line(format!("block $bb{}", id));
+            }
+            self.line("local.get $block");
+            let labels: Vec<String> = (0..function.blocks.len())
+                .map(|id| format!("$bb{}", id))
+                .collect();
+            self.line(format!("br_table {} $bb0", labels.join(" ")));
+        }
+        for (id, block) in function.blocks.iter().enumerate() {
+            if dispatch {
+                self.line(format!("end ;; bb{}", id));
+            }
+            for (instruction, span) in block.instructions.iter().zip(block.spans.iter()) {
+                self.instruction(instruction, *span)?;
+            }
+            self.terminator(&block.terminator, id + 1);
+        }
+        if dispatch {
+            self.close("end");
+        }
+        Ok(self)
+    }
+
+    /// Wraps the body in a block that returns branch out of,
+    /// between pushing and popping the function's frame.
+    fn finish(self, name: &str) -> String {
+        let return_type = &self.function.return_type;
+        let has_result = *return_type != Type::Void;
+        let mut output = format!("  (func {}", name);
+        for param in self.params.iter() {
+            output.push(' ');
+            output.push_str(param);
+        }
+        if has_result {
+            output.push_str(&format!(" (result {})", value_type_name(return_type)));
+        }
+        output.push('\n');
+
+        let mut lines = self.locals;
+        if has_result {
+            lines.push(format!("(local $result {})", value_type_name(return_type)));
+        }
+        if self.has_frame {
+            lines.push(String::from("(local $frame i32)"));
+            lines.extend(
+                [
+                    "global.get $sp",
+                    "local.tee $frame",
+                    &format!("i32.const {}", align(self.frame_size)),
+                    "i32.add",
+                    "global.set $sp",
+                    "global.get $sp",
+                    "global.get $stack.end",
+                    "i32.gt_u",
+                    "if",
+                    "  unreachable",
+                    "end",
+                ]
+                .map(String::from),
+            );
+        }
+        lines.extend(self.prologue);
+        lines.push(String::from("block $return"));
+        lines.extend(self.body);
+        lines.push(String::from("end"));
+        if self.has_frame {
+            lines.extend(["local.get $frame", "global.set $sp"].map(String::from));
+        }
+        if has_result {
+            lines.push(String::from("local.get $result"));
+        }
+
+        for line in lines {
+            output.push_str("    ");
+            output.push_str(&line);
+            output.push('\n');
+        }
+        // Close the function on its last line, as is usual for WebAssembly text
+        output.pop();
+        output.push_str(")\n");
+        output
+    }
+
+    fn line(&mut self, instruction: impl AsRef<str>) {
+        self.body.push(format!(
+            "{}{}",
+            "  ".repeat(self.indent - 1),
+            instruction.as_ref()
+        ));
+    }
+
+    /// Emits an instruction that starts a nested block, like `if`
+    fn open(&mut self, instruction: impl AsRef<str>) {
+        self.line(instruction);
+        self.indent += 1;
+    }
+
+    /// Ends the innermost block, with `instruction`, like `else` or `end`
+    fn close(&mut self, instruction: &str) {
+        self.indent -= 1;
+        self.line(instruction);
+    }
+
+    /// Where a variable is stored. Locals of enclosing procedures are always in their frames.
+    fn storage(&self, variable: Variable) -> Storage {
+        match variable {
+            Variable::Local(index) => self.storage[index].clone(),
+            Variable::Global(index) => self.module.globals[index].clone(),
+            Variable::Outer(depth, index) => {
+                // The IR has been checked to only use enclosing procedures that exist
+                let enclosing = self.module.module.enclosing(self.function, depth).unwrap();
+                Storage::Frame(depth, shared_frame_offsets(enclosing).0[index])
+            }
+        }
+    }
+
+    fn variable_type(&self, variable: Variable) -> Type {
+        // The IR has been checked to only use variables that exist
+        self.function
+            .variable_type(self.module.module, variable)
+            .unwrap()
+            .clone()
+    }
+
+    /// Pushes the address of the frame `depth` levels out,
+    /// following the links saved at the start of each frame in between
+    fn frame_address(&mut self, depth: usize) {
+        match depth {
+            0 => self.line("local.get $frame"),
+            _ => {
+                self.line("local.get $link");
+                for _ in 1..depth {
+                    self.line("i32.load");
+                }
+            }
+        }
+    }
+
+    /// Pushes the address of a variable in linear memory
+    fn address(&mut self, storage: &Storage) {
+        match storage {
+            Storage::Frame(depth, offset) => {
+                self.frame_address(*depth);
+                self.line(format!("i32.const {}", offset));
+                self.line("i32.add");
+            }
+            Storage::Static(address) => self.line(format!("i32.const {}", address)),
+            Storage::Local(_) | Storage::Global(_) => {
+                unreachable!("WebAssembly locals and globals have no address")
+            }
+        }
+    }
+
+    /// Pushes the value of a variable of `value_type`. Arrays are pushed as their address.
+    fn load(&mut self, storage: &Storage, value_type: &Type) {
+        match storage {
+            Storage::Local(symbol) => self.line(format!("local.get {}", symbol)),
+            Storage::Global(symbol) => self.line(format!("global.get {}", symbol)),
+            Storage::Frame(..) | Storage::Static(_) => {
+                self.address(storage);
+                if !matches!(value_type, Type::Array(..)) {
+                    self.line(load_instruction(value_type));
+                }
+            }
+        }
+    }
+
+    /// Stores a scalar in a variable
+    fn store(&mut self, storage: &Storage, value: &Operand) {
+        match storage {
+            Storage::Local(symbol) => {
+                self.operand(value);
+                self.line(format!("local.set {}", symbol));
+            }
+            Storage::Global(symbol) => {
+                self.operand(value);
+                self.line(format!("global.set {}", symbol));
+            }
+            Storage::Frame(..) | Storage::Static(_) => {
+                self.address(storage);
+                self.operand(value);
+                let value_type = self.function.operand_type(value);
+                self.line(store_instruction(&value_type));
+            }
+        }
+    }
+
+    fn operand(&mut self, operand: &Operand) {
+        let instruction = match operand {
+            Operand::Temp(temp) => format!("local.get $t{}", temp.0),
+            Operand::Const(Constant::Bool(value)) => format!("i32.const {}", *value as i32),
+            Operand::Const(Constant::Int(value)) => format!("i64.const {}", value),
+            Operand::Const(Constant::Float(value)) => float_constant(*value),
+            Operand::Const(Constant::String(value)) => {
+                format!("i32.const {}", self.module.string_constant(value))
+            }
+        };
+        self.line(instruction);
+    }
+
+    /// Pushes the address of an array element, trapping if the index is out of bounds
+    fn element_address(&mut self, array: Variable, index: &Operand) -> Type {
+        let storage = self.storage(array);
+        let Type::Array(element_type, bound) = self.variable_type(array) else {
+            unreachable!("only arrays are indexed")
+        };
+        self.address(&storage);
+        self.operand(index);
+        // Negative indices are also out of bounds, as unsigned integers
+        self.operand(index);
+        self.line(format!("i64.const {}", bound));
+        self.line("i64.ge_u");
+        self.open("if");
+        self.line("unreachable");
+        self.close("end");
+        self.line("i32.wrap_i64");
+        self.line(format!("i32.const {}", ELEMENT_SIZE));
+        self.line("i32.mul");
+        self.line("i32.add");
+        *element_type
+    }
+
+    fn instruction(&mut self, instruction: &Instruction, span: Span) -> Result<(), CodegenError> {
+        match instruction {
+            Instruction::Load { dest, source } => {
+                match source {
+                    Place::Variable(variable) => {
+                        let storage = self.storage(*variable);
+                        self.load(&storage, &self.variable_type(*variable));
+                    }
+                    Place::Element(array, index) => {
+                        let element_type = self.element_address(*array, index);
+                        self.line(load_instruction(&element_type));
+                    }
+                }
+                self.line(format!("local.set $t{}", dest.0));
+            }
+            Instruction::Store { dest, value } => match dest {
+                Place::Variable(variable) => {
+                    let storage = self.storage(*variable);
+                    self.store(&storage, value);
+                }
+                Place::Element(array, index) => {
+                    let element_type = self.element_address(*array, index);
+                    self.operand(value);
+                    self.line(store_instruction(&element_type));
+                }
+            },
+            Instruction::CopyArray { dest, source } => {
+                let dest_storage = self.storage(*dest);
+                let source_storage = self.storage(*source);
+                self.address(&dest_storage);
+                self.address(&source_storage);
+                self.line(format!("i32.const {}", size_of(&self.variable_type(*dest))));
+                self.line("memory.copy");
+            }
+            Instruction::Unary { dest, op, operand } => {
+                self.operand(operand);
+                let instructions: &[&str] = match (op, self.function.operand_type(operand)) {
+                    (UnaryOp::Negate, Type::Float) => &["f64.neg"],
+                    // Multiplying wraps, so negating the smallest integer gives itself
+                    (UnaryOp::Negate, _) => &["i64.const -1", "i64.mul"],
+                    (UnaryOp::Not, Type::Bool) => &["i32.eqz"],
+                    (UnaryOp::Not, _) => &["i64.const -1", "i64.xor"],
+                };
+                for instruction in instructions {
+                    self.line(*instruction);
+                }
+                self.line(format!("local.set $t{}", dest.0));
+            }
+            Instruction::Binary { dest, op, lhs, rhs } => {
+                self.operand(lhs);
+                self.operand(rhs);
+                let value_type = self.function.operand_type(lhs);
+                self.binary(*op, &value_type);
+                self.line(format!("local.set $t{}", dest.0));
+            }
+            Instruction::Cast { dest, operand } => {
+                self.operand(operand);
+                let from = self.function.operand_type(operand);
+                let to = self.function.temp_type(*dest);
+                let instructions: &[&str] = match (&from, to) {
+                    (from, to) if from == to => &[],
+                    (Type::Int, Type::Float) => &["f64.convert_i64_s"],
+                    // Saturates, like the interpreter
+                    (Type::Float, Type::Int) => &["i64.trunc_sat_f64_s"],
+                    (Type::Bool, Type::Int) => &["i64.extend_i32_u"],
+                    (Type::Int, Type::Bool) => &["i64.const 0", "i64.ne"],
+                    (from, to) => {
+                        return Err(CodegenError::InvalidCast(
+                            from.to_string(),
+                            to.to_string(),
+                            span,
+                        ))
+                    }
+                };
+                for instruction in instructions {
+                    self.line(*instruction);
+                }
+                self.line(format!("local.set $t{}", dest.0));
+            }
+            Instruction::Call { dest, callee, args } => {
+                // A nested procedure is passed the frame of the procedure it is declared in
+                if let Callee::Nested(_, depth) = callee {
+                    self.frame_address(*depth);
+                }
+                for arg in args.iter() {
+                    match arg {
+                        Argument::Scalar(operand) => self.operand(operand),
+                        // The callee copies the array
+                        Argument::Array(variable) => {
+                            let storage = self.storage(*variable);
+                            self.address(&storage);
+                        }
+                    }
+                }
+                let symbol = match callee {
+                    Callee::Procedure(id) | Callee::Nested(id, _) => {
+                        procedure_symbol(self.module.module.procedure(*id))
+                    }
+                    Callee::Builtin(builtin) => format!("$builtin.{}", builtin),
+                };
+                self.line(format!("call {}", symbol));
+                self.line(format!("local.set $t{}", dest.0));
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies a binary operator to the top two values, which have type `value_type`
+    fn binary(&mut self, op: BinaryOp, value_type: &Type) {
+        let comparison = match op {
+            BinaryOp::LessThan => ("lt", "lt_s", "lt_u"),
+            BinaryOp::LessThanEq => ("le", "le_s", "le_u"),
+            BinaryOp::GreaterThan => ("gt", "gt_s", "gt_u"),
+            BinaryOp::GreaterThanEq => ("ge", "ge_s", "ge_u"),
+            BinaryOp::Equals => ("eq", "eq", "eq"),
+            // `f64.ne` is the only float comparison that is true for NaN
+            BinaryOp::NotEquals => ("ne", "ne", "ne"),
+            _ => {
+                let instruction = match (op, value_type) {
+                    (BinaryOp::Add, Type::Float) => "f64.add",
+                    (BinaryOp::Subtract, Type::Float) => "f64.sub",
+                    (BinaryOp::Multiply, Type::Float) => "f64.mul",
+                    (BinaryOp::Divide, Type::Float) => "f64.div",
+                    (BinaryOp::Add, _) => "i64.add",
+                    (BinaryOp::Subtract, _) => "i64.sub",
+                    (BinaryOp::Multiply, _) => "i64.mul",
+                    (BinaryOp::Divide, _) => "call $div",
+                    (BinaryOp::And, Type::Bool) => "i32.and",
+                    (BinaryOp::Or, Type::Bool) => "i32.or",
+                    (BinaryOp::And, _) => "i64.and",
+                    (BinaryOp::Or, _) => "i64.or",
+                    _ => unreachable!("comparisons are handled above"),
+                };
+                self.line(instruction);
+                return;
+            }
+        };
+        let (float, signed, unsigned) = comparison;
+        match value_type {
+            Type::Float => self.line(format!("f64.{}", float)),
+            Type::Int => self.line(format!("i64.{}", signed)),
+            Type::String => {
+                self.line("call $strcmp");
+                self.line("i32.const 0");
+                self.line(format!("i32.{}", signed));
+            }
+            _ => self.line(format!("i32.{}", unsigned)),
+        }
+    }
+
+    /// Branches to the start of a block, through the dispatch loop
+    fn jump(&mut self, block: usize) {
+        self.line(format!("i32.const {}", block));
+        self.line("local.set $block");
+        self.line("br $dispatch");
+    }
+
+    /// Ends a block, falling through to the block after it where possible
+    fn terminator(&mut self, terminator: &Terminator, next: usize) {
+        match terminator {
+            Terminator::Jump(target) => {
+                if target.0 != next {
+                    self.jump(target.0);
+                }
+            }
+            Terminator::Branch {
+                condition,
+                then_block,
+                else_block,
+            } => {
+                self.operand(condition);
+                self.open("if");
+                self.jump(then_block.0);
+                self.close("else");
+                self.indent += 1;
+                self.jump(else_block.0);
+                self.close("end");
+            }
+            Terminator::Return(value) => {
+                if let Some(value) = value {
+                    self.operand(value);
+                    self.line("local.set $result");
+                }
+                self.line("br $return");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+use super::generate_test_program;
+#[cfg(test)]
+use crate::session::{NESTED_RUN, TEST_RUNS};
+#[cfg(test)]
+use rstest::rstest;
+#[cfg(test)]
+use std::path::PathBuf;
+#[cfg(test)]
+use wasmi::{Caller, Engine, Extern, Linker, Store, Val};
+
+/// The host side of the builtins, reading and writing in memory like the interpreter
+#[cfg(test)]
+struct Host {
+    input: std::io::Cursor<Vec<u8>>,
+    output: Vec<u8>,
+}
+
+#[cfg(test)]
+fn memory(caller: &Caller<'_, Host>) -> wasmi::Memory {
+    caller
+        .get_export("memory")
+        .and_then(Extern::into_memory)
+        .unwrap()
+}
+
+#[cfg(test)]
+fn input_error(kind: &str) -> wasmi::Error {
+    wasmi::Error::new(format!("Expected a {} as input", kind))
+}
+
+/// Runs a generated module with `input`, returning what it wrote
+#[cfg(test)]
+fn run_test_module(source: &str, input: &str) -> Result<String, wasmi::Error> {
+    let engine = Engine::default();
+    let module = wasmi::Module::new(&engine, &wat::parse_str(source).unwrap()[..])?;
+    let host = Host {
+        input: std::io::Cursor::new(input.as_bytes().to_vec()),
+        output: Vec::new(),
+    };
+    let mut store = Store::new(&engine, host);
+    let mut linker = Linker::<Host>::new(&engine);
+
+    linker.func_wrap("crust", "getbool", |mut caller: Caller<'_, Host>| {
+        crust_runtime::read_bool(&mut caller.data_mut().input)
+            .ok()
+            .flatten()
+            .map(i32::from)
+            .ok_or_else(|| input_error("bool"))
+    })?;
+    linker.func_wrap("crust", "getinteger", |mut caller: Caller<'_, Host>| {
+        crust_runtime::read_integer(&mut caller.data_mut().input)
+            .ok()
+            .flatten()
+            .ok_or_else(|| input_error("integer"))
+    })?;
+    linker.func_wrap("crust", "getfloat", |mut caller: Caller<'_, Host>| {
+        crust_runtime::read_float(&mut caller.data_mut().input)
+            .ok()
+            .flatten()
+            .ok_or_else(|| input_error("float"))
+    })?;
+    linker.func_wrap(
+        "crust",
+        "getstring",
+        |mut caller: Caller<'_, Host>| -> Result<i32, wasmi::Error> {
+            let line = crust_runtime::read_string(&mut caller.data_mut().input)
+                .map_err(|_| input_error("string"))?;
+            let mut bytes: Vec<u8> = line.bytes().take_while(|byte| *byte != 0).collect();
+            bytes.push(0);
+
+            let heap = caller
+                .get_export("heap")
+                .and_then(Extern::into_global)
+                .unwrap();
+            let Val::I32(address) = heap.get(&caller) else {
+                unreachable!("the heap pointer is an i32")
+            };
+            let memory = memory(&caller);
+            let end = address as usize + bytes.len();
+            let size = memory.data(&caller).len();
+            if end > size {
+                let pages = (end - size).div_ceil(PAGE_SIZE as usize);
+                memory.grow(&mut caller, wasmi::core::Pages::new(pages as u32).unwrap())?;
+            }
+            memory.write(&mut caller, address as usize, &bytes)?;
+            heap.set(&mut caller, Val::I32(end as i32))?;
+            Ok(address)
+        },
+    )?;
+    linker.func_wrap(
+        "crust",
+        "putbool",
+        |mut caller: Caller<'_, Host>, value: i32| {
+            i32::from(crust_runtime::write_bool(&mut caller.data_mut().output, value != 0).is_ok())
+        },
+    )?;
+    linker.func_wrap(
+        "crust",
+        "putinteger",
+        |mut caller: Caller<'_, Host>, value: i64| {
+            i32::from(crust_runtime::write_integer(&mut caller.data_mut().output, value).is_ok())
+        },
+    )?;
+    linker.func_wrap(
+        "crust",
+        "putfloat",
+        |mut caller: Caller<'_, Host>, value: f64| {
+            i32::from(crust_runtime::write_float(&mut caller.data_mut().output, value).is_ok())
+        },
+    )?;
+    linker.func_wrap(
+        "crust",
+        "putstring",
+        |mut caller: Caller<'_, Host>, address: i32| {
+            let data = memory(&caller).data(&caller);
+            let bytes = &data[address as usize..];
+            let length = bytes.iter().position(|byte| *byte == 0).unwrap();
+            let value = String::from_utf8_lossy(&bytes[..length]).into_owned();
+            i32::from(crust_runtime::write_string(&mut caller.data_mut().output, &value).is_ok())
+        },
+    )?;
+    linker.func_wrap("crust", "sqrt", |value: i64| crust_runtime::sqrt(value))?;
+
+    let instance = linker.instantiate(&mut store, &module)?.start(&mut store)?;
+    let main = instance.get_typed_func::<(), ()>(&store, "main")?;
+    main.call(&mut store, ())?;
+    Ok(String::from_utf8(store.into_data().output).unwrap())
+}
+
+#[cfg(test)]
+#[rstest]
+#[case("", "")]
+#[case("Enter a string:", "Enter a string:")]
+#[case("say \"hi\"\\", "say \\22hi\\22\\5c")]
+#[case("tab\there\n", "tab\\09here\\0a")]
+#[case("é", "\\c3\\a9")]
+fn escape_string_for_wat(#[case] value: &str, #[case] expected: &str) {
+    assert_eq!(escape_string(value), expected);
+}
+
+#[cfg(test)]
+#[rstest]
+fn generate_valid_wat(#[files("tests/correct/*.src")] source_file: PathBuf) {
+    let source = generate_test_program(source_file.to_str().unwrap(), generate);
+    let binary = wat::parse_str(&source).unwrap();
+    wasmi::Module::new(&Engine::default(), &binary[..]).unwrap();
+}
+
+#[cfg(test)]
+#[test]
+fn run_generated_programs() {
+    for &(path, input, expected) in TEST_RUNS.iter().chain([&NESTED_RUN]) {
+        let source = generate_test_program(path, generate);
+        assert_eq!(
+            run_test_module(&source, input).unwrap(),
+            expected,
+            "{}",
+            path
+        );
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn run_traps_on_invalid_input() {
+    let source = generate_test_program("tests/correct/recursiveFib.src", generate);
+    assert!(run_test_module(&source, "five\n").is_err());
+}
@@ -0,0 +1,761 @@
+//! x86-64 assembly for Linux in GNU as (AT&T) syntax, generated from the IR.
+//!
+//! Every temporary and local variable lives in a slot of its function's stack frame, so each
+//! instruction loads its operands into scratch registers and stores its result straight back.
+//! Scalars take 8 bytes, with bools held as 0 or 1 and strings as pointers, and arrays take 8 bytes per element.
+//! Procedures follow the System V calling convention, and arrays are passed as a pointer the callee copies from.
+//! Nested procedures are passed a static link to their parent's frame as a hidden first argument,
+//! which they keep in the first slot of their own frame.
+
+use std::collections::HashMap;
+
+use crate::ir::lower::lower;
+use crate::ir::{
+    Argument, BinaryOp, Callee, Constant, Function, Instruction, Module, Operand, Place, Temp,
+    Terminator, UnaryOp, Variable,
+};
+use crate::semantics::value::Type;
+use crate::semantics::AnalyzedProgram;
+
+use super::CodegenError;
+
+const INTEGER_ARG_REGISTERS: [&str; 6] = ["%rdi", "%rsi", "%rdx", "%rcx", "%r8", "%r9"];
+const FLOAT_ARG_REGISTERS: [&str; 8] = [
+    "%xmm0", "%xmm1", "%xmm2", "%xmm3", "%xmm4", "%xmm5", "%xmm6", "%xmm7",
+];
+
+/// Where a function with a parent keeps its static link, which is the frame pointer of the parent's frame
+const STATIC_LINK_SLOT: usize = 8;
+
+/// Generates an assembly file for a program, to be linked against the `crust_runtime` static library.
+pub fn generate(program: &AnalyzedProgram) -> Result<String, CodegenError> {
+    let module = lower(program)?;
+    let mut assembler = Assembler {
+        module: &module,
+        strings: HashMap::new(),
+        text: String::new(),
+    };
+
+    assembler.line(".text");
+    FunctionAssembler::new(&mut assembler, &module.body, String::from("main")).assemble();
+    for procedure in module.procedures.iter() {
+        let symbol = procedure_symbol(procedure);
+        FunctionAssembler::new(&mut assembler, procedure, symbol).assemble();
+    }
+    Ok(assembler.finish())
+}
+
+fn procedure_symbol(function: &Function) -> String {
+    format!("proc.{}", function.name)
+}
+
+fn global_symbol(name: &str) -> String {
+    format!("var.{}", name)
+}
+
+/// The number of 8 byte words a value of `value_type` takes
+fn word_count(value_type: &Type) -> usize {
+    match value_type {
+        Type::Array(_, bound) => *bound,
+        _ => 1,
+    }
+}
+
+/// Offsets below the frame pointer of each of a function's locals, which come after its static link
+fn local_offsets(function: &Function) -> Vec<usize> {
+    let mut frame_size = match function.parent {
+        Some(_) => STATIC_LINK_SLOT,
+        None => 0,
+    };
+    function
+        .locals
+        .iter()
+        .map(|local| {
+            frame_size += 8 * word_count(&local.value_type);
+            frame_size
+        })
+        .collect()
+}
+
+/// Whether a value is passed in an SSE register, rather than a general purpose one
+fn is_float(value_type: &Type) -> bool {
+    matches!(value_type, Type::Float)
+}
+
+/// Escapes a string for a `.string` directive
+fn escape_string(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'"' | b'\\' => format!("\\{}", byte as char),
+            b' '..=b'~' => String::from(byte as char),
+            _ => format!("\\{:03o}", byte),
+        })
+        .collect()
+}
+
+struct Assembler<'m> {
+    module: &'m Module,
+    /// The number of each string constant's label, by its value
+    strings: HashMap<String, usize>,
+    text: String,
+}
+
+impl<'m> Assembler<'m> {
+    fn line(&mut self, line: &str) {
+        self.text.push_str(line);
+        self.text.push('\n');
+    }
+
+    fn string_label(&mut self, value: &str) -> String {
+        if value.is_empty() {
+            return String::from(".Lstr.empty");
+        }
+        let next = self.strings.len();
+        let number = self.strings.entry(String::from(value)).or_insert(next);
+        format!(".Lstr.{}", number)
+    }
+
+    fn finish(mut self) -> String {
+        self.line("");
+        self.line(".section .rodata");
+        self.line(".Lstr.empty:");
+        self.line("\t.string \"\"");
+        let mut strings: Vec<(String, usize)> = self.strings.drain().collect();
+        strings.sort_by_key(|(_, number)| *number);
+        for (value, number) in strings {
+            self.line(&format!(".Lstr.{}:", number));
+            self.line(&format!("\t.string \"{}\"", escape_string(&value)));
+        }
+
+        // Strings default to the empty string, so they can't go in .bss
+        self.line("");
+        self.line(".data");
+        for global in self.module.globals.iter() {
+            self.line("\t.balign 8");
+            self.line(&format!("{}:", global_symbol(&global.name)));
+            let line = match &global.value_type {
+                Type::String => String::from("\t.quad .Lstr.empty"),
+                Type::Array(box Type::String, bound) => {
+                    format!("\t.rept {}\n\t.quad .Lstr.empty\n\t.endr", bound)
+                }
+                value_type => format!("\t.zero {}", 8 * word_count(value_type)),
+            };
+            self.line(&line);
+        }
+
+        self.line("");
+        self.line(".section .note.GNU-stack,\"\",@progbits");
+        self.text
+    }
+}
+
+/// Where an argument is passed
+enum ArgLocation {
+    Integer(&'static str),
+    Float(&'static str),
+    Stack,
+}
+
+/// Assigns each argument a register in order, passing the rest on the stack
+fn arg_locations(float_args: impl Iterator<Item = bool>) -> Vec<ArgLocation> {
+    let mut integer_registers = INTEGER_ARG_REGISTERS.into_iter();
+    let mut float_registers = FLOAT_ARG_REGISTERS.into_iter();
+    float_args
+        .map(|float| {
+            let register = match float {
+                true => float_registers.next().map(ArgLocation::Float),
+                false => integer_registers.next().map(ArgLocation::Integer),
+            };
+            register.unwrap_or(ArgLocation::Stack)
+        })
+        .collect()
+}
+
+struct FunctionAssembler<'a, 'm> {
+    assembler: &'a mut Assembler<'m>,
+    module: &'m Module,
+    function: &'m Function,
+    symbol: String,
+    /// Offsets below the frame pointer of each local, then each temporary
+    local_offsets: Vec<usize>,
+    temp_offsets: Vec<usize>,
+    /// Slots holding the pointers passed for array parameters, until they are copied
+    array_param_offsets: HashMap<usize, usize>,
+    frame_size: usize,
+}
+
+impl<'a, 'm> FunctionAssembler<'a, 'm> {
+    fn new(assembler: &'a mut Assembler<'m>, function: &'m Function, symbol: String) -> Self {
+        let local_offsets = local_offsets(function);
+        let mut frame_size = match (local_offsets.last(), function.parent) {
+            (Some(offset), _) => *offset,
+            (None, Some(_)) => STATIC_LINK_SLOT,
+            (None, None) => 0,
+        };
+        let mut allocate = |words: usize| {
+            frame_size += 8 * words;
+            frame_size
+        };
+        let temp_offsets = function.temps.iter().map(|_| allocate(1)).collect();
+        let array_param_offsets = function
+            .params()
+            .iter()
+            .enumerate()
+            .filter(|(_, param)| matches!(param.value_type, Type::Array(..)))
+            .map(|(index, _)| (index, allocate(1)))
+            .collect();
+
+        FunctionAssembler {
+            module: assembler.module,
+            assembler,
+            function,
+            symbol,
+            local_offsets,
+            temp_offsets,
+            array_param_offsets,
+            // The stack stays 16 byte aligned, as calls need it to be
+            frame_size: frame_size.next_multiple_of(16),
+        }
+    }
+
+    fn emit(&mut self, instruction: &str) {
+        self.assembler.line(&format!("\t{}", instruction));
+    }
+
+    fn block_label(&self, block: usize) -> String {
+        format!(".L{}.bb{}", self.symbol, block)
+    }
+
+    fn assemble(mut self) {
+        let symbol = self.symbol.clone();
+        self.assembler.line("");
+        if symbol == "main" {
+            self.assembler.line(&format!(".globl {}", symbol));
+        }
+        self.assembler.line(&format!(".type {}, @function", symbol));
+        self.assembler.line(&format!("{}:", symbol));
+        self.emit("pushq %rbp");
+        self.emit("movq %rsp, %rbp");
+        if self.frame_size > 0 {
+            self.emit(&format!("subq ${}, %rsp", self.frame_size));
+        }
+        self.prologue();
+
+        for (id, block) in self.function.blocks.iter().enumerate() {
+            self.assembler.line(&format!("{}:", self.block_label(id)));
+            for instruction in block.instructions.iter() {
+                self.instruction(instruction);
+            }
+            self.terminator(&block.terminator, id);
+        }
+        self.assembler
+            .line(&format!("\t.size {}, .-{}", symbol, symbol));
+    }
+
+    /// Saves the static link and parameters, then gives every other local its default value
+    fn prologue(&mut self) {
+        let function = self.function;
+        let link = function.parent.map(|_| false);
+        let mut locations = arg_locations(
+            link.into_iter().chain(
+                function
+                    .params()
+                    .iter()
+                    .map(|param| is_float(&param.value_type)),
+            ),
+        );
+        if link.is_some() {
+            // The static link is always the first integer argument
+            locations.remove(0);
+            self.emit(&format!("movq %rdi, -{}(%rbp)", STATIC_LINK_SLOT));
+        }
+        let mut stack_offset = 16;
+        for (index, location) in locations.into_iter().enumerate() {
+            let slot = match self.array_param_offsets.get(&index) {
+                Some(offset) => format!("-{}(%rbp)", offset),
+                None => self.variable_address(Variable::Local(index), "%r11"),
+            };
+            match location {
+                ArgLocation::Integer(register) | ArgLocation::Float(register) => {
+                    self.emit(&format!("movq {}, {}", register, slot))
+                }
+                ArgLocation::Stack => {
+                    self.emit(&format!("movq {}(%rbp), %rax", stack_offset));
+                    self.emit(&format!("movq %rax, {}", slot));
+                    stack_offset += 8;
+                }
+            }
+        }
+
+        for (index, local) in function
+            .locals
+            .iter()
+            .enumerate()
+            .skip(function.param_count)
+        {
+            let address = self.variable_address(Variable::Local(index), "%r11");
+            match &local.value_type {
+                Type::String => {
+                    self.emit("leaq .Lstr.empty(%rip), %rax");
+                    self.emit(&format!("movq %rax, {}", address));
+                }
+                Type::Array(element_type, bound) => {
+                    match **element_type {
+                        Type::String => self.emit("leaq .Lstr.empty(%rip), %rax"),
+                        _ => self.emit("xorl %eax, %eax"),
+                    }
+                    self.emit(&format!("leaq {}, %rdi", address));
+                    self.emit(&format!("movq ${}, %rcx", bound));
+                    self.emit("rep stosq");
+                }
+                _ => self.emit(&format!("movq $0, {}", address)),
+            }
+        }
+
+        let mut array_params: Vec<(usize, usize)> = self
+            .array_param_offsets
+            .iter()
+            .map(|(index, offset)| (*index, *offset))
+            .collect();
+        array_params.sort();
+        for (index, offset) in array_params {
+            let value_type = &function.locals[index].value_type;
+            self.emit(&format!("movq -{}(%rbp), %rsi", offset));
+            let address = self.variable_address(Variable::Local(index), "%r11");
+            self.emit(&format!("leaq {}, %rdi", address));
+            self.emit(&format!("movq ${}, %rcx", word_count(value_type)));
+            self.emit("rep movsq");
+        }
+    }
+
+    /// Loads the frame pointer of the frame `depth` static links out into `register`
+    fn load_frame(&mut self, depth: usize, register: &str) {
+        self.emit(&format!("movq %rbp, {}", register));
+        for _ in 0..depth {
+            self.emit(&format!(
+                "movq -{}({}), {}",
+                STATIC_LINK_SLOT, register, register
+            ));
+        }
+    }
+
+    /// The memory operand for the start of a variable.
+    /// Locals of an enclosing procedure are addressed through `frame_register`.
+    fn variable_address(&mut self, variable: Variable, frame_register: &str) -> String {
+        match variable {
+            Variable::Local(index) => format!("-{}(%rbp)", self.local_offsets[index]),
+            Variable::Outer(depth, index) => {
+                // The IR has been verified, so the enclosing procedure exists
+                let enclosing = self.module.enclosing(self.function, depth).unwrap();
+                let offset = local_offsets(enclosing)[index];
+                self.load_frame(depth, frame_register);
+                format!("-{}({})", offset, frame_register)
+            }
+            Variable::Global(index) => {
+                format!("{}(%rip)", global_symbol(&self.module.globals[index].name))
+            }
+        }
+    }
+
+    fn variable_type(&self, variable: Variable) -> &'m Type {
+        // The IR has been verified, so every variable exists
+        self.function.variable_type(self.module, variable).unwrap()
+    }
+
+    fn temp_slot(&self, temp: Temp) -> String {
+        format!("-{}(%rbp)", self.temp_offsets[temp.0])
+    }
+
+    /// Loads a scalar into a general purpose register
+    fn load_operand(&mut self, operand: &Operand, register: &str) {
+        let instruction = match operand {
+            Operand::Temp(temp) => format!("movq {}, {}", self.temp_slot(*temp), register),
+            Operand::Const(Constant::Bool(value)) => {
+                format!("movq ${}, {}", *value as i64, register)
+            }
+            Operand::Const(Constant::Int(value)) => format!("movabsq ${}, {}", value, register),
+            Operand::Const(Constant::Float(value)) => {
+                format!("movabsq ${:#x}, {}", value.to_bits(), register)
+            }
+            Operand::Const(Constant::String(value)) => {
+                let label = self.assembler.string_label(value);
+                format!("leaq {}(%rip), {}", label, register)
+            }
+        };
+        self.emit(&instruction);
+    }
+
+    fn store_temp(&mut self, temp: Temp, register: &str) {
+        let slot = self.temp_slot(temp);
+        self.emit(&format!("movq {}, {}", register, slot));
+    }
+
+    /// The memory operand for a place. Elements are addressed through %rdx and %rcx.
+    fn place_address(&mut self, place: &Place) -> String {
+        match place {
+            Place::Variable(variable) => self.variable_address(*variable, "%r11"),
+            Place::Element(variable, index) => {
+                let address = self.variable_address(*variable, "%r11");
+                self.emit(&format!("leaq {}, %rdx", address));
+                self.load_operand(index, "%rcx");
+                String::from("(%rdx,%rcx,8)")
+            }
+        }
+    }
+
+    /// Sets %rax to 1 if the condition code holds, or 0 otherwise
+    fn set_bool(&mut self, condition: &str) {
+        self.emit(&format!("set{} %al", condition));
+        self.emit("movzbq %al, %rax");
+    }
+
+    fn instruction(&mut self, instruction: &Instruction) {
+        match instruction {
+            Instruction::Load { dest, source } => {
+                let address = self.place_address(source);
+                self.emit(&format!("movq {}, %rax", address));
+                self.store_temp(*dest, "%rax");
+            }
+            Instruction::Store { dest, value } => {
+                self.load_operand(value, "%rax");
+                let address = self.place_address(dest);
+                self.emit(&format!("movq %rax, {}", address));
+            }
+            Instruction::CopyArray { dest, source } => {
+                let words = word_count(self.variable_type(*dest));
+                let source = self.variable_address(*source, "%r10");
+                let dest = self.variable_address(*dest, "%r11");
+                self.emit(&format!("leaq {}, %rsi", source));
+                self.emit(&format!("leaq {}, %rdi", dest));
+                self.emit(&format!("movq ${}, %rcx", words));
+                self.emit("rep movsq");
+            }
+            Instruction::Unary { dest, op, operand } => {
+                self.load_operand(operand, "%rax");
+                match (op, self.function.operand_type(operand)) {
+                    (UnaryOp::Negate, Type::Float) => {
+                        // Flip the sign bit
+                        self.emit("movabsq $0x8000000000000000, %rcx");
+                        self.emit("xorq %rcx, %rax");
+                    }
+                    (UnaryOp::Negate, _) => self.emit("negq %rax"),
+                    (UnaryOp::Not, Type::Bool) => self.emit("xorq $1, %rax"),
+                    (UnaryOp::Not, _) => self.emit("notq %rax"),
+                }
+                self.store_temp(*dest, "%rax");
+            }
+            Instruction::Binary { dest, op, lhs, rhs } => {
+                match self.function.operand_type(lhs) {
+                    Type::Float => self.float_binary(*op, lhs, rhs),
+                    Type::String => self.string_comparison(*op, lhs, rhs),
+                    _ => self.integer_binary(*op, lhs, rhs),
+                }
+                self.store_temp(*dest, "%rax");
+            }
+            Instruction::Cast { dest, operand } => {
+                self.load_operand(operand, "%rax");
+                let from = self.function.operand_type(operand);
+                match (from, self.function.temp_type(*dest)) {
+                    (Type::Int, Type::Float) => {
+                        self.emit("cvtsi2sdq %rax, %xmm0");
+                        self.emit("movq %xmm0, %rax");
+                    }
+                    (Type::Float, Type::Int) => {
+                        self.emit("movq %rax, %xmm0");
+                        self.emit("cvttsd2siq %xmm0, %rax");
+                    }
+                    (Type::Int, Type::Bool) => {
+                        self.emit("testq %rax, %rax");
+                        self.set_bool("ne");
+                    }
+                    // Bools are already 0 or 1
+                    _ => (),
+                }
+                self.store_temp(*dest, "%rax");
+            }
+            Instruction::Call { dest, callee, args } => {
+                self.call(callee, args);
+                self.store_temp(*dest, "%rax");
+            }
+        }
+    }
+
+    /// Applies an operator to integers or bools, leaving the result in %rax
+    fn integer_binary(&mut self, op: BinaryOp, lhs: &Operand, rhs: &Operand) {
+        self.load_operand(lhs, "%rax");
+        self.load_operand(rhs, "%rcx");
+        let condition = match op {
+            BinaryOp::Add => return self.emit("addq %rcx, %rax"),
+            BinaryOp::Subtract => return self.emit("subq %rcx, %rax"),
+            BinaryOp::Multiply => return self.emit("imulq %rcx, %rax"),
+            BinaryOp::Divide => {
+                self.emit("cqto");
+                return self.emit("idivq %rcx");
+            }
+            BinaryOp::And => return self.emit("andq %rcx, %rax"),
+            BinaryOp::Or => return self.emit("orq %rcx, %rax"),
+            BinaryOp::LessThan => "l",
+            BinaryOp::LessThanEq => "le",
+            BinaryOp::GreaterThan => "g",
+            BinaryOp::GreaterThanEq => "ge",
+            BinaryOp::Equals => "e",
+            BinaryOp::NotEquals => "ne",
+        };
+        self.emit("cmpq %rcx, %rax");
+        self.set_bool(condition);
+    }
+
+    /// Applies an operator to floats, leaving the result in %rax.
+    /// Comparisons with NaN are false, except for `!=`.
+    fn float_binary(&mut self, op: BinaryOp, lhs: &Operand, rhs: &Operand) {
+        self.load_operand(lhs, "%rax");
+        self.emit("movq %rax, %xmm0");
+        self.load_operand(rhs, "%rcx");
+        self.emit("movq %rcx, %xmm1");
+        let arithmetic = match op {
+            BinaryOp::Add => Some("addsd"),
+            BinaryOp::Subtract => Some("subsd"),
+            BinaryOp::Multiply => Some("mulsd"),
+            BinaryOp::Divide => Some("divsd"),
+            _ => None,
+        };
+        if let Some(arithmetic) = arithmetic {
+            self.emit(&format!("{} %xmm1, %xmm0", arithmetic));
+            return self.emit("movq %xmm0, %rax");
+        }
+
+        // `ucomisd` sets the carry and zero flags for unordered operands,
+        // which `a` and `ae` treat as false
+        match op {
+            BinaryOp::LessThan | BinaryOp::LessThanEq => self.emit("ucomisd %xmm0, %xmm1"),
+            _ => self.emit("ucomisd %xmm1, %xmm0"),
+        }
+        match op {
+            BinaryOp::LessThan | BinaryOp::GreaterThan => self.set_bool("a"),
+            BinaryOp::LessThanEq | BinaryOp::GreaterThanEq => self.set_bool("ae"),
+            BinaryOp::Equals => {
+                self.emit("sete %al");
+                self.emit("setnp %cl");
+                self.emit("andb %cl, %al");
+                self.emit("movzbq %al, %rax");
+            }
+            BinaryOp::NotEquals => {
+                self.emit("setne %al");
+                self.emit("setp %cl");
+                self.emit("orb %cl, %al");
+                self.emit("movzbq %al, %rax");
+            }
+            _ => unreachable!("only comparisons are left"),
+        }
+    }
+
+    /// Compares strings by content, leaving the result in %rax
+    fn string_comparison(&mut self, op: BinaryOp, lhs: &Operand, rhs: &Operand) {
+        self.load_operand(lhs, "%rdi");
+        self.load_operand(rhs, "%rsi");
+        self.emit("call strcmp@PLT");
+        self.emit("testl %eax, %eax");
+        match op {
+            BinaryOp::Equals => self.set_bool("e"),
+            _ => self.set_bool("ne"),
+        }
+    }
+
+    /// Calls a procedure or builtin, leaving the result in %rax
+    fn call(&mut self, callee: &Callee, args: &[Argument]) {
+        let arg_types: Vec<Type> = args
+            .iter()
+            .map(|arg| match arg {
+                Argument::Scalar(operand) => self.function.operand_type(operand),
+                Argument::Array(variable) => self.variable_type(*variable).clone(),
+            })
+            .collect();
+        let link_depth = match callee {
+            Callee::Nested(_, depth) => Some(*depth),
+            _ => None,
+        };
+        let mut locations = arg_locations(
+            link_depth
+                .map(|_| false)
+                .into_iter()
+                .chain(arg_types.iter().map(is_float)),
+        );
+        // The static link goes in the first integer register, after the arguments are loaded
+        let link_register = link_depth.map(|_| locations.remove(0));
+
+        let stack_args: Vec<&Argument> = args
+            .iter()
+            .zip(locations.iter())
+            .filter(|(_, location)| matches!(location, ArgLocation::Stack))
+            .map(|(arg, _)| arg)
+            .collect();
+        // Keep the stack 16 byte aligned at the call
+        let padding = 8 * (stack_args.len() % 2);
+        if padding > 0 {
+            self.emit(&format!("subq ${}, %rsp", padding));
+        }
+        for arg in stack_args.iter().rev() {
+            self.load_argument(arg, "%rax");
+            self.emit("pushq %rax");
+        }
+
+        for (arg, location) in args.iter().zip(locations.iter()) {
+            match location {
+                ArgLocation::Integer(register) => self.load_argument(arg, register),
+                ArgLocation::Float(register) => {
+                    self.load_argument(arg, "%rax");
+                    self.emit(&format!("movq %rax, {}", register));
+                }
+                ArgLocation::Stack => (),
+            }
+        }
+        if let (Some(depth), Some(ArgLocation::Integer(register))) = (link_depth, link_register) {
+            self.load_frame(depth, register);
+        }
+
+        let (symbol, return_type) = match callee {
+            Callee::Procedure(id) | Callee::Nested(id, _) => {
+                let procedure = self.module.procedure(*id);
+                (procedure_symbol(procedure), &procedure.return_type)
+            }
+            Callee::Builtin(builtin) => (format!("crust_{}@PLT", builtin), &builtin.return_type()),
+        };
+        let return_type = return_type.clone();
+        self.emit(&format!("call {}", symbol));
+        let stack_size = 8 * stack_args.len() + padding;
+        if stack_size > 0 {
+            self.emit(&format!("addq ${}, %rsp", stack_size));
+        }
+
+        match return_type {
+            Type::Float => self.emit("movq %xmm0, %rax"),
+            // Only the low byte of a returned bool is defined
+            Type::Bool => self.emit("movzbq %al, %rax"),
+            _ => (),
+        }
+    }
+
+    /// Loads an argument into a general purpose register, as a pointer for arrays
+    fn load_argument(&mut self, arg: &Argument, register: &str) {
+        match arg {
+            Argument::Scalar(operand) => self.load_operand(operand, register),
+            Argument::Array(variable) => {
+                let address = self.variable_address(*variable, "%r11");
+                self.emit(&format!("leaq {}, {}", address, register));
+            }
+        }
+    }
+
+    fn terminator(&mut self, terminator: &Terminator, block: usize) {
+        match terminator {
+            Terminator::Jump(target) => {
+                // Falling through to the next block needs no jump
+                if target.0 != block + 1 {
+                    let label = self.block_label(target.0);
+                    self.emit(&format!("jmp {}", label));
+                }
+            }
+            Terminator::Branch {
+                condition,
+                then_block,
+                else_block,
+            } => {
+                self.load_operand(condition, "%rax");
+                self.emit("testq %rax, %rax");
+                let then_label = self.block_label(then_block.0);
+                self.emit(&format!("jne {}", then_label));
+                if else_block.0 != block + 1 {
+                    let else_label = self.block_label(else_block.0);
+                    self.emit(&format!("jmp {}", else_label));
+                }
+            }
+            Terminator::Return(value) => {
+                match value {
+                    Some(value) => {
+                        self.load_operand(value, "%rax");
+                        if is_float(&self.function.return_type) {
+                            self.emit("movq %rax, %xmm0");
+                        }
+                    }
+                    // The program body is `main`, which exits with status 0
+                    None => self.emit("xorl %eax, %eax"),
+                }
+                self.emit("leave");
+                self.emit("ret");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+use super::{generate_test_program, run_tool};
+#[cfg(test)]
+use crate::session::{NESTED_RUN, TEST_RUNS};
+#[cfg(test)]
+use rstest::rstest;
+#[cfg(test)]
+use std::path::PathBuf;
+#[cfg(test)]
+use std::process::Command;
+
+#[cfg(test)]
+#[rstest]
+#[case("", "")]
+#[case("Enter a string:", "Enter a string:")]
+#[case("say \"hi\"\\", "say \\\"hi\\\"\\\\")]
+#[case("tab\there\n", "tab\\011here\\012")]
+#[case("é", "\\303\\251")]
+fn escape_string_for_as(#[case] value: &str, #[case] expected: &str) {
+    assert_eq!(escape_string(value), expected);
+}
+
+#[cfg(test)]
+#[rstest]
+fn generate_valid_asm(#[files("tests/correct/*.src")] source_file: PathBuf) {
+    let assembly = generate_test_program(source_file.to_str().unwrap(), generate);
+    let mut assembler = Command::new("as");
+    assembler.args(["--64", "-o", "/dev/null", "-"]);
+    if run_tool(assembler, &assembly).is_none() {
+        eprintln!("as not found, skipping");
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn run_generated_programs() {
+    let Some(runtime) = super::runtime_library("lib", ".a") else {
+        eprintln!("the runtime library not found, skipping");
+        return;
+    };
+    for &(path, input, expected) in TEST_RUNS.iter().chain([&NESTED_RUN]) {
+        let assembly = generate_test_program(path, generate);
+
+        let stem = format!(
+            "crust-{}-{}",
+            std::process::id(),
+            PathBuf::from(path).file_stem().unwrap().to_str().unwrap()
+        );
+        let assembly_path = std::env::temp_dir().join(format!("{}.s", stem));
+        let executable_path = std::env::temp_dir().join(stem);
+        std::fs::write(&assembly_path, assembly).unwrap();
+
+        let mut linker = Command::new("cc");
+        linker
+            .arg("-o")
+            .arg(&executable_path)
+            .arg(&assembly_path)
+            .arg(&runtime)
+            .args(["-lpthread", "-ldl", "-lm"]);
+        let linked = run_tool(linker, "");
+        std::fs::remove_file(&assembly_path).unwrap();
+        if linked.is_none() {
+            eprintln!("cc not found, skipping");
+            return;
+        }
+
+        let output = run_tool(Command::new(&executable_path), input).unwrap();
+        std::fs::remove_file(&executable_path).unwrap();
+        assert_eq!(output, expected, "{}", path);
+    }
+}
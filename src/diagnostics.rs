@@ -0,0 +1,380 @@
+pub mod render;
+
+use std::num::{IntErrorKind, ParseIntError};
+
+use crate::codegen::CodegenError;
+use crate::format::FormatError;
+use crate::interpreter::RuntimeError;
+use crate::lint::LintWarning;
+use crate::parser::utils::ParserError;
+use crate::scanner::ScannerError;
+use crate::semantics::assignment::AssignmentError;
+use crate::semantics::flow::FlowError;
+use crate::semantics::fold::FoldError;
+use crate::semantics::SemanticsError;
+use crate::span::Span;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A span of source text to point at, and what to say about it.
+/// Primary labels mark the cause of a diagnostic, secondary labels add context.
+#[derive(Debug, Clone)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+    pub primary: bool,
+}
+
+impl Label {
+    pub fn primary(span: Span, message: impl Into<String>) -> Self {
+        Label {
+            span,
+            message: message.into(),
+            primary: true,
+        }
+    }
+
+    pub fn secondary(span: Span, message: impl Into<String>) -> Self {
+        Label {
+            span,
+            message: message.into(),
+            primary: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, message: impl Into<String>) -> Self {
+        Diagnostic {
+            severity,
+            message: message.into(),
+            labels: Vec::new(),
+            notes: Vec::new(),
+        }
+    }
+
+    pub fn error(message: impl Into<String>) -> Self {
+        Diagnostic::new(Severity::Error, message)
+    }
+
+    pub fn warning(message: impl Into<String>) -> Self {
+        Diagnostic::new(Severity::Warning, message)
+    }
+
+    pub fn with_label(mut self, label: Label) -> Self {
+        self.labels.push(label);
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    /// The label the diagnostic is reported at. Falls back to the first label if none are primary.
+    pub fn primary_label(&self) -> Option<&Label> {
+        self.labels
+            .iter()
+            .find(|label| label.primary)
+            .or_else(|| self.labels.first())
+    }
+}
+
+impl From<&ScannerError> for Diagnostic {
+    fn from(value: &ScannerError) -> Self {
+        let label = match value {
+            ScannerError::StripError(..) => "comment nested too deeply",
+            ScannerError::NumLitError(_) => "numeric literal has more than one decimal point",
+            ScannerError::TokenError(..) => "unrecognized symbol",
+            ScannerError::InvalidCharacterError(_) => "unexpected character",
+        };
+        Diagnostic::error(value.to_string()).with_label(Label::primary(value.span(), label))
+    }
+}
+
+impl From<&ParserError> for Diagnostic {
+    fn from(value: &ParserError) -> Self {
+        let label = match value {
+            ParserError::EarlyEOF(_) => String::from("end of file reached here"),
+            ParserError::UnexpectedEOFToken(expected, _) => format!("expected {:?}", expected),
+            ParserError::UnexpectedEOF(expected, _) => format!("expected {}", expected),
+            ParserError::ExpectedEOF(..) => String::from("expected end of file"),
+            ParserError::UnexpectedToken(expected, _, _) => format!("expected {}", expected),
+        };
+        Diagnostic::error(value.to_string()).with_label(Label::primary(value.span(), label))
+    }
+}
+
+impl From<&SemanticsError> for Diagnostic {
+    fn from(value: &SemanticsError) -> Self {
+        let label = match value {
+            SemanticsError::TypeMismatch(expected, got, _) => {
+                format!("expected {:?}, found {:?}", expected, got)
+            }
+            SemanticsError::InvalidType(expected, got, _) => {
+                format!("expected {}, found {:?}", expected, got)
+            }
+            SemanticsError::ParamCountMismatch(got, expected, _) => {
+                format!("expected {} arguments, found {}", expected, got)
+            }
+            SemanticsError::Redeclared(..) => String::from("redeclared here"),
+            SemanticsError::UndefinedRef(..) => String::from("not found in this scope"),
+            SemanticsError::OutOfScope(_) => String::from("no enclosing scope"),
+            SemanticsError::IndexOnNonArray(identifier, _) => {
+                format!("`{}` is not an array", identifier)
+            }
+            SemanticsError::NonIntIndex(_, index_type, _) => {
+                format!("expected Int, found {:?}", index_type)
+            }
+            SemanticsError::IndexOutOfBounds(_, index, _, _) if *index < 0 => {
+                String::from("negative index")
+            }
+            SemanticsError::IndexOutOfBounds(..) => String::from("out of bounds"),
+            SemanticsError::UnexpectedReturn(_) => String::from("not inside a procedure"),
+            SemanticsError::InvalidIntLiteral(err, _) if is_overflow(err) => {
+                String::from("doesn't fit in a 64-bit integer")
+            }
+            SemanticsError::InvalidIntLiteral(..) | SemanticsError::InvalidFloatLiteral(..) => {
+                String::from("invalid literal")
+            }
+        };
+        let diagnostic =
+            Diagnostic::error(value.to_string()).with_label(Label::primary(value.span(), label));
+
+        match value {
+            SemanticsError::Redeclared(_, _, Some(previous_span)) => {
+                diagnostic.with_label(Label::secondary(*previous_span, "first declared here"))
+            }
+            SemanticsError::Redeclared(identifier, _, None) => {
+                diagnostic.with_note(format!("`{}` is a builtin", identifier))
+            }
+            SemanticsError::InvalidIntLiteral(err, _) if is_overflow(err) => {
+                diagnostic.with_note(integer_range_note())
+            }
+            _ => diagnostic,
+        }
+    }
+}
+
+fn is_overflow(err: &ParseIntError) -> bool {
+    matches!(
+        err.kind(),
+        IntErrorKind::PosOverflow | IntErrorKind::NegOverflow
+    )
+}
+
+fn integer_range_note() -> String {
+    format!("integers range from {} to {}", i64::MIN, i64::MAX)
+}
+
+impl From<&FlowError> for Diagnostic {
+    fn from(value: &FlowError) -> Self {
+        let diagnostic = Diagnostic::warning(value.to_string());
+        match value {
+            FlowError::MissingReturn(_, span) => diagnostic
+                .with_label(Label::primary(*span, "can reach the end without returning"))
+                .with_note("add a `return` at the end of the procedure"),
+            FlowError::Unreachable(span, return_span) => diagnostic
+                .with_label(Label::primary(*span, "unreachable"))
+                .with_label(Label::secondary(
+                    *return_span,
+                    "every path returns before here",
+                )),
+        }
+    }
+}
+
+impl From<&FoldError> for Diagnostic {
+    fn from(value: &FoldError) -> Self {
+        let diagnostic = Diagnostic::error(value.to_string());
+        match value {
+            FoldError::DivisionByZero(span, divisor_span) => diagnostic
+                .with_label(Label::primary(*span, "would fail when run"))
+                .with_label(Label::secondary(*divisor_span, "this is always zero")),
+            FoldError::Overflow(span) => diagnostic
+                .with_label(Label::primary(*span, "doesn't fit in a 64-bit integer"))
+                .with_note(integer_range_note()),
+        }
+    }
+}
+
+impl From<&AssignmentError> for Diagnostic {
+    fn from(value: &AssignmentError) -> Self {
+        let label = match value {
+            AssignmentError::Unassigned(..) | AssignmentError::UnassignedElement(..) => {
+                "may not be assigned yet"
+            }
+            AssignmentError::UnassignedInCall(..) => "called here",
+        };
+        Diagnostic::warning(value.to_string()).with_label(Label::primary(value.span(), label))
+    }
+}
+
+impl From<&LintWarning> for Diagnostic {
+    fn from(value: &LintWarning) -> Self {
+        let label = match value {
+            LintWarning::UnusedVariable(..) | LintWarning::UnusedParameter(..) => {
+                String::from("never read")
+            }
+            LintWarning::UnusedProcedure(..) => String::from("never called"),
+            LintWarning::ShadowedGlobal(..) => String::from("shadows a global"),
+            LintWarning::UnusedAssignment(..) => {
+                String::from("overwritten or out of scope before it's read")
+            }
+            LintWarning::ConstantCondition(_) => {
+                String::from("the same every time through the loop")
+            }
+            LintWarning::LoopIndexOutOfBounds { low, high, .. } => {
+                format!("ranges from {} to {}", low, high)
+            }
+        };
+        let diagnostic =
+            Diagnostic::warning(value.to_string()).with_label(Label::primary(value.span(), label));
+        match value {
+            LintWarning::ShadowedGlobal(_, _, Some(global_span)) => {
+                diagnostic.with_label(Label::secondary(*global_span, "global declared here"))
+            }
+            LintWarning::LoopIndexOutOfBounds { condition, .. } => diagnostic.with_label(
+                Label::secondary(*condition, "range inferred from this condition"),
+            ),
+            _ => diagnostic,
+        }
+    }
+}
+
+impl From<&RuntimeError> for Diagnostic {
+    fn from(value: &RuntimeError) -> Self {
+        let label = match value {
+            RuntimeError::DivisionByZero(_) | RuntimeError::IntegerDivisionByZero(..) => {
+                "division by zero"
+            }
+            RuntimeError::IndexOutOfBounds(..) | RuntimeError::ArrayIndexOutOfBounds(..) => {
+                "index out of bounds"
+            }
+            RuntimeError::IntegerOverflow(..) => "doesn't fit in a 64-bit integer",
+            RuntimeError::InvalidOperands(..) | RuntimeError::InvalidOperand(..) => {
+                "invalid operation"
+            }
+            RuntimeError::InvalidInput(..) => "while reading input here",
+            RuntimeError::StackOverflow(..) => "called here",
+            RuntimeError::UndefinedRef(..) => "not found at run time",
+            RuntimeError::IoError(..) => "while running this",
+            RuntimeError::InvalidBytecode(..) => "compiled from here",
+        };
+        let diagnostic =
+            Diagnostic::error(value.to_string()).with_label(Label::primary(value.span(), label));
+        match value {
+            RuntimeError::IntegerOverflow(..) => diagnostic.with_note(integer_range_note()),
+            _ => diagnostic,
+        }
+    }
+}
+
+impl From<&CodegenError> for Diagnostic {
+    fn from(value: &CodegenError) -> Self {
+        let (label, note) = match value {
+            CodegenError::UndefinedRef(..) => (
+                "not found while generating code",
+                "this is a bug in the compiler",
+            ),
+            CodegenError::InvalidCast(..) => ("invalid cast", "this is a bug in the compiler"),
+        };
+        Diagnostic::error(value.to_string())
+            .with_label(Label::primary(value.span(), label))
+            .with_note(note)
+    }
+}
+
+impl From<&FormatError> for Diagnostic {
+    fn from(value: &FormatError) -> Self {
+        let diagnostic = Diagnostic::error(value.to_string());
+        match value {
+            FormatError::Failed(_) => diagnostic,
+            FormatError::SkippedText(_, span) => diagnostic
+                .with_label(Label::primary(*span, "not part of any token"))
+                .with_note("formatting would delete it, so the file was left as it is"),
+        }
+    }
+}
+
+/// Collects the diagnostics reported while compiling a file.
+/// Once `error_limit` errors have been reported, any further diagnostics are counted but not kept.
+#[derive(Debug, Default)]
+pub struct DiagnosticSink {
+    diagnostics: Vec<Diagnostic>,
+    error_count: usize,
+    suppressed_count: usize,
+    error_limit: Option<usize>,
+}
+
+impl DiagnosticSink {
+    pub fn new(error_limit: Option<usize>) -> Self {
+        DiagnosticSink {
+            error_limit,
+            ..Default::default()
+        }
+    }
+
+    pub fn push(&mut self, diagnostic: Diagnostic) {
+        if self.limit_reached() {
+            self.suppressed_count += 1;
+            return;
+        }
+        if diagnostic.severity == Severity::Error {
+            self.error_count += 1;
+        }
+        self.diagnostics.push(diagnostic);
+    }
+
+    pub fn error(&mut self, diagnostic: impl Into<Diagnostic>) {
+        self.push(diagnostic.into())
+    }
+
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    pub fn error_count(&self) -> usize {
+        self.error_count
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.error_count > 0
+    }
+
+    /// Number of diagnostics dropped because the error limit was reached
+    pub fn suppressed_count(&self) -> usize {
+        self.suppressed_count
+    }
+
+    pub fn limit_reached(&self) -> bool {
+        self.error_limit
+            .is_some_and(|error_limit| self.error_count >= error_limit)
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn sink_stops_at_error_limit() {
+    let mut sink = DiagnosticSink::new(Some(2));
+    sink.push(Diagnostic::warning("first"));
+    for _ in 0..4 {
+        sink.push(Diagnostic::error("again"));
+    }
+    assert_eq!(sink.error_count(), 2);
+    assert_eq!(sink.diagnostics().len(), 3);
+    assert_eq!(sink.suppressed_count(), 2);
+    assert!(sink.limit_reached());
+}
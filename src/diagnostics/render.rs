@@ -0,0 +1,245 @@
+use std::fmt::Write;
+
+use crate::span::SourceFile;
+
+use super::{Diagnostic, Label, Severity};
+
+const TAB_WIDTH: usize = 4;
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const RED: &str = "\x1b[1;31m";
+const YELLOW: &str = "\x1b[1;33m";
+const BLUE: &str = "\x1b[1;34m";
+
+/// Renders diagnostics in the style of rustc:
+///
+/// ```text
+/// error: Type mismatch. Expected: Int, Got: String
+///  --> prog.src:3:10
+///   |
+/// 3 |     x := "hi";
+///   |          ^^^^ expected Int, found String
+///   |
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct Renderer {
+    color: bool,
+}
+
+impl Renderer {
+    pub fn new(color: bool) -> Self {
+        Renderer { color }
+    }
+
+    /// Colour if stderr is a terminal and `NO_COLOR` is not set.
+    pub fn for_stderr() -> Self {
+        use std::io::IsTerminal;
+        Renderer::new(std::io::stderr().is_terminal() && std::env::var_os("NO_COLOR").is_none())
+    }
+
+    fn paint(&self, style: &str, text: &str) -> String {
+        if self.color {
+            format!("{style}{text}{RESET}")
+        } else {
+            String::from(text)
+        }
+    }
+
+    fn severity_style(severity: Severity) -> (&'static str, &'static str) {
+        match severity {
+            Severity::Error => ("error", RED),
+            Severity::Warning => ("warning", YELLOW),
+        }
+    }
+
+    pub fn render(&self, source: &SourceFile, diagnostic: &Diagnostic) -> String {
+        let mut output = String::new();
+        let (severity_name, severity_color) = Renderer::severity_style(diagnostic.severity);
+
+        writeln!(
+            output,
+            "{}{}",
+            self.paint(severity_color, severity_name),
+            self.paint(BOLD, &format!(": {}", diagnostic.message))
+        )
+        .unwrap();
+
+        let mut labels: Vec<&Label> = diagnostic.labels.iter().collect();
+        labels.sort_by_key(|label| (label.span.line, label.span.column));
+
+        let gutter_width = labels
+            .iter()
+            .map(|label| label.span.line.to_string().len())
+            .max()
+            .unwrap_or(0);
+        let gutter = " ".repeat(gutter_width);
+        let bar = self.paint(BLUE, "|");
+
+        if let Some(primary) = diagnostic.primary_label() {
+            writeln!(
+                output,
+                "{}{} {}:{}:{}",
+                gutter,
+                self.paint(BLUE, "-->"),
+                source.name,
+                primary.span.line,
+                primary.span.column
+            )
+            .unwrap();
+            writeln!(output, "{} {}", gutter, bar).unwrap();
+
+            let mut previous_line: Option<usize> = None;
+            for label in labels.iter() {
+                let line = label.span.line;
+                if previous_line != Some(line) {
+                    if previous_line.is_some_and(|previous| line > previous + 1) {
+                        writeln!(output, "{}", self.paint(BLUE, "...")).unwrap();
+                    }
+                    writeln!(
+                        output,
+                        "{} {}",
+                        self.paint(BLUE, &format!("{:>gutter_width$} |", line)),
+                        expand_tabs(source.line_text(line))
+                    )
+                    .unwrap();
+                    previous_line = Some(line);
+                }
+                self.render_underline(&mut output, source, label, &gutter, severity_color);
+            }
+            writeln!(output, "{} {}", gutter, bar).unwrap();
+        }
+
+        for note in diagnostic.notes.iter() {
+            writeln!(
+                output,
+                "{} {} note: {}",
+                gutter,
+                self.paint(BLUE, "="),
+                note
+            )
+            .unwrap();
+        }
+
+        output
+    }
+
+    fn render_underline(
+        &self,
+        output: &mut String,
+        source: &SourceFile,
+        label: &Label,
+        gutter: &str,
+        severity_color: &str,
+    ) {
+        let line_start = source.line_start(label.span.line);
+        let line_text = source.line_text(label.span.line);
+        let line_end = line_start + line_text.len();
+
+        let start = label.span.start.clamp(line_start, line_end);
+        // Spans running past the end of the line are cut off there
+        let end = label.span.end.clamp(start, line_end);
+
+        let padding = display_width(&line_text[..start - line_start]);
+        let width = display_width(&line_text[start - line_start..end - line_start]).max(1);
+
+        let (marker, color) = if label.primary {
+            ("^", severity_color)
+        } else {
+            ("-", BLUE)
+        };
+        let underline = marker.repeat(width);
+        let annotated = if label.message.is_empty() {
+            underline
+        } else {
+            format!("{} {}", underline, label.message)
+        };
+
+        writeln!(
+            output,
+            "{} {} {}{}",
+            gutter,
+            self.paint(BLUE, "|"),
+            " ".repeat(padding),
+            self.paint(color, &annotated)
+        )
+        .unwrap();
+    }
+}
+
+fn display_width(text: &str) -> usize {
+    text.chars()
+        .map(|curr_char| if curr_char == '\t' { TAB_WIDTH } else { 1 })
+        .sum()
+}
+
+fn expand_tabs(text: &str) -> String {
+    text.replace('\t', &" ".repeat(TAB_WIDTH))
+}
+
+#[cfg(test)]
+use crate::span::Span;
+
+#[cfg(test)]
+#[test]
+fn render_type_mismatch() {
+    let source = SourceFile::new(
+        String::from("prog.src"),
+        String::from("program p is\nbegin\n\tx := \"hi\";\nend program.\n"),
+    );
+    let diagnostic = Diagnostic::error("Type mismatch. Expected: Int, Got: String").with_label(
+        Label::primary(Span::new(25, 29, 3, 7), "expected Int, found String"),
+    );
+
+    let rendered = Renderer::new(false).render(&source, &diagnostic);
+    assert_eq!(
+        rendered,
+        "error: Type mismatch. Expected: Int, Got: String\n \
+         --> prog.src:3:7\n  \
+          |\n\
+         3 |     x := \"hi\";\n  \
+          |          ^^^^ expected Int, found String\n  \
+          |\n"
+    );
+}
+
+#[cfg(test)]
+#[test]
+fn render_secondary_label() {
+    let text = String::from("variable x : integer;\n\n\nvariable x : float;\n");
+    let source = SourceFile::new(String::from("prog.src"), text);
+    let diagnostic = Diagnostic::error("Variable x redeclared within local scope.")
+        .with_label(Label::primary(Span::new(24, 43, 4, 1), "redeclared here"))
+        .with_label(Label::secondary(
+            Span::new(0, 20, 1, 1),
+            "first declared here",
+        ));
+
+    let rendered = Renderer::new(false).render(&source, &diagnostic);
+    assert_eq!(
+        rendered,
+        "error: Variable x redeclared within local scope.\n \
+         --> prog.src:4:1\n  \
+          |\n\
+         1 | variable x : integer;\n  \
+          | -------------------- first declared here\n\
+         ...\n\
+         4 | variable x : float;\n  \
+          | ^^^^^^^^^^^^^^^^^^^ redeclared here\n  \
+          |\n"
+    );
+}
+
+#[cfg(test)]
+#[test]
+fn render_with_color() {
+    let source = SourceFile::new(String::from("prog.src"), String::from("x := 1;\n"));
+    let diagnostic = Diagnostic::error("Undeclared reference x").with_label(Label::primary(
+        Span::new(0, 1, 1, 1),
+        "not found in this scope",
+    ));
+
+    let rendered = Renderer::new(true).render(&source, &diagnostic);
+    assert!(rendered.starts_with("\x1b[1;31merror\x1b[0m\x1b[1m: Undeclared reference x\x1b[0m\n"));
+    assert!(rendered.contains("\x1b[1;31m^ not found in this scope\x1b[0m"));
+}
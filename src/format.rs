@@ -0,0 +1,547 @@
+//! An opinionated pretty-printer, which writes the syntax tree back out as canonically laid out source.
+//!
+//! Keywords are written in lower case, and each identifier is spelled the way it is first written in the file.
+//! Comments are kept. Ones on their own line stay before the line that follows them,
+//! and ones at the end of a line stay at the end of it.
+//! Comments inside an expression stay before the token they were before.
+//! Single blank lines between lines are kept, except at the start of a block.
+//!
+//! Files with text the scanner skipped aren't formatted, since the skipped text would be lost.
+
+use std::collections::HashMap;
+
+use thiserror::Error;
+
+use crate::parser::declaratons::{Declaration, ProcedureDeclaration, VariableDeclaration};
+use crate::parser::program::ProgramStruct;
+use crate::parser::statement::Statement;
+use crate::session::{Phase, Session};
+use crate::span::{LineIndex, Span};
+use crate::syntax::token::{lex, TriviaKind};
+use crate::tokens::{Comment, SpannedToken, Token};
+
+mod expression;
+
+const INDENT: &str = "    ";
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum FormatError {
+    /// The phase reported errors, which are in the session's diagnostics
+    #[error("{0:?} failed")]
+    Failed(Phase),
+    #[error("Cannot format a file with text that isn't part of any token: {0}")]
+    SkippedText(String, Span),
+}
+
+impl FormatError {
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            FormatError::Failed(_) => None,
+            FormatError::SkippedText(_, span) => Some(*span),
+        }
+    }
+}
+
+/// Formats the session's source, which must parse without errors
+pub fn format(session: &mut Session) -> Result<String, FormatError> {
+    session.parse().map_err(FormatError::Failed)?;
+    let (Some(tokens), Some(comments), Some(program)) =
+        (session.tokens(), session.comments(), session.ast())
+    else {
+        return Err(FormatError::Failed(Phase::Parse));
+    };
+    let source = &session.source().text;
+    let skipped = lex(source, tokens, comments)
+        .into_iter()
+        .flat_map(|token| token.leading.into_iter().chain(token.trailing))
+        .find(|trivia| trivia.kind == TriviaKind::Skipped);
+    if let Some(trivia) = skipped {
+        return Err(FormatError::SkippedText(trivia.text, trivia.span));
+    }
+    let mut formatter = Formatter::new(&session.source().text, tokens, comments);
+    formatter.program(program);
+    Ok(formatter.output)
+}
+
+struct Formatter<'a> {
+    source: &'a str,
+    lines: LineIndex,
+    tokens: &'a [SpannedToken],
+    comments: &'a [Comment],
+    /// Index of the next comment to write
+    next_comment: usize,
+    /// How each identifier is first spelled, since identifiers are case insensitive
+    spellings: HashMap<&'a str, &'a str>,
+    output: String,
+    indent: usize,
+    /// Source line of the end of the last line written
+    last_line: usize,
+    /// Whether nothing has been written in the current block yet, so blank lines are dropped
+    block_start: bool,
+}
+
+impl<'a> Formatter<'a> {
+    fn new(source: &'a str, tokens: &'a [SpannedToken], comments: &'a [Comment]) -> Self {
+        let mut spellings = HashMap::new();
+        for token in tokens {
+            if let Token::Identifier(name) = &token.token {
+                spellings
+                    .entry(name.as_str())
+                    .or_insert(&source[token.span.start..token.span.end]);
+            }
+        }
+        Formatter {
+            source,
+            lines: LineIndex::new(source),
+            tokens,
+            comments,
+            next_comment: 0,
+            spellings,
+            output: String::with_capacity(source.len()),
+            indent: 0,
+            last_line: 1,
+            block_start: true,
+        }
+    }
+
+    fn program(&mut self, program: &ProgramStruct) {
+        let header = &program.program_header;
+        let name = self.identifier(&header.header_identifier);
+        self.line(
+            header.span.start,
+            header.span.end,
+            format!("program {} is", name),
+        );
+        let body = &program.program_body;
+        self.block(
+            &body.declarations,
+            &body.statements,
+            header.span.end,
+            program.span.end,
+        );
+        self.closing_line(
+            self.closing(program.span.end),
+            program.span.end,
+            "end program.",
+        );
+
+        // Comments after the end of the program
+        self.comments_before(self.source.len() + 1);
+    }
+
+    /// Writes the declarations and statements of a program or procedure,
+    /// where `start` is the end of its header and `end` is the end of its `end` keywords
+    fn block(
+        &mut self,
+        declarations: &[Declaration],
+        statements: &[Statement],
+        start: usize,
+        end: usize,
+    ) {
+        let declarations_end = declarations.last().map_or(start, |last| last.span().end);
+        let (begin_start, begin_end) = self.find(Token::Begin, declarations_end, end);
+        self.indented(begin_start, |formatter| {
+            for declaration in declarations {
+                formatter.declaration(declaration);
+            }
+        });
+        self.closing_line(begin_start, begin_end, "begin");
+        self.statements(statements, self.closing(end));
+    }
+
+    fn declaration(&mut self, declaration: &Declaration) {
+        match declaration {
+            Declaration::Procedure(is_global, procedure) => self.procedure(*is_global, procedure),
+            Declaration::Variable(is_global, variable) => {
+                let text = format!("{}{};", global(*is_global), self.variable(variable));
+                self.line(declaration.span().start, declaration.span().end, text);
+            }
+            // Programs with syntax errors aren't formatted
+            Declaration::Error(_) => {}
+        }
+    }
+
+    fn procedure(&mut self, is_global: bool, procedure: &ProcedureDeclaration) {
+        let header = &procedure.procedure_header;
+        let params: Vec<String> = header
+            .param_list
+            .iter()
+            .flat_map(|params| params.param_list.iter())
+            .map(|param| self.variable(&param.variable_declaration))
+            .collect();
+        let text = format!(
+            "{}procedure {} : {}({})",
+            global(is_global),
+            self.identifier(&header.identifier),
+            expression::type_mark(&header.type_mark),
+            params.join(", ")
+        );
+        self.line(procedure.span.start, header.span.end, text);
+        let body = &procedure.procedure_body;
+        self.block(
+            &body.declarations,
+            &body.statements,
+            header.span.end,
+            procedure.span.end,
+        );
+        let end = procedure.span.end;
+        self.closing_line(self.closing(end), end, "end procedure;");
+    }
+
+    fn variable(&self, variable: &VariableDeclaration) -> String {
+        let bound = variable
+            .array_bound
+            .as_ref()
+            .map_or(String::new(), |bound| {
+                format!("[{}]", bound.number.literal_string)
+            });
+        format!(
+            "variable {} : {}{}",
+            self.identifier(&variable.identifier),
+            expression::type_mark(&variable.type_mark),
+            bound
+        )
+    }
+
+    /// Writes a block of statements, which is closed by a keyword at `end`
+    fn statements(&mut self, statements: &[Statement], end: usize) {
+        self.indented(end, |formatter| {
+            for statement in statements {
+                formatter.statement(statement);
+            }
+        });
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        let span = statement.span();
+        // The comments before the statement go on lines of their own, not in its expressions
+        self.comments_before(span.start);
+        match statement {
+            Statement::Assignment(assignment) => {
+                let text = format!("{};", self.assignment(assignment));
+                self.line(span.start, span.end, text);
+            }
+            Statement::Return(statement) => {
+                let text = format!("return {};", self.expression(&statement.expression));
+                self.line(span.start, span.end, text);
+            }
+            Statement::If(statement) => {
+                let condition = &statement.condition;
+                let text = format!("if ({}) then", self.expression(condition));
+                let (_, then_end) = self.find(Token::Then, condition.span().end, span.end);
+                self.line(span.start, then_end, text);
+                let end = self.closing(span.end);
+                match &statement.else_statement {
+                    Some(else_statement) => {
+                        let then_block_end = statement
+                            .then_statement
+                            .last()
+                            .map_or(then_end, |last| last.span().end);
+                        let (else_start, else_end) = self.find(Token::Else, then_block_end, end);
+                        self.statements(&statement.then_statement, else_start);
+                        self.closing_line(else_start, else_end, "else");
+                        self.statements(else_statement, end);
+                    }
+                    None => self.statements(&statement.then_statement, end),
+                }
+                self.closing_line(end, span.end, "end if;");
+            }
+            Statement::Loop(statement) => {
+                let condition = &statement.condition;
+                let text = format!(
+                    "for ({}; {})",
+                    self.assignment(&statement.assignment_statement),
+                    self.expression(condition)
+                );
+                let (_, header_end) = self.find(Token::RParen, condition.span().end, span.end);
+                self.line(span.start, header_end, text);
+                let end = self.closing(span.end);
+                self.statements(&statement.loop_body, end);
+                self.closing_line(end, span.end, "end for;");
+            }
+            Statement::Error(_) => {}
+        }
+    }
+
+    /// Writes an indented block, along with the comments in it before `end`
+    fn indented(&mut self, end: usize, write: impl FnOnce(&mut Self)) {
+        self.indent += 1;
+        self.block_start = true;
+        write(self);
+        self.comments_before(end);
+        self.indent -= 1;
+    }
+
+    /// Writes a line for the source from `start` to `end`, after the comments before it.
+    /// A comment after `end` on the same line is written at the end of it,
+    /// unless there's more source before the comment.
+    fn line(&mut self, start: usize, end: usize, mut text: String) {
+        self.comments_before(start);
+        self.blank_line(start);
+        // Comments before a token that wasn't written with one, like a closing parenthesis
+        while let Some(comment) = self
+            .comments
+            .get(self.next_comment)
+            .filter(|comment| comment.span.start < end)
+        {
+            text.push(' ');
+            text.push_str(&comment.text);
+            self.next_comment += 1;
+        }
+        self.write(&text);
+        self.last_line = self.line_of(end);
+
+        if let Some(comment) = self.comments.get(self.next_comment) {
+            // Terminators aren't on a line of their own, so can come before a trailing comment
+            let next_token = self
+                .tokens
+                .iter()
+                .skip_while(|spanned| spanned.span.start < end)
+                .find(|spanned| !matches!(spanned.token, Token::Semicolon | Token::Period))
+                .map_or(self.source.len(), |spanned| spanned.span.start);
+            if comment.span.line == self.last_line
+                && end <= comment.span.start
+                && comment.span.start < next_token
+            {
+                self.output.pop();
+                self.output.push(' ');
+                self.output.push_str(&comment.text);
+                self.output.push('\n');
+                self.last_line = self.line_of(comment.span.end);
+                self.next_comment += 1;
+            }
+        }
+    }
+
+    /// Writes a line that closes a block, like `end if;`, which never has a blank line before it
+    fn closing_line(&mut self, start: usize, end: usize, text: &str) {
+        self.comments_before(start);
+        self.block_start = true;
+        self.line(start, end, String::from(text));
+    }
+
+    /// The comments before `offset` that haven't been written yet, to write in a line before the token there.
+    /// A line comment ends the line, so the rest of it continues on the next line, indented once more.
+    fn inline_comments(&mut self, offset: usize) -> String {
+        let mut text = String::new();
+        while let Some(comment) = self
+            .comments
+            .get(self.next_comment)
+            .filter(|comment| comment.span.start < offset)
+        {
+            text.push_str(&comment.text);
+            match comment.text.starts_with("//") {
+                true => {
+                    text.push('\n');
+                    for _ in 0..=self.indent {
+                        text.push_str(INDENT);
+                    }
+                }
+                false => text.push(' '),
+            }
+            self.next_comment += 1;
+        }
+        text
+    }
+
+    fn comments_before(&mut self, offset: usize) {
+        while let Some(comment) = self.comments.get(self.next_comment) {
+            if comment.span.start >= offset {
+                break;
+            }
+            self.blank_line(comment.span.start);
+            self.write(&comment.text);
+            self.last_line = self.line_of(comment.span.end);
+            self.next_comment += 1;
+        }
+    }
+
+    /// Keeps a blank line before source at `offset`, if there is one
+    fn blank_line(&mut self, offset: usize) {
+        if !self.block_start && self.line_of(offset) > self.last_line + 1 {
+            self.output.push('\n');
+        }
+    }
+
+    fn write(&mut self, text: &str) {
+        for _ in 0..self.indent {
+            self.output.push_str(INDENT);
+        }
+        self.output.push_str(text);
+        self.output.push('\n');
+        self.block_start = false;
+    }
+
+    fn line_of(&self, offset: usize) -> usize {
+        self.lines.locate(self.source, offset).0
+    }
+
+    /// The start and end of the first `token` between `start` and `end`, or `end` if there isn't one
+    fn find(&self, token: Token, start: usize, end: usize) -> (usize, usize) {
+        self.tokens
+            .iter()
+            .skip_while(|spanned| spanned.span.start < start)
+            .take_while(|spanned| spanned.span.start < end)
+            .find(|spanned| spanned.token == token)
+            .map_or((end, end), |spanned| (spanned.span.start, spanned.span.end))
+    }
+
+    /// The start of the `end` keyword that closes a block ending at `end`, like the `end` of `end if`
+    fn closing(&self, end: usize) -> usize {
+        let last = self
+            .tokens
+            .partition_point(|spanned| spanned.span.end < end);
+        last.checked_sub(1)
+            .and_then(|index| self.tokens.get(index))
+            .map_or(end, |spanned| spanned.span.start)
+    }
+
+    fn identifier(&self, name: &str) -> &'a str {
+        match self.spellings.get(name) {
+            Some(spelling) => spelling,
+            // Every identifier in the tree came from a token
+            None => unreachable!("identifier {} has no token", name),
+        }
+    }
+}
+
+fn global(is_global: bool) -> &'static str {
+    match is_global {
+        true => "global ",
+        false => "",
+    }
+}
+
+#[cfg(test)]
+use rstest::rstest;
+#[cfg(test)]
+use std::path::PathBuf;
+
+#[cfg(test)]
+fn format_text(text: &str) -> String {
+    format(&mut Session::from_text("test.src", text)).unwrap()
+}
+
+#[cfg(test)]
+#[test]
+fn format_lays_out_program() {
+    let source = "PROGRAM Example IS
+// counts up
+Global Variable Count:INTEGER;variable Flags : bool[2];   // trailing
+
+
+PROCEDURE Bump : integer(variable by:integer,VARIABLE Again : BOOL)
+begin
+  if(again)then count:=count+by*2; else
+  count := -count;
+  end if;
+  for(by:=0;by < 10) /* loop */ count := count - (by + 1); end for;
+  return count;
+END PROCEDURE;
+begin
+
+flags[0] := not bump(1, true) & count >= 2 | false;
+
+/* done */
+end program
+
+// after the end
+";
+    let expected = "program Example is
+    // counts up
+    global variable Count : integer;
+    variable Flags : bool[2]; // trailing
+
+    procedure Bump : integer(variable by : integer, variable Again : bool)
+    begin
+        if (Again) then
+            Count := Count + by * 2;
+        else
+            Count := -Count;
+        end if;
+        for (by := 0; by < 10) /* loop */
+            Count := Count - (by + 1);
+        end for;
+        return Count;
+    end procedure;
+begin
+    Flags[0] := not Bump(1, true) & Count >= 2 | false;
+
+    /* done */
+end program.
+
+// after the end
+";
+    assert_eq!(format_text(source), expected);
+}
+
+#[cfg(test)]
+#[rstest]
+fn format_is_idempotent(#[files("tests/correct/*.src")] path: PathBuf) {
+    let source = std::fs::read_to_string(path).unwrap();
+    let mut session = Session::from_text("test.src", source.as_str());
+    let formatted = format(&mut session).unwrap();
+    assert_eq!(format_text(&formatted), formatted);
+
+    // Nothing is lost
+    let comments = |session: &Session| -> Vec<String> {
+        let comments = session.comments().unwrap();
+        comments
+            .iter()
+            .map(|comment| comment.text.clone())
+            .collect()
+    };
+    let mut reformatted = Session::from_text("formatted.src", formatted.as_str());
+    reformatted.parse().unwrap();
+    assert_eq!(comments(&reformatted), comments(&session));
+    let tokens = |session: &Session| -> Vec<Token> {
+        let tokens = session.tokens().unwrap();
+        tokens
+            .iter()
+            .map(|token| token.token.clone())
+            .filter(|token| *token != Token::Period)
+            .collect()
+    };
+    assert_eq!(tokens(&reformatted), tokens(&session));
+}
+
+#[cfg(test)]
+#[test]
+fn format_rejects_syntax_errors() {
+    let mut session = Session::from_text("test.src", "program broken is begin x := ; end program.");
+    assert_eq!(format(&mut session), Err(FormatError::Failed(Phase::Parse)));
+}
+
+#[cfg(test)]
+#[test]
+fn format_rejects_skipped_text() {
+    let mut session = Session::from_text(
+        "test.src",
+        "program skipped is begin x := 1; @ # $ end program.",
+    );
+    assert!(matches!(
+        format(&mut session),
+        Err(FormatError::SkippedText(text, _)) if text == "@"
+    ));
+}
+
+#[cfg(test)]
+#[test]
+fn format_keeps_comments_inside_expressions() {
+    let source = "program g is begin
+a:=2+/* two */3;
+a := 2 // two
++ 3;
+a := (/* one */ 1 + 2) * f(/* x */ a /* last */);
+end program.
+";
+    let expected = "program g is
+begin
+    a := 2 + /* two */ 3;
+    a := 2 // two
+        + 3;
+    a := (/* one */ 1 + 2) * f(/* x */ a); /* last */
+end program.
+";
+    assert_eq!(format_text(source), expected);
+}
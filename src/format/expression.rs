@@ -0,0 +1,130 @@
+use crate::parser::expression::{ArtihOp, Expression, Factor, Name, Relation, Term};
+use crate::parser::statement::{AssignmentStatement, Destination};
+use crate::parser::types::TypeMark;
+use crate::span::Span;
+use crate::tokens::Token;
+
+use super::Formatter;
+
+pub(super) fn type_mark(type_mark: &TypeMark) -> &'static str {
+    match type_mark {
+        TypeMark::Integer => "integer",
+        TypeMark::Float => "float",
+        TypeMark::String => "string",
+        TypeMark::Bool => "bool",
+    }
+}
+
+impl Formatter<'_> {
+    pub(super) fn assignment(&mut self, assignment: &AssignmentStatement) -> String {
+        format!(
+            "{} := {}",
+            self.destination(&assignment.destination),
+            self.expression(&assignment.expression)
+        )
+    }
+
+    fn destination(&mut self, destination: &Destination) -> String {
+        let identifier = self.identifier(&destination.identifier.identifier_string);
+        match &destination.expression {
+            Some(index) => format!("{}[{}]", identifier, self.expression(index)),
+            None => String::from(identifier),
+        }
+    }
+
+    pub(super) fn expression(&mut self, expression: &Expression) -> String {
+        let (lhs, operator, token, arith_op) = match expression {
+            Expression::AndExp(lhs, arith_op) => (lhs, "&", Token::Amp, arith_op),
+            Expression::OrExp(lhs, arith_op) => (lhs, "|", Token::Pipe, arith_op),
+            Expression::NotExp(arith_op) => return format!("not {}", self.arith_op(arith_op)),
+            Expression::BasicExp(arith_op) => return self.arith_op(arith_op),
+        };
+        let lhs_text = self.expression(lhs);
+        let operator = self.operator(operator, token, lhs.span().end, arith_op.span().start);
+        format!("{} {} {}", lhs_text, operator, self.arith_op(arith_op))
+    }
+
+    fn arith_op(&mut self, arith_op: &ArtihOp) -> String {
+        let (lhs, operator, token, relation) = match arith_op {
+            ArtihOp::AddOp(lhs, relation) => (lhs, "+", Token::Plus, relation),
+            ArtihOp::SubOp(lhs, relation) => (lhs, "-", Token::Minus, relation),
+            ArtihOp::Relation(relation) => return self.relation(relation),
+        };
+        let lhs_text = self.arith_op(lhs);
+        let operator = self.operator(operator, token, lhs.span().end, relation.span().start);
+        format!("{} {} {}", lhs_text, operator, self.relation(relation))
+    }
+
+    fn relation(&mut self, relation: &Relation) -> String {
+        let (lhs, operator, token, term) = match relation {
+            Relation::LessThan(lhs, term) => (lhs, "<", Token::LessThan, term),
+            Relation::LessThanEq(lhs, term) => (lhs, "<=", Token::LessThanEq, term),
+            Relation::GreaterThan(lhs, term) => (lhs, ">", Token::GreaterThan, term),
+            Relation::GreaterThanEq(lhs, term) => (lhs, ">=", Token::GreaterThanEq, term),
+            Relation::Equals(lhs, term) => (lhs, "==", Token::EqualsComp, term),
+            Relation::NotEquals(lhs, term) => (lhs, "!=", Token::NotEquals, term),
+            Relation::Term(term) => return self.term(term),
+        };
+        let lhs_text = self.relation(lhs);
+        let operator = self.operator(operator, token, lhs.span().end, term.span().start);
+        format!("{} {} {}", lhs_text, operator, self.term(term))
+    }
+
+    fn term(&mut self, term: &Term) -> String {
+        let (lhs, operator, token, factor) = match term {
+            Term::MultTerm(lhs, factor) => (lhs, "*", Token::Mult, factor),
+            Term::DivTerm(lhs, factor) => (lhs, "/", Token::Div, factor),
+            Term::Factor(factor) => return self.factor(factor),
+        };
+        let lhs_text = self.term(lhs);
+        let operator = self.operator(operator, token, lhs.span().end, factor.span().start);
+        format!("{} {} {}", lhs_text, operator, self.factor(factor))
+    }
+
+    /// The operator between `start` and `end`, after the comments before it
+    fn operator(&mut self, operator: &str, token: Token, start: usize, end: usize) -> String {
+        let (offset, _) = self.find(token, start, end);
+        format!("{}{}", self.inline_comments(offset), operator)
+    }
+
+    fn factor(&mut self, factor: &Factor) -> String {
+        let sign = |negate: &Option<Span>| if negate.is_some() { "-" } else { "" };
+        // A nested expression's comments are written before the tokens inside it
+        let comments = match factor {
+            Factor::NestedExpression(_) => String::new(),
+            _ => self.inline_comments(factor.span().start),
+        };
+        let text = match factor {
+            Factor::NestedExpression(expression) => format!("({})", self.expression(expression)),
+            Factor::ProcedureCall(call) => {
+                let args: Vec<String> = call
+                    .arg_list
+                    .iter()
+                    .flat_map(|args| args.expr_list.iter())
+                    .map(|arg| self.expression(arg))
+                    .collect();
+                format!(
+                    "{}({})",
+                    self.identifier(&call.identifier.identifier_string),
+                    args.join(", ")
+                )
+            }
+            Factor::Name { negate, name } => format!("{}{}", sign(negate), self.name(name)),
+            Factor::Number { negate, number } => {
+                format!("{}{}", sign(negate), number.literal_string)
+            }
+            Factor::String(string) => format!("\"{}\"", string.literal_string),
+            Factor::TrueLit(_) => String::from("true"),
+            Factor::FalseLit(_) => String::from("false"),
+        };
+        comments + &text
+    }
+
+    fn name(&mut self, name: &Name) -> String {
+        let identifier = self.identifier(&name.identifier.identifier_string);
+        match &name.expression {
+            Some(index) => format!("{}[{}]", identifier, self.expression(index)),
+            None => String::from(identifier),
+        }
+    }
+}
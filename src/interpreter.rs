@@ -0,0 +1,440 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::ops::ControlFlow;
+
+use thiserror::Error;
+
+pub mod builtins;
+pub mod expression;
+pub mod statement;
+pub mod traits;
+pub mod value;
+
+use crate::semantics::context::{Binding, ScopeContext};
+use crate::semantics::procedure::{AnalyzedProcedure, CallResolver};
+use crate::semantics::AnalyzedProgram;
+use crate::span::Span;
+
+use self::builtins::call_builtin;
+use self::traits::Execute;
+use self::value::Value;
+
+/// Calls nested deeper than this are reported as a stack overflow,
+/// rather than overflowing the interpreter's own stack.
+pub const MAX_CALL_DEPTH: usize = 10_000;
+
+#[derive(Debug, Error)]
+pub enum RuntimeError {
+    #[error("Attempted to divide by zero.")]
+    DivisionByZero(Span),
+    #[error("Index {0} is out of bounds for an array of length {1}.")]
+    IndexOutOfBounds(i64, usize, Span),
+    #[error("Index {1} is out of bounds for array {0} of length {2}.")]
+    ArrayIndexOutOfBounds(String, i64, usize, Span),
+    #[error("Attempted to divide {0} by zero.")]
+    IntegerDivisionByZero(i64, Span),
+    #[error("Integer overflow computing {0}.")]
+    IntegerOverflow(String, Span),
+    #[error("Invalid operands {0:?} and {1:?}.")]
+    InvalidOperands(Value, Value, Span),
+    #[error("Invalid operand {0:?}.")]
+    InvalidOperand(Value, Span),
+    #[error("Could not read a {0} from input.")]
+    InvalidInput(String, Span),
+    #[error("Exceeded the maximum call depth of {0}.")]
+    StackOverflow(usize, Span),
+    #[error("Undeclared reference {0}")]
+    UndefinedRef(String, Span),
+    #[error("{0}")]
+    IoError(io::Error, Span),
+    #[error("Invalid bytecode: {0}.")]
+    InvalidBytecode(String, Span),
+}
+
+impl RuntimeError {
+    pub fn span(&self) -> Span {
+        match self {
+            RuntimeError::DivisionByZero(span)
+            | RuntimeError::IndexOutOfBounds(_, _, span)
+            | RuntimeError::ArrayIndexOutOfBounds(_, _, _, span)
+            | RuntimeError::IntegerDivisionByZero(_, span)
+            | RuntimeError::IntegerOverflow(_, span)
+            | RuntimeError::InvalidOperands(_, _, span)
+            | RuntimeError::InvalidOperand(_, span)
+            | RuntimeError::InvalidInput(_, span)
+            | RuntimeError::StackOverflow(_, span)
+            | RuntimeError::UndefinedRef(_, span)
+            | RuntimeError::IoError(_, span)
+            | RuntimeError::InvalidBytecode(_, span) => *span,
+        }
+    }
+}
+
+/// Guards added to every array index, integer division and integer operation, as chosen with `--checks`.
+/// Indices and divisors are always checked, but only these name the array or the operands.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Checks {
+    pub bounds: bool,
+    pub division: bool,
+    /// Integer results that don't fit in 64 bits fail instead of wrapping
+    pub overflow: bool,
+}
+
+impl Checks {
+    /// Turns on the check called `name`, returning false if there is no such check
+    pub fn enable(&mut self, name: &str) -> bool {
+        let check = match name {
+            "bounds" => &mut self.bounds,
+            "div0" => &mut self.division,
+            "overflow" => &mut self.overflow,
+            _ => return false,
+        };
+        *check = true;
+        true
+    }
+
+    pub fn any(&self) -> bool {
+        self.bounds || self.division || self.overflow
+    }
+}
+
+/// Variables of a single procedure call
+#[derive(Debug, Default)]
+struct Frame<'a> {
+    variables: HashMap<String, Value>,
+    /// The procedure called, or `None` for the program body
+    procedure: Option<&'a AnalyzedProcedure>,
+    /// The static link: the index of the frame of the procedure this one is nested in,
+    /// or `None` for procedures in the global scope
+    parent: Option<usize>,
+}
+
+impl<'a> Frame<'a> {
+    fn new(procedure: &'a AnalyzedProcedure, args: Vec<Value>, parent: Option<usize>) -> Self {
+        let mut variables = default_variables(&procedure.declarations);
+        for (arg, value) in procedure.arg_list.iter().zip(args) {
+            variables.insert(arg.0.clone(), value);
+        }
+        Frame {
+            variables,
+            procedure: Some(procedure),
+            parent,
+        }
+    }
+}
+
+fn default_variables(scope: &ScopeContext) -> HashMap<String, Value> {
+    scope
+        .variables
+        .iter()
+        .map(|(identifier, value_type)| (identifier.clone(), Value::default_for(value_type)))
+        .collect()
+}
+
+/// Runs an `AnalyzedProgram` by walking its tree.
+pub struct Interpreter<'a> {
+    program: &'a AnalyzedProgram,
+    globals: HashMap<String, Value>,
+    procedures: CallResolver<'a>,
+    frames: Vec<Frame<'a>>,
+    input: Box<dyn BufRead + 'a>,
+    output: Box<dyn Write + 'a>,
+    checks: Checks,
+}
+
+impl<'a> Interpreter<'a> {
+    pub fn new(
+        program: &'a AnalyzedProgram,
+        input: Box<dyn BufRead + 'a>,
+        output: Box<dyn Write + 'a>,
+    ) -> Self {
+        Interpreter {
+            program,
+            globals: default_variables(&program.declarations),
+            procedures: CallResolver::new(&program.procedures),
+            // The program body runs in a frame of its own, with no locals
+            frames: vec![Frame::default()],
+            input,
+            output,
+            checks: Checks::default(),
+        }
+    }
+
+    pub fn with_checks(mut self, checks: Checks) -> Self {
+        self.checks = checks;
+        self
+    }
+
+    pub fn run(mut self) -> Result<(), RuntimeError> {
+        let program = self.program;
+        // Returning from the program body is rejected by the analyzer, so it always falls through
+        let _ = program.block.execute(&mut self)?;
+        self.output
+            .flush()
+            .map_err(|err| RuntimeError::IoError(err, program.span))
+    }
+
+    /// The index of the frame `depth` static links out from the current one
+    fn enclosing_frame(&self, depth: usize) -> Option<usize> {
+        // There is always at least the program body's frame
+        let mut index = self.frames.len() - 1;
+        for _ in 0..depth {
+            index = self.frames[index].parent?;
+        }
+        Some(index)
+    }
+
+    /// Looks up a variable where the analyzer found it
+    pub fn get_variable(
+        &self,
+        identifier: &str,
+        binding: Binding,
+        span: Span,
+    ) -> Result<&Value, RuntimeError> {
+        let variables = match binding {
+            Binding::Global => Some(&self.globals),
+            Binding::Local(depth) => self
+                .enclosing_frame(depth)
+                .map(|index| &self.frames[index].variables),
+        };
+        variables
+            .and_then(|variables| variables.get(identifier))
+            .ok_or_else(|| RuntimeError::UndefinedRef(String::from(identifier), span))
+    }
+
+    pub fn get_variable_mut(
+        &mut self,
+        identifier: &str,
+        binding: Binding,
+        span: Span,
+    ) -> Result<&mut Value, RuntimeError> {
+        let variables = match binding {
+            Binding::Global => Some(&mut self.globals),
+            Binding::Local(depth) => self
+                .enclosing_frame(depth)
+                .map(|index| &mut self.frames[index].variables),
+        };
+        variables
+            .and_then(|variables| variables.get_mut(identifier))
+            .ok_or_else(|| RuntimeError::UndefinedRef(String::from(identifier), span))
+    }
+
+    /// Names the array `identifier` in an out of bounds error from indexing it, if bounds are checked
+    pub fn index_error(&self, identifier: &str, err: RuntimeError) -> RuntimeError {
+        match err {
+            RuntimeError::IndexOutOfBounds(index, len, span) if self.checks.bounds => {
+                RuntimeError::ArrayIndexOutOfBounds(String::from(identifier), index, len, span)
+            }
+            err => err,
+        }
+    }
+
+    /// Runs a procedure in a new frame, linked to the frame of the procedure it is declared in,
+    /// or runs the builtin of that name if the program has no such procedure
+    pub fn call(
+        &mut self,
+        identifier: &str,
+        binding: Binding,
+        args: Vec<Value>,
+        span: Span,
+    ) -> Result<Value, RuntimeError> {
+        // Nested procedures link to the frame of the procedure they're declared in
+        let procedure = match binding {
+            Binding::Global => self
+                .procedures
+                .global(identifier)
+                .map(|procedure| (procedure, None)),
+            Binding::Local(depth) => self.enclosing_frame(depth).and_then(|index| {
+                self.frames[index]
+                    .procedure
+                    .and_then(|parent| CallResolver::nested(parent, identifier))
+                    .map(|procedure| (procedure, Some(index)))
+            }),
+        };
+
+        let Some((procedure, parent)) = procedure else {
+            return call_builtin(&mut self.input, &mut self.output, identifier, &args, span)
+                .unwrap_or_else(|| {
+                    Err(RuntimeError::UndefinedRef(String::from(identifier), span))
+                });
+        };
+
+        if self.frames.len() > MAX_CALL_DEPTH {
+            return Err(RuntimeError::StackOverflow(MAX_CALL_DEPTH, span));
+        }
+
+        self.frames.push(Frame::new(procedure, args, parent));
+        let result = procedure.block.execute(self);
+        self.frames.pop();
+
+        match result? {
+            ControlFlow::Break(value) => Ok(value),
+            // Falling off the end of a procedure returns the default for its type
+            ControlFlow::Continue(()) => {
+                Ok(Value::default_for(&procedure.declarations.return_type))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+use crate::session::{with_test_source, NESTED_RUN, TEST_RUNS};
+#[cfg(test)]
+use rstest::rstest;
+
+/// Compiles and runs a test program, returning what it wrote to stdout
+#[cfg(test)]
+fn run_test_program(path: &str, input: &str) -> Result<String, RuntimeError> {
+    run_source(std::fs::read_to_string(path).unwrap(), input)
+}
+
+#[cfg(test)]
+fn run_source(source: String, input: &str) -> Result<String, RuntimeError> {
+    run_checked_source(source, input, Checks::default())
+}
+
+#[cfg(test)]
+fn run_checked_source(source: String, input: &str, checks: Checks) -> Result<String, RuntimeError> {
+    with_test_source(&source, |program| {
+        let mut output = Vec::new();
+        Interpreter::new(program, Box::new(input.as_bytes()), Box::new(&mut output))
+            .with_checks(checks)
+            .run()?;
+        Ok(String::from_utf8(output).unwrap())
+    })
+}
+
+#[cfg(test)]
+#[test]
+fn interpret_test_programs() {
+    for &(path, input, expected) in TEST_RUNS {
+        assert_eq!(run_test_program(path, input).unwrap(), expected, "{}", path);
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn interpret_reports_bad_input() {
+    let result = run_test_program("tests/correct/recursiveFib.src", "five\n");
+    assert!(matches!(result, Err(RuntimeError::InvalidInput(..))));
+}
+
+#[cfg(test)]
+#[test]
+fn interpret_nested_procedures_use_enclosing_locals() {
+    let (path, input, expected) = NESTED_RUN;
+    assert_eq!(run_test_program(path, input).unwrap(), expected);
+}
+
+/// Runs `statement` after reading `n` from `input`.
+/// `n` is read so that nothing can be folded or checked before the program runs.
+#[cfg(test)]
+fn run_trap_program(statement: &str, input: &str, checks: Checks) -> Result<String, RuntimeError> {
+    let source = format!(
+        "program traps is
+    variable n : integer;
+    variable a : integer[4];
+    variable printed : bool;
+begin
+    n := getinteger();
+    {}
+end program.
+",
+        statement
+    );
+    run_checked_source(source, input, checks)
+}
+
+#[cfg(test)]
+const ALL_CHECKS: Checks = Checks {
+    bounds: true,
+    division: true,
+    overflow: true,
+};
+
+#[cfg(test)]
+#[rstest]
+#[case(
+    "a[n] := 1;",
+    "4\n",
+    "Index 4 is out of bounds for array a of length 4."
+)]
+#[case(
+    "printed := putinteger(a[n - 5]);",
+    "3\n",
+    "Index -2 is out of bounds for array a of length 4."
+)]
+#[case(
+    "printed := putinteger(10 / n);",
+    "0\n",
+    "Attempted to divide 10 by zero."
+)]
+#[case("a := a / n;", "0\n", "Attempted to divide 0 by zero.")]
+#[case(
+    "printed := putinteger(n * 2);",
+    "9223372036854775807\n",
+    "Integer overflow computing 9223372036854775807 * 2."
+)]
+#[case(
+    "n := n + 1;",
+    "9223372036854775807\n",
+    "Integer overflow computing 9223372036854775807 + 1."
+)]
+#[case(
+    "n := n - 1 - 9223372036854775807; n := -n;",
+    "0\n",
+    "Integer overflow computing -(-9223372036854775808)."
+)]
+fn interpret_traps_failed_checks(
+    #[case] statement: &str,
+    #[case] input: &str,
+    #[case] message: &str,
+) {
+    let err = run_trap_program(statement, input, ALL_CHECKS).unwrap_err();
+    assert_eq!(err.to_string(), message);
+    assert_eq!(err.span().line, 7);
+}
+
+#[cfg(test)]
+#[rstest]
+#[case(
+    "a[n] := 1;",
+    "4\n",
+    Err("Index 4 is out of bounds for an array of length 4.")
+)]
+#[case(
+    "printed := putinteger(10 / n);",
+    "0\n",
+    Err("Attempted to divide by zero.")
+)]
+#[case(
+    "printed := putinteger(n + 1);",
+    "9223372036854775807\n",
+    Ok("-9223372036854775808\n")
+)]
+fn interpret_without_checks(
+    #[case] statement: &str,
+    #[case] input: &str,
+    #[case] expected: Result<&str, &str>,
+) {
+    let result = run_trap_program(statement, input, Checks::default());
+    assert_eq!(
+        result.as_deref().map_err(|err| err.to_string()),
+        expected.map_err(String::from)
+    );
+}
+
+#[cfg(test)]
+#[test]
+fn interpret_only_enabled_checks() {
+    let mut checks = Checks::default();
+    assert!(checks.enable("div0"));
+    assert!(!checks.enable("nan"));
+    let result = run_trap_program("n := n * n;", "4294967296\n", checks);
+    assert!(result.is_ok());
+    let result = run_trap_program("a[0] := 1 / (n - n);", "3\n", checks);
+    assert!(matches!(
+        result,
+        Err(RuntimeError::IntegerDivisionByZero(1, _))
+    ));
+}
@@ -0,0 +1,67 @@
+use std::io::{self, BufRead, Write};
+
+use crate::span::Span;
+
+use super::value::Value;
+use super::RuntimeError;
+
+/// Calls one of the builtin procedures declared in `ScopeContext::new_global_ctx`,
+/// with the behaviour defined by `crust_runtime`.
+/// Returns `None` if `identifier` is not a builtin.
+pub fn call_builtin<'a>(
+    input: &mut Box<dyn BufRead + 'a>,
+    output: &mut Box<dyn Write + 'a>,
+    identifier: &str,
+    args: &[Value],
+    span: Span,
+) -> Option<Result<Value, RuntimeError>> {
+    let result = match (identifier, args) {
+        ("getbool", []) => {
+            read_value(input, output, "bool", span, crust_runtime::read_bool).map(Value::Bool)
+        }
+        ("getinteger", []) => {
+            read_value(input, output, "integer", span, crust_runtime::read_integer).map(Value::Int)
+        }
+        ("getfloat", []) => {
+            read_value(input, output, "float", span, crust_runtime::read_float).map(Value::Float)
+        }
+        ("getstring", []) => read_value(input, output, "string", span, |input| {
+            crust_runtime::read_string(input).map(Some)
+        })
+        .map(Value::String),
+        // The put procedures return whether writing succeeded
+        ("putbool", [Value::Bool(value)]) => Ok(Value::Bool(
+            crust_runtime::write_bool(output, *value).is_ok(),
+        )),
+        ("putinteger", [Value::Int(value)]) => Ok(Value::Bool(
+            crust_runtime::write_integer(output, *value).is_ok(),
+        )),
+        ("putfloat", [Value::Float(value)]) => Ok(Value::Bool(
+            crust_runtime::write_float(output, *value).is_ok(),
+        )),
+        ("putstring", [Value::String(value)]) => Ok(Value::Bool(
+            crust_runtime::write_string(output, value).is_ok(),
+        )),
+        ("sqrt", [Value::Int(value)]) => Ok(Value::Float(crust_runtime::sqrt(*value))),
+        _ => return None,
+    };
+    Some(result)
+}
+
+/// Reads a value with `read`, which gives `None` for malformed input or the end of input.
+fn read_value<'a, T>(
+    input: &mut Box<dyn BufRead + 'a>,
+    output: &mut Box<dyn Write + 'a>,
+    expected: &str,
+    span: Span,
+    read: impl FnOnce(&mut Box<dyn BufRead + 'a>) -> io::Result<Option<T>>,
+) -> Result<T, RuntimeError> {
+    // Make sure any prompt has been written before waiting on input
+    output
+        .flush()
+        .map_err(|err| RuntimeError::IoError(err, span))?;
+
+    read(input)
+        .map_err(|err| RuntimeError::IoError(err, span))?
+        .ok_or_else(|| RuntimeError::InvalidInput(String::from(expected), span))
+}
@@ -0,0 +1,186 @@
+use crate::semantics::expression::{
+    AnalyzedArithOp, AnalyzedExpression, AnalyzedFactor, AnalyzedName, AnalyzedNumber,
+    AnalyzedRelation, AnalyzedTerm,
+};
+use crate::semantics::procedure::AnalyzedProcedureCall;
+
+use super::traits::Evaluate;
+use super::value::{ArithOperator, Comparison, Value};
+use super::{Interpreter, RuntimeError};
+
+impl Evaluate for AnalyzedExpression {
+    fn evaluate(&self, interpreter: &mut Interpreter) -> Result<Value, RuntimeError> {
+        let span = self.span();
+        // Both operands are always evaluated, since either may call a procedure
+        match self {
+            AnalyzedExpression::BitwiseAnd(expression, arith_op)
+            | AnalyzedExpression::BitwiseOr(expression, arith_op)
+            | AnalyzedExpression::LogicalAnd(expression, arith_op)
+            | AnalyzedExpression::LogicalOr(expression, arith_op) => {
+                let lhs = expression.evaluate(interpreter)?;
+                let rhs = arith_op.evaluate(interpreter)?;
+                match (self, lhs, rhs) {
+                    (AnalyzedExpression::BitwiseAnd(..), Value::Int(lhs), Value::Int(rhs)) => {
+                        Ok(Value::Int(lhs & rhs))
+                    }
+                    (AnalyzedExpression::BitwiseOr(..), Value::Int(lhs), Value::Int(rhs)) => {
+                        Ok(Value::Int(lhs | rhs))
+                    }
+                    (AnalyzedExpression::LogicalAnd(..), Value::Bool(lhs), Value::Bool(rhs)) => {
+                        Ok(Value::Bool(lhs && rhs))
+                    }
+                    (AnalyzedExpression::LogicalOr(..), Value::Bool(lhs), Value::Bool(rhs)) => {
+                        Ok(Value::Bool(lhs || rhs))
+                    }
+                    (_, lhs, rhs) => Err(RuntimeError::InvalidOperands(lhs, rhs, span)),
+                }
+            }
+            AnalyzedExpression::BitwiseNot(arith_op) => match arith_op.evaluate(interpreter)? {
+                Value::Int(value) => Ok(Value::Int(!value)),
+                value => Err(RuntimeError::InvalidOperand(value, span)),
+            },
+            AnalyzedExpression::LogicalNot(arith_op) => match arith_op.evaluate(interpreter)? {
+                Value::Bool(value) => Ok(Value::Bool(!value)),
+                value => Err(RuntimeError::InvalidOperand(value, span)),
+            },
+            AnalyzedExpression::Cast(expression, value_type) => {
+                Ok(expression.evaluate(interpreter)?.cast(value_type))
+            }
+            AnalyzedExpression::ArithOp(arith_op) => arith_op.evaluate(interpreter),
+        }
+    }
+}
+
+impl Evaluate for AnalyzedArithOp {
+    fn evaluate(&self, interpreter: &mut Interpreter) -> Result<Value, RuntimeError> {
+        // Array operands are broadcast by `Value::arith`, so the array variants need no special handling
+        let (arith_op, relation, operator) = match self {
+            AnalyzedArithOp::Plus(arith_op, relation)
+            | AnalyzedArithOp::ArrayScalarPlus(arith_op, relation)
+            | AnalyzedArithOp::ScalarArrayPlus(arith_op, relation)
+            | AnalyzedArithOp::ArrayPlus(arith_op, relation) => {
+                (arith_op, relation, ArithOperator::Add)
+            }
+            AnalyzedArithOp::Minus(arith_op, relation)
+            | AnalyzedArithOp::ArrayScalarMinus(arith_op, relation)
+            | AnalyzedArithOp::ScalarArrayMinus(arith_op, relation)
+            | AnalyzedArithOp::ArrayMinus(arith_op, relation) => {
+                (arith_op, relation, ArithOperator::Subtract)
+            }
+            AnalyzedArithOp::Cast(arith_op, value_type) => {
+                return Ok(arith_op.evaluate(interpreter)?.cast(value_type))
+            }
+            AnalyzedArithOp::Relation(relation) => return relation.evaluate(interpreter),
+        };
+        let lhs = arith_op.evaluate(interpreter)?;
+        let rhs = relation.evaluate(interpreter)?;
+        lhs.checked_arith(operator, rhs, interpreter.checks, self.span())
+    }
+}
+
+impl Evaluate for AnalyzedRelation {
+    fn evaluate(&self, interpreter: &mut Interpreter) -> Result<Value, RuntimeError> {
+        let (relation, term, comparison) = match self {
+            AnalyzedRelation::LessThan(relation, term) => (relation, term, Comparison::LessThan),
+            AnalyzedRelation::LessThanEq(relation, term) => {
+                (relation, term, Comparison::LessThanEq)
+            }
+            AnalyzedRelation::GreaterThan(relation, term) => {
+                (relation, term, Comparison::GreaterThan)
+            }
+            AnalyzedRelation::GreaterThanEq(relation, term) => {
+                (relation, term, Comparison::GreaterThanEq)
+            }
+            AnalyzedRelation::Equals(relation, term) => (relation, term, Comparison::Equals),
+            AnalyzedRelation::NotEquals(relation, term) => (relation, term, Comparison::NotEquals),
+            AnalyzedRelation::Cast(relation, value_type) => {
+                return Ok(relation.evaluate(interpreter)?.cast(value_type))
+            }
+            AnalyzedRelation::Term(term) => return term.evaluate(interpreter),
+        };
+        let lhs = relation.evaluate(interpreter)?;
+        let rhs = term.evaluate(interpreter)?;
+        lhs.compare(comparison, rhs, self.span())
+    }
+}
+
+impl Evaluate for AnalyzedTerm {
+    fn evaluate(&self, interpreter: &mut Interpreter) -> Result<Value, RuntimeError> {
+        let (term, factor, operator) = match self {
+            AnalyzedTerm::Multiply(term, factor)
+            | AnalyzedTerm::ArrayScalarMultiply(term, factor)
+            | AnalyzedTerm::ScalarArrayMultiply(term, factor)
+            | AnalyzedTerm::ArrayMultiply(term, factor) => (term, factor, ArithOperator::Multiply),
+            AnalyzedTerm::Divide(term, factor)
+            | AnalyzedTerm::ArrayScalarDivide(term, factor)
+            | AnalyzedTerm::ScalarArrayDivide(term, factor)
+            | AnalyzedTerm::ArrayDivide(term, factor) => (term, factor, ArithOperator::Divide),
+            AnalyzedTerm::Cast(term, value_type) => {
+                return Ok(term.evaluate(interpreter)?.cast(value_type))
+            }
+            AnalyzedTerm::Factor(factor) => return factor.evaluate(interpreter),
+        };
+        let lhs = term.evaluate(interpreter)?;
+        let rhs = factor.evaluate(interpreter)?;
+        lhs.checked_arith(operator, rhs, interpreter.checks, self.span())
+    }
+}
+
+impl Evaluate for AnalyzedFactor {
+    fn evaluate(&self, interpreter: &mut Interpreter) -> Result<Value, RuntimeError> {
+        match self {
+            AnalyzedFactor::NestedExpression(expression) => expression.evaluate(interpreter),
+            AnalyzedFactor::ProcedureCall(proc_call) => proc_call.evaluate(interpreter),
+            AnalyzedFactor::Name(name) => name.evaluate(interpreter),
+            AnalyzedFactor::NegatedName(name) => name
+                .evaluate(interpreter)?
+                .checked_negate(interpreter.checks, name.span()),
+            AnalyzedFactor::Number(number, _) => Ok(number.into()),
+            AnalyzedFactor::NegatedNumber(number, span) => Value::from(number).negate(*span),
+            AnalyzedFactor::String(value, _) => Ok(Value::String(value.clone())),
+            AnalyzedFactor::True(_) => Ok(Value::Bool(true)),
+            AnalyzedFactor::False(_) => Ok(Value::Bool(false)),
+            AnalyzedFactor::Cast(factor, value_type) => {
+                Ok(factor.evaluate(interpreter)?.cast(value_type))
+            }
+        }
+    }
+}
+
+impl From<&AnalyzedNumber> for Value {
+    fn from(value: &AnalyzedNumber) -> Self {
+        match value {
+            AnalyzedNumber::Integer(value) => Value::Int(*value),
+            AnalyzedNumber::Float(value) => Value::Float(*value),
+        }
+    }
+}
+
+impl Evaluate for AnalyzedName {
+    fn evaluate(&self, interpreter: &mut Interpreter) -> Result<Value, RuntimeError> {
+        match self {
+            AnalyzedName::Name(identifier, binding, span) => interpreter
+                .get_variable(identifier, *binding, *span)
+                .cloned(),
+            AnalyzedName::Indexed(identifier, binding, expression, span) => {
+                let index = expression.evaluate(interpreter)?;
+                let result = interpreter
+                    .get_variable(identifier, *binding, *span)?
+                    .index(&index, expression.span())
+                    .cloned();
+                result.map_err(|err| interpreter.index_error(identifier, err))
+            }
+        }
+    }
+}
+
+impl Evaluate for AnalyzedProcedureCall {
+    fn evaluate(&self, interpreter: &mut Interpreter) -> Result<Value, RuntimeError> {
+        let args = self
+            .arg_list
+            .iter()
+            .map(|arg| arg.evaluate(interpreter))
+            .collect::<Result<Vec<Value>, RuntimeError>>()?;
+        interpreter.call(&self.identifier, self.binding, args, self.span)
+    }
+}
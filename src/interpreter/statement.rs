@@ -0,0 +1,88 @@
+use std::ops::ControlFlow;
+
+use crate::semantics::statement::{
+    AnalyzedAssignment, AnalyzedBlock, AnalyzedIf, AnalyzedLoop, AnalyzedReturn, AnalyzedStatement,
+};
+
+use super::traits::{Evaluate, Execute};
+use super::value::Value;
+use super::{Interpreter, RuntimeError};
+
+impl Execute for AnalyzedBlock {
+    fn execute(&self, interpreter: &mut Interpreter) -> Result<ControlFlow<Value>, RuntimeError> {
+        for statement in self.0.iter() {
+            if let ControlFlow::Break(value) = statement.execute(interpreter)? {
+                return Ok(ControlFlow::Break(value));
+            }
+        }
+        Ok(ControlFlow::Continue(()))
+    }
+}
+
+impl Execute for AnalyzedStatement {
+    fn execute(&self, interpreter: &mut Interpreter) -> Result<ControlFlow<Value>, RuntimeError> {
+        match self {
+            AnalyzedStatement::Assignment(statement) => statement.execute(interpreter),
+            AnalyzedStatement::If(statement) => statement.execute(interpreter),
+            AnalyzedStatement::Loop(statement) => statement.execute(interpreter),
+            AnalyzedStatement::Return(statement) => statement.execute(interpreter),
+        }
+    }
+}
+
+impl Execute for AnalyzedAssignment {
+    fn execute(&self, interpreter: &mut Interpreter) -> Result<ControlFlow<Value>, RuntimeError> {
+        let destination = &self.destination;
+        let index = destination
+            .expression
+            .as_ref()
+            .map(|expression| Ok((expression.evaluate(interpreter)?, expression.span())))
+            .transpose()?;
+        let value = self.expression.evaluate(interpreter)?;
+
+        let variable = interpreter.get_variable_mut(
+            &destination.identifier,
+            destination.binding,
+            destination.span,
+        )?;
+        match index {
+            Some((index, index_span)) => match variable.index_mut(&index, index_span) {
+                Ok(element) => *element = value,
+                Err(err) => return Err(interpreter.index_error(&destination.identifier, err)),
+            },
+            None => *variable = value,
+        }
+        Ok(ControlFlow::Continue(()))
+    }
+}
+
+impl Execute for AnalyzedIf {
+    fn execute(&self, interpreter: &mut Interpreter) -> Result<ControlFlow<Value>, RuntimeError> {
+        if self.conditional_expr.evaluate(interpreter)?.is_true() {
+            self.then_block.execute(interpreter)
+        } else if let Some(else_block) = &self.else_block {
+            else_block.execute(interpreter)
+        } else {
+            Ok(ControlFlow::Continue(()))
+        }
+    }
+}
+
+impl Execute for AnalyzedLoop {
+    fn execute(&self, interpreter: &mut Interpreter) -> Result<ControlFlow<Value>, RuntimeError> {
+        // Assignments always fall through
+        let _ = self.assignment.execute(interpreter)?;
+        while self.condition.evaluate(interpreter)?.is_true() {
+            if let ControlFlow::Break(value) = self.loop_body.execute(interpreter)? {
+                return Ok(ControlFlow::Break(value));
+            }
+        }
+        Ok(ControlFlow::Continue(()))
+    }
+}
+
+impl Execute for AnalyzedReturn {
+    fn execute(&self, interpreter: &mut Interpreter) -> Result<ControlFlow<Value>, RuntimeError> {
+        Ok(ControlFlow::Break(self.expression.evaluate(interpreter)?))
+    }
+}
@@ -0,0 +1,13 @@
+use std::ops::ControlFlow;
+
+use super::value::Value;
+use super::{Interpreter, RuntimeError};
+
+pub trait Evaluate {
+    fn evaluate(&self, interpreter: &mut Interpreter) -> Result<Value, RuntimeError>;
+}
+
+/// Statements either fall through to the next statement, or break out of the procedure with its return value.
+pub trait Execute {
+    fn execute(&self, interpreter: &mut Interpreter) -> Result<ControlFlow<Value>, RuntimeError>;
+}
@@ -0,0 +1,251 @@
+use std::cmp::Ordering;
+use std::fmt::Display;
+
+use crate::semantics::value::Type;
+use crate::span::Span;
+
+use super::{Checks, RuntimeError};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(String),
+    Array(Vec<Value>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArithOperator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+}
+
+impl ArithOperator {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            ArithOperator::Add => "+",
+            ArithOperator::Subtract => "-",
+            ArithOperator::Multiply => "*",
+            ArithOperator::Divide => "/",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    LessThan,
+    LessThanEq,
+    GreaterThan,
+    GreaterThanEq,
+    Equals,
+    NotEquals,
+}
+
+impl Value {
+    /// The value a variable of `value_type` holds before it is first assigned
+    pub fn default_for(value_type: &Type) -> Value {
+        match value_type {
+            Type::Bool => Value::Bool(false),
+            Type::Int => Value::Int(0),
+            Type::Float => Value::Float(0.0),
+            Type::String => Value::String(String::new()),
+            Type::Array(element_type, bound) => {
+                Value::Array(vec![Value::default_for(element_type); *bound])
+            }
+            // Variables and procedure results always have a type
+            Type::Void => Value::Bool(false),
+        }
+    }
+
+    pub fn cast(self, value_type: &Type) -> Value {
+        match (self, value_type) {
+            (Value::Int(value), Type::Float) => Value::Float(value as f64),
+            (Value::Float(value), Type::Int) => Value::Int(value as i64),
+            (Value::Bool(value), Type::Int) => Value::Int(i64::from(value)),
+            (Value::Int(value), Type::Bool) => Value::Bool(value != 0),
+            (Value::Array(values), Type::Array(element_type, _)) => Value::Array(
+                values
+                    .into_iter()
+                    .map(|value| value.cast(element_type))
+                    .collect(),
+            ),
+            (value, _) => value,
+        }
+    }
+
+    /// Conditions are either bools, or integers where anything but 0 is true
+    pub fn is_true(&self) -> bool {
+        match self {
+            Value::Bool(value) => *value,
+            Value::Int(value) => *value != 0,
+            _ => false,
+        }
+    }
+
+    /// Integer results wrap around on overflow
+    pub fn arith(
+        self,
+        operator: ArithOperator,
+        rhs: Value,
+        span: Span,
+    ) -> Result<Value, RuntimeError> {
+        self.checked_arith(operator, rhs, Checks::default(), span)
+    }
+
+    pub fn checked_arith(
+        self,
+        operator: ArithOperator,
+        rhs: Value,
+        checks: Checks,
+        span: Span,
+    ) -> Result<Value, RuntimeError> {
+        match (self, rhs) {
+            (Value::Int(lhs), Value::Int(rhs)) => {
+                if operator == ArithOperator::Divide && rhs == 0 {
+                    return Err(match checks.division {
+                        true => RuntimeError::IntegerDivisionByZero(lhs, span),
+                        false => RuntimeError::DivisionByZero(span),
+                    });
+                }
+                let (result, overflowed) = match operator {
+                    ArithOperator::Add => lhs.overflowing_add(rhs),
+                    ArithOperator::Subtract => lhs.overflowing_sub(rhs),
+                    ArithOperator::Multiply => lhs.overflowing_mul(rhs),
+                    ArithOperator::Divide => lhs.overflowing_div(rhs),
+                };
+                if overflowed && checks.overflow {
+                    let operation = format!("{} {} {}", lhs, operator.symbol(), rhs);
+                    return Err(RuntimeError::IntegerOverflow(operation, span));
+                }
+                Ok(Value::Int(result))
+            }
+            (Value::Float(lhs), Value::Float(rhs)) => Ok(Value::Float(match operator {
+                ArithOperator::Add => lhs + rhs,
+                ArithOperator::Subtract => lhs - rhs,
+                ArithOperator::Multiply => lhs * rhs,
+                ArithOperator::Divide => lhs / rhs,
+            })),
+            (Value::Array(lhs), Value::Array(rhs)) => lhs
+                .into_iter()
+                .zip(rhs)
+                .map(|(lhs, rhs)| lhs.checked_arith(operator, rhs, checks, span))
+                .collect::<Result<Vec<Value>, RuntimeError>>()
+                .map(Value::Array),
+            (Value::Array(lhs), rhs) => lhs
+                .into_iter()
+                .map(|lhs| lhs.checked_arith(operator, rhs.clone(), checks, span))
+                .collect::<Result<Vec<Value>, RuntimeError>>()
+                .map(Value::Array),
+            (lhs, Value::Array(rhs)) => rhs
+                .into_iter()
+                .map(|rhs| lhs.clone().checked_arith(operator, rhs, checks, span))
+                .collect::<Result<Vec<Value>, RuntimeError>>()
+                .map(Value::Array),
+            (lhs, rhs) => Err(RuntimeError::InvalidOperands(lhs, rhs, span)),
+        }
+    }
+
+    pub fn compare(
+        self,
+        comparison: Comparison,
+        rhs: Value,
+        span: Span,
+    ) -> Result<Value, RuntimeError> {
+        let ordering = match (&self, &rhs) {
+            (Value::Int(lhs), Value::Int(rhs)) => lhs.partial_cmp(rhs),
+            (Value::Float(lhs), Value::Float(rhs)) => lhs.partial_cmp(rhs),
+            (Value::String(lhs), Value::String(rhs)) => lhs.partial_cmp(rhs),
+            (Value::Bool(lhs), Value::Bool(rhs)) => lhs.partial_cmp(rhs),
+            _ => return Err(RuntimeError::InvalidOperands(self, rhs, span)),
+        };
+        // NaN compares false with everything, except for `!=`
+        let result = match (comparison, ordering) {
+            (Comparison::NotEquals, ordering) => ordering != Some(Ordering::Equal),
+            (_, None) => false,
+            (Comparison::LessThan, Some(ordering)) => ordering.is_lt(),
+            (Comparison::LessThanEq, Some(ordering)) => ordering.is_le(),
+            (Comparison::GreaterThan, Some(ordering)) => ordering.is_gt(),
+            (Comparison::GreaterThanEq, Some(ordering)) => ordering.is_ge(),
+            (Comparison::Equals, Some(ordering)) => ordering.is_eq(),
+        };
+        Ok(Value::Bool(result))
+    }
+
+    pub fn negate(self, span: Span) -> Result<Value, RuntimeError> {
+        self.checked_negate(Checks::default(), span)
+    }
+
+    pub fn checked_negate(self, checks: Checks, span: Span) -> Result<Value, RuntimeError> {
+        match self {
+            Value::Int(value) => match value.checked_neg() {
+                Some(result) => Ok(Value::Int(result)),
+                None if checks.overflow => {
+                    Err(RuntimeError::IntegerOverflow(format!("-({})", value), span))
+                }
+                None => Ok(Value::Int(value.wrapping_neg())),
+            },
+            Value::Float(value) => Ok(Value::Float(-value)),
+            Value::Array(values) => values
+                .into_iter()
+                .map(|value| value.checked_negate(checks, span))
+                .collect::<Result<Vec<Value>, RuntimeError>>()
+                .map(Value::Array),
+            value => Err(RuntimeError::InvalidOperand(value, span)),
+        }
+    }
+
+    pub fn index(&self, index: &Value, span: Span) -> Result<&Value, RuntimeError> {
+        match (self, index) {
+            (Value::Array(values), Value::Int(index)) => usize::try_from(*index)
+                .ok()
+                .and_then(|position| values.get(position))
+                .ok_or(RuntimeError::IndexOutOfBounds(*index, values.len(), span)),
+            (value, index) => Err(RuntimeError::InvalidOperands(
+                value.clone(),
+                index.clone(),
+                span,
+            )),
+        }
+    }
+
+    pub fn index_mut(&mut self, index: &Value, span: Span) -> Result<&mut Value, RuntimeError> {
+        match (self, index) {
+            (Value::Array(values), Value::Int(index)) => {
+                let len = values.len();
+                usize::try_from(*index)
+                    .ok()
+                    .and_then(|position| values.get_mut(position))
+                    .ok_or(RuntimeError::IndexOutOfBounds(*index, len, span))
+            }
+            (value, index) => Err(RuntimeError::InvalidOperands(
+                value.clone(),
+                index.clone(),
+                span,
+            )),
+        }
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Bool(value) => write!(f, "{}", value),
+            Value::Int(value) => write!(f, "{}", value),
+            Value::Float(value) => write!(f, "{}", value),
+            Value::String(value) => write!(f, "{}", value),
+            Value::Array(values) => {
+                write!(f, "[")?;
+                for (position, value) in values.iter().enumerate() {
+                    if position > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                write!(f, "]")
+            }
+        }
+    }
+}
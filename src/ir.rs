@@ -0,0 +1,378 @@
+//! A flat, typed intermediate representation for backends to generate code from.
+//!
+//! Each procedure is a list of basic blocks, made of three-address instructions and ending in a terminator.
+//! Temporaries hold scalars (bools, integers, floats and strings), and are assigned exactly once.
+//! Variables are mutable, can hold any type, and are either locals of a function or globals.
+//! Procedures nested in another can use its locals too, through a static link to its frame.
+//! Every variable holds the default value for its type when its function is entered,
+//! except for parameters, which hold their arguments.
+//! Arrays are only ever handled through variables, and arrays passed to a procedure are copied.
+//! Instructions and terminators keep the span of the source they were lowered from, for runtime errors.
+
+use std::fmt::Display;
+
+pub mod display;
+pub mod lower;
+pub mod verify;
+
+use crate::semantics::value::Type;
+use crate::span::Span;
+
+#[derive(Debug)]
+pub struct Module {
+    pub name: String,
+    pub globals: Vec<VariableDecl>,
+    /// The program body, which takes no arguments and returns nothing
+    pub body: Function,
+    /// Every procedure, including nested ones, indexed by `FunctionId`
+    pub procedures: Vec<Function>,
+}
+
+impl Module {
+    pub fn procedure(&self, id: FunctionId) -> &Function {
+        &self.procedures[id.0]
+    }
+
+    /// The function `depth` static links out from `function`, where 0 is `function` itself
+    pub fn enclosing<'m>(&'m self, function: &'m Function, depth: usize) -> Option<&'m Function> {
+        let mut enclosing = function;
+        for _ in 0..depth {
+            enclosing = self.procedures.get(enclosing.parent?.0)?;
+        }
+        Some(enclosing)
+    }
+
+    /// The body, then each procedure
+    pub fn functions(&self) -> impl Iterator<Item = &Function> {
+        std::iter::once(&self.body).chain(self.procedures.iter())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct VariableDecl {
+    pub name: String,
+    pub value_type: Type,
+}
+
+#[derive(Debug)]
+pub struct Function {
+    /// Nested procedures are named by their path, like `outer.inner`
+    pub name: String,
+    /// The procedure this one is nested in, if it can use that procedure's locals.
+    /// The function is then passed a static link to the frame of the call to `parent` it runs in.
+    pub parent: Option<FunctionId>,
+    /// The first `param_count` locals are the parameters, in order
+    pub param_count: usize,
+    pub locals: Vec<VariableDecl>,
+    /// The type of each temporary, indexed by `Temp`
+    pub temps: Vec<Type>,
+    /// `Type::Void` for the program body
+    pub return_type: Type,
+    /// The first block is the entry block
+    pub blocks: Vec<Block>,
+}
+
+impl Function {
+    pub fn params(&self) -> &[VariableDecl] {
+        &self.locals[..self.param_count]
+    }
+
+    pub fn block(&self, id: BlockId) -> &Block {
+        &self.blocks[id.0]
+    }
+
+    pub fn temp_type(&self, temp: Temp) -> &Type {
+        &self.temps[temp.0]
+    }
+
+    pub fn operand_type(&self, operand: &Operand) -> Type {
+        match operand {
+            Operand::Temp(temp) => self.temp_type(*temp).clone(),
+            Operand::Const(constant) => constant.value_type(),
+        }
+    }
+
+    /// The type of a variable, or `None` if it doesn't exist
+    pub fn variable_type<'m>(&'m self, module: &'m Module, variable: Variable) -> Option<&'m Type> {
+        match variable {
+            Variable::Local(index) => self.locals.get(index).map(|local| &local.value_type),
+            Variable::Outer(depth, index) => module
+                .enclosing(self, depth)
+                .filter(|_| depth > 0)
+                .and_then(|enclosing| enclosing.locals.get(index))
+                .map(|local| &local.value_type),
+            Variable::Global(index) => module.globals.get(index).map(|global| &global.value_type),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Temp(pub usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BlockId(pub usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FunctionId(pub usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Variable {
+    /// Index into the current function's `locals`
+    Local(usize),
+    /// Index into the `locals` of the procedure `depth` static links out, where 1 is the current function's `parent`
+    Outer(usize, usize),
+    /// Index into the module's `globals`
+    Global(usize),
+}
+
+#[derive(Debug)]
+pub struct Block {
+    pub instructions: Vec<Instruction>,
+    /// The source each instruction was lowered from
+    pub spans: Vec<Span>,
+    pub terminator: Terminator,
+    pub terminator_span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Constant {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(String),
+}
+
+impl Constant {
+    pub fn value_type(&self) -> Type {
+        match self {
+            Constant::Bool(_) => Type::Bool,
+            Constant::Int(_) => Type::Int,
+            Constant::Float(_) => Type::Float,
+            Constant::String(_) => Type::String,
+        }
+    }
+}
+
+/// A scalar value
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand {
+    Temp(Temp),
+    Const(Constant),
+}
+
+/// Somewhere a scalar can be loaded from or stored to
+#[derive(Debug, Clone, PartialEq)]
+pub enum Place {
+    Variable(Variable),
+    /// An element of an array variable
+    Element(Variable, Operand),
+}
+
+impl Place {
+    pub fn variable(&self) -> Variable {
+        match self {
+            Place::Variable(variable) | Place::Element(variable, _) => *variable,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOp {
+    /// Arithmetic negation of an integer or float
+    Negate,
+    /// Logical not of a bool, or bitwise not of an integer
+    Not,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add,
+    Subtract,
+    Multiply,
+    /// Integer division truncates towards zero
+    Divide,
+    /// Logical and of bools, or bitwise and of integers
+    And,
+    /// Logical or of bools, or bitwise or of integers
+    Or,
+    LessThan,
+    LessThanEq,
+    GreaterThan,
+    GreaterThanEq,
+    Equals,
+    NotEquals,
+}
+
+impl BinaryOp {
+    pub fn is_comparison(self) -> bool {
+        matches!(
+            self,
+            BinaryOp::LessThan
+                | BinaryOp::LessThanEq
+                | BinaryOp::GreaterThan
+                | BinaryOp::GreaterThanEq
+                | BinaryOp::Equals
+                | BinaryOp::NotEquals
+        )
+    }
+}
+
+/// The builtin procedures declared in `ScopeContext::new_global_ctx`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Builtin {
+    GetBool,
+    GetInteger,
+    GetFloat,
+    GetString,
+    PutBool,
+    PutInteger,
+    PutFloat,
+    PutString,
+    Sqrt,
+}
+
+impl Builtin {
+    pub const ALL: [Builtin; 9] = [
+        Builtin::GetBool,
+        Builtin::GetInteger,
+        Builtin::GetFloat,
+        Builtin::GetString,
+        Builtin::PutBool,
+        Builtin::PutInteger,
+        Builtin::PutFloat,
+        Builtin::PutString,
+        Builtin::Sqrt,
+    ];
+
+    pub fn from_name(name: &str) -> Option<Builtin> {
+        Builtin::ALL
+            .into_iter()
+            .find(|builtin| builtin.name() == name)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Builtin::GetBool => "getbool",
+            Builtin::GetInteger => "getinteger",
+            Builtin::GetFloat => "getfloat",
+            Builtin::GetString => "getstring",
+            Builtin::PutBool => "putbool",
+            Builtin::PutInteger => "putinteger",
+            Builtin::PutFloat => "putfloat",
+            Builtin::PutString => "putstring",
+            Builtin::Sqrt => "sqrt",
+        }
+    }
+
+    pub fn param_types(self) -> Vec<Type> {
+        match self {
+            Builtin::GetBool | Builtin::GetInteger | Builtin::GetFloat | Builtin::GetString => {
+                vec![]
+            }
+            Builtin::PutBool => vec![Type::Bool],
+            Builtin::PutInteger | Builtin::Sqrt => vec![Type::Int],
+            Builtin::PutFloat => vec![Type::Float],
+            Builtin::PutString => vec![Type::String],
+        }
+    }
+
+    pub fn return_type(self) -> Type {
+        match self {
+            Builtin::GetBool => Type::Bool,
+            Builtin::GetInteger => Type::Int,
+            Builtin::GetFloat | Builtin::Sqrt => Type::Float,
+            Builtin::GetString => Type::String,
+            Builtin::PutBool | Builtin::PutInteger | Builtin::PutFloat | Builtin::PutString => {
+                Type::Bool
+            }
+        }
+    }
+}
+
+impl Display for Builtin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Callee {
+    Procedure(FunctionId),
+    /// A procedure with a `parent`, passed a static link to the frame `depth` static links out from the caller,
+    /// where 0 is the caller's own frame
+    Nested(FunctionId, usize),
+    Builtin(Builtin),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Argument {
+    Scalar(Operand),
+    /// Arrays are passed by value, so the callee gets a copy
+    Array(Variable),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Instruction {
+    Load {
+        dest: Temp,
+        source: Place,
+    },
+    Store {
+        dest: Place,
+        value: Operand,
+    },
+    /// Copies every element of one array variable into another of the same type
+    CopyArray {
+        dest: Variable,
+        source: Variable,
+    },
+    Unary {
+        dest: Temp,
+        op: UnaryOp,
+        operand: Operand,
+    },
+    /// Both operands have the same type. Comparisons give a bool, and the rest give the operands' type.
+    Binary {
+        dest: Temp,
+        op: BinaryOp,
+        lhs: Operand,
+        rhs: Operand,
+    },
+    /// Converts between integers and floats, or integers and bools.
+    /// Floats are truncated towards zero, and any non-zero integer is true.
+    Cast {
+        dest: Temp,
+        operand: Operand,
+    },
+    Call {
+        dest: Temp,
+        callee: Callee,
+        args: Vec<Argument>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Terminator {
+    Jump(BlockId),
+    Branch {
+        condition: Operand,
+        then_block: BlockId,
+        else_block: BlockId,
+    },
+    /// Returns from the function, with a value unless it is the program body
+    Return(Option<Operand>),
+}
+
+impl Terminator {
+    pub fn successors(&self) -> Vec<BlockId> {
+        match self {
+            Terminator::Jump(target) => vec![*target],
+            Terminator::Branch {
+                then_block,
+                else_block,
+                ..
+            } => vec![*then_block, *else_block],
+            Terminator::Return(_) => vec![],
+        }
+    }
+}
@@ -0,0 +1,217 @@
+//! The textual form of the IR, as written by `--emit=ir`.
+//!
+//! ```text
+//! program example
+//!
+//! global total : integer
+//!
+//! procedure square(x : integer) : integer
+//! bb0:
+//!   %0 : integer = load x
+//!   %1 : integer = mul %0, %0
+//!   return %1
+//! end procedure
+//!
+//! begin
+//! bb0:
+//!   %0 : integer = call square(4)
+//!   store %0 to @total
+//!   return
+//! end program
+//! ```
+//!
+//! Locals of an enclosing procedure are followed by the number of static links out they are,
+//! like `total^1`, and so are calls to nested procedures, with the frame they are passed.
+
+use std::fmt::{self, Display, Formatter};
+
+use super::{
+    Argument, BinaryOp, Callee, Constant, Function, Instruction, Module, Operand, Place, Temp,
+    Terminator, UnaryOp, Variable,
+};
+
+impl Display for Module {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(f, "program {}", self.name)?;
+        if !self.globals.is_empty() {
+            writeln!(f)?;
+        }
+        for global in self.globals.iter() {
+            writeln!(f, "global {} : {}", global.name, global.value_type)?;
+        }
+
+        for procedure in self.procedures.iter() {
+            writeln!(f)?;
+            let params = procedure
+                .params()
+                .iter()
+                .map(|param| format!("{} : {}", param.name, param.value_type))
+                .collect::<Vec<String>>()
+                .join(", ");
+            writeln!(
+                f,
+                "procedure {}({}) : {}",
+                procedure.name, params, procedure.return_type
+            )?;
+            write_function(f, self, procedure)?;
+            writeln!(f, "end procedure")?;
+        }
+
+        writeln!(f)?;
+        writeln!(f, "begin")?;
+        write_function(f, self, &self.body)?;
+        writeln!(f, "end program")
+    }
+}
+
+fn write_function(f: &mut Formatter<'_>, module: &Module, function: &Function) -> fmt::Result {
+    let names = Names { module, function };
+    for local in function.locals.iter().skip(function.param_count) {
+        writeln!(f, "  local {} : {}", local.name, local.value_type)?;
+    }
+    for (id, block) in function.blocks.iter().enumerate() {
+        writeln!(f, "bb{}:", id)?;
+        for instruction in block.instructions.iter() {
+            writeln!(f, "  {}", names.instruction(instruction))?;
+        }
+        writeln!(f, "  {}", names.terminator(&block.terminator))?;
+    }
+    Ok(())
+}
+
+/// Resolves the variables and procedures a function refers to
+struct Names<'m> {
+    module: &'m Module,
+    function: &'m Function,
+}
+
+impl<'m> Names<'m> {
+    fn variable(&self, variable: Variable) -> String {
+        match variable {
+            Variable::Local(index) => self.function.locals[index].name.clone(),
+            Variable::Outer(depth, index) => {
+                // The IR has been verified, so the enclosing procedure exists
+                let enclosing = self.module.enclosing(self.function, depth).unwrap();
+                format!("{}^{}", enclosing.locals[index].name, depth)
+            }
+            Variable::Global(index) => format!("@{}", self.module.globals[index].name),
+        }
+    }
+
+    fn place(&self, place: &Place) -> String {
+        match place {
+            Place::Variable(variable) => self.variable(*variable),
+            Place::Element(variable, index) => format!("{}[{}]", self.variable(*variable), index),
+        }
+    }
+
+    fn callee(&self, callee: &Callee) -> String {
+        match callee {
+            Callee::Procedure(id) => self.module.procedure(*id).name.clone(),
+            Callee::Nested(id, depth) => format!("{}^{}", self.module.procedure(*id).name, depth),
+            Callee::Builtin(builtin) => builtin.to_string(),
+        }
+    }
+
+    fn instruction(&self, instruction: &Instruction) -> String {
+        let temp = |dest: &Temp| format!("%{} : {}", dest.0, self.function.temp_type(*dest));
+        match instruction {
+            Instruction::Load { dest, source } => {
+                format!("{} = load {}", temp(dest), self.place(source))
+            }
+            Instruction::Store { dest, value } => {
+                format!("store {} to {}", value, self.place(dest))
+            }
+            Instruction::CopyArray { dest, source } => format!(
+                "copy {} to {}",
+                self.variable(*source),
+                self.variable(*dest)
+            ),
+            Instruction::Unary { dest, op, operand } => {
+                format!("{} = {} {}", temp(dest), op, operand)
+            }
+            Instruction::Binary { dest, op, lhs, rhs } => {
+                format!("{} = {} {}, {}", temp(dest), op, lhs, rhs)
+            }
+            Instruction::Cast { dest, operand } => format!("{} = cast {}", temp(dest), operand),
+            Instruction::Call { dest, callee, args } => {
+                let args = args
+                    .iter()
+                    .map(|arg| match arg {
+                        Argument::Scalar(operand) => operand.to_string(),
+                        Argument::Array(variable) => self.variable(*variable),
+                    })
+                    .collect::<Vec<String>>()
+                    .join(", ");
+                format!("{} = call {}({})", temp(dest), self.callee(callee), args)
+            }
+        }
+    }
+
+    fn terminator(&self, terminator: &Terminator) -> String {
+        match terminator {
+            Terminator::Jump(target) => format!("jump bb{}", target.0),
+            Terminator::Branch {
+                condition,
+                then_block,
+                else_block,
+            } => format!(
+                "branch {}, bb{}, bb{}",
+                condition, then_block.0, else_block.0
+            ),
+            Terminator::Return(Some(value)) => format!("return {}", value),
+            Terminator::Return(None) => String::from("return"),
+        }
+    }
+}
+
+impl Display for Constant {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Constant::Bool(value) => write!(f, "{}", value),
+            Constant::Int(value) => write!(f, "{}", value),
+            // Debug always includes a decimal point, so floats can't be mistaken for integers
+            Constant::Float(value) => write!(f, "{:?}", value),
+            Constant::String(value) => write!(f, "{:?}", value),
+        }
+    }
+}
+
+impl Display for Operand {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Operand::Temp(temp) => write!(f, "%{}", temp.0),
+            Operand::Const(constant) => write!(f, "{}", constant),
+        }
+    }
+}
+
+impl Display for UnaryOp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            UnaryOp::Negate => "neg",
+            UnaryOp::Not => "not",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl Display for BinaryOp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            BinaryOp::Add => "add",
+            BinaryOp::Subtract => "sub",
+            BinaryOp::Multiply => "mul",
+            BinaryOp::Divide => "div",
+            BinaryOp::And => "and",
+            BinaryOp::Or => "or",
+            BinaryOp::LessThan => "lt",
+            BinaryOp::LessThanEq => "le",
+            BinaryOp::GreaterThan => "gt",
+            BinaryOp::GreaterThanEq => "ge",
+            BinaryOp::Equals => "eq",
+            BinaryOp::NotEquals => "ne",
+        };
+        write!(f, "{}", name)
+    }
+}
@@ -0,0 +1,943 @@
+use std::collections::HashMap;
+
+use crate::codegen::CodegenError;
+use crate::semantics::context::Binding;
+use crate::semantics::expression::{
+    AnalyzedArithOp, AnalyzedExpression, AnalyzedFactor, AnalyzedName, AnalyzedNumber,
+    AnalyzedRelation, AnalyzedTerm,
+};
+use crate::semantics::procedure::{AnalyzedProcedure, AnalyzedProcedureCall};
+use crate::semantics::statement::{
+    AnalyzedAssignment, AnalyzedBlock, AnalyzedIf, AnalyzedLoop, AnalyzedReturn, AnalyzedStatement,
+};
+use crate::semantics::value::Type;
+use crate::semantics::AnalyzedProgram;
+use crate::span::Span;
+
+use super::{
+    Argument, BinaryOp, Block, BlockId, Builtin, Callee, Constant, Function, FunctionId,
+    Instruction, Module, Operand, Place, Temp, Terminator, UnaryOp, Variable, VariableDecl,
+};
+
+/// Lowers an analyzed program into a module.
+pub fn lower(program: &AnalyzedProgram) -> Result<Module, CodegenError> {
+    // Every procedure is numbered up front, so calls can refer to procedures that come later
+    let mut procedures = Vec::new();
+    for procedure in program.procedures.iter() {
+        collect_procedures(
+            procedure,
+            procedure.identifier.clone(),
+            None,
+            &mut procedures,
+        );
+    }
+    let global_procedures = procedures
+        .iter()
+        .enumerate()
+        .filter(|(_, entry)| entry.procedure.is_global)
+        .map(|(id, entry)| (entry.procedure.identifier.as_str(), FunctionId(id)))
+        .collect();
+
+    let globals = sorted_variables(&program.declarations.variables);
+    let locals: Vec<Vec<VariableDecl>> = procedures
+        .iter()
+        .map(|entry| procedure_locals(entry.procedure))
+        .collect();
+    let lowering = Lowering {
+        globals: &globals,
+        global_ids: variable_ids(&globals),
+        global_procedures,
+        procedures: &procedures,
+        local_ids: locals.iter().map(|locals| variable_ids(locals)).collect(),
+        locals,
+    };
+
+    let body = lowering.lower_function(
+        program.name.clone(),
+        Vec::new(),
+        Vec::new(),
+        Vec::new(),
+        Type::Void,
+        &program.block,
+        program.span,
+    )?;
+    let procedures = (0..procedures.len())
+        .map(|id| lowering.lower_procedure(FunctionId(id)))
+        .collect::<Result<Vec<Function>, CodegenError>>()?;
+
+    Ok(Module {
+        name: program.name.clone(),
+        globals,
+        body,
+        procedures,
+    })
+}
+
+struct ProcedureEntry<'a> {
+    name: String,
+    procedure: &'a AnalyzedProcedure,
+    /// The procedure this one is nested in
+    parent: Option<FunctionId>,
+    nested: Vec<FunctionId>,
+}
+
+impl ProcedureEntry<'_> {
+    /// The procedure whose locals this one can use. Global procedures only see globals.
+    fn static_parent(&self) -> Option<FunctionId> {
+        self.parent.filter(|_| !self.procedure.is_global)
+    }
+}
+
+/// Numbers a procedure, then each procedure nested in it
+fn collect_procedures<'a>(
+    procedure: &'a AnalyzedProcedure,
+    name: String,
+    parent: Option<FunctionId>,
+    procedures: &mut Vec<ProcedureEntry<'a>>,
+) -> FunctionId {
+    let id = FunctionId(procedures.len());
+    procedures.push(ProcedureEntry {
+        name: name.clone(),
+        procedure,
+        parent,
+        nested: Vec::new(),
+    });
+    let nested = procedure
+        .procedures
+        .iter()
+        .map(|nested| {
+            collect_procedures(
+                nested,
+                format!("{}.{}", name, nested.identifier),
+                Some(id),
+                procedures,
+            )
+        })
+        .collect();
+    procedures[id.0].nested = nested;
+    id
+}
+
+/// Orders a scope's variables by identifier, so the output is deterministic
+fn sorted_variables(variables: &HashMap<String, Type>) -> Vec<VariableDecl> {
+    let mut variables: Vec<VariableDecl> = variables
+        .iter()
+        .map(|(name, value_type)| VariableDecl {
+            name: name.clone(),
+            value_type: value_type.clone(),
+        })
+        .collect();
+    variables.sort_by(|lhs, rhs| lhs.name.cmp(&rhs.name));
+    variables
+}
+
+/// The parameters in order, then the other locals by identifier
+fn procedure_locals(procedure: &AnalyzedProcedure) -> Vec<VariableDecl> {
+    let mut locals: Vec<VariableDecl> = procedure
+        .arg_list
+        .iter()
+        .map(|arg| VariableDecl {
+            name: arg.0.clone(),
+            value_type: arg.1.clone(),
+        })
+        .collect();
+    locals.extend(
+        sorted_variables(&procedure.declarations.variables)
+            .into_iter()
+            .filter(|variable| !procedure.arg_list.iter().any(|arg| arg.0 == variable.name)),
+    );
+    locals
+}
+
+fn variable_ids(variables: &[VariableDecl]) -> HashMap<String, usize> {
+    variables
+        .iter()
+        .enumerate()
+        .map(|(index, variable)| (variable.name.clone(), index))
+        .collect()
+}
+
+fn default_constant(value_type: &Type) -> Option<Constant> {
+    match value_type {
+        Type::Bool => Some(Constant::Bool(false)),
+        Type::Int => Some(Constant::Int(0)),
+        Type::Float => Some(Constant::Float(0.0)),
+        Type::String => Some(Constant::String(String::new())),
+        Type::Array(..) | Type::Void => None,
+    }
+}
+
+struct Lowering<'l, 'a> {
+    globals: &'l [VariableDecl],
+    global_ids: HashMap<String, usize>,
+    global_procedures: HashMap<&'a str, FunctionId>,
+    procedures: &'l [ProcedureEntry<'a>],
+    /// The locals each procedure declares, indexed by `FunctionId`, without the ones lowering adds
+    locals: Vec<Vec<VariableDecl>>,
+    local_ids: Vec<HashMap<String, usize>>,
+}
+
+impl<'l, 'a> Lowering<'l, 'a> {
+    fn lower_procedure(&self, id: FunctionId) -> Result<Function, CodegenError> {
+        let entry = &self.procedures[id.0];
+        let procedure = entry.procedure;
+
+        let mut links = Vec::new();
+        let mut link = entry.static_parent();
+        while let Some(id) = link {
+            links.push(id);
+            link = self.procedures[id.0].static_parent();
+        }
+
+        let mut procedures = Vec::new();
+        let mut enclosing = Some(entry);
+        while let Some(entry) = enclosing {
+            let nested = entry
+                .nested
+                .iter()
+                .filter(|id| !self.procedures[id.0].procedure.is_global)
+                .map(|id| (self.procedures[id.0].procedure.identifier.as_str(), *id))
+                .collect();
+            procedures.push(nested);
+            enclosing = entry.parent.map(|id| &self.procedures[id.0]);
+        }
+
+        let mut function = self.lower_function(
+            entry.name.clone(),
+            self.locals[id.0].clone(),
+            procedures,
+            links,
+            procedure.declarations.return_type.clone(),
+            &procedure.block,
+            procedure.span,
+        )?;
+        function.parent = entry.static_parent();
+        function.param_count = procedure.arg_list.len();
+        Ok(function)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn lower_function(
+        &self,
+        name: String,
+        locals: Vec<VariableDecl>,
+        procedures: Vec<HashMap<&'a str, FunctionId>>,
+        links: Vec<FunctionId>,
+        return_type: Type,
+        block: &AnalyzedBlock,
+        span: Span,
+    ) -> Result<Function, CodegenError> {
+        let mut builder = FunctionBuilder {
+            lowering: self,
+            local_ids: variable_ids(&locals),
+            procedures,
+            links,
+            function: Function {
+                name,
+                parent: None,
+                param_count: 0,
+                locals,
+                temps: Vec::new(),
+                return_type,
+                blocks: Vec::new(),
+            },
+            blocks: vec![PendingBlock::default()],
+            current: BlockId(0),
+            span,
+        };
+        block.lower(&mut builder)?;
+        Ok(builder.finish())
+    }
+}
+
+#[derive(Default)]
+struct PendingBlock {
+    instructions: Vec<Instruction>,
+    spans: Vec<Span>,
+    terminator: Option<(Terminator, Span)>,
+}
+
+/// The result of lowering an expression
+#[derive(Debug, Clone)]
+enum Value {
+    Scalar(Operand),
+    /// Arrays are always held in a variable
+    Array(Variable),
+}
+
+impl Value {
+    fn scalar(self) -> Operand {
+        match self {
+            Value::Scalar(operand) => operand,
+            Value::Array(_) => unreachable!("arrays are only used element-wise"),
+        }
+    }
+}
+
+struct FunctionBuilder<'b, 'l, 'a> {
+    lowering: &'b Lowering<'l, 'a>,
+    local_ids: HashMap<String, usize>,
+    /// Nested procedures that aren't global, of this procedure and then each one enclosing it
+    procedures: Vec<HashMap<&'a str, FunctionId>>,
+    /// The procedures 1, 2 and so on static links out, whose locals this one can use
+    links: Vec<FunctionId>,
+    function: Function,
+    blocks: Vec<PendingBlock>,
+    current: BlockId,
+    /// The source being lowered, which instructions are tagged with
+    span: Span,
+}
+
+impl<'b, 'l, 'a> FunctionBuilder<'b, 'l, 'a> {
+    fn finish(mut self) -> Function {
+        if self.blocks[self.current.0].terminator.is_none() {
+            // Falling off the end of a procedure returns the default for its type
+            let value = default_constant(&self.function.return_type).map(Operand::Const);
+            self.terminate(Terminator::Return(value));
+        }
+        self.function.blocks = self
+            .blocks
+            .into_iter()
+            .map(|block| {
+                // Blocks are only left by terminating them
+                let (terminator, terminator_span) = block.terminator.unwrap();
+                Block {
+                    instructions: block.instructions,
+                    spans: block.spans,
+                    terminator,
+                    terminator_span,
+                }
+            })
+            .collect();
+        self.function
+    }
+
+    fn new_block(&mut self) -> BlockId {
+        self.blocks.push(PendingBlock::default());
+        BlockId(self.blocks.len() - 1)
+    }
+
+    /// Jumps to `block`, unless the current block has already been terminated
+    fn branch(&mut self, block: BlockId) {
+        if self.blocks[self.current.0].terminator.is_none() {
+            self.terminate(Terminator::Jump(block));
+        }
+    }
+
+    /// Continues in `block`, falling through to it from the current block
+    fn start_block(&mut self, block: BlockId) {
+        self.branch(block);
+        self.current = block;
+    }
+
+    /// Makes sure there is an unterminated block to add to.
+    /// Code after a return is unreachable, but still needs a block to live in.
+    fn reachable_block(&mut self) -> &mut PendingBlock {
+        if self.blocks[self.current.0].terminator.is_some() {
+            self.current = self.new_block();
+        }
+        &mut self.blocks[self.current.0]
+    }
+
+    fn emit(&mut self, instruction: Instruction) {
+        let span = self.span;
+        let block = self.reachable_block();
+        block.instructions.push(instruction);
+        block.spans.push(span);
+    }
+
+    fn terminate(&mut self, terminator: Terminator) {
+        let span = self.span;
+        self.reachable_block().terminator = Some((terminator, span));
+    }
+
+    /// Lowers something with the instructions it emits tagged with `span`
+    fn spanned<T>(
+        &mut self,
+        span: Span,
+        lower: impl FnOnce(&mut Self) -> Result<T, CodegenError>,
+    ) -> Result<T, CodegenError> {
+        let outer = std::mem::replace(&mut self.span, span);
+        let result = lower(self);
+        self.span = outer;
+        result
+    }
+
+    fn temp(&mut self, value_type: Type) -> Temp {
+        self.function.temps.push(value_type);
+        Temp(self.function.temps.len() - 1)
+    }
+
+    /// Adds a local for the compiler's own use. Its name can't clash with an identifier.
+    fn generated_local(&mut self, name: &str, value_type: Type) -> Variable {
+        let index = self.function.locals.len();
+        self.function.locals.push(VariableDecl {
+            name: format!("{}.{}", name, index),
+            value_type,
+        });
+        Variable::Local(index)
+    }
+
+    /// Looks up a variable where the analyzer found it, in the current procedure,
+    /// one enclosing it, or the global scope.
+    fn variable(
+        &self,
+        identifier: &str,
+        binding: Binding,
+        span: Span,
+    ) -> Result<(Variable, Type), CodegenError> {
+        if let Binding::Local(depth @ 1..) = binding {
+            return self
+                .links
+                .get(depth - 1)
+                .and_then(|id| {
+                    let index = *self.lowering.local_ids[id.0].get(identifier)?;
+                    let value_type = self.lowering.locals[id.0][index].value_type.clone();
+                    Some((Variable::Outer(depth, index), value_type))
+                })
+                .ok_or_else(|| CodegenError::UndefinedRef(String::from(identifier), span));
+        }
+        if let Some(index) = self.local_ids.get(identifier) {
+            let value_type = self.function.locals[*index].value_type.clone();
+            return Ok((Variable::Local(*index), value_type));
+        }
+        self.lowering
+            .global_ids
+            .get(identifier)
+            .map(|index| {
+                let value_type = self.lowering.globals[*index].value_type.clone();
+                (Variable::Global(*index), value_type)
+            })
+            .ok_or_else(|| CodegenError::UndefinedRef(String::from(identifier), span))
+    }
+
+    fn operand_type(&self, operand: &Operand) -> Type {
+        self.function.operand_type(operand)
+    }
+
+    fn value_type(&self, value: &Value) -> Type {
+        match value {
+            Value::Scalar(operand) => self.operand_type(operand),
+            Value::Array(Variable::Local(index)) => self.function.locals[*index].value_type.clone(),
+            Value::Array(Variable::Outer(depth, index)) => {
+                let id = self.links[depth - 1];
+                self.lowering.locals[id.0][*index].value_type.clone()
+            }
+            Value::Array(Variable::Global(index)) => {
+                self.lowering.globals[*index].value_type.clone()
+            }
+        }
+    }
+
+    fn load(&mut self, source: Place, value_type: Type) -> Operand {
+        let dest = self.temp(value_type);
+        self.emit(Instruction::Load { dest, source });
+        Operand::Temp(dest)
+    }
+
+    fn unary(&mut self, op: UnaryOp, operand: Operand) -> Operand {
+        let dest = self.temp(self.operand_type(&operand));
+        self.emit(Instruction::Unary { dest, op, operand });
+        Operand::Temp(dest)
+    }
+
+    fn binary(&mut self, op: BinaryOp, lhs: Operand, rhs: Operand) -> Operand {
+        let value_type = match op.is_comparison() {
+            true => Type::Bool,
+            false => self.operand_type(&lhs),
+        };
+        let dest = self.temp(value_type);
+        self.emit(Instruction::Binary { dest, op, lhs, rhs });
+        Operand::Temp(dest)
+    }
+
+    fn cast(&mut self, value: Value, value_type: &Type, span: Span) -> Result<Value, CodegenError> {
+        let from = self.value_type(&value);
+        match (&from, value_type) {
+            (from, to) if from == to => Ok(value),
+            (Type::Int, Type::Float | Type::Bool) | (Type::Float | Type::Bool, Type::Int) => {
+                let dest = self.temp(value_type.clone());
+                self.emit(Instruction::Cast {
+                    dest,
+                    operand: value.scalar(),
+                });
+                Ok(Value::Scalar(Operand::Temp(dest)))
+            }
+            (from, to) => Err(CodegenError::InvalidCast(
+                from.to_string(),
+                to.to_string(),
+                span,
+            )),
+        }
+    }
+
+    /// Emits a loop running `body` with each index from 0 up to `bound`
+    fn for_each_index(
+        &mut self,
+        bound: usize,
+        mut body: impl FnMut(&mut Self, Operand) -> Result<(), CodegenError>,
+    ) -> Result<(), CodegenError> {
+        let counter = self.generated_local("index", Type::Int);
+        self.emit(Instruction::Store {
+            dest: Place::Variable(counter),
+            value: Operand::Const(Constant::Int(0)),
+        });
+
+        let condition = self.new_block();
+        let loop_body = self.new_block();
+        let end = self.new_block();
+
+        self.start_block(condition);
+        let index = self.load(Place::Variable(counter), Type::Int);
+        let in_bounds = self.binary(
+            BinaryOp::LessThan,
+            index.clone(),
+            Operand::Const(Constant::Int(bound as i64)),
+        );
+        self.terminate(Terminator::Branch {
+            condition: in_bounds,
+            then_block: loop_body,
+            else_block: end,
+        });
+
+        self.current = loop_body;
+        body(self, index.clone())?;
+        let next = self.binary(BinaryOp::Add, index, Operand::Const(Constant::Int(1)));
+        self.emit(Instruction::Store {
+            dest: Place::Variable(counter),
+            value: next,
+        });
+        self.terminate(Terminator::Jump(condition));
+
+        self.current = end;
+        Ok(())
+    }
+
+    /// Builds a new array of `array_type`, computing each element with `element`
+    fn map_array(
+        &mut self,
+        array_type: Type,
+        mut element: impl FnMut(&mut Self, Operand) -> Result<Operand, CodegenError>,
+    ) -> Result<Value, CodegenError> {
+        let Type::Array(_, bound) = array_type else {
+            unreachable!("only arrays are mapped")
+        };
+        let array = self.generated_local("array", array_type);
+        self.for_each_index(bound, |builder, index| {
+            let value = element(builder, index.clone())?;
+            builder.emit(Instruction::Store {
+                dest: Place::Element(array, index),
+                value,
+            });
+            Ok(())
+        })?;
+        Ok(Value::Array(array))
+    }
+
+    /// Loads an element of an array, or gives a scalar as is
+    fn broadcast(&mut self, value: &Value, index: Operand) -> Operand {
+        match value {
+            Value::Scalar(operand) => operand.clone(),
+            Value::Array(array) => {
+                let Type::Array(element_type, _) = self.value_type(value) else {
+                    unreachable!("array values have array types")
+                };
+                self.load(Place::Element(*array, index), *element_type)
+            }
+        }
+    }
+
+    /// Applies a binary operator, element-wise if either operand is an array
+    fn arith(&mut self, op: BinaryOp, lhs: Value, rhs: Value) -> Result<Value, CodegenError> {
+        let array_type = match (&lhs, &rhs) {
+            (Value::Scalar(lhs), Value::Scalar(rhs)) => {
+                return Ok(Value::Scalar(self.binary(op, lhs.clone(), rhs.clone())))
+            }
+            (Value::Array(_), _) => self.value_type(&lhs),
+            (_, Value::Array(_)) => self.value_type(&rhs),
+        };
+        self.map_array(array_type, |builder, index| {
+            let lhs = builder.broadcast(&lhs, index.clone());
+            let rhs = builder.broadcast(&rhs, index);
+            Ok(builder.binary(op, lhs, rhs))
+        })
+    }
+
+    fn negate(&mut self, value: Value) -> Result<Value, CodegenError> {
+        match value {
+            Value::Scalar(operand) => Ok(Value::Scalar(self.unary(UnaryOp::Negate, operand))),
+            Value::Array(_) => {
+                let array_type = self.value_type(&value);
+                self.map_array(array_type, |builder, index| {
+                    let element = builder.broadcast(&value, index);
+                    Ok(builder.unary(UnaryOp::Negate, element))
+                })
+            }
+        }
+    }
+
+    /// Emits a call to the procedure the analyzer bound the call to, or to a builtin if there is none.
+    /// Calls to nested procedures record how many frames out the callee was declared.
+    fn call(
+        &mut self,
+        identifier: &str,
+        binding: Binding,
+        args: Vec<Argument>,
+        span: Span,
+    ) -> Result<Operand, CodegenError> {
+        let procedure = match binding {
+            Binding::Global => self.lowering.global_procedures.get(identifier),
+            Binding::Local(depth) => self
+                .procedures
+                .get(depth)
+                .and_then(|procedures| procedures.get(identifier)),
+        };
+        let (callee, return_type) = match procedure {
+            Some(id) => {
+                let entry = &self.lowering.procedures[id.0];
+                // Nested procedures are passed the frame of the procedure they're declared in
+                let callee = match (entry.static_parent(), binding) {
+                    (Some(_), Binding::Local(depth)) => Callee::Nested(*id, depth),
+                    _ => Callee::Procedure(*id),
+                };
+                (callee, entry.procedure.declarations.return_type.clone())
+            }
+            None => match Builtin::from_name(identifier) {
+                Some(builtin) => (Callee::Builtin(builtin), builtin.return_type()),
+                None => return Err(CodegenError::UndefinedRef(String::from(identifier), span)),
+            },
+        };
+
+        let dest = self.temp(return_type);
+        self.emit(Instruction::Call { dest, callee, args });
+        Ok(Operand::Temp(dest))
+    }
+}
+
+trait Lower<T> {
+    fn lower(&self, builder: &mut FunctionBuilder) -> Result<T, CodegenError>;
+}
+
+impl Lower<()> for AnalyzedBlock {
+    fn lower(&self, builder: &mut FunctionBuilder) -> Result<(), CodegenError> {
+        for statement in self.0.iter() {
+            statement.lower(builder)?;
+        }
+        Ok(())
+    }
+}
+
+impl Lower<()> for AnalyzedStatement {
+    fn lower(&self, builder: &mut FunctionBuilder) -> Result<(), CodegenError> {
+        builder.spanned(self.span(), |builder| match self {
+            AnalyzedStatement::Assignment(statement) => statement.lower(builder),
+            AnalyzedStatement::If(statement) => statement.lower(builder),
+            AnalyzedStatement::Loop(statement) => statement.lower(builder),
+            AnalyzedStatement::Return(statement) => statement.lower(builder),
+        })
+    }
+}
+
+impl Lower<()> for AnalyzedAssignment {
+    fn lower(&self, builder: &mut FunctionBuilder) -> Result<(), CodegenError> {
+        let destination = &self.destination;
+        let index = destination
+            .expression
+            .as_ref()
+            .map(|expression| expression.lower(builder).map(Value::scalar))
+            .transpose()?;
+        let value = self.expression.lower(builder)?;
+
+        let (variable, _) = builder.variable(
+            &destination.identifier,
+            destination.binding,
+            destination.span,
+        )?;
+        let instruction = match (index, value) {
+            (Some(index), value) => Instruction::Store {
+                dest: Place::Element(variable, index),
+                value: value.scalar(),
+            },
+            (None, Value::Array(source)) => Instruction::CopyArray {
+                dest: variable,
+                source,
+            },
+            (None, Value::Scalar(value)) => Instruction::Store {
+                dest: Place::Variable(variable),
+                value,
+            },
+        };
+        builder.emit(instruction);
+        Ok(())
+    }
+}
+
+impl Lower<()> for AnalyzedIf {
+    fn lower(&self, builder: &mut FunctionBuilder) -> Result<(), CodegenError> {
+        let condition = self.conditional_expr.lower(builder)?.scalar();
+        let then_block = builder.new_block();
+        let else_block = self.else_block.as_ref().map(|_| builder.new_block());
+        let end = builder.new_block();
+
+        builder.terminate(Terminator::Branch {
+            condition,
+            then_block,
+            else_block: else_block.unwrap_or(end),
+        });
+
+        builder.current = then_block;
+        self.then_block.lower(builder)?;
+        builder.branch(end);
+
+        if let (Some(else_block), Some(statements)) = (else_block, &self.else_block) {
+            builder.current = else_block;
+            statements.lower(builder)?;
+            builder.branch(end);
+        }
+
+        builder.current = end;
+        Ok(())
+    }
+}
+
+impl Lower<()> for AnalyzedLoop {
+    fn lower(&self, builder: &mut FunctionBuilder) -> Result<(), CodegenError> {
+        self.assignment.lower(builder)?;
+
+        let condition_block = builder.new_block();
+        let body = builder.new_block();
+        let end = builder.new_block();
+
+        builder.start_block(condition_block);
+        let condition = self.condition.lower(builder)?.scalar();
+        builder.terminate(Terminator::Branch {
+            condition,
+            then_block: body,
+            else_block: end,
+        });
+
+        builder.current = body;
+        self.loop_body.lower(builder)?;
+        builder.branch(condition_block);
+
+        builder.current = end;
+        Ok(())
+    }
+}
+
+impl Lower<()> for AnalyzedReturn {
+    fn lower(&self, builder: &mut FunctionBuilder) -> Result<(), CodegenError> {
+        let value = self.expression.lower(builder)?.scalar();
+        builder.terminate(Terminator::Return(Some(value)));
+        Ok(())
+    }
+}
+
+impl Lower<Value> for AnalyzedExpression {
+    fn lower(&self, builder: &mut FunctionBuilder) -> Result<Value, CodegenError> {
+        builder.spanned(self.span(), |builder| {
+            // Both operands are always evaluated, since either may call a procedure
+            let (expression, arith_op, op) = match self {
+                AnalyzedExpression::BitwiseAnd(expression, arith_op)
+                | AnalyzedExpression::LogicalAnd(expression, arith_op) => {
+                    (expression, arith_op, BinaryOp::And)
+                }
+                AnalyzedExpression::BitwiseOr(expression, arith_op)
+                | AnalyzedExpression::LogicalOr(expression, arith_op) => {
+                    (expression, arith_op, BinaryOp::Or)
+                }
+                AnalyzedExpression::BitwiseNot(arith_op)
+                | AnalyzedExpression::LogicalNot(arith_op) => {
+                    let operand = arith_op.lower(builder)?.scalar();
+                    return Ok(Value::Scalar(builder.unary(UnaryOp::Not, operand)));
+                }
+                AnalyzedExpression::Cast(expression, value_type) => {
+                    let value = expression.lower(builder)?;
+                    return builder.cast(value, value_type, self.span());
+                }
+                AnalyzedExpression::ArithOp(arith_op) => return arith_op.lower(builder),
+            };
+            let lhs = expression.lower(builder)?;
+            let rhs = arith_op.lower(builder)?;
+            builder.arith(op, lhs, rhs)
+        })
+    }
+}
+
+impl Lower<Value> for AnalyzedArithOp {
+    fn lower(&self, builder: &mut FunctionBuilder) -> Result<Value, CodegenError> {
+        builder.spanned(self.span(), |builder| {
+            let (arith_op, relation, op) = match self {
+                AnalyzedArithOp::Plus(arith_op, relation)
+                | AnalyzedArithOp::ArrayScalarPlus(arith_op, relation)
+                | AnalyzedArithOp::ScalarArrayPlus(arith_op, relation)
+                | AnalyzedArithOp::ArrayPlus(arith_op, relation) => {
+                    (arith_op, relation, BinaryOp::Add)
+                }
+                AnalyzedArithOp::Minus(arith_op, relation)
+                | AnalyzedArithOp::ArrayScalarMinus(arith_op, relation)
+                | AnalyzedArithOp::ScalarArrayMinus(arith_op, relation)
+                | AnalyzedArithOp::ArrayMinus(arith_op, relation) => {
+                    (arith_op, relation, BinaryOp::Subtract)
+                }
+                AnalyzedArithOp::Cast(arith_op, value_type) => {
+                    let value = arith_op.lower(builder)?;
+                    return builder.cast(value, value_type, self.span());
+                }
+                AnalyzedArithOp::Relation(relation) => return relation.lower(builder),
+            };
+            let lhs = arith_op.lower(builder)?;
+            let rhs = relation.lower(builder)?;
+            builder.arith(op, lhs, rhs)
+        })
+    }
+}
+
+impl Lower<Value> for AnalyzedRelation {
+    fn lower(&self, builder: &mut FunctionBuilder) -> Result<Value, CodegenError> {
+        builder.spanned(self.span(), |builder| {
+            let (relation, term, op) = match self {
+                AnalyzedRelation::LessThan(relation, term) => (relation, term, BinaryOp::LessThan),
+                AnalyzedRelation::LessThanEq(relation, term) => {
+                    (relation, term, BinaryOp::LessThanEq)
+                }
+                AnalyzedRelation::GreaterThan(relation, term) => {
+                    (relation, term, BinaryOp::GreaterThan)
+                }
+                AnalyzedRelation::GreaterThanEq(relation, term) => {
+                    (relation, term, BinaryOp::GreaterThanEq)
+                }
+                AnalyzedRelation::Equals(relation, term) => (relation, term, BinaryOp::Equals),
+                AnalyzedRelation::NotEquals(relation, term) => {
+                    (relation, term, BinaryOp::NotEquals)
+                }
+                AnalyzedRelation::Cast(relation, value_type) => {
+                    let value = relation.lower(builder)?;
+                    return builder.cast(value, value_type, self.span());
+                }
+                AnalyzedRelation::Term(term) => return term.lower(builder),
+            };
+            let lhs = relation.lower(builder)?.scalar();
+            let rhs = term.lower(builder)?.scalar();
+            Ok(Value::Scalar(builder.binary(op, lhs, rhs)))
+        })
+    }
+}
+
+impl Lower<Value> for AnalyzedTerm {
+    fn lower(&self, builder: &mut FunctionBuilder) -> Result<Value, CodegenError> {
+        builder.spanned(self.span(), |builder| {
+            let (term, factor, op) = match self {
+                AnalyzedTerm::Multiply(term, factor)
+                | AnalyzedTerm::ArrayScalarMultiply(term, factor)
+                | AnalyzedTerm::ScalarArrayMultiply(term, factor)
+                | AnalyzedTerm::ArrayMultiply(term, factor) => (term, factor, BinaryOp::Multiply),
+                AnalyzedTerm::Divide(term, factor)
+                | AnalyzedTerm::ArrayScalarDivide(term, factor)
+                | AnalyzedTerm::ScalarArrayDivide(term, factor)
+                | AnalyzedTerm::ArrayDivide(term, factor) => (term, factor, BinaryOp::Divide),
+                AnalyzedTerm::Cast(term, value_type) => {
+                    let value = term.lower(builder)?;
+                    return builder.cast(value, value_type, self.span());
+                }
+                AnalyzedTerm::Factor(factor) => return factor.lower(builder),
+            };
+            let lhs = term.lower(builder)?;
+            let rhs = factor.lower(builder)?;
+            builder.arith(op, lhs, rhs)
+        })
+    }
+}
+
+impl Lower<Value> for AnalyzedFactor {
+    fn lower(&self, builder: &mut FunctionBuilder) -> Result<Value, CodegenError> {
+        builder.spanned(self.span(), |builder| match self {
+            AnalyzedFactor::NestedExpression(expression) => expression.lower(builder),
+            AnalyzedFactor::ProcedureCall(proc_call) => proc_call.lower(builder),
+            AnalyzedFactor::Name(name) => name.lower(builder),
+            AnalyzedFactor::NegatedName(name) => {
+                let value = name.lower(builder)?;
+                builder.negate(value)
+            }
+            AnalyzedFactor::Number(number, _) => Ok(number_constant(number, false)),
+            AnalyzedFactor::NegatedNumber(number, _) => Ok(number_constant(number, true)),
+            AnalyzedFactor::String(value, _) => Ok(Value::Scalar(Operand::Const(
+                Constant::String(value.clone()),
+            ))),
+            AnalyzedFactor::True(_) => Ok(Value::Scalar(Operand::Const(Constant::Bool(true)))),
+            AnalyzedFactor::False(_) => Ok(Value::Scalar(Operand::Const(Constant::Bool(false)))),
+            AnalyzedFactor::Cast(factor, value_type) => {
+                let value = factor.lower(builder)?;
+                builder.cast(value, value_type, self.span())
+            }
+        })
+    }
+}
+
+fn number_constant(number: &AnalyzedNumber, negate: bool) -> Value {
+    let constant = match (number, negate) {
+        (AnalyzedNumber::Integer(value), false) => Constant::Int(*value),
+        (AnalyzedNumber::Integer(value), true) => Constant::Int(value.wrapping_neg()),
+        (AnalyzedNumber::Float(value), false) => Constant::Float(*value),
+        (AnalyzedNumber::Float(value), true) => Constant::Float(-value),
+    };
+    Value::Scalar(Operand::Const(constant))
+}
+
+impl Lower<Value> for AnalyzedName {
+    fn lower(&self, builder: &mut FunctionBuilder) -> Result<Value, CodegenError> {
+        builder.spanned(self.span(), |builder| {
+            match self {
+                AnalyzedName::Name(identifier, binding, span) => {
+                    let (variable, value_type) = builder.variable(identifier, *binding, *span)?;
+                    if let Type::Array(..) = value_type {
+                        // Arrays are copied when read, in case evaluating the rest
+                        // of the expression assigns to the variable
+                        let copy = builder.generated_local("array", value_type);
+                        builder.emit(Instruction::CopyArray {
+                            dest: copy,
+                            source: variable,
+                        });
+                        return Ok(Value::Array(copy));
+                    }
+                    Ok(Value::Scalar(
+                        builder.load(Place::Variable(variable), value_type),
+                    ))
+                }
+                AnalyzedName::Indexed(identifier, binding, expression, span) => {
+                    let index = expression.lower(builder)?.scalar();
+                    let (variable, value_type) = builder.variable(identifier, *binding, *span)?;
+                    let Type::Array(element_type, _) = value_type else {
+                        unreachable!("only arrays are indexed")
+                    };
+                    Ok(Value::Scalar(
+                        builder.load(Place::Element(variable, index), *element_type),
+                    ))
+                }
+            }
+        })
+    }
+}
+
+impl Lower<Value> for AnalyzedProcedureCall {
+    fn lower(&self, builder: &mut FunctionBuilder) -> Result<Value, CodegenError> {
+        builder.spanned(self.span, |builder| {
+            let args = self
+                .arg_list
+                .iter()
+                .map(|arg| {
+                    Ok(match arg.lower(builder)? {
+                        Value::Scalar(operand) => Argument::Scalar(operand),
+                        Value::Array(variable) => Argument::Array(variable),
+                    })
+                })
+                .collect::<Result<Vec<Argument>, CodegenError>>()?;
+            Ok(Value::Scalar(builder.call(
+                &self.identifier,
+                self.binding,
+                args,
+                self.span,
+            )?))
+        })
+    }
+}
@@ -0,0 +1,660 @@
+use std::collections::HashSet;
+
+use thiserror::Error;
+
+use crate::semantics::value::Type;
+
+use super::{
+    Argument, BinaryOp, BlockId, Callee, Function, Instruction, Module, Operand, Place, Temp,
+    Terminator, UnaryOp, Variable,
+};
+
+/// A module that breaks one of the IR's rules.
+/// Lowering an analyzed program always gives a valid module, so these indicate a bug.
+#[derive(Debug, Error, PartialEq)]
+pub enum VerifyError {
+    #[error("In {0}: the function has no blocks")]
+    NoBlocks(String),
+    #[error("In {0}, bb{1}: jumps to bb{2}, which does not exist")]
+    MissingBlock(String, usize, usize),
+    #[error("In {0}, bb{1}: temporary %{2} does not exist")]
+    UndefinedTemp(String, usize, usize),
+    #[error("In {0}, bb{1}: temporary %{2} is assigned more than once")]
+    ReassignedTemp(String, usize, usize),
+    #[error("In {0}, bb{1}: temporary %{2} may be used before it is assigned")]
+    UnassignedTemp(String, usize, usize),
+    #[error("In {0}, bb{1}: variable {2:?} does not exist")]
+    UndefinedVariable(String, usize, Variable),
+    #[error("In {0}, bb{1}: procedure {2} does not exist")]
+    UndefinedProcedure(String, usize, usize),
+    #[error("In {0}, bb{1}: expected {2}, got {3}")]
+    TypeMismatch(String, usize, String, Type),
+    #[error("In {0}, bb{1}: call to {2} passes the frame {3} static link(s) out, which it isn't nested in")]
+    InvalidStaticLink(String, usize, String, usize),
+    #[error("In {0}, bb{1}: call to {2} has {3} arguments, expected {4}")]
+    ArgCountMismatch(String, usize, String, usize, usize),
+    #[error("In {0}, bb{1}: cannot cast {2} to {3}")]
+    InvalidCast(String, usize, Type, Type),
+    #[error("In {0}, bb{1}: the block has {2} instructions, but {3} spans")]
+    SpanCountMismatch(String, usize, usize, usize),
+}
+
+/// Checks that every function in a module is well-formed and consistently typed.
+pub fn verify(module: &Module) -> Result<(), VerifyError> {
+    for function in module.functions() {
+        FunctionVerifier {
+            module,
+            function,
+            block: 0,
+        }
+        .verify()?;
+    }
+    Ok(())
+}
+
+struct FunctionVerifier<'m> {
+    module: &'m Module,
+    function: &'m Function,
+    /// The block being checked, for error messages
+    block: usize,
+}
+
+impl<'m> FunctionVerifier<'m> {
+    fn verify(mut self) -> Result<(), VerifyError> {
+        let function = self.function;
+        if function.blocks.is_empty() {
+            return Err(VerifyError::NoBlocks(function.name.clone()));
+        }
+
+        let mut assigned = HashSet::new();
+        for (id, block) in function.blocks.iter().enumerate() {
+            self.block = id;
+            if block.spans.len() != block.instructions.len() {
+                return Err(VerifyError::SpanCountMismatch(
+                    function.name.clone(),
+                    id,
+                    block.instructions.len(),
+                    block.spans.len(),
+                ));
+            }
+            for successor in block.terminator.successors() {
+                if successor.0 >= function.blocks.len() {
+                    return Err(VerifyError::MissingBlock(
+                        function.name.clone(),
+                        id,
+                        successor.0,
+                    ));
+                }
+            }
+            for instruction in block.instructions.iter() {
+                let dest = destination(instruction);
+                if let Some(dest) = dest {
+                    self.temp_type(dest)?;
+                    if !assigned.insert(dest) {
+                        return Err(VerifyError::ReassignedTemp(
+                            function.name.clone(),
+                            id,
+                            dest.0,
+                        ));
+                    }
+                }
+            }
+        }
+
+        for (id, block) in function.blocks.iter().enumerate() {
+            self.block = id;
+            for instruction in block.instructions.iter() {
+                self.check_instruction(instruction)?;
+            }
+            self.check_terminator(&block.terminator)?;
+        }
+
+        self.check_assigned_before_use()
+    }
+
+    /// Checks each temporary is assigned on every path to each of its uses.
+    /// Unreachable blocks are skipped, since no path reaches them.
+    fn check_assigned_before_use(&mut self) -> Result<(), VerifyError> {
+        let function = self.function;
+        let predecessors = predecessors(function);
+        let reachable = reachable(function);
+
+        // The temporaries assigned on every path to the start of each block, where
+        // `None` means no path has been seen yet
+        let mut assigned_on_entry: Vec<Option<HashSet<Temp>>> = vec![None; function.blocks.len()];
+        assigned_on_entry[0] = Some(HashSet::new());
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for id in 1..function.blocks.len() {
+                let mut entry: Option<HashSet<Temp>> = None;
+                for predecessor in predecessors[id].iter() {
+                    let Some(assigned) = &assigned_on_entry[predecessor.0] else {
+                        continue;
+                    };
+                    let mut assigned = assigned.clone();
+                    assigned.extend(
+                        function.blocks[predecessor.0]
+                            .instructions
+                            .iter()
+                            .filter_map(destination),
+                    );
+                    entry = Some(match entry {
+                        Some(entry) => entry.intersection(&assigned).copied().collect(),
+                        None => assigned,
+                    });
+                }
+                if entry.is_some() && entry != assigned_on_entry[id] {
+                    assigned_on_entry[id] = entry;
+                    changed = true;
+                }
+            }
+        }
+
+        for (id, block) in function.blocks.iter().enumerate() {
+            if !reachable.contains(&BlockId(id)) {
+                continue;
+            }
+            self.block = id;
+            let mut assigned = assigned_on_entry[id].clone().unwrap_or_default();
+            for instruction in block.instructions.iter() {
+                for temp in used_temps(instruction) {
+                    self.check_assigned(&assigned, temp)?;
+                }
+                assigned.extend(destination(instruction));
+            }
+            for temp in terminator_temps(&block.terminator) {
+                self.check_assigned(&assigned, temp)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn check_assigned(&self, assigned: &HashSet<Temp>, temp: Temp) -> Result<(), VerifyError> {
+        match assigned.contains(&temp) {
+            true => Ok(()),
+            false => Err(VerifyError::UnassignedTemp(
+                self.function.name.clone(),
+                self.block,
+                temp.0,
+            )),
+        }
+    }
+
+    fn check_instruction(&self, instruction: &Instruction) -> Result<(), VerifyError> {
+        match instruction {
+            Instruction::Load { dest, source } => {
+                let place_type = self.place_type(source)?;
+                self.expect_type(&place_type, self.temp_type(*dest)?)
+            }
+            Instruction::Store { dest, value } => {
+                let place_type = self.place_type(dest)?;
+                self.expect_type(&place_type, &self.operand_type(value)?)
+            }
+            Instruction::CopyArray { dest, source } => {
+                let dest_type = self.variable_type(*dest)?;
+                let source_type = self.variable_type(*source)?;
+                if !matches!(dest_type, Type::Array(..)) {
+                    return Err(self.mismatch("an array", dest_type));
+                }
+                self.expect_type(dest_type, source_type)
+            }
+            Instruction::Unary { dest, op, operand } => {
+                let operand_type = self.operand_type(operand)?;
+                match (op, &operand_type) {
+                    (UnaryOp::Negate, Type::Int | Type::Float) => (),
+                    (UnaryOp::Not, Type::Bool | Type::Int) => (),
+                    (UnaryOp::Negate, _) => return Err(self.mismatch("a number", &operand_type)),
+                    (UnaryOp::Not, _) => {
+                        return Err(self.mismatch("a bool or integer", &operand_type))
+                    }
+                }
+                self.expect_type(&operand_type, self.temp_type(*dest)?)
+            }
+            Instruction::Binary { dest, op, lhs, rhs } => {
+                let lhs_type = self.operand_type(lhs)?;
+                self.expect_type(&lhs_type, &self.operand_type(rhs)?)?;
+                match (op, &lhs_type) {
+                    (BinaryOp::And | BinaryOp::Or, Type::Bool | Type::Int) => (),
+                    (BinaryOp::And | BinaryOp::Or, _) => {
+                        return Err(self.mismatch("a bool or integer", &lhs_type))
+                    }
+                    (BinaryOp::Equals | BinaryOp::NotEquals, Type::String) => (),
+                    (_, Type::Int | Type::Float) => (),
+                    (_, _) => return Err(self.mismatch("a number", &lhs_type)),
+                }
+                let result_type = match op.is_comparison() {
+                    true => Type::Bool,
+                    false => lhs_type,
+                };
+                self.expect_type(&result_type, self.temp_type(*dest)?)
+            }
+            Instruction::Cast { dest, operand } => {
+                let from = self.operand_type(operand)?;
+                let to = self.temp_type(*dest)?;
+                match (&from, to) {
+                    (Type::Int, Type::Float | Type::Bool)
+                    | (Type::Float | Type::Bool, Type::Int) => Ok(()),
+                    _ => Err(VerifyError::InvalidCast(
+                        self.function.name.clone(),
+                        self.block,
+                        from,
+                        to.clone(),
+                    )),
+                }
+            }
+            Instruction::Call { dest, callee, args } => {
+                let (name, param_types, return_type) = match callee {
+                    Callee::Procedure(id) | Callee::Nested(id, _) => {
+                        let procedure = self.module.procedures.get(id.0).ok_or_else(|| {
+                            VerifyError::UndefinedProcedure(
+                                self.function.name.clone(),
+                                self.block,
+                                id.0,
+                            )
+                        })?;
+                        self.check_static_link(callee, procedure)?;
+                        let param_types = procedure
+                            .params()
+                            .iter()
+                            .map(|param| param.value_type.clone())
+                            .collect();
+                        (
+                            procedure.name.clone(),
+                            param_types,
+                            procedure.return_type.clone(),
+                        )
+                    }
+                    Callee::Builtin(builtin) => (
+                        builtin.to_string(),
+                        builtin.param_types(),
+                        builtin.return_type(),
+                    ),
+                };
+
+                if args.len() != param_types.len() {
+                    return Err(VerifyError::ArgCountMismatch(
+                        self.function.name.clone(),
+                        self.block,
+                        name,
+                        args.len(),
+                        param_types.len(),
+                    ));
+                }
+                for (arg, param_type) in args.iter().zip(param_types.iter()) {
+                    let arg_type = match arg {
+                        Argument::Scalar(operand) => self.operand_type(operand)?,
+                        Argument::Array(variable) => self.variable_type(*variable)?.clone(),
+                    };
+                    self.expect_type(param_type, &arg_type)?;
+                }
+                self.expect_type(&return_type, self.temp_type(*dest)?)
+            }
+        }
+    }
+
+    /// Checks a procedure with a parent is passed that parent's frame, and only those are passed one
+    fn check_static_link(&self, callee: &Callee, procedure: &Function) -> Result<(), VerifyError> {
+        let (depth, linked) = match (callee, procedure.parent) {
+            (Callee::Procedure(_) | Callee::Builtin(_), None) => return Ok(()),
+            (Callee::Nested(_, depth), Some(parent)) => {
+                let parent = self.module.procedure(parent);
+                let linked = self.module.enclosing(self.function, *depth);
+                (
+                    *depth,
+                    linked.is_some_and(|linked| std::ptr::eq(linked, parent)),
+                )
+            }
+            (Callee::Nested(_, depth), None) => (*depth, false),
+            // A procedure with a parent can't be called without a frame
+            (_, Some(_)) => (0, false),
+        };
+        match linked {
+            true => Ok(()),
+            false => Err(VerifyError::InvalidStaticLink(
+                self.function.name.clone(),
+                self.block,
+                procedure.name.clone(),
+                depth,
+            )),
+        }
+    }
+
+    fn check_terminator(&self, terminator: &Terminator) -> Result<(), VerifyError> {
+        match terminator {
+            Terminator::Jump(_) => Ok(()),
+            Terminator::Branch { condition, .. } => {
+                self.expect_type(&Type::Bool, &self.operand_type(condition)?)
+            }
+            Terminator::Return(value) => match (value, &self.function.return_type) {
+                (None, Type::Void) => Ok(()),
+                (None, return_type) => Err(self.mismatch(&return_type.to_string(), &Type::Void)),
+                (Some(value), return_type) => {
+                    self.expect_type(return_type, &self.operand_type(value)?)
+                }
+            },
+        }
+    }
+
+    fn mismatch(&self, expected: &str, found: &Type) -> VerifyError {
+        VerifyError::TypeMismatch(
+            self.function.name.clone(),
+            self.block,
+            String::from(expected),
+            found.clone(),
+        )
+    }
+
+    fn expect_type(&self, expected: &Type, found: &Type) -> Result<(), VerifyError> {
+        match expected == found {
+            true => Ok(()),
+            false => Err(self.mismatch(&expected.to_string(), found)),
+        }
+    }
+
+    fn temp_type(&self, temp: Temp) -> Result<&'m Type, VerifyError> {
+        self.function.temps.get(temp.0).ok_or_else(|| {
+            VerifyError::UndefinedTemp(self.function.name.clone(), self.block, temp.0)
+        })
+    }
+
+    fn operand_type(&self, operand: &Operand) -> Result<Type, VerifyError> {
+        match operand {
+            Operand::Temp(temp) => self.temp_type(*temp).cloned(),
+            Operand::Const(constant) => Ok(constant.value_type()),
+        }
+    }
+
+    fn variable_type(&self, variable: Variable) -> Result<&'m Type, VerifyError> {
+        self.function
+            .variable_type(self.module, variable)
+            .ok_or_else(|| {
+                VerifyError::UndefinedVariable(self.function.name.clone(), self.block, variable)
+            })
+    }
+
+    /// The scalar type a place holds
+    fn place_type(&self, place: &Place) -> Result<Type, VerifyError> {
+        let variable_type = self.variable_type(place.variable())?;
+        match (place, variable_type) {
+            (Place::Variable(_), Type::Array(..) | Type::Void) => {
+                Err(self.mismatch("a scalar", variable_type))
+            }
+            (Place::Variable(_), _) => Ok(variable_type.clone()),
+            (Place::Element(_, index), Type::Array(element_type, _)) => {
+                self.expect_type(&Type::Int, &self.operand_type(index)?)?;
+                Ok(*element_type.clone())
+            }
+            (Place::Element(..), _) => Err(self.mismatch("an array", variable_type)),
+        }
+    }
+}
+
+fn destination(instruction: &Instruction) -> Option<Temp> {
+    match instruction {
+        Instruction::Load { dest, .. }
+        | Instruction::Unary { dest, .. }
+        | Instruction::Binary { dest, .. }
+        | Instruction::Cast { dest, .. }
+        | Instruction::Call { dest, .. } => Some(*dest),
+        Instruction::Store { .. } | Instruction::CopyArray { .. } => None,
+    }
+}
+
+fn operand_temps<'o>(operands: impl IntoIterator<Item = &'o Operand>) -> Vec<Temp> {
+    operands
+        .into_iter()
+        .filter_map(|operand| match operand {
+            Operand::Temp(temp) => Some(*temp),
+            Operand::Const(_) => None,
+        })
+        .collect()
+}
+
+fn place_operand(place: &Place) -> Option<&Operand> {
+    match place {
+        Place::Variable(_) => None,
+        Place::Element(_, index) => Some(index),
+    }
+}
+
+fn used_temps(instruction: &Instruction) -> Vec<Temp> {
+    match instruction {
+        Instruction::Load { source, .. } => operand_temps(place_operand(source)),
+        Instruction::Store { dest, value } => {
+            operand_temps(place_operand(dest).into_iter().chain([value]))
+        }
+        Instruction::CopyArray { .. } => Vec::new(),
+        Instruction::Unary { operand, .. } | Instruction::Cast { operand, .. } => {
+            operand_temps([operand])
+        }
+        Instruction::Binary { lhs, rhs, .. } => operand_temps([lhs, rhs]),
+        Instruction::Call { args, .. } => operand_temps(args.iter().filter_map(|arg| match arg {
+            Argument::Scalar(operand) => Some(operand),
+            Argument::Array(_) => None,
+        })),
+    }
+}
+
+fn terminator_temps(terminator: &Terminator) -> Vec<Temp> {
+    match terminator {
+        Terminator::Jump(_) | Terminator::Return(None) => Vec::new(),
+        Terminator::Branch { condition, .. } => operand_temps([condition]),
+        Terminator::Return(Some(value)) => operand_temps([value]),
+    }
+}
+
+fn predecessors(function: &Function) -> Vec<Vec<BlockId>> {
+    let mut predecessors = vec![Vec::new(); function.blocks.len()];
+    for (id, block) in function.blocks.iter().enumerate() {
+        for successor in block.terminator.successors() {
+            predecessors[successor.0].push(BlockId(id));
+        }
+    }
+    predecessors
+}
+
+fn reachable(function: &Function) -> HashSet<BlockId> {
+    let mut reachable = HashSet::from([BlockId(0)]);
+    let mut pending = vec![BlockId(0)];
+    while let Some(block) = pending.pop() {
+        for successor in function.block(block).terminator.successors() {
+            if reachable.insert(successor) {
+                pending.push(successor);
+            }
+        }
+    }
+    reachable
+}
+
+#[cfg(test)]
+use super::lower::lower;
+#[cfg(test)]
+use super::{Block, Builtin, Constant, FunctionId};
+#[cfg(test)]
+use crate::session::{with_test_program, NESTED_RUN};
+#[cfg(test)]
+use crate::span::Span;
+#[cfg(test)]
+use rstest::rstest;
+#[cfg(test)]
+use std::path::PathBuf;
+
+#[cfg(test)]
+#[rstest]
+fn verify_lowered_programs(#[files("tests/correct/*.src")] source_file: PathBuf) {
+    let module = with_test_program(source_file.to_str().unwrap(), |program| {
+        lower(program).unwrap()
+    });
+    assert_eq!(verify(&module), Ok(()), "{}", module);
+}
+
+#[cfg(test)]
+#[test]
+fn verify_lowered_static_links() {
+    let module = with_test_program(NESTED_RUN.0, |program| lower(program).unwrap());
+    assert_eq!(verify(&module), Ok(()), "{}", module);
+}
+
+#[cfg(test)]
+#[test]
+fn verify_rejects_wrong_static_link() {
+    let mut module = with_test_program(NESTED_RUN.0, |program| lower(program).unwrap());
+    // Link every nested call one frame further out than its callee's parent
+    for procedure in &mut module.procedures {
+        for block in &mut procedure.blocks {
+            for instruction in &mut block.instructions {
+                if let Instruction::Call {
+                    callee: Callee::Nested(_, depth),
+                    ..
+                } = instruction
+                {
+                    *depth += 1;
+                }
+            }
+        }
+    }
+    assert!(matches!(
+        verify(&module),
+        Err(VerifyError::InvalidStaticLink(..))
+    ));
+}
+
+/// A program with nothing but a body, made of the given temporaries and blocks
+#[cfg(test)]
+fn test_module(temps: Vec<Type>, blocks: Vec<Block>) -> Module {
+    Module {
+        name: String::from("test"),
+        globals: Vec::new(),
+        body: Function {
+            name: String::from("test"),
+            parent: None,
+            param_count: 0,
+            locals: Vec::new(),
+            temps,
+            return_type: Type::Void,
+            blocks,
+        },
+        procedures: Vec::new(),
+    }
+}
+
+/// A block whose instructions have no source
+#[cfg(test)]
+fn test_block(instructions: Vec<Instruction>, terminator: Terminator) -> Block {
+    Block {
+        spans: vec![Span::default(); instructions.len()],
+        instructions,
+        terminator,
+        terminator_span: Span::default(),
+    }
+}
+
+#[cfg(test)]
+fn call_getbool(dest: usize) -> Instruction {
+    Instruction::Call {
+        dest: Temp(dest),
+        callee: Callee::Builtin(Builtin::GetBool),
+        args: Vec::new(),
+    }
+}
+
+#[cfg(test)]
+#[rstest]
+#[case::missing_block(
+    vec![],
+    vec![test_block(vec![], Terminator::Jump(BlockId(3)))],
+    VerifyError::MissingBlock(String::from("test"), 0, 3),
+)]
+#[case::reassigned_temp(
+    vec![Type::Bool],
+    vec![test_block(vec![call_getbool(0), call_getbool(0)], Terminator::Return(None))],
+    VerifyError::ReassignedTemp(String::from("test"), 0, 0),
+)]
+#[case::non_bool_condition(
+    vec![],
+    vec![
+        test_block(
+            vec![],
+            Terminator::Branch { condition: Operand::Const(Constant::Int(1)), then_block: BlockId(0), else_block: BlockId(0) },
+        ),
+    ],
+    VerifyError::TypeMismatch(String::from("test"), 0, String::from("bool"), Type::Int),
+)]
+#[case::invalid_cast(
+    vec![Type::Int],
+    vec![test_block(
+        vec![Instruction::Cast { dest: Temp(0), operand: Operand::Const(Constant::String(String::new())) }],
+        Terminator::Return(None),
+    )],
+    VerifyError::InvalidCast(String::from("test"), 0, Type::String, Type::Int),
+)]
+#[case::wrong_argument(
+    vec![Type::Bool],
+    vec![test_block(
+        vec![Instruction::Call {
+            dest: Temp(0),
+            callee: Callee::Builtin(Builtin::PutInteger),
+            args: vec![Argument::Scalar(Operand::Const(Constant::Float(1.0)))],
+        }],
+        Terminator::Return(None),
+    )],
+    VerifyError::TypeMismatch(String::from("test"), 0, String::from("integer"), Type::Float),
+)]
+#[case::missing_procedure(
+    vec![Type::Bool],
+    vec![test_block(
+        vec![Instruction::Call { dest: Temp(0), callee: Callee::Procedure(FunctionId(0)), args: vec![] }],
+        Terminator::Return(None),
+    )],
+    VerifyError::UndefinedProcedure(String::from("test"), 0, 0),
+)]
+#[case::missing_span(
+    vec![Type::Bool],
+    vec![Block {
+        instructions: vec![call_getbool(0)],
+        spans: vec![],
+        terminator: Terminator::Return(None),
+        terminator_span: Span::default(),
+    }],
+    VerifyError::SpanCountMismatch(String::from("test"), 0, 1, 0),
+)]
+fn verify_rejects_invalid_functions(
+    #[case] temps: Vec<Type>,
+    #[case] blocks: Vec<Block>,
+    #[case] expected: VerifyError,
+) {
+    assert_eq!(verify(&test_module(temps, blocks)), Err(expected));
+}
+
+#[cfg(test)]
+#[test]
+fn verify_rejects_use_before_assignment() {
+    // %1 is only assigned when the branch is taken
+    let module = test_module(
+        vec![Type::Bool, Type::Bool, Type::Bool],
+        vec![
+            test_block(
+                vec![call_getbool(0)],
+                Terminator::Branch {
+                    condition: Operand::Temp(Temp(0)),
+                    then_block: BlockId(1),
+                    else_block: BlockId(2),
+                },
+            ),
+            test_block(vec![call_getbool(1)], Terminator::Jump(BlockId(2))),
+            test_block(
+                vec![Instruction::Unary {
+                    dest: Temp(2),
+                    op: UnaryOp::Not,
+                    operand: Operand::Temp(Temp(1)),
+                }],
+                Terminator::Return(None),
+            ),
+        ],
+    );
+    assert_eq!(
+        verify(&module),
+        Err(VerifyError::UnassignedTemp(String::from("test"), 2, 1))
+    );
+}
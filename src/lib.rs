@@ -0,0 +1,35 @@
+//! A compiler and interpreter for the crust language.
+//!
+//! [`session::Session`] runs the front end over a source file: [`scanner::scan`] turns the text
+//! into tokens, [`parser::parse_tokens`] builds a syntax tree from them, and
+//! [`semantics::AnalyzedProgram::analyze`] checks it and resolves types.
+//! Programs without errors are then checked by the [`lint`]s, for code that is valid but likely a mistake.
+//! Errors from every phase are collected in a [`diagnostics::DiagnosticSink`],
+//! and can be rendered against the source with [`diagnostics::render::Renderer`].
+//!
+//! An analyzed program can then be run with [`interpreter::Interpreter`], compiled to bytecode for
+//! [`bytecode::vm::Vm`] with [`bytecode::compile::compile`], lowered to the IR with
+//! [`ir::lower::lower`], or generated as LLVM IR, x86-64 assembly, C or WebAssembly by the
+//! backends in [`codegen`].
+//!
+//! For tools working on the source itself, [`syntax`] builds a lossless concrete syntax tree that
+//! keeps every comment and whitespace character, and [`format`](mod@format) lays a program out canonically.
+
+#![feature(box_patterns)]
+
+pub mod bytecode;
+pub mod codegen;
+pub mod diagnostics;
+pub mod format;
+pub mod interpreter;
+pub mod ir;
+pub mod lint;
+pub mod parser;
+pub mod scanner;
+pub mod semantics;
+pub mod session;
+pub mod span;
+pub mod syntax;
+pub mod tokens;
+
+pub use session::{Phase, Session};
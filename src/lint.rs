@@ -0,0 +1,284 @@
+//! Lints: checks for code that is valid, but likely a mistake.
+//!
+//! Each lint has a name it can be configured by, to allow it, warn about it (the default), or deny it.
+//! Denied lints are reported as errors, so the program fails to compile.
+//! Lints run once a program analyzes without errors, over the analyzed program and the symbols in its scopes.
+
+use std::collections::HashMap;
+
+use thiserror::Error;
+
+use crate::diagnostics::{Diagnostic, DiagnosticSink, Severity};
+use crate::semantics::flow::{self, FlowError};
+use crate::semantics::{assignment, AnalyzedProgram};
+use crate::span::Span;
+
+pub mod condition;
+pub mod range;
+pub mod shadow;
+pub mod unused;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Lint {
+    /// A procedure that can reach its end without returning a value
+    MissingReturn,
+    /// Statements after a `return` on every path
+    UnreachableCode,
+    /// A read of a variable that may not be assigned yet
+    UnassignedVariable,
+    /// A local or parameter that is never read
+    UnusedVariable,
+    UnusedProcedure,
+    /// A local with the same name as a global, which it hides
+    ShadowedGlobal,
+    /// A value assigned to a local that is overwritten or goes out of scope before it is read
+    UnusedAssignment,
+    /// A `for` loop whose condition doesn't use any variable or call
+    ConstantCondition,
+    /// An index in a `for` loop that may step outside its array
+    LoopBounds,
+}
+
+impl Lint {
+    pub const ALL: [Lint; 9] = [
+        Lint::MissingReturn,
+        Lint::UnreachableCode,
+        Lint::UnassignedVariable,
+        Lint::UnusedVariable,
+        Lint::UnusedProcedure,
+        Lint::ShadowedGlobal,
+        Lint::UnusedAssignment,
+        Lint::ConstantCondition,
+        Lint::LoopBounds,
+    ];
+
+    /// The name the lint is configured by
+    pub fn name(self) -> &'static str {
+        match self {
+            Lint::MissingReturn => "missing-return",
+            Lint::UnreachableCode => "unreachable-code",
+            Lint::UnassignedVariable => "unassigned-variable",
+            Lint::UnusedVariable => "unused-variable",
+            Lint::UnusedProcedure => "unused-procedure",
+            Lint::ShadowedGlobal => "shadowed-global",
+            Lint::UnusedAssignment => "unused-assignment",
+            Lint::ConstantCondition => "constant-condition",
+            Lint::LoopBounds => "loop-bounds",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Lint> {
+        Lint::ALL.into_iter().find(|lint| lint.name() == name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Allow,
+    Warn,
+    Deny,
+}
+
+/// The level each lint is reported at. Lints that haven't been set warn.
+#[derive(Debug, Clone, Default)]
+pub struct LintLevels(HashMap<Lint, Level>);
+
+impl LintLevels {
+    pub fn set(&mut self, lint: Lint, level: Level) {
+        self.0.insert(lint, level);
+    }
+
+    pub fn level(&self, lint: Lint) -> Level {
+        self.0.get(&lint).copied().unwrap_or(Level::Warn)
+    }
+
+    /// Reports `diagnostic`, found by `lint`, at the lint's level
+    pub fn report(&self, lint: Lint, diagnostic: Diagnostic, sink: &mut DiagnosticSink) {
+        let severity = match self.level(lint) {
+            Level::Allow => return,
+            Level::Warn => Severity::Warning,
+            Level::Deny => Severity::Error,
+        };
+        let mut diagnostic =
+            diagnostic.with_note(format!("reported by the `{}` lint", lint.name()));
+        diagnostic.severity = severity;
+        sink.push(diagnostic);
+    }
+}
+
+/// What the lints in this module find. Control flow and definite assignment are checked in
+/// [`flow`] and [`assignment`], which report errors of their own.
+#[derive(Debug, Error)]
+pub enum LintWarning {
+    #[error("Variable {0} is never read.")]
+    UnusedVariable(String, Span),
+    #[error("Parameter {0} is never read.")]
+    UnusedParameter(String, Span),
+    #[error("Procedure {0} is never called.")]
+    UnusedProcedure(String, Span),
+    /// Holds the span of the local, then the span of the global it shadows if it has one
+    #[error("Variable {0} shadows a global variable.")]
+    ShadowedGlobal(String, Span, Option<Span>),
+    #[error("Value assigned to {0} is never read.")]
+    UnusedAssignment(String, Span),
+    #[error("Loop condition is constant.")]
+    ConstantCondition(Span),
+    #[error("Index may be out of bounds for array {array} of length {length}.")]
+    LoopIndexOutOfBounds {
+        array: String,
+        /// The lowest and highest index the array may be given
+        low: i64,
+        high: i64,
+        length: usize,
+        index: Span,
+        /// The loop condition the range comes from
+        condition: Span,
+    },
+}
+
+impl LintWarning {
+    pub fn span(&self) -> Span {
+        match self {
+            LintWarning::UnusedVariable(_, span)
+            | LintWarning::UnusedParameter(_, span)
+            | LintWarning::UnusedProcedure(_, span)
+            | LintWarning::ShadowedGlobal(_, span, _)
+            | LintWarning::UnusedAssignment(_, span)
+            | LintWarning::ConstantCondition(span)
+            | LintWarning::LoopIndexOutOfBounds { index: span, .. } => *span,
+        }
+    }
+
+    pub fn lint(&self) -> Lint {
+        match self {
+            LintWarning::UnusedVariable(..) | LintWarning::UnusedParameter(..) => {
+                Lint::UnusedVariable
+            }
+            LintWarning::UnusedProcedure(..) => Lint::UnusedProcedure,
+            LintWarning::ShadowedGlobal(..) => Lint::ShadowedGlobal,
+            LintWarning::UnusedAssignment(..) => Lint::UnusedAssignment,
+            LintWarning::ConstantCondition(_) => Lint::ConstantCondition,
+            LintWarning::LoopIndexOutOfBounds { .. } => Lint::LoopBounds,
+        }
+    }
+}
+
+/// The lint each control-flow error is reported by
+fn flow_lint(err: &FlowError) -> Lint {
+    match err {
+        FlowError::MissingReturn(..) => Lint::MissingReturn,
+        FlowError::Unreachable(..) => Lint::UnreachableCode,
+    }
+}
+
+/// Runs every lint over `program`, reporting what they find to `sink` in source order.
+/// Statements with errors are left out of analyzed programs, so this should only run on programs without any.
+pub fn check(program: &AnalyzedProgram, levels: &LintLevels, sink: &mut DiagnosticSink) {
+    let mut found: Vec<(Lint, Span, Diagnostic)> = Vec::new();
+    for err in flow::check(program).iter() {
+        found.push((flow_lint(err), err.span(), Diagnostic::from(err)));
+    }
+    for err in assignment::check(program).iter() {
+        found.push((Lint::UnassignedVariable, err.span(), Diagnostic::from(err)));
+    }
+    let warnings = [
+        unused::check(program),
+        shadow::check(program),
+        condition::check(program),
+        range::check(program),
+    ];
+    for warning in warnings.iter().flatten() {
+        found.push((warning.lint(), warning.span(), Diagnostic::from(warning)));
+    }
+
+    // Sorting is stable, so findings at the same place stay in the order they were found
+    found.sort_by_key(|(_, span, _)| span.start);
+    for (lint, _, diagnostic) in found {
+        levels.report(lint, diagnostic, sink);
+    }
+}
+
+#[cfg(test)]
+use crate::session::Session;
+
+#[cfg(test)]
+#[test]
+fn lints_are_reported_at_their_level() {
+    let source = "program levels is
+    procedure unused : integer(variable n : integer)
+    begin
+    end procedure;
+begin
+end program.
+";
+    let mut levels = LintLevels::default();
+    levels.set(Lint::UnusedVariable, Level::Allow);
+    levels.set(Lint::MissingReturn, Level::Deny);
+    let mut session = Session::from_text("test.src", source).with_lint_levels(levels);
+    assert!(session.analyze().is_err());
+
+    let diagnostics = session.diagnostics().diagnostics();
+    let found: Vec<(&str, Severity)> = diagnostics
+        .iter()
+        .map(|diagnostic| (diagnostic.message.as_str(), diagnostic.severity))
+        .collect();
+    assert_eq!(
+        found,
+        [
+            (
+                "Not all paths in procedure unused return a value.",
+                Severity::Error
+            ),
+            ("Procedure unused is never called.", Severity::Warning),
+        ]
+    );
+    assert_eq!(
+        diagnostics[1].notes,
+        ["reported by the `unused-procedure` lint"]
+    );
+}
+
+#[cfg(test)]
+#[test]
+fn shadowed_globals_and_constant_conditions_are_found() {
+    let source = "program lints is
+    variable total : integer;
+    procedure sum : integer(variable total : integer)
+        variable i : integer;
+    begin
+        for (i := 0; 1 < 2)
+            return total;
+        end for;
+        for (i := 0; i < total)
+            i := i + 1;
+        end for;
+        return 0;
+    end procedure;
+begin
+    total := sum(1);
+end program.
+";
+    let mut session = Session::from_text("test.src", source);
+    let program = session.analyze().unwrap();
+    let found: Vec<String> = [shadow::check(program), condition::check(program)]
+        .iter()
+        .flatten()
+        .map(|warning| warning.to_string())
+        .collect();
+    assert_eq!(
+        found,
+        [
+            "Variable total shadows a global variable.",
+            "Loop condition is constant."
+        ]
+    );
+}
+
+#[cfg(test)]
+#[test]
+fn lint_names_round_trip() {
+    for lint in Lint::ALL {
+        assert_eq!(Lint::from_name(lint.name()), Some(lint));
+    }
+    assert_eq!(Lint::from_name("unused"), None);
+}
@@ -0,0 +1,46 @@
+use crate::semantics::expression::AnalyzedFactor;
+use crate::semantics::procedure::AnalyzedProcedure;
+use crate::semantics::statement::{AnalyzedBlock, AnalyzedStatement};
+use crate::semantics::AnalyzedProgram;
+
+use super::LintWarning;
+
+/// Finds `for` loops whose condition doesn't use any variable or call,
+/// so it's the same every time it's checked
+pub fn check(program: &AnalyzedProgram) -> Vec<LintWarning> {
+    let mut warnings = Vec::new();
+    check_block(&program.block, &mut warnings);
+    AnalyzedProcedure::visit_all(&program.procedures, &mut Vec::new(), &mut |enclosing| {
+        check_block(&enclosing[enclosing.len() - 1].block, &mut warnings);
+    });
+    warnings
+}
+
+fn check_block(block: &AnalyzedBlock, warnings: &mut Vec<LintWarning>) {
+    for statement in block.0.iter() {
+        match statement {
+            AnalyzedStatement::If(statement) => {
+                check_block(&statement.then_block, warnings);
+                if let Some(else_block) = &statement.else_block {
+                    check_block(else_block, warnings);
+                }
+            }
+            AnalyzedStatement::Loop(statement) => {
+                let mut constant = true;
+                statement.condition.visit_factors(&mut |factor| {
+                    if let AnalyzedFactor::Name(_)
+                    | AnalyzedFactor::NegatedName(_)
+                    | AnalyzedFactor::ProcedureCall(_) = factor
+                    {
+                        constant = false;
+                    }
+                });
+                if constant {
+                    warnings.push(LintWarning::ConstantCondition(statement.condition.span()));
+                }
+                check_block(&statement.loop_body, warnings);
+            }
+            AnalyzedStatement::Assignment(_) | AnalyzedStatement::Return(_) => {}
+        }
+    }
+}
@@ -0,0 +1,322 @@
+use crate::semantics::context::Binding;
+use crate::semantics::expression::{
+    AnalyzedArithOp, AnalyzedExpression, AnalyzedFactor, AnalyzedName, AnalyzedRelation,
+    AnalyzedTerm,
+};
+use crate::semantics::procedure::AnalyzedProcedure;
+use crate::semantics::statement::{
+    AnalyzedAssignment, AnalyzedBlock, AnalyzedLoop, AnalyzedStatement,
+};
+use crate::semantics::value::Type;
+use crate::semantics::AnalyzedProgram;
+use crate::span::Span;
+
+use super::LintWarning;
+
+/// Finds indices in `for` loops that may step outside their array, going by the range of the loop variable.
+/// The range is only known for loops like `for (i := 0; i < 10)`, where the body only ever steps `i`
+/// by constants in the direction the condition expects.
+/// Indices into the array are then checked if they're `i`, or `i` plus or minus a constant.
+pub fn check(program: &AnalyzedProgram) -> Vec<LintWarning> {
+    let mut warnings = Vec::new();
+    check_block(program, &program.block, &[], &mut warnings);
+    AnalyzedProcedure::visit_all(&program.procedures, &mut Vec::new(), &mut |enclosing| {
+        let block = &enclosing[enclosing.len() - 1].block;
+        check_block(program, block, enclosing, &mut warnings);
+    });
+    warnings
+}
+
+fn check_block<'p>(
+    program: &'p AnalyzedProgram,
+    block: &'p AnalyzedBlock,
+    enclosing: &[&'p AnalyzedProcedure],
+    warnings: &mut Vec<LintWarning>,
+) {
+    for statement in block.0.iter() {
+        match statement {
+            AnalyzedStatement::If(statement) => {
+                check_block(program, &statement.then_block, enclosing, warnings);
+                if let Some(else_block) = &statement.else_block {
+                    check_block(program, else_block, enclosing, warnings);
+                }
+            }
+            AnalyzedStatement::Loop(statement) => {
+                if let Some(range) = LoopRange::infer(statement) {
+                    let mut walker = Walker {
+                        program,
+                        enclosing,
+                        range: &range,
+                        least_stepped: 0,
+                        most_stepped: 0,
+                        nested_loops: 0,
+                        steps: true,
+                        warnings: Vec::new(),
+                    };
+                    walker.block(&statement.loop_body);
+                    // Without a step the loop never ends, unless something else changes the variable
+                    if walker.steps && walker.most_stepped > 0 {
+                        warnings.append(&mut walker.warnings);
+                    }
+                }
+                check_block(program, &statement.loop_body, enclosing, warnings);
+            }
+            AnalyzedStatement::Assignment(_) | AnalyzedStatement::Return(_) => {}
+        }
+    }
+}
+
+/// The values a loop variable can have at the start of each time through the loop
+struct LoopRange<'p> {
+    identifier: &'p str,
+    binding: Binding,
+    increasing: bool,
+    low: i64,
+    high: i64,
+    condition: Span,
+}
+
+impl<'p> LoopRange<'p> {
+    fn infer(statement: &'p AnalyzedLoop) -> Option<Self> {
+        let destination = &statement.assignment.destination;
+        if destination.expression.is_some() {
+            return None;
+        }
+        let start = statement.assignment.expression.integer_literal()?;
+        let AnalyzedExpression::ArithOp(AnalyzedArithOp::Relation(condition)) =
+            &statement.condition
+        else {
+            return None;
+        };
+        let (AnalyzedRelation::LessThan(variable, end)
+        | AnalyzedRelation::LessThanEq(variable, end)
+        | AnalyzedRelation::GreaterThan(variable, end)
+        | AnalyzedRelation::GreaterThanEq(variable, end)) = condition
+        else {
+            return None;
+        };
+        if variable_of(variable) != Some((destination.identifier.as_str(), destination.binding)) {
+            return None;
+        }
+        let end = end.integer_literal()?;
+        let (increasing, low, high) = match condition {
+            AnalyzedRelation::LessThan(..) => (true, start, end.checked_sub(1)?),
+            AnalyzedRelation::LessThanEq(..) => (true, start, end),
+            AnalyzedRelation::GreaterThan(..) => (false, end.checked_add(1)?, start),
+            _ => (false, end, start),
+        };
+        // Otherwise the body never runs
+        (low <= high).then_some(LoopRange {
+            identifier: &destination.identifier,
+            binding: destination.binding,
+            increasing,
+            low,
+            high,
+            condition: statement.condition.span(),
+        })
+    }
+
+    /// The constant `arith_op` adds to the loop variable, if it's the variable plus or minus a constant
+    fn offset(&self, arith_op: &AnalyzedArithOp) -> Option<i64> {
+        let (variable, offset) = match arith_op {
+            AnalyzedArithOp::Relation(relation) => (relation, 0),
+            AnalyzedArithOp::Plus(box AnalyzedArithOp::Relation(relation), constant) => {
+                (relation, constant.integer_literal()?)
+            }
+            AnalyzedArithOp::Minus(box AnalyzedArithOp::Relation(relation), constant) => {
+                (relation, constant.integer_literal()?.checked_neg()?)
+            }
+            _ => return None,
+        };
+        (variable_of(variable) == Some((self.identifier, self.binding))).then_some(offset)
+    }
+}
+
+/// The scalar variable `relation` reads, if that's all it is
+fn variable_of(relation: &AnalyzedRelation) -> Option<(&str, Binding)> {
+    match relation {
+        AnalyzedRelation::Term(AnalyzedTerm::Factor(AnalyzedFactor::Name(AnalyzedName::Name(
+            identifier,
+            binding,
+            _,
+        )))) => Some((identifier, *binding)),
+        _ => None,
+    }
+}
+
+/// Walks the body of a loop in order, checking indices against the range of its variable
+struct Walker<'p, 'r> {
+    program: &'p AnalyzedProgram,
+    enclosing: &'r [&'p AnalyzedProcedure],
+    range: &'r LoopRange<'p>,
+    /// The least and the most the variable may have stepped since the start of this time through the loop
+    least_stepped: i64,
+    most_stepped: i64,
+    nested_loops: usize,
+    /// Whether every assignment to the variable so far is a step the range allows for
+    steps: bool,
+    warnings: Vec<LintWarning>,
+}
+
+impl<'p, 'r> Walker<'p, 'r> {
+    fn block(&mut self, block: &'p AnalyzedBlock) {
+        for statement in block.0.iter() {
+            self.statement(statement);
+        }
+    }
+
+    fn statement(&mut self, statement: &'p AnalyzedStatement) {
+        match statement {
+            AnalyzedStatement::Assignment(assignment) => self.assignment(assignment),
+            AnalyzedStatement::If(statement) => {
+                self.expression(&statement.conditional_expr);
+                let before = (self.least_stepped, self.most_stepped);
+                self.block(&statement.then_block);
+                let then_stepped = (self.least_stepped, self.most_stepped);
+                (self.least_stepped, self.most_stepped) = before;
+                if let Some(else_block) = &statement.else_block {
+                    self.block(else_block);
+                }
+                self.least_stepped = self.least_stepped.min(then_stepped.0);
+                self.most_stepped = self.most_stepped.max(then_stepped.1);
+            }
+            // Steps in a nested loop could happen any number of times, so the range can't be trusted
+            AnalyzedStatement::Loop(statement) => {
+                self.nested_loops += 1;
+                self.assignment(&statement.assignment);
+                self.expression(&statement.condition);
+                self.block(&statement.loop_body);
+                self.nested_loops -= 1;
+            }
+            AnalyzedStatement::Return(statement) => self.expression(&statement.expression),
+        }
+    }
+
+    fn assignment(&mut self, assignment: &'p AnalyzedAssignment) {
+        let destination = &assignment.destination;
+        if let Some(index) = &destination.expression {
+            self.expression(index);
+            self.index(&destination.identifier, destination.binding, index);
+        }
+        self.expression(&assignment.expression);
+
+        let is_variable = destination.expression.is_none()
+            && destination.identifier == self.range.identifier
+            && destination.binding == self.range.binding;
+        if !is_variable {
+            return;
+        }
+        let step = match &assignment.expression {
+            AnalyzedExpression::ArithOp(arith_op) => self.range.offset(arith_op),
+            _ => None,
+        };
+        match step {
+            Some(step)
+                if self.nested_loops == 0 && step != 0 && (step > 0) == self.range.increasing =>
+            {
+                self.least_stepped = self.least_stepped.saturating_add(step.abs());
+                self.most_stepped = self.most_stepped.saturating_add(step.abs());
+            }
+            _ => self.steps = false,
+        }
+    }
+
+    fn expression(&mut self, expression: &'p AnalyzedExpression) {
+        expression.visit_factors(&mut |factor| {
+            if let AnalyzedFactor::Name(AnalyzedName::Indexed(identifier, binding, index, _))
+            | AnalyzedFactor::NegatedName(AnalyzedName::Indexed(
+                identifier,
+                binding,
+                index,
+                _,
+            )) = factor
+            {
+                self.index(identifier, *binding, index);
+            }
+        });
+    }
+
+    fn index(&mut self, identifier: &str, binding: Binding, index: &AnalyzedExpression) {
+        let AnalyzedExpression::ArithOp(arith_op) = index else {
+            return;
+        };
+        let Some(offset) = self.range.offset(arith_op) else {
+            return;
+        };
+        let Some(Type::Array(_, length)) =
+            self.program
+                .variable_type(identifier, binding, self.enclosing)
+        else {
+            return;
+        };
+        let (low, high) = match self.range.increasing {
+            true => (
+                self.range.low.saturating_add(self.least_stepped),
+                self.range.high.saturating_add(self.most_stepped),
+            ),
+            false => (
+                self.range.low.saturating_sub(self.most_stepped),
+                self.range.high.saturating_sub(self.least_stepped),
+            ),
+        };
+        let (low, high) = (low.saturating_add(offset), high.saturating_add(offset));
+        if low < 0 || high >= i64::try_from(*length).unwrap_or(i64::MAX) {
+            self.warnings.push(LintWarning::LoopIndexOutOfBounds {
+                array: identifier.to_owned(),
+                low,
+                high,
+                length: *length,
+                index: index.span(),
+                condition: self.range.condition,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+use crate::session::with_test_source;
+#[cfg(test)]
+use rstest::rstest;
+
+#[cfg(test)]
+#[rstest]
+#[case("for (i := 0; i < 15) a[i] := i; i := i + 1; end for;", &[])]
+#[case("for (i := 0; i <= 15) a[i] := i; i := i + 1; end for;", &["0 to 15"])]
+#[case("for (i := 0; i < 15) i := i + 1; a[i] := i; end for;", &["1 to 15"])]
+#[case("for (i := 0; i < 15) a[i + 1] := a[i - 1]; i := i + 2; end for;", &["1 to 15", "-1 to 13"])]
+#[case("for (i := 14; i >= 0) a[i] := i; i := i - 1; end for;", &[])]
+#[case("for (i := 14; i > -2) a[i] := i; i := i - 1; end for;", &["-1 to 14"])]
+#[case(
+    "for (i := 0; i < 15) if (i > 3) then i := i + 1; end if; a[i] := i; i := i + 1; end for;",
+    &["0 to 15"]
+)]
+#[case("for (i := 0; i < 20) a[i] := i; i := i * 2; end for;", &[])]
+#[case("for (i := 0; i < 20) a[i] := i; i := i - 1; end for;", &[])]
+#[case("for (i := 0; i < 20) a[i] := i; end for;", &[])]
+#[case(
+    "for (i := 0; i < 20) a[i] := i; for (j := 0; j < 2) i := i + 1; j := j + 1; end for; end for;",
+    &[]
+)]
+fn loop_indices_are_checked(#[case] statements: &str, #[case] ranges: &[&str]) {
+    let source = format!(
+        "program range is
+    variable i : integer;
+    variable j : integer;
+    variable a : integer[15];
+begin
+    {}
+end program.
+",
+        statements
+    );
+    let found: Vec<String> = with_test_source(&source, check)
+        .iter()
+        .map(|warning| match warning {
+            LintWarning::LoopIndexOutOfBounds { low, high, .. } => {
+                format!("{} to {}", low, high)
+            }
+            warning => warning.to_string(),
+        })
+        .collect();
+    assert_eq!(found, ranges);
+}
@@ -0,0 +1,23 @@
+use crate::semantics::procedure::AnalyzedProcedure;
+use crate::semantics::AnalyzedProgram;
+
+use super::LintWarning;
+
+/// Finds locals and parameters with the same name as a global variable, which they hide
+pub fn check(program: &AnalyzedProgram) -> Vec<LintWarning> {
+    let globals = &program.declarations;
+    let mut warnings = Vec::new();
+    AnalyzedProcedure::visit_all(&program.procedures, &mut Vec::new(), &mut |enclosing| {
+        let procedure = enclosing[enclosing.len() - 1];
+        for (identifier, span) in procedure.declarations.variable_spans.iter() {
+            if globals.variables.contains_key(identifier) {
+                warnings.push(LintWarning::ShadowedGlobal(
+                    identifier.clone(),
+                    *span,
+                    globals.variable_spans.get(identifier).copied(),
+                ));
+            }
+        }
+    });
+    warnings
+}
@@ -0,0 +1,294 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::semantics::context::Binding;
+use crate::semantics::expression::{AnalyzedExpression, AnalyzedFactor, AnalyzedName};
+use crate::semantics::procedure::{AnalyzedProcedure, CallResolver};
+use crate::semantics::statement::{AnalyzedAssignment, AnalyzedBlock, AnalyzedStatement};
+use crate::semantics::value::Type;
+use crate::semantics::AnalyzedProgram;
+
+use super::LintWarning;
+
+/// Finds locals and parameters that are never read, procedures that are never called,
+/// and values assigned to locals that are never read
+pub fn check(program: &AnalyzedProgram) -> Vec<LintWarning> {
+    let resolver = CallResolver::new(&program.procedures);
+    let mut usage = Usage::default();
+    usage.record(&resolver, &program.block, &[]);
+    AnalyzedProcedure::visit_all(&program.procedures, &mut Vec::new(), &mut |enclosing| {
+        usage.record(&resolver, &enclosing[enclosing.len() - 1].block, enclosing);
+    });
+
+    let mut warnings = Vec::new();
+    AnalyzedProcedure::visit_all(&program.procedures, &mut Vec::new(), &mut |enclosing| {
+        let procedure = enclosing[enclosing.len() - 1];
+        let key = procedure as *const AnalyzedProcedure;
+        if !usage.called.contains(&key) {
+            warnings.push(LintWarning::UnusedProcedure(
+                procedure.identifier.clone(),
+                procedure.span,
+            ));
+        }
+
+        for (identifier, span) in procedure.declarations.variable_spans.iter() {
+            if usage.reads.contains_key(&(key, identifier.as_str())) {
+                continue;
+            }
+            let is_parameter = procedure.arg_list.iter().any(|arg| &arg.0 == identifier);
+            warnings.push(match is_parameter {
+                true => LintWarning::UnusedParameter(identifier.clone(), *span),
+                false => LintWarning::UnusedVariable(identifier.clone(), *span),
+            });
+        }
+
+        // Locals read from nested procedures could be read by any call, so those are left out.
+        // Locals that are never read at all are already reported.
+        let locals = procedure
+            .declarations
+            .variables
+            .iter()
+            .filter(|(identifier, value_type)| {
+                !matches!(value_type, Type::Array(..))
+                    && usage.reads.get(&(key, identifier.as_str())) == Some(&false)
+            })
+            .map(|(identifier, _)| identifier.as_str())
+            .collect();
+        let mut liveness = Liveness {
+            locals,
+            report: true,
+            warnings: Vec::new(),
+        };
+        liveness.block(&procedure.block, HashSet::new());
+        warnings.append(&mut liveness.warnings);
+    });
+    warnings
+}
+
+#[derive(Debug, Default)]
+struct Usage<'p> {
+    /// Every local that is read, and whether it's read from a procedure nested in the one it's declared in
+    reads: HashMap<(*const AnalyzedProcedure, &'p str), bool>,
+    /// Every procedure called from somewhere other than itself
+    called: HashSet<*const AnalyzedProcedure>,
+}
+
+impl<'p> Usage<'p> {
+    /// Records the reads and calls in `block`, where `enclosing` are the procedures around it, innermost last
+    fn record(
+        &mut self,
+        resolver: &CallResolver<'p>,
+        block: &'p AnalyzedBlock,
+        enclosing: &[&'p AnalyzedProcedure],
+    ) {
+        block.visit_factors(&mut |factor| match factor {
+            AnalyzedFactor::Name(name) | AnalyzedFactor::NegatedName(name) => {
+                let (AnalyzedName::Name(identifier, binding, _)
+                | AnalyzedName::Indexed(identifier, binding, _, _)) = name;
+                if let Some(procedure) = binding.procedure(enclosing) {
+                    let from_nested = *binding != Binding::Local(0);
+                    *self
+                        .reads
+                        .entry((procedure, identifier.as_str()))
+                        .or_default() |= from_nested;
+                }
+            }
+            AnalyzedFactor::ProcedureCall(call) => {
+                let Some(callee) = resolver.resolve(call, enclosing) else {
+                    return;
+                };
+                let recursive = enclosing
+                    .last()
+                    .is_some_and(|procedure| std::ptr::eq(*procedure, callee));
+                if !recursive {
+                    self.called.insert(callee);
+                }
+            }
+            _ => {}
+        });
+    }
+}
+
+/// Finds values assigned to `locals` that are never read, working backwards from the end of a procedure.
+/// Each statement is walked with the locals that are live after it, whose values may still be read,
+/// and gives the ones live before it.
+struct Liveness<'p> {
+    locals: HashSet<&'p str>,
+    /// Off while finding what's live at the start of a loop, so nothing in it is reported more than once
+    report: bool,
+    warnings: Vec<LintWarning>,
+}
+
+impl<'p> Liveness<'p> {
+    fn block(&mut self, block: &'p AnalyzedBlock, mut live: HashSet<&'p str>) -> HashSet<&'p str> {
+        for statement in block.0.iter().rev() {
+            live = self.statement(statement, live);
+        }
+        live
+    }
+
+    fn statement(
+        &mut self,
+        statement: &'p AnalyzedStatement,
+        live: HashSet<&'p str>,
+    ) -> HashSet<&'p str> {
+        match statement {
+            AnalyzedStatement::Assignment(assignment) => self.assignment(assignment, live),
+            AnalyzedStatement::If(statement) => {
+                let mut before = self.block(&statement.then_block, live.clone());
+                match &statement.else_block {
+                    Some(else_block) => before.extend(self.block(else_block, live)),
+                    None => before.extend(live),
+                }
+                self.reads(&statement.conditional_expr, &mut before);
+                before
+            }
+            AnalyzedStatement::Loop(statement) => {
+                // The condition is checked before each time through the body, and once more after the last
+                let report = std::mem::replace(&mut self.report, false);
+                let mut start = live.clone();
+                self.reads(&statement.condition, &mut start);
+                loop {
+                    let mut next = self.block(&statement.loop_body, start.clone());
+                    next.extend(live.iter().copied());
+                    self.reads(&statement.condition, &mut next);
+                    if next == start {
+                        break;
+                    }
+                    start = next;
+                }
+                self.report = report;
+                self.block(&statement.loop_body, start.clone());
+                self.assignment(&statement.assignment, start)
+            }
+            AnalyzedStatement::Return(statement) => {
+                let mut before = HashSet::new();
+                self.reads(&statement.expression, &mut before);
+                before
+            }
+        }
+    }
+
+    fn assignment(
+        &mut self,
+        assignment: &'p AnalyzedAssignment,
+        mut live: HashSet<&'p str>,
+    ) -> HashSet<&'p str> {
+        let destination = &assignment.destination;
+        let identifier = destination.identifier.as_str();
+        let is_local = destination.expression.is_none()
+            && destination.binding == Binding::Local(0)
+            && self.locals.contains(identifier);
+        if is_local && !live.remove(identifier) && self.report {
+            self.warnings.push(LintWarning::UnusedAssignment(
+                destination.identifier.clone(),
+                destination.span,
+            ));
+        }
+        if let Some(index) = &destination.expression {
+            self.reads(index, &mut live);
+        }
+        self.reads(&assignment.expression, &mut live);
+        live
+    }
+
+    fn reads(&self, expression: &'p AnalyzedExpression, live: &mut HashSet<&'p str>) {
+        expression.visit_factors(&mut |factor| {
+            if let AnalyzedFactor::Name(name) | AnalyzedFactor::NegatedName(name) = factor {
+                let (AnalyzedName::Name(identifier, binding, _)
+                | AnalyzedName::Indexed(identifier, binding, _, _)) = name;
+                if *binding == Binding::Local(0) && self.locals.contains(identifier.as_str()) {
+                    live.insert(identifier.as_str());
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+use super::Lint;
+#[cfg(test)]
+use crate::session::{analyze_messages, Session};
+#[cfg(test)]
+use rstest::rstest;
+
+#[cfg(test)]
+#[rstest]
+#[case("x := n; return x;", &[])]
+#[case("x := 0; return x;", &["Parameter n is never read."])]
+#[case("x := n; return n;", &["Variable x is never read."])]
+#[case("x := n; x := 2; return x;", &["Value assigned to x is never read."])]
+#[case("x := 1; if (n > 0) then x := 2; end if; return x;", &[])]
+#[case(
+    "x := 1; if (n > 0) then x := 2; else x := 3; end if; return x;",
+    &["Value assigned to x is never read."]
+)]
+#[case(
+    "x := 0; for (n := n; n > 0) x := x + n; n := n - 1; end for; return x;",
+    &[]
+)]
+#[case("x := n; n := x; return 0;", &["Value assigned to n is never read."])]
+fn unused_code_is_found(#[case] body: &str, #[case] messages: &[&str]) {
+    let source = format!(
+        "program unused is
+    variable result : integer;
+    procedure f : integer(variable n : integer)
+        variable x : integer;
+    begin
+        {}
+    end procedure;
+begin
+    result := f(1);
+end program.
+",
+        body
+    );
+    assert_eq!(
+        analyze_messages(
+            &source,
+            &[
+                Lint::UnusedVariable,
+                Lint::UnusedProcedure,
+                Lint::UnusedAssignment
+            ],
+        ),
+        messages
+    );
+}
+
+#[cfg(test)]
+#[test]
+fn nested_procedures_use_enclosing_locals() {
+    let source = "program unused is
+    procedure outer : integer()
+        variable x : integer;
+        procedure inner : integer()
+        begin
+            return x;
+        end procedure;
+        procedure unused : integer()
+        begin
+            return unused();
+        end procedure;
+    begin
+        x := 1;
+        x := 2;
+        return inner();
+    end procedure;
+begin
+end program.
+";
+    let mut session = Session::from_text("test.src", source);
+    let mut found: Vec<String> = check(session.analyze().unwrap())
+        .iter()
+        .map(|warning| warning.to_string())
+        .collect();
+    found.sort();
+    // `inner` could read either value of `x`, and recursive calls don't count
+    assert_eq!(
+        found,
+        [
+            "Procedure outer is never called.",
+            "Procedure unused is never called."
+        ]
+    );
+}
@@ -1,13 +1,15 @@
-#![feature(box_patterns)]
-
-mod parser;
-mod scanner;
-mod semantics;
-mod tokens;
-use std::collections::VecDeque;
-use std::path::Path;
+use crust::bytecode::vm::Vm;
+use crust::diagnostics::render::Renderer;
+use crust::diagnostics::{Diagnostic, Severity};
+use crust::interpreter::{Checks, Interpreter};
+use crust::lint::{Level, Lint, LintLevels};
+use crust::semantics::AnalyzedProgram;
+use crust::span::SourceFile;
+use crust::{bytecode, codegen, format, interpreter, ir, Phase, Session};
+use std::fmt::Display;
+use std::io::Write;
 use std::process::exit;
-use std::{env, fs, io};
+use std::{env, fs, io, thread};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -15,14 +17,23 @@ enum CompilerError {
     #[error(transparent)]
     FileError(#[from] io::Error),
     #[error(transparent)]
-    ScannerError(#[from] scanner::ScannerError),
+    ArgumentError(#[from] ArgumentError),
+    #[error("aborting due to {} previous error{}", .1, if *.1 == 1 { "" } else { "s" })]
+    CompilationFailed(Phase, usize),
     #[error(transparent)]
-    ParserError(#[from] parser::utils::ParserError),
+    CodegenError(#[from] codegen::CodegenError),
     #[error(transparent)]
-    SemanticsError(#[from] semantics::SemanticsError),
+    VerifyError(#[from] ir::verify::VerifyError),
     #[error(transparent)]
-    ArgumentError(#[from] ArgumentError),
+    RuntimeError(#[from] interpreter::RuntimeError),
+    #[error(transparent)]
+    DecodeError(#[from] bytecode::encoding::DecodeError),
+    #[error(transparent)]
+    FormatError(#[from] format::FormatError),
+    #[error("{} is not formatted, starting at line {}", .0.display(), .1)]
+    Unformatted(PathBuf, usize),
 }
+
 #[derive(Error, Debug)]
 enum ArgumentError {
     #[error("Expected at least one file argument to compile, but found none!")]
@@ -33,71 +44,661 @@ enum ArgumentError {
     TooManyArguments,
     #[error("Input file does not exist")]
     FileDoesNotExist,
+    #[error("Invalid error limit {0}. Expected a non-negative integer.")]
+    InvalidErrorLimit(String),
+    #[error(
+        "Invalid output format {0}. Expected one of: tokens, ast, analyzed, ir, asm, llvm, bytecode, c, wat."
+    )]
+    InvalidEmit(String),
+    #[error("Unknown option {0}")]
+    UnknownOption(String),
+    #[error("Expected a value after {0}")]
+    MissingValue(&'static str),
+    #[error("Unknown lint {0}")]
+    UnknownLint(String),
+    #[error("Unknown check {0}. Expected one of: bounds, div0, overflow.")]
+    UnknownCheck(String),
+    #[error("{0} cannot be used with `{1}`")]
+    InvalidOption(&'static str, &'static str),
+    #[error("--checks can only be used when running a source file")]
+    ChecksOnBytecode,
+}
+
+impl CompilerError {
+    /// The status the process exits with, so scripts can tell which phase failed
+    fn exit_code(&self) -> i32 {
+        match self {
+            CompilerError::RuntimeError(_) | CompilerError::Unformatted(..) => EXIT_FAILURE,
+            CompilerError::ArgumentError(_) => EXIT_USAGE,
+            CompilerError::CompilationFailed(Phase::Scan, _) | CompilerError::FormatError(_) => {
+                EXIT_SCAN
+            }
+            CompilerError::CompilationFailed(Phase::Parse, _) | CompilerError::DecodeError(_) => {
+                EXIT_PARSE
+            }
+            CompilerError::CompilationFailed(Phase::Analyze, _) => EXIT_SEMANTIC,
+            CompilerError::FileError(_) => EXIT_IO,
+            CompilerError::CodegenError(_) | CompilerError::VerifyError(_) => EXIT_INTERNAL,
+        }
+    }
+}
+
+/// The program failed at run time, a backend can't compile a program the analyzer accepted,
+/// or `fmt --check` found a file that isn't formatted
+const EXIT_FAILURE: i32 = 1;
+const EXIT_USAGE: i32 = 2;
+const EXIT_SCAN: i32 = 3;
+const EXIT_PARSE: i32 = 4;
+const EXIT_SEMANTIC: i32 = 5;
+const EXIT_IO: i32 = 6;
+/// Codegen and IR verification errors, which are bugs in the compiler
+const EXIT_INTERNAL: i32 = 7;
+
+const USAGE: &str = "\
+Usage: crust [command] [options] <input> [output]
+
+Commands:
+    build     Compile <input> to the format given by --emit (the default)
+    check     Report errors in <input> without writing anything
+    run       Interpret <input>, or run a bytecode file on the VM
+    fmt       Format <input> in place
+    tokens    Print the tokens of <input>
+    ast       Print the syntax tree of <input>
+
+Options:
+    -o <path>            Write the output to <path>, or to stdout if it is -
+    --emit=<format>      One of tokens, ast, analyzed, ir, asm, llvm (the default), bytecode, c or wat
+    --error-limit=<n>    Stop reporting errors after <n>, or never if it is 0 (default 20)
+    --trace              Run on the bytecode VM, logging every instruction to stderr
+    --checks=<checks>    With run, guard against the comma-separated <checks> failing,
+                         reporting the array or operation involved
+    --check              With fmt, fail if <input> isn't formatted instead of formatting it
+    --allow=<lints>      Don't report the comma-separated <lints>
+    --warn=<lints>       Report <lints> as warnings, which they all are by default
+    --deny=<lints>       Report <lints> as errors
+    --flow-errors        Report missing returns and unreachable statements as errors,
+                         the same as --deny=missing-return,unreachable-code
+    -q, --quiet          Only report errors
+    -v, --verbose        Report each phase as it finishes
+    -h, --help           Print this message
+
+Lints:
+    missing-return, unreachable-code, unassigned-variable, unused-variable,
+    unused-procedure, shadowed-global, unused-assignment, constant-condition,
+    loop-bounds
+
+Checks:
+    bounds (array indices), div0 (integer division), overflow (integer arithmetic)
+
+Exits with 0 on success, 1 if the program fails at run time or fmt --check finds
+an unformatted file, 2 for invalid arguments,
+3, 4 or 5 for errors scanning, parsing or analyzing, 6 for I/O errors,
+and 7 for errors in the compiler itself.
+";
+
+/// Errors reported before the rest are suppressed, unless overridden with `--error-limit`
+const DEFAULT_ERROR_LIMIT: usize = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Command {
+    /// Check the program without writing anything
+    Check,
+    /// Check the program and write it to the output path in the format given by `--emit`.
+    /// The `tokens` and `ast` commands are builds that stop early.
+    Build,
+    /// Check the program, then interpret it.
+    /// Bytecode files written with `--emit=bytecode` are run on the VM instead.
+    Run,
+    /// Parse the program, then write it back out formatted, to the input file by default
+    Format,
+}
+
+/// What `Command::Build` writes to the output path
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Emit {
+    /// Each token with its position, one per line
+    Tokens,
+    /// The syntax tree from the parser
+    Ast,
+    /// The analyzed program, with its types and casts
+    Analyzed,
+    Llvm,
+    /// The typed IR from `ir::lower`, after verifying it
+    Ir,
+    /// x86-64 assembly for Linux
+    Asm,
+    /// The binary format from `bytecode::encoding`, which `crust run` can run
+    Bytecode,
+    /// A C99 file, written along with the runtime header it includes
+    C,
+    /// A WebAssembly text module, importing the builtins from the host
+    Wat,
+}
+
+impl Emit {
+    fn extension(self) -> &'static str {
+        match self {
+            Emit::Tokens => "tokens",
+            Emit::Ast => "ast",
+            Emit::Analyzed => "analyzed",
+            Emit::Llvm => "ll",
+            Emit::Ir => "ir",
+            Emit::Asm => "s",
+            Emit::Bytecode => "cbc",
+            Emit::C => "c",
+            Emit::Wat => "wat",
+        }
+    }
+
+    /// The last phase that has to run before writing the output
+    fn phase(self) -> Phase {
+        match self {
+            Emit::Tokens => Phase::Scan,
+            Emit::Ast => Phase::Parse,
+            _ => Phase::Analyze,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Verbosity {
+    /// Only errors are reported
+    Quiet,
+    Normal,
+    /// Progress is reported after each phase
+    Verbose,
+}
+
+#[derive(Debug)]
+struct Arguments {
+    command: Command,
+    emit: Emit,
+    input_path: PathBuf,
+    /// `None` to write to stdout
+    output_path: Option<PathBuf>,
+    /// `None` when there is no limit
+    error_limit: Option<usize>,
+    /// Run on the bytecode VM, logging every instruction to stderr
+    trace: bool,
+    /// Runtime guards for the interpreter
+    checks: Checks,
+    /// Check that the input is formatted instead of formatting it
+    check: bool,
+    lint_levels: LintLevels,
+    verbosity: Verbosity,
 }
+
 fn main() {
-    let main_result = run_comp();
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        print!("{}", USAGE);
+        return;
+    }
+    let main_result = parse_args(args)
+        .map_err(CompilerError::from)
+        .and_then(|arguments| run_comp(&arguments));
     if let Err(err) = main_result {
-        eprintln!("{}", err);
-        exit(1)
+        // Codegen, runtime and format errors have already been rendered against the source
+        if !matches!(
+            err,
+            CompilerError::CodegenError(_)
+                | CompilerError::RuntimeError(_)
+                | CompilerError::FormatError(_)
+        ) {
+            eprintln!("error: {}", err);
+        }
+        if let CompilerError::ArgumentError(_) = err {
+            eprintln!("note: run `crust --help` for usage");
+        }
+        exit(err.exit_code())
     }
 }
 
-fn run_comp() -> Result<(), CompilerError> {
-    let (parsed_input, parsed_output) = parse_args()?;
+fn run_comp(arguments: &Arguments) -> Result<(), CompilerError> {
+    if arguments.command == Command::Run {
+        let bytes = fs::read(&arguments.input_path)?;
+        if bytecode::encoding::is_bytecode(&bytes) {
+            if arguments.checks.any() {
+                return Err(ArgumentError::ChecksOnBytecode.into());
+            }
+            let program = bytecode::Program::from_bytes(&bytes)?;
+            return run_bytecode(None, &program, arguments.trace);
+        }
+    }
 
-    let main_result = compile_file(&parsed_input)?;
+    let mut session = Session::from_path(&arguments.input_path)?
+        .with_error_limit(arguments.error_limit)
+        .with_lint_levels(arguments.lint_levels.clone());
+    let last_phase = match arguments.command {
+        Command::Build => arguments.emit.phase(),
+        Command::Check | Command::Run => Phase::Analyze,
+        Command::Format => Phase::Parse,
+    };
+    compile_file(&mut session, last_phase, arguments.verbosity)?;
+    if arguments.command == Command::Format {
+        return format_source(arguments, &mut session);
+    }
+    let source = session.source();
+    // Every phase up to `last_phase` has run without errors
+    let analyzed_program = match last_phase {
+        Phase::Scan => {
+            let output: String = session
+                .tokens()
+                .unwrap()
+                .iter()
+                .map(|token| {
+                    format!(
+                        "{}:{}\t{:?}\n",
+                        token.span.line, token.span.column, token.token
+                    )
+                })
+                .collect();
+            return write_output(arguments, output.as_bytes());
+        }
+        Phase::Parse => {
+            let program = session.ast().unwrap();
+            return write_output(arguments, format!("{:#?}\n", program).as_bytes());
+        }
+        Phase::Analyze => session.analyzed().unwrap(),
+    };
+    match arguments.command {
+        Command::Check => log(arguments.verbosity, "no errors found"),
+        Command::Build => {
+            let output = match arguments.emit {
+                Emit::Tokens | Emit::Ast => unreachable!("builds stop before analysis"),
+                Emit::Analyzed => format!("{:#?}\n", analyzed_program).into_bytes(),
+                Emit::Llvm => codegen::llvm::generate(analyzed_program)
+                    .inspect_err(|err| render_error(source, err))?
+                    .into_bytes(),
+                Emit::Ir => {
+                    let module = ir::lower::lower(analyzed_program)
+                        .inspect_err(|err| render_error(source, err))?;
+                    ir::verify::verify(&module)?;
+                    module.to_string().into_bytes()
+                }
+                Emit::Asm => codegen::x86_64::generate(analyzed_program)
+                    .inspect_err(|err| render_error(source, err))?
+                    .into_bytes(),
+                Emit::Bytecode => bytecode::compile::compile(analyzed_program)
+                    .inspect_err(|err| render_error(source, err))?
+                    .to_bytes(),
+                Emit::C => {
+                    let output = codegen::c::generate(analyzed_program)
+                        .inspect_err(|err| render_error(source, err))?;
+                    // Nowhere to put the header when writing to stdout
+                    if let Some(output_path) = &arguments.output_path {
+                        let header_path =
+                            output_path.with_file_name(codegen::c::RUNTIME_HEADER_NAME);
+                        fs::write(header_path, codegen::c::RUNTIME_HEADER)?;
+                    }
+                    output.into_bytes()
+                }
+                Emit::Wat => codegen::wasm::generate(analyzed_program)
+                    .inspect_err(|err| render_error(source, err))?
+                    .into_bytes(),
+            };
+            write_output(arguments, &output)?;
+        }
+        Command::Run if arguments.trace => {
+            let program = bytecode::compile::compile(analyzed_program)
+                .inspect_err(|err| render_error(source, err))?;
+            run_bytecode(Some(source), &program, true)?
+        }
+        Command::Run => run_program(source, analyzed_program, arguments.checks)?,
+        Command::Format => unreachable!("formatting stops after parsing"),
+    }
     Ok(())
 }
 
-fn parse_args() -> Result<(PathBuf, PathBuf), ArgumentError> {
-    let mut args = env::args().skip(1);
+/// Writes the formatted source of a session that parsed without errors.
+/// With `--check`, fails if the source isn't formatted already instead.
+fn format_source(arguments: &Arguments, session: &mut Session) -> Result<(), CompilerError> {
+    let formatted = match format::format(session) {
+        Ok(formatted) => formatted,
+        Err(format::FormatError::Failed(phase)) => {
+            return Err(CompilerError::CompilationFailed(phase, 0))
+        }
+        Err(err) => {
+            render_error(session.source(), &err);
+            return Err(err.into());
+        }
+    };
+    let source = &session.source().text;
+    if *source == formatted {
+        log(arguments.verbosity, "already formatted");
+        // Only a separate output still needs writing
+        if arguments.check || arguments.output_path.as_ref() == Some(&arguments.input_path) {
+            return Ok(());
+        }
+    }
+    if arguments.check {
+        let line = source
+            .lines()
+            .zip(formatted.lines())
+            .position(|(source_line, formatted_line)| source_line != formatted_line)
+            .unwrap_or(source.lines().count().min(formatted.lines().count()));
+        return Err(CompilerError::Unformatted(
+            arguments.input_path.clone(),
+            line + 1,
+        ));
+    }
+    write_output(arguments, formatted.as_bytes())
+}
+
+/// Writes the output of a build to the output path, or stdout
+fn write_output(arguments: &Arguments, output: &[u8]) -> Result<(), CompilerError> {
+    match &arguments.output_path {
+        Some(output_path) => {
+            fs::write(output_path, output)?;
+            log(
+                arguments.verbosity,
+                format!("wrote {}", output_path.display()),
+            );
+        }
+        None => io::stdout().lock().write_all(output)?,
+    }
+    Ok(())
+}
+
+/// Reports progress on stderr with `--verbose`
+fn log(verbosity: Verbosity, message: impl Display) {
+    if verbosity == Verbosity::Verbose {
+        eprintln!("info: {}", message);
+    }
+}
+
+/// The option, level and comma-separated lints of `--allow`, `--warn` or `--deny`
+fn lint_level_option(arg: &str) -> Option<(&'static str, Level, &str)> {
+    [
+        ("--allow", Level::Allow),
+        ("--warn", Level::Warn),
+        ("--deny", Level::Deny),
+    ]
+    .into_iter()
+    .find_map(|(option, level)| {
+        let lints = arg.strip_prefix(option)?.strip_prefix('=')?;
+        Some((option, level, lints))
+    })
+}
+
+fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Arguments, ArgumentError> {
+    let mut error_limit = Some(DEFAULT_ERROR_LIMIT);
+    let mut emit = None;
+    let mut output_option = None;
+    let mut trace = false;
+    let mut checks = Checks::default();
+    let mut check = false;
+    let mut lint_levels = LintLevels::default();
+    // The last lint option given, which only applies to commands that analyze
+    let mut lint_option = None;
+    let mut verbosity = Verbosity::Normal;
+    let mut positional = Vec::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if let Some(limit) = arg.strip_prefix("--error-limit=") {
+            let limit: usize = limit
+                .parse()
+                .map_err(|_| ArgumentError::InvalidErrorLimit(String::from(limit)))?;
+            // A limit of 0 means no limit, like `-fmax-errors=0`
+            error_limit = (limit > 0).then_some(limit);
+        } else if let Some(format) = arg.strip_prefix("--emit=") {
+            emit = Some(match format {
+                "tokens" => Emit::Tokens,
+                "ast" => Emit::Ast,
+                "analyzed" => Emit::Analyzed,
+                "llvm" => Emit::Llvm,
+                "ir" => Emit::Ir,
+                "asm" => Emit::Asm,
+                "bytecode" => Emit::Bytecode,
+                "c" => Emit::C,
+                "wat" => Emit::Wat,
+                _ => return Err(ArgumentError::InvalidEmit(String::from(format))),
+            });
+        } else if arg == "-o" {
+            output_option = Some(args.next().ok_or(ArgumentError::MissingValue("-o"))?);
+        } else if arg == "--trace" {
+            trace = true;
+        } else if let Some(names) = arg.strip_prefix("--checks=") {
+            for name in names.split(',') {
+                if !checks.enable(name) {
+                    return Err(ArgumentError::UnknownCheck(name.into()));
+                }
+            }
+        } else if arg == "--check" {
+            check = true;
+        } else if let Some((option, level, lints)) = lint_level_option(&arg) {
+            for name in lints.split(',') {
+                let lint =
+                    Lint::from_name(name).ok_or_else(|| ArgumentError::UnknownLint(name.into()))?;
+                lint_levels.set(lint, level);
+            }
+            lint_option = Some(option);
+        } else if arg == "--flow-errors" {
+            lint_levels.set(Lint::MissingReturn, Level::Deny);
+            lint_levels.set(Lint::UnreachableCode, Level::Deny);
+            lint_option = Some("--flow-errors");
+        } else if arg == "-q" || arg == "--quiet" {
+            verbosity = Verbosity::Quiet;
+        } else if arg == "-v" || arg == "--verbose" {
+            verbosity = Verbosity::Verbose;
+        } else if arg.starts_with('-') && arg != "-" {
+            return Err(ArgumentError::UnknownOption(arg));
+        } else {
+            positional.push(arg);
+        }
+    }
+
+    let mut args = positional.into_iter().peekable();
+    // Without a command, the arguments are for `build`
+    let (command, name, implied_emit) = match args.peek().map(String::as_str) {
+        Some("build") => (Command::Build, "build", None),
+        Some("check") => (Command::Check, "check", None),
+        Some("run") => (Command::Run, "run", None),
+        Some("fmt") => (Command::Format, "fmt", None),
+        Some("tokens") => (Command::Build, "tokens", Some(Emit::Tokens)),
+        Some("ast") => (Command::Build, "ast", Some(Emit::Ast)),
+        _ => (Command::Build, "", None),
+    };
+    if !name.is_empty() {
+        args.next();
+    }
+
     let input_filename_opt = args.next();
-    let output_filename_opt = args.next();
+    // Only builds take an output file, which can also be given with `-o`.
+    // `fmt` can only be given one with `-o`.
+    let output_filename_opt = match command {
+        Command::Build if output_option.is_none() => args.next(),
+        _ => output_option,
+    };
 
     let input_path = match input_filename_opt {
         None => return Err(ArgumentError::NoArguments),
         Some(input_filename) => PathBuf::from(input_filename),
     };
-    if args.len() > 0 {
+    if args.next().is_some() {
         return Err(ArgumentError::TooManyArguments);
     }
+    let takes_output = command == Command::Build || (command == Command::Format && !check);
+    if !takes_output && output_filename_opt.is_some() {
+        return Err(ArgumentError::InvalidOption("-o", name));
+    }
+    if (command != Command::Build || implied_emit.is_some()) && emit.is_some() {
+        return Err(ArgumentError::InvalidOption("--emit", name));
+    }
+    if command != Command::Run && trace {
+        return Err(ArgumentError::InvalidOption("--trace", name));
+    }
+    if command != Command::Run && checks.any() {
+        return Err(ArgumentError::InvalidOption("--checks", name));
+    }
+    // The bytecode VM has no guards of its own
+    if trace && checks.any() {
+        return Err(ArgumentError::InvalidOption("--checks", "--trace"));
+    }
+    if command != Command::Format && check {
+        return Err(ArgumentError::InvalidOption("--check", name));
+    }
+    if let Some(option) = lint_option.filter(|_| command == Command::Format) {
+        return Err(ArgumentError::InvalidOption(option, name));
+    }
     if !input_path.is_file() {
         return Err(ArgumentError::FileDoesNotExist);
     }
 
-    let output_path = match output_filename_opt {
+    let emit = implied_emit.or(emit).unwrap_or(Emit::Llvm);
+    let output_path = match output_filename_opt.as_deref() {
+        Some("-") => None,
         Some(output_filename) => {
             let mut output_path = PathBuf::from(output_filename);
             if output_path.is_dir() {
                 // This can be unwrapped, since we checked that `input_path` is a file earlier.
                 output_path.push(input_path.file_name().unwrap());
+                if command == Command::Build {
+                    output_path.set_extension(emit.extension());
+                }
             }
-            output_path
+            Some(output_path)
         }
+        // `fmt` formats in place, unless it's only checking
+        None if command == Command::Format && !check => Some(input_path.clone()),
+        // `tokens` and `ast` print to stdout by default, and other commands don't write output
+        None if implied_emit.is_some() || command != Command::Build => None,
         None => {
             let mut output_path = input_path.clone();
-            output_path.set_extension("out");
-            output_path
+            output_path.set_extension(emit.extension());
+            Some(output_path)
         }
     };
 
-    Ok((input_path, output_path))
+    Ok(Arguments {
+        command,
+        emit,
+        input_path,
+        output_path,
+        error_limit,
+        trace,
+        checks,
+        check,
+        lint_levels,
+        verbosity,
+    })
 }
 
-fn compile_file(file_path: &Path) -> Result<(), CompilerError> {
-    let file_name = file_path.to_str();
-    let mut file_contents = fs::read_to_string(file_path)?;
-    let scanner_result = scanner::scan(file_contents)?;
-    let token_deque = VecDeque::from(scanner_result);
-    let program_struct = parser::parse_tokens(token_deque)?;
-    let anayzed_program = semantics::AnalyzedProgram::analyze(program_struct)?;
-    println!("{:?}", anayzed_program);
+/// Runs the phases of `session` up to and including `last_phase`,
+/// rendering every diagnostic reported along the way to stderr.
+/// Warnings aren't rendered when `verbosity` is quiet.
+fn compile_file(
+    session: &mut Session,
+    last_phase: Phase,
+    verbosity: Verbosity,
+) -> Result<(), CompilerError> {
+    let mut result = Ok(());
+    for phase in [Phase::Scan, Phase::Parse, Phase::Analyze] {
+        if phase > last_phase {
+            break;
+        }
+        result = session.run_until(phase);
+        match (phase, &result) {
+            // The parser recovered, so analysis can still report errors of its own
+            (Phase::Parse, Err(_)) if session.partial_ast().is_some() => {}
+            (_, Err(_)) => break,
+            (Phase::Scan, Ok(_)) => log(
+                verbosity,
+                format!("scanned {} tokens", session.tokens().unwrap().len()),
+            ),
+            (Phase::Parse, Ok(_)) => log(verbosity, "parsed"),
+            (Phase::Analyze, Ok(_)) => log(verbosity, "analyzed"),
+        }
+    }
 
-    Ok(())
+    let source = session.source();
+    let sink = session.diagnostics();
+    let renderer = Renderer::for_stderr();
+    for diagnostic in sink.diagnostics() {
+        if verbosity == Verbosity::Quiet && diagnostic.severity != Severity::Error {
+            continue;
+        }
+        eprintln!("{}", renderer.render(source, diagnostic));
+    }
+    if sink.suppressed_count() > 0 && verbosity != Verbosity::Quiet {
+        eprintln!(
+            "note: {} more diagnostics were suppressed after reaching the error limit",
+            sink.suppressed_count()
+        );
+    }
+
+    result.map_err(|phase| CompilerError::CompilationFailed(phase, sink.error_count()))
+}
+
+/// Stack size of the thread the interpreter runs on.
+/// Each call in the interpreted program takes several native frames, so the default is too small.
+const INTERPRETER_STACK_SIZE: usize = 1024 * 1024 * 1024;
+
+/// Interprets a program on stdin and stdout, rendering any runtime error against the source.
+fn run_program(
+    source: &SourceFile,
+    program: &AnalyzedProgram,
+    checks: Checks,
+) -> Result<(), CompilerError> {
+    let result = thread::scope(|scope| {
+        thread::Builder::new()
+            .stack_size(INTERPRETER_STACK_SIZE)
+            .spawn_scoped(scope, || {
+                Interpreter::new(
+                    program,
+                    Box::new(io::stdin().lock()),
+                    Box::new(io::BufWriter::new(io::stdout().lock())),
+                )
+                .with_checks(checks)
+                .run()
+            })?
+            .join()
+            .map_err(|_| io::Error::other("interpreter thread panicked"))
+    })?;
+    result
+        .inspect_err(|err| render_error(source, err))
+        .map_err(CompilerError::from)
+}
+
+/// Runs bytecode on stdin and stdout, optionally tracing to stderr.
+/// Runtime errors are rendered against `source` if there is one, otherwise just their position is given.
+fn run_bytecode(
+    source: Option<&SourceFile>,
+    program: &bytecode::Program,
+    trace: bool,
+) -> Result<(), CompilerError> {
+    let mut vm = Vm::new(
+        program,
+        Box::new(io::stdin().lock()),
+        Box::new(io::BufWriter::new(io::stdout().lock())),
+    );
+    if trace {
+        vm = vm.with_trace(Box::new(io::BufWriter::new(io::stderr().lock())));
+    }
+    vm.run()
+        .inspect_err(|err| match source {
+            Some(source) => render_error(source, err),
+            None => {
+                let span = err.span();
+                eprintln!("error: {} (at {}:{})", err, span.line, span.column)
+            }
+        })
+        .map_err(CompilerError::from)
+}
+
+/// Renders an error from after analysis against the source
+fn render_error<'e, E>(source: &SourceFile, err: &'e E)
+where
+    Diagnostic: From<&'e E>,
+{
+    let diagnostic = Diagnostic::from(err);
+    eprintln!("{}", Renderer::for_stderr().render(source, &diagnostic));
 }
 
 #[cfg(test)]
 use rstest::rstest;
+#[cfg(test)]
+use std::path::Path;
 use std::path::PathBuf;
 #[cfg(test)]
 #[rstest]
@@ -105,7 +706,8 @@ use std::path::PathBuf;
 fn compile_test_correct(
     #[files("tests/correct/*.src")] source_file: PathBuf,
 ) -> Result<(), CompilerError> {
-    compile_file(source_file.as_path())
+    let mut session = Session::from_path(source_file.as_path())?;
+    compile_file(&mut session, Phase::Analyze, Verbosity::Normal)
 }
 
 #[cfg(test)]
@@ -113,5 +715,207 @@ fn compile_test_correct(
 fn compile_test_incorrect(
     #[files("tests/incorrect/*.src")] source_file: PathBuf,
 ) -> Result<(), CompilerError> {
-    compile_file(source_file.as_path())
+    let mut session = Session::from_path(source_file.as_path())?;
+    compile_file(&mut session, Phase::Analyze, Verbosity::Normal)
+}
+
+#[cfg(test)]
+#[test]
+fn compile_reports_every_error() {
+    let source = "program errors is
+    procedure first : integer()
+    begin
+        return x;
+    end procedure;
+    procedure second : integer()
+    begin
+        return y;
+    end procedure;
+begin
+end program.
+";
+    let mut session = Session::from_text("errors.src", source);
+    let result = compile_file(&mut session, Phase::Analyze, Verbosity::Normal);
+    assert!(matches!(
+        result,
+        Err(CompilerError::CompilationFailed(Phase::Analyze, _))
+    ));
+    // `x` and `y` are both undeclared, in different procedures
+    assert_eq!(session.diagnostics().error_count(), 2);
+}
+
+#[cfg(test)]
+#[test]
+fn compile_reports_semantic_errors_after_syntax_errors() {
+    let mut session = Session::from_path(Path::new("tests/recovery/syntax_errors.src")).unwrap();
+    let result = compile_file(&mut session, Phase::Analyze, Verbosity::Quiet);
+    assert!(matches!(
+        result,
+        Err(CompilerError::CompilationFailed(Phase::Parse, _))
+    ));
+    let messages: Vec<&str> = session
+        .diagnostics()
+        .diagnostics()
+        .iter()
+        .map(|diagnostic| diagnostic.message.as_str())
+        .collect();
+    assert!(messages.contains(&"Expected token: Factor Encountered token: Semicolon"));
+    assert!(
+        messages.contains(&"Undeclared reference y"),
+        "{:?}",
+        messages
+    );
+    assert!(messages
+        .iter()
+        .any(|message| message.starts_with("Type mismatch")));
+}
+
+#[cfg(test)]
+fn args(args: &[&str]) -> Vec<String> {
+    args.iter().map(|arg| String::from(*arg)).collect()
+}
+
+#[cfg(test)]
+#[rstest]
+#[case(&["tests/correct/math.src"], Command::Build, Emit::Llvm, Some("tests/correct/math.ll"))]
+#[case(&["tests/correct/math.src", "out.s", "--emit=asm"], Command::Build, Emit::Asm, Some("out.s"))]
+#[case(&["build", "-o", "-", "tests/correct/math.src", "--emit=ir"], Command::Build, Emit::Ir, None)]
+#[case(&["check", "-q", "tests/correct/math.src"], Command::Check, Emit::Llvm, None)]
+#[case(&["check", "--flow-errors", "tests/correct/math.src"], Command::Check, Emit::Llvm, None)]
+#[case(&["check", "--deny=missing-return,unused-variable", "tests/correct/math.src"], Command::Check, Emit::Llvm, None)]
+#[case(&["tokens", "tests/correct/math.src"], Command::Build, Emit::Tokens, None)]
+#[case(&["ast", "tests/correct/math.src", "-o", "math.ast"], Command::Build, Emit::Ast, Some("math.ast"))]
+#[case(&["run", "--trace", "tests/correct/math.src"], Command::Run, Emit::Llvm, None)]
+#[case(&["run", "--checks=bounds,div0,overflow", "tests/correct/math.src"], Command::Run, Emit::Llvm, None)]
+#[case(&["fmt", "tests/correct/math.src"], Command::Format, Emit::Llvm, Some("tests/correct/math.src"))]
+#[case(&["fmt", "--check", "tests/correct/math.src"], Command::Format, Emit::Llvm, None)]
+fn parse_args_for_commands(
+    #[case] arguments: &[&str],
+    #[case] command: Command,
+    #[case] emit: Emit,
+    #[case] output_path: Option<&str>,
+) {
+    let arguments = parse_args(args(arguments)).unwrap();
+    assert_eq!(arguments.command, command);
+    assert_eq!(arguments.emit, emit);
+    assert_eq!(arguments.output_path, output_path.map(PathBuf::from));
+}
+
+#[cfg(test)]
+#[rstest]
+#[case(&[])]
+#[case(&["--emit=wasm", "tests/correct/math.src"])]
+#[case(&["check", "tests/correct/math.src", "--emit=ir"])]
+#[case(&["run", "tests/correct/math.src", "-o", "out"])]
+#[case(&["tokens", "tests/correct/math.src", "--trace"])]
+#[case(&["build", "tests/correct/math.src", "-o"])]
+#[case(&["--optimize", "tests/correct/math.src"])]
+#[case(&["check", "tests/correct/math.src", "extra"])]
+#[case(&["build", "--check", "tests/correct/math.src"])]
+#[case(&["fmt", "--check", "tests/correct/math.src", "-o", "-"])]
+#[case(&["fmt", "--flow-errors", "tests/correct/math.src"])]
+#[case(&["fmt", "--deny=missing-return", "tests/correct/math.src"])]
+#[case(&["check", "--warn=unused", "tests/correct/math.src"])]
+#[case(&["run", "--checks=bounds,nan", "tests/correct/math.src"])]
+#[case(&["build", "--checks=overflow", "tests/correct/math.src"])]
+#[case(&["run", "--trace", "--checks=div0", "tests/correct/math.src"])]
+fn parse_args_rejects_invalid_arguments(#[case] arguments: &[&str]) {
+    assert!(parse_args(args(arguments)).is_err());
+}
+
+#[cfg(test)]
+#[test]
+fn parse_args_flow_errors_denies_flow_lints() {
+    let arguments =
+        parse_args(args(&["check", "--flow-errors", "tests/correct/math.src"])).unwrap();
+    assert_eq!(
+        arguments.lint_levels.level(Lint::MissingReturn),
+        Level::Deny
+    );
+    assert_eq!(
+        arguments.lint_levels.level(Lint::UnreachableCode),
+        Level::Deny
+    );
+    assert_eq!(
+        arguments.lint_levels.level(Lint::UnusedVariable),
+        Level::Warn
+    );
+}
+
+#[cfg(test)]
+#[rstest]
+#[case("tests/correct/math.src", Phase::Scan)]
+#[case("tests/recovery/syntax_errors.src", Phase::Parse)]
+#[case("tests/incorrect/test1.src", Phase::Analyze)]
+fn compile_stops_after_phase(#[case] path: &str, #[case] phase: Phase) {
+    let mut session = Session::from_path(Path::new(path)).unwrap();
+    let result = compile_file(&mut session, phase, Verbosity::Quiet);
+    match result {
+        Ok(()) => assert!(phase == Phase::Scan && session.tokens().is_some()),
+        Err(err) => {
+            assert!(matches!(err, CompilerError::CompilationFailed(failed, _) if failed == phase))
+        }
+    }
+}
+
+#[cfg(test)]
+#[rstest]
+#[case("tests/correct/math.src", Some(2))]
+#[case("tests/correct/test_program_minimal.src", Some(10))]
+fn format_check_finds_unformatted_line(#[case] path: &str, #[case] line: Option<usize>) {
+    let arguments = parse_args(args(&["fmt", "--check", "-q", path])).unwrap();
+    let mut session = Session::from_path(Path::new(path)).unwrap();
+    compile_file(&mut session, Phase::Parse, Verbosity::Quiet).unwrap();
+    match (format_source(&arguments, &mut session), line) {
+        (Ok(()), None) => {}
+        (Err(CompilerError::Unformatted(_, found)), Some(line)) => assert_eq!(found, line),
+        (result, _) => panic!("unexpected result {:?}", result),
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn format_writes_formatted_files_to_output() {
+    let directory = std::env::temp_dir();
+    let input = directory.join(format!("crust-{}-formatted.src", std::process::id()));
+    let output = directory.join(format!("crust-{}-formatted-out.src", std::process::id()));
+    fs::copy("tests/correct/math.src", &input).unwrap();
+    let input_path = input.to_str().unwrap();
+    let output_path = output.to_str().unwrap();
+
+    for args_list in [
+        &["fmt", "-q", input_path][..],
+        &["fmt", "-q", "-o", output_path, input_path],
+    ] {
+        let arguments = parse_args(args(args_list)).unwrap();
+        let mut session = Session::from_path(&input).unwrap();
+        compile_file(&mut session, Phase::Parse, Verbosity::Quiet).unwrap();
+        format_source(&arguments, &mut session).unwrap();
+    }
+    let formatted = fs::read_to_string(&input).unwrap();
+    let written = fs::read_to_string(&output);
+    fs::remove_file(&input).unwrap();
+    fs::remove_file(&output).ok();
+    assert_eq!(written.unwrap(), formatted);
+}
+
+#[cfg(test)]
+#[test]
+fn format_leaves_files_with_skipped_text() {
+    let source = "program skipped is begin x := 1; @ end program.";
+    let path = std::env::temp_dir().join(format!("crust-{}-skipped.src", std::process::id()));
+    fs::write(&path, source).unwrap();
+    let arguments = parse_args(args(&["fmt", "-q", path.to_str().unwrap()])).unwrap();
+    let mut session = Session::from_path(&path).unwrap();
+    compile_file(&mut session, Phase::Parse, Verbosity::Quiet).unwrap();
+    let result = format_source(&arguments, &mut session);
+    let written = fs::read_to_string(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert!(matches!(
+        result,
+        Err(CompilerError::FormatError(
+            format::FormatError::SkippedText(..)
+        ))
+    ));
+    assert_eq!(written, source);
 }
@@ -1,12 +1,8 @@
-use crate::tokens::Token;
+use crate::diagnostics::DiagnosticSink;
+use crate::tokens::SpannedToken;
 use std::collections::VecDeque;
-use thiserror::Error;
 
-use self::{
-    program::ProgramStruct,
-    traits::ParseTokens,
-    utils::{ParserError, TokenQueue},
-};
+use self::{program::ProgramStruct, traits::ParseTokens, utils::TokenQueue};
 
 pub mod declaratons;
 pub mod expression;
@@ -17,7 +13,48 @@ pub mod traits;
 pub mod types;
 pub mod utils;
 
-pub fn parse_tokens(token_deque: VecDeque<Token>) -> Result<ProgramStruct, ParserError> {
-    let mut tokens = TokenQueue::new(token_deque);
+/// Parses a program, reporting any errors to `sink`.
+/// Returns `None` if the program could not be parsed.
+pub fn parse_tokens(
+    token_deque: VecDeque<SpannedToken>,
+    sink: &mut DiagnosticSink,
+) -> Option<ProgramStruct> {
+    let mut tokens = TokenQueue::new(token_deque, sink);
     ProgramStruct::parse(&mut tokens)
+        .inspect_err(|err| tokens.report(err))
+        .ok()
+}
+
+#[cfg(test)]
+use crate::scanner;
+
+#[cfg(test)]
+#[test]
+fn parse_recovers_from_syntax_errors() {
+    let source = std::fs::read_to_string("tests/recovery/syntax_errors.src").unwrap();
+    let tokens = scanner::scan(source).unwrap();
+    let mut sink = DiagnosticSink::default();
+
+    let program = parse_tokens(VecDeque::from(tokens), &mut sink).expect("parse should recover");
+    assert_eq!(sink.error_count(), 6);
+
+    let body = program.program_body;
+    assert!(matches!(
+        body.declarations[1],
+        declaratons::Declaration::Error(_)
+    ));
+    assert!(matches!(
+        body.declarations[2],
+        declaratons::Declaration::Error(_)
+    ));
+    assert!(matches!(
+        body.declarations[3],
+        declaratons::Declaration::Procedure(..)
+    ));
+    // `x := 1` is missing its semicolon, so it is skipped along with `x := 2`
+    assert!(matches!(body.statements[0], statement::Statement::Error(_)));
+    assert!(matches!(
+        body.statements[1],
+        statement::Statement::Assignment(_)
+    ));
 }
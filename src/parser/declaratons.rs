@@ -2,12 +2,15 @@ use super::procedure::{ProcedureBody, ProcedureHeader};
 use super::traits::ParseTokens;
 use super::types::{ArrayBound, TypeMark};
 use super::utils::ParserError;
+use crate::span::Span;
 use crate::tokens::Token;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Declaration {
     Procedure(bool, ProcedureDeclaration),
     Variable(bool, VariableDeclaration),
+    /// A declaration that failed to parse. The syntax error has already been reported.
+    Error(Span),
 }
 
 impl ParseTokens for Declaration {
@@ -25,36 +28,55 @@ impl ParseTokens for Declaration {
             Some(token) => Err(ParserError::UnexpectedToken(
                 String::from("Declaration"),
                 token.clone(),
+                tokens.peek_span(),
             )),
-            None => Err(ParserError::UnexpectedEOF(String::from("Declaration"))),
+            None => Err(ParserError::UnexpectedEOF(
+                String::from("Declaration"),
+                tokens.peek_span(),
+            )),
+        }
+    }
+}
+
+impl Declaration {
+    pub fn span(&self) -> Span {
+        match self {
+            Declaration::Procedure(_, procedure) => procedure.span,
+            Declaration::Variable(_, variable) => variable.span,
+            Declaration::Error(span) => *span,
         }
     }
 }
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ProcedureDeclaration {
     pub procedure_header: ProcedureHeader,
     pub procedure_body: ProcedureBody,
+    pub span: Span,
 }
 
 impl ParseTokens for ProcedureDeclaration {
     fn parse(tokens: &mut super::utils::TokenQueue) -> Result<Self, ParserError> {
+        let start = tokens.peek_span();
         let proc_header = ProcedureHeader::parse(tokens)?;
         let proc_body = ProcedureBody::parse(tokens)?;
         Ok(ProcedureDeclaration {
             procedure_header: proc_header,
             procedure_body: proc_body,
+            span: tokens.span_from(start),
         })
     }
 }
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct VariableDeclaration {
     pub identifier: String,
     pub type_mark: TypeMark,
     pub array_bound: Option<ArrayBound>,
+    pub span: Span,
 }
 
 impl ParseTokens for VariableDeclaration {
     fn parse(tokens: &mut super::utils::TokenQueue) -> Result<Self, ParserError> {
+        let start = tokens.peek_span();
         tokens.consume_expected(Token::Variable)?;
         let identifier = tokens.consume_identifier()?;
         tokens.consume_expected(Token::Colon)?;
@@ -70,9 +92,10 @@ impl ParseTokens for VariableDeclaration {
         };
 
         Ok(VariableDeclaration {
-            identifier: identifier,
-            type_mark: type_mark,
-            array_bound: array_bound,
+            identifier,
+            type_mark,
+            array_bound,
+            span: tokens.span_from(start),
         })
     }
 }
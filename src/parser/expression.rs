@@ -2,9 +2,10 @@ use super::procedure::ProcedureCall;
 use super::traits::ParseTokens;
 use super::types::*;
 use super::utils::{ParserError, TokenQueue};
+use crate::span::Span;
 use crate::tokens::Token;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Expression {
     AndExp(Box<Expression>, ArtihOp),
     OrExp(Box<Expression>, ArtihOp),
@@ -38,7 +39,18 @@ impl ParseTokens for Expression {
     }
 }
 
-#[derive(Debug)]
+impl Expression {
+    pub fn span(&self) -> Span {
+        match self {
+            Expression::AndExp(expression, arith_op) | Expression::OrExp(expression, arith_op) => {
+                expression.span().to(arith_op.span())
+            }
+            Expression::NotExp(arith_op) | Expression::BasicExp(arith_op) => arith_op.span(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub enum ArtihOp {
     AddOp(Box<ArtihOp>, Relation),
     SubOp(Box<ArtihOp>, Relation),
@@ -67,7 +79,18 @@ impl ParseTokens for ArtihOp {
     }
 }
 
-#[derive(Debug)]
+impl ArtihOp {
+    pub fn span(&self) -> Span {
+        match self {
+            ArtihOp::AddOp(arith_op, relation) | ArtihOp::SubOp(arith_op, relation) => {
+                arith_op.span().to(relation.span())
+            }
+            ArtihOp::Relation(relation) => relation.span(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub enum Relation {
     LessThan(Box<Relation>, Term),
     LessThanEq(Box<Relation>, Term),
@@ -120,7 +143,21 @@ impl ParseTokens for Relation {
     }
 }
 
-#[derive(Debug)]
+impl Relation {
+    pub fn span(&self) -> Span {
+        match self {
+            Relation::LessThan(relation, term)
+            | Relation::LessThanEq(relation, term)
+            | Relation::GreaterThan(relation, term)
+            | Relation::GreaterThanEq(relation, term)
+            | Relation::Equals(relation, term)
+            | Relation::NotEquals(relation, term) => relation.span().to(term.span()),
+            Relation::Term(term) => term.span(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub enum Term {
     MultTerm(Box<Term>, Factor),
     DivTerm(Box<Term>, Factor),
@@ -149,15 +186,48 @@ impl ParseTokens for Term {
     }
 }
 
-#[derive(Debug)]
+impl Term {
+    pub fn span(&self) -> Span {
+        match self {
+            Term::MultTerm(term, factor) | Term::DivTerm(term, factor) => {
+                term.span().to(factor.span())
+            }
+            Term::Factor(factor) => factor.span(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub enum Factor {
     NestedExpression(Box<Expression>),
     ProcedureCall(ProcedureCall),
-    Name { negate: bool, name: Name },
-    Number { negate: bool, number: Number },
+    /// `negate` is the span of the `-` before the name or number, if there is one
+    Name {
+        negate: Option<Span>,
+        name: Name,
+    },
+    Number {
+        negate: Option<Span>,
+        number: Number,
+    },
     String(StringNode),
-    TrueLit,
-    FalseLit,
+    TrueLit(Span),
+    FalseLit(Span),
+}
+
+impl Factor {
+    pub fn span(&self) -> Span {
+        match self {
+            Factor::NestedExpression(expression) => expression.span(),
+            Factor::ProcedureCall(proc_call) => proc_call.span,
+            Factor::Name { negate, name } => negate.map_or(name.span, |minus| minus.to(name.span)),
+            Factor::Number { negate, number } => {
+                negate.map_or(number.span, |minus| minus.to(number.span))
+            }
+            Factor::String(string) => string.span,
+            Factor::TrueLit(span) | Factor::FalseLit(span) => *span,
+        }
+    }
 }
 
 impl ParseTokens for Factor {
@@ -168,26 +238,31 @@ impl ParseTokens for Factor {
                 tokens.consume_expected(Token::RParen)?;
                 Ok(Factor::NestedExpression(Box::new(expression)))
             }
-            Some(Token::True) => Ok(Factor::TrueLit),
-            Some(Token::False) => Ok(Factor::FalseLit),
+            Some(Token::True) => Ok(Factor::TrueLit(tokens.last_span())),
+            Some(Token::False) => Ok(Factor::FalseLit(tokens.last_span())),
             Some(Token::StringLiteral(value)) => Ok(Factor::String(StringNode {
                 literal_string: value,
+                span: tokens.last_span(),
             })),
 
             Some(Token::Minus) => match tokens.peek_front() {
                 Some(Token::Identifier(_)) => Ok(Factor::Name {
-                    negate: true,
+                    negate: Some(tokens.last_span()),
                     name: Name::parse(tokens)?,
                 }),
                 Some(Token::NumberLiteral(_)) => Ok(Factor::Number {
-                    negate: true,
+                    negate: Some(tokens.last_span()),
                     number: Number::parse(tokens)?,
                 }),
                 Some(token) => Err(ParserError::UnexpectedToken(
                     String::from("Negation"),
                     token.clone(),
+                    tokens.peek_span(),
+                )),
+                None => Err(ParserError::UnexpectedEOF(
+                    String::from("Negation"),
+                    tokens.peek_span(),
                 )),
-                None => Err(ParserError::UnexpectedEOF(String::from("Negation"))),
             },
             Some(Token::Identifier(value)) => match tokens.peek_front() {
                 Some(Token::LParen) => {
@@ -197,7 +272,7 @@ impl ParseTokens for Factor {
                 _ => {
                     tokens.push_front(Token::Identifier(value));
                     Ok(Factor::Name {
-                        negate: false,
+                        negate: None,
                         name: Name::parse(tokens)?,
                     })
                 }
@@ -205,39 +280,49 @@ impl ParseTokens for Factor {
             Some(Token::NumberLiteral(value)) => {
                 tokens.push_front(Token::NumberLiteral(value));
                 Ok(Factor::Number {
-                    negate: false,
+                    negate: None,
                     number: Number::parse(tokens)?,
                 })
             }
-            Some(token) => Err(ParserError::UnexpectedToken(String::from("Factor"), token)),
-            None => Err(ParserError::UnexpectedEOF(String::from("Factor"))),
+            Some(token) => Err(ParserError::UnexpectedToken(
+                String::from("Factor"),
+                token,
+                tokens.last_span(),
+            )),
+            None => Err(ParserError::UnexpectedEOF(
+                String::from("Factor"),
+                tokens.peek_span(),
+            )),
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Name {
     pub identifier: Identifier,
     pub expression: Option<Box<Expression>>,
+    pub span: Span,
 }
 
 impl ParseTokens for Name {
     fn parse(tokens: &mut TokenQueue) -> Result<Self, ParserError> {
         let identifier = tokens.consume_identifier()?;
+        let identifier = Identifier {
+            identifier_string: identifier,
+            span: tokens.last_span(),
+        };
         if tokens.consume_as_bool(&Token::LBracket) {
             let expression = Expression::parse(tokens)?;
             tokens.consume_expected(Token::RBracket)?;
             Ok(Name {
-                identifier: Identifier {
-                    identifier_string: identifier,
-                },
+                span: tokens.span_from(identifier.span),
+                identifier,
                 expression: Some(Box::new(expression)),
             })
         } else {
             Ok(Name {
-                identifier: Identifier {
-                    identifier_string: identifier,
-                },
+                span: identifier.span,
+                identifier,
                 expression: None,
             })
         }
@@ -1,3 +1,4 @@
+use crate::span::Span;
 use crate::tokens::Token;
 
 use super::declaratons::{self, Declaration, VariableDeclaration};
@@ -6,15 +7,17 @@ use super::statement::Statement;
 use super::traits::ParseTokens;
 use super::types::{Identifier, TypeMark};
 use super::utils::{ParserError, TokenQueue};
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ProcedureHeader {
     pub identifier: String,
     pub type_mark: TypeMark,
     pub param_list: Option<ParamList>,
+    pub span: Span,
 }
 
 impl ParseTokens for ProcedureHeader {
     fn parse(tokens: &mut TokenQueue) -> Result<Self, ParserError> {
+        let start = tokens.peek_span();
         tokens.consume_expected(Token::Procedure)?;
         let identifier = tokens.consume_identifier()?;
         tokens.consume_expected(Token::Colon)?;
@@ -25,32 +28,36 @@ impl ParseTokens for ProcedureHeader {
             Some(Token::RParen) => {
                 tokens.pop_front();
                 Ok(ProcedureHeader {
-                    identifier: identifier,
-                    type_mark: type_mark,
+                    identifier,
+                    type_mark,
                     param_list: None,
+                    span: tokens.span_from(start),
                 })
             }
             Some(Token::Variable) => {
                 let params = ParamList::parse(tokens)?;
                 tokens.consume_expected(Token::RParen)?;
                 Ok(ProcedureHeader {
-                    identifier: identifier,
-                    type_mark: type_mark,
+                    identifier,
+                    type_mark,
                     param_list: Some(params),
+                    span: tokens.span_from(start),
                 })
             }
             Some(token) => Err(ParserError::UnexpectedToken(
                 String::from("RParen,VariableDeclaration"),
                 token.clone(),
+                tokens.peek_span(),
+            )),
+            None => Err(ParserError::UnexpectedEOF(
+                String::from("RParen,VariableDeclaration"),
+                tokens.peek_span(),
             )),
-            None => Err(ParserError::UnexpectedEOF(String::from(
-                "RParen,VariableDeclaration",
-            ))),
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ProcedureBody {
     pub declarations: Vec<Declaration>,
     pub statements: Vec<Statement>,
@@ -65,13 +72,13 @@ impl ParseTokens for ProcedureBody {
             let next_token = tokens.peek_front();
             if let Some(Token::Begin) = next_token {
                 break; // Next token is Begin. We're at the end of the declarations block.
-            } else if let None = next_token {
-                return Err(ParserError::UnexpectedEOF(String::from(
-                    "Identifier, Begin",
-                )));
+            } else if tokens.at_eof() {
+                return Err(ParserError::UnexpectedEOF(
+                    String::from("Identifier, Begin"),
+                    tokens.peek_span(),
+                ));
             } else {
-                declaratons.push(Declaration::parse(tokens)?);
-                tokens.consume_expected(Token::Semicolon)?;
+                declaratons.push(tokens.parse_terminated(Declaration::Error));
             }
         }
         tokens.consume_expected(Token::Begin)?; // Start Statements after this
@@ -80,11 +87,13 @@ impl ParseTokens for ProcedureBody {
             let next_token = tokens.peek_front();
             if let Some(Token::End) = next_token {
                 break; // Next token is End. We're at the end of the statements block.
-            } else if let None = next_token {
-                return Err(ParserError::UnexpectedEOF(String::from("Identifier, End")));
+            } else if tokens.at_eof() {
+                return Err(ParserError::UnexpectedEOF(
+                    String::from("Identifier, End"),
+                    tokens.peek_span(),
+                ));
             } else {
-                statements.push(Statement::parse(tokens)?);
-                tokens.consume_expected(Token::Semicolon)?;
+                statements.push(tokens.parse_terminated(Statement::Error));
             }
         }
         tokens.consume_expected(Token::End)?;
@@ -92,11 +101,11 @@ impl ParseTokens for ProcedureBody {
 
         Ok(ProcedureBody {
             declarations: declaratons,
-            statements: statements,
+            statements,
         })
     }
 }
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ParamList {
     pub param_list: Vec<Parameter>,
 }
@@ -118,7 +127,7 @@ impl ParseTokens for ParamList {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Parameter {
     pub variable_declaration: VariableDeclaration,
 }
@@ -131,24 +140,28 @@ impl ParseTokens for Parameter {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ProcedureCall {
     pub identifier: Identifier,
     pub arg_list: Option<ArgumentList>,
+    pub span: Span,
 }
 
 impl ParseTokens for ProcedureCall {
     fn parse(tokens: &mut TokenQueue) -> Result<Self, ParserError> {
         let identifier = tokens.consume_identifier()?;
+        let identifier = Identifier {
+            identifier_string: identifier,
+            span: tokens.last_span(),
+        };
         tokens.consume_expected(Token::LParen)?;
 
         match tokens.peek_front() {
             Some(Token::RParen) => {
                 tokens.pop_front();
                 Ok(ProcedureCall {
-                    identifier: Identifier {
-                        identifier_string: identifier,
-                    },
+                    span: tokens.span_from(identifier.span),
+                    identifier,
                     arg_list: None,
                 })
             }
@@ -156,20 +169,20 @@ impl ParseTokens for ProcedureCall {
                 let args = ArgumentList::parse(tokens)?;
                 tokens.consume_expected(Token::RParen)?;
                 Ok(ProcedureCall {
-                    identifier: Identifier {
-                        identifier_string: identifier,
-                    },
+                    span: tokens.span_from(identifier.span),
+                    identifier,
                     arg_list: Some(args),
                 })
             }
-            None => Err(ParserError::UnexpectedEOF(String::from(
-                "RParen,ArgumentList",
-            ))),
+            None => Err(ParserError::UnexpectedEOF(
+                String::from("RParen,ArgumentList"),
+                tokens.peek_span(),
+            )),
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ArgumentList {
     pub expr_list: Vec<Expression>,
 }
@@ -1,51 +1,67 @@
 use super::traits::ParseTokens;
 use super::utils::*;
 use super::{declaratons::Declaration, statement::Statement};
+use crate::diagnostics::{Diagnostic, Label};
+use crate::span::Span;
 use crate::tokens::Token;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ProgramStruct {
     pub program_header: ProgramHeader,
     pub program_body: ProgramBody,
+    pub span: Span,
 }
 
 impl ParseTokens for ProgramStruct {
     fn parse(tokens: &mut TokenQueue) -> Result<Self, ParserError> {
+        let start = tokens.peek_span();
         let header = ProgramHeader::parse(tokens)?;
         let body = ProgramBody::parse(tokens)?;
+        let span = tokens.span_from(start);
 
-        tokens
-            .consume_expected(Token::Period)
-            .unwrap_or_else(|_| println!("WARNING: Missing terminating period"));
+        if let Err(err) = tokens.consume_expected(Token::Period) {
+            tokens.report(
+                Diagnostic::warning("Missing terminating period").with_label(Label::primary(
+                    err.span(),
+                    "expected `.` after `end program`",
+                )),
+            );
+        }
         tokens.consume_expected(Token::EOF)?;
 
         if let Some(next_token) = tokens.pop_front() {
-            return Err(ParserError::ExpectedEOF(next_token));
+            return Err(ParserError::ExpectedEOF(next_token, tokens.last_span()));
         }
 
         Ok(ProgramStruct {
             program_header: header,
             program_body: body,
+            span,
         })
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ProgramHeader {
     pub header_identifier: String,
+    pub span: Span,
 }
 
 impl ParseTokens for ProgramHeader {
     fn parse(tokens: &mut TokenQueue) -> Result<Self, ParserError> {
+        let start = tokens.peek_span();
         tokens.consume_expected(Token::Program)?;
         let header_identifier = tokens.consume_identifier()?;
         tokens.consume_expected(Token::Is)?;
 
-        Ok(ProgramHeader { header_identifier })
+        Ok(ProgramHeader {
+            header_identifier,
+            span: tokens.span_from(start),
+        })
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ProgramBody {
     pub declarations: Vec<Declaration>,
     pub statements: Vec<Statement>,
@@ -60,13 +76,13 @@ impl ParseTokens for ProgramBody {
             let next_token = tokens.peek_front();
             if let Some(Token::Begin) = next_token {
                 break; // Next token is Begin. We're at the end of the declarations block.
-            } else if let None = next_token {
-                return Err(ParserError::UnexpectedEOF(String::from(
-                    "Identifier, Begin",
-                )));
+            } else if tokens.at_eof() {
+                return Err(ParserError::UnexpectedEOF(
+                    String::from("Identifier, Begin"),
+                    tokens.peek_span(),
+                ));
             } else {
-                declaratons.push(Declaration::parse(tokens)?);
-                tokens.consume_expected(Token::Semicolon)?;
+                declaratons.push(tokens.parse_terminated(Declaration::Error));
             }
         }
         tokens.consume_expected(Token::Begin)?; // Start Statements after this
@@ -75,11 +91,13 @@ impl ParseTokens for ProgramBody {
             let next_token = tokens.peek_front();
             if let Some(Token::End) = next_token {
                 break; // Next token is End. We're at the end of the statements block.
-            } else if let None = next_token {
-                return Err(ParserError::UnexpectedEOF(String::from("Identifier, End")));
+            } else if tokens.at_eof() {
+                return Err(ParserError::UnexpectedEOF(
+                    String::from("Identifier, End"),
+                    tokens.peek_span(),
+                ));
             } else {
-                statements.push(Statement::parse(tokens)?);
-                tokens.consume_expected(Token::Semicolon)?;
+                statements.push(tokens.parse_terminated(Statement::Error));
             }
         }
         tokens.consume_expected(Token::End)?;
@@ -87,7 +105,7 @@ impl ParseTokens for ProgramBody {
 
         Ok(ProgramBody {
             declarations: declaratons,
-            statements: statements,
+            statements,
         })
     }
 }
@@ -2,30 +2,49 @@ use super::expression::Expression;
 use super::traits::{CanParse, ParseTokens};
 use super::types::Identifier;
 use super::utils::{ParserError, TokenQueue};
+use crate::span::Span;
 use crate::tokens::Token;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Statement {
-    Assignment(AssignmentStatement),
-    If(IfStatement),
-    Loop(LoopStatement),
+    Assignment(Box<AssignmentStatement>),
+    If(Box<IfStatement>),
+    Loop(Box<LoopStatement>),
     Return(ReturnStatement),
+    /// A statement that failed to parse. The syntax error has already been reported.
+    Error(Span),
 }
 
 impl ParseTokens for Statement {
     fn parse(tokens: &mut TokenQueue) -> Result<Self, ParserError> {
         match tokens.peek_front() {
-            Some(Token::Identifier(_)) => {
-                Ok(Statement::Assignment(AssignmentStatement::parse(tokens)?))
-            }
-            Some(Token::If) => Ok(Statement::If(IfStatement::parse(tokens)?)),
-            Some(Token::For) => Ok(Statement::Loop(LoopStatement::parse(tokens)?)),
+            Some(Token::Identifier(_)) => Ok(Statement::Assignment(Box::new(
+                AssignmentStatement::parse(tokens)?,
+            ))),
+            Some(Token::If) => Ok(Statement::If(Box::new(IfStatement::parse(tokens)?))),
+            Some(Token::For) => Ok(Statement::Loop(Box::new(LoopStatement::parse(tokens)?))),
             Some(Token::Return) => Ok(Statement::Return(ReturnStatement::parse(tokens)?)),
             Some(token) => Err(ParserError::UnexpectedToken(
                 String::from("Satement"),
                 token.clone(),
+                tokens.peek_span(),
+            )),
+            None => Err(ParserError::UnexpectedEOF(
+                String::from("Statement"),
+                tokens.peek_span(),
             )),
-            None => Err(ParserError::UnexpectedEOF(String::from("Statement"))),
+        }
+    }
+}
+
+impl Statement {
+    pub fn span(&self) -> Span {
+        match self {
+            Statement::Assignment(statement) => statement.span,
+            Statement::If(statement) => statement.span,
+            Statement::Loop(statement) => statement.span,
+            Statement::Return(statement) => statement.span,
+            Statement::Error(span) => *span,
         }
     }
 }
@@ -42,10 +61,11 @@ impl CanParse for Statement {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct AssignmentStatement {
     pub destination: Destination,
     pub expression: Expression,
+    pub span: Span,
 }
 
 impl ParseTokens for AssignmentStatement {
@@ -54,17 +74,19 @@ impl ParseTokens for AssignmentStatement {
         tokens.consume_expected(Token::Assignment)?;
         let expression = Expression::parse(tokens)?;
         Ok(AssignmentStatement {
-            destination: destination,
-            expression: expression,
+            span: tokens.span_from(destination.span),
+            destination,
+            expression,
         })
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct IfStatement {
     pub condition: Expression,
     pub then_statement: Vec<Statement>,
     pub else_statement: Option<Vec<Statement>>,
+    pub span: Span,
 }
 
 impl ParseTokens for IfStatement {
@@ -73,6 +95,7 @@ impl ParseTokens for IfStatement {
         let mut else_block = Vec::new();
 
         // Take care of "header"
+        let start = tokens.peek_span();
         tokens.consume_expected(Token::If)?;
         tokens.consume_expected(Token::LParen)?;
         let condition = Expression::parse(tokens)?;
@@ -81,8 +104,7 @@ impl ParseTokens for IfStatement {
 
         // Then block
         while Statement::can_parse(tokens) {
-            then_block.push(Statement::parse(tokens)?);
-            tokens.consume_expected(Token::Semicolon)?;
+            then_block.push(tokens.parse_terminated(Statement::Error));
         }
 
         // Check if else block exists
@@ -91,8 +113,7 @@ impl ParseTokens for IfStatement {
         // Else block
         if else_exists {
             while Statement::can_parse(tokens) {
-                else_block.push(Statement::parse(tokens)?);
-                tokens.consume_expected(Token::Semicolon)?;
+                else_block.push(tokens.parse_terminated(Statement::Error));
             }
         }
 
@@ -104,21 +125,24 @@ impl ParseTokens for IfStatement {
             condition,
             then_statement: then_block,
             else_statement: if else_exists { Some(else_block) } else { None },
+            span: tokens.span_from(start),
         })
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct LoopStatement {
     pub assignment_statement: AssignmentStatement,
     pub condition: Expression,
     pub loop_body: Vec<Statement>,
+    pub span: Span,
 }
 
 impl ParseTokens for LoopStatement {
     fn parse(tokens: &mut TokenQueue) -> Result<Self, ParserError> {
         let mut loop_body = Vec::new();
 
+        let start = tokens.peek_span();
         tokens.consume_expected(Token::For)?;
         tokens.consume_expected(Token::LParen)?;
 
@@ -130,62 +154,68 @@ impl ParseTokens for LoopStatement {
 
         // Loop body
         while Statement::can_parse(tokens) {
-            loop_body.push(Statement::parse(tokens)?);
-            tokens.consume_expected(Token::Semicolon)?;
+            loop_body.push(tokens.parse_terminated(Statement::Error));
         }
 
         tokens.consume_expected(Token::End)?;
         tokens.consume_expected(Token::For)?;
 
         Ok(LoopStatement {
-            assignment_statement: assignment_statement,
+            assignment_statement,
             condition,
             loop_body,
+            span: tokens.span_from(start),
         })
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ReturnStatement {
     pub expression: Expression,
+    pub span: Span,
 }
 
 impl ParseTokens for ReturnStatement {
     fn parse(tokens: &mut TokenQueue) -> Result<Self, ParserError> {
+        let start = tokens.peek_span();
         tokens.consume_expected(Token::Return)?;
 
         let expression = Expression::parse(tokens)?;
 
         Ok(ReturnStatement {
-            expression: expression,
+            expression,
+            span: tokens.span_from(start),
         })
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Destination {
     pub identifier: Identifier,
     pub expression: Option<Expression>,
+    pub span: Span,
 }
 
 impl ParseTokens for Destination {
     fn parse(tokens: &mut TokenQueue) -> Result<Self, ParserError> {
         let identifier = tokens.consume_identifier()?;
+        let identifier = Identifier {
+            identifier_string: identifier,
+            span: tokens.last_span(),
+        };
 
         if tokens.consume_as_bool(&Token::LBracket) {
             let expression = Expression::parse(tokens)?;
             tokens.consume_expected(Token::RBracket)?;
             Ok(Destination {
-                identifier: Identifier {
-                    identifier_string: identifier,
-                },
+                span: tokens.span_from(identifier.span),
+                identifier,
                 expression: Some(expression),
             })
         } else {
             Ok(Destination {
-                identifier: Identifier {
-                    identifier_string: identifier,
-                },
+                span: identifier.span,
+                identifier,
                 expression: None,
             })
         }
@@ -1,9 +1,10 @@
 use super::traits::ParseTokens;
 use super::utils::ParserError;
 use crate::semantics::SemanticsError;
+use crate::span::Span;
 use crate::tokens::Token;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum TypeMark {
     Integer,
     Float,
@@ -21,12 +22,16 @@ impl ParseTokens for TypeMark {
             Some(token) => Err(ParserError::UnexpectedToken(
                 String::from("TypeMark"),
                 token,
+                tokens.last_span(),
+            )),
+            None => Err(ParserError::UnexpectedEOF(
+                String::from("TypeMark"),
+                tokens.peek_span(),
             )),
-            None => Err(ParserError::UnexpectedEOF(String::from("TypeMark"))),
         }
     }
 }
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ArrayBound {
     pub number: Number,
 }
@@ -37,9 +42,10 @@ impl ParseTokens for ArrayBound {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Number {
     pub literal_string: String,
+    pub span: Span,
 }
 
 impl ParseTokens for Number {
@@ -47,12 +53,17 @@ impl ParseTokens for Number {
         match tokens.pop_front() {
             Some(Token::NumberLiteral(val)) => Ok(Number {
                 literal_string: val,
+                span: tokens.last_span(),
             }),
             Some(token) => Err(ParserError::UnexpectedToken(
                 String::from("NumberLiteral"),
                 token,
+                tokens.last_span(),
+            )),
+            None => Err(ParserError::UnexpectedEOF(
+                String::from("NumberLiteral"),
+                tokens.peek_span(),
             )),
-            None => Err(ParserError::UnexpectedEOF(String::from("NumberLiteral"))),
         }
     }
 }
@@ -60,30 +71,38 @@ impl ParseTokens for Number {
 impl TryFrom<Number> for usize {
     type Error = SemanticsError;
     fn try_from(value: Number) -> Result<Self, Self::Error> {
-        let value = value.literal_string.replace("_", "");
-        Ok(usize::from_str_radix(&value, 10)?)
+        let literal = value.literal_string.replace('_', "");
+        literal
+            .parse::<usize>()
+            .map_err(|err| SemanticsError::InvalidIntLiteral(err, value.span))
     }
 }
 impl TryFrom<Number> for i64 {
     type Error = SemanticsError;
     fn try_from(value: Number) -> Result<Self, Self::Error> {
-        let value = value.literal_string.replace("_", "");
-        Ok(i64::from_str_radix(&value, 10)?)
+        let literal = value.literal_string.replace('_', "");
+        literal
+            .parse::<i64>()
+            .map_err(|err| SemanticsError::InvalidIntLiteral(err, value.span))
     }
 }
 impl TryFrom<Number> for f64 {
     type Error = SemanticsError;
     fn try_from(value: Number) -> Result<Self, Self::Error> {
-        let value = value.literal_string.replace("_", "");
-        Ok(value.parse::<f64>()?)
+        let literal = value.literal_string.replace('_', "");
+        literal
+            .parse::<f64>()
+            .map_err(|err| SemanticsError::InvalidFloatLiteral(err, value.span))
     }
 }
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct StringNode {
     pub literal_string: String,
+    pub span: Span,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Identifier {
     pub identifier_string: String,
+    pub span: Span,
 }
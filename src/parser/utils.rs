@@ -1,41 +1,110 @@
 use std::collections::VecDeque;
 use thiserror::Error;
 
-use crate::tokens::Token;
+use super::traits::ParseTokens;
+use crate::diagnostics::{Diagnostic, DiagnosticSink};
+use crate::span::Span;
+use crate::tokens::{SpannedToken, Token};
 
 #[derive(Error, Debug)]
 pub enum ParserError {
     #[error("Encountered EOF token before exhausting token queue.")]
-    EarlyEOF,
+    EarlyEOF(Span),
     #[error("Encountered EOF token. Expected token: {0:?}")]
-    UnexpectedEOFToken(Token),
+    UnexpectedEOFToken(Token, Span),
     #[error("Encountered EOF. Expected the following tokens: {0}")]
-    UnexpectedEOF(String),
+    UnexpectedEOF(String, Span),
     #[error("Encountered token: {0:?} Expected EOF.")]
-    ExpectedEOF(Token),
+    ExpectedEOF(Token, Span),
     #[error("Expected token: {0} Encountered token: {1:?}")]
-    UnexpectedToken(String, Token),
+    UnexpectedToken(String, Token, Span),
 }
+
+impl ParserError {
+    pub fn span(&self) -> Span {
+        match self {
+            ParserError::EarlyEOF(span)
+            | ParserError::UnexpectedEOFToken(_, span)
+            | ParserError::UnexpectedEOF(_, span)
+            | ParserError::ExpectedEOF(_, span)
+            | ParserError::UnexpectedToken(_, _, span) => *span,
+        }
+    }
+}
+
 #[derive(Debug)]
-pub struct TokenQueue {
-    tokens: VecDeque<Token>,
+pub struct TokenQueue<'a> {
+    tokens: VecDeque<SpannedToken>,
+    /// Span of the most recently popped token
+    last_span: Span,
+    /// The most recently popped token, used to tell whether an error consumed its token
+    last_token: Option<Token>,
+    sink: &'a mut DiagnosticSink,
 }
 
-impl TokenQueue {
-    pub fn new(tokens: VecDeque<Token>) -> Self {
-        TokenQueue { tokens: tokens }
+impl<'a> TokenQueue<'a> {
+    pub fn new(tokens: VecDeque<SpannedToken>, sink: &'a mut DiagnosticSink) -> Self {
+        TokenQueue {
+            tokens,
+            last_span: Span::default(),
+            last_token: None,
+            sink,
+        }
+    }
+
+    /// Reports a diagnostic without stopping the parse
+    pub fn report(&mut self, diagnostic: impl Into<Diagnostic>) {
+        self.sink.push(diagnostic.into())
     }
 
     pub fn pop_front(&mut self) -> Option<Token> {
-        self.tokens.pop_front()
+        self.tokens.pop_front().map(|spanned| {
+            self.last_span = spanned.span;
+            self.last_token = Some(spanned.token.clone());
+            spanned.token
+        })
     }
 
-    pub fn push_front(&mut self, value: Token) -> () {
-        self.tokens.push_front(value)
+    /// Puts a token back on the queue. Meant to be used right after `pop_front`,
+    /// so the token gets the span of the last popped token.
+    pub fn push_front(&mut self, value: Token) {
+        self.tokens
+            .push_front(SpannedToken::new(value, self.last_span))
     }
 
     pub fn peek_front(&self) -> Option<&Token> {
-        self.tokens.front()
+        self.tokens.front().map(|spanned| &spanned.token)
+    }
+
+    pub fn peek_nth(&self, index: usize) -> Option<&Token> {
+        self.tokens.get(index).map(|spanned| &spanned.token)
+    }
+
+    /// True if the next token is EOF, or there are no tokens left
+    pub fn at_eof(&self) -> bool {
+        matches!(self.peek_front(), None | Some(Token::EOF))
+    }
+
+    /// Span of the next token in the queue.
+    /// If the queue is empty, returns an empty span at the end of the last popped token.
+    pub fn peek_span(&self) -> Span {
+        self.tokens.front().map_or(
+            Span {
+                start: self.last_span.end,
+                ..self.last_span
+            },
+            |spanned| spanned.span,
+        )
+    }
+
+    /// Span of the most recently popped token
+    pub fn last_span(&self) -> Span {
+        self.last_span
+    }
+
+    /// Span from `start` up to the end of the most recently popped token
+    pub fn span_from(&self, start: Span) -> Span {
+        start.to(self.last_span)
     }
 
     pub fn remaining(&self) -> usize {
@@ -48,17 +117,18 @@ impl TokenQueue {
         }
         if let Some(token) = self.pop_front() {
             if self.remaining() > 0 && token == Token::EOF {
-                return Err(ParserError::EarlyEOF);
+                return Err(ParserError::EarlyEOF(self.last_span));
             }
             if token != expected {
                 return Err(ParserError::UnexpectedToken(
                     format!("{:?}", expected),
                     token,
+                    self.last_span,
                 ));
             }
             return Ok(());
         }
-        Err(ParserError::UnexpectedEOFToken(expected))
+        Err(ParserError::UnexpectedEOFToken(expected, self.peek_span()))
     }
 
     /// Need a separate function for consuming identifiers, since we need to take the data out of them.
@@ -68,10 +138,12 @@ impl TokenQueue {
             Some(token) => Err(ParserError::UnexpectedToken(
                 String::from("Identifier"),
                 token,
+                self.last_span,
+            )),
+            _ => Err(ParserError::UnexpectedEOFToken(
+                Token::Identifier(String::from("Identifier")),
+                self.peek_span(),
             )),
-            _ => Err(ParserError::UnexpectedEOFToken(Token::Identifier(
-                String::from("Identifier"),
-            ))),
         }
     }
 
@@ -86,4 +158,87 @@ impl TokenQueue {
             _ => false,
         }
     }
+
+    /// Parses a `T` terminated by a semicolon.
+    /// On a syntax error, reports it, skips ahead with `recover` and returns the node made by `error_node`
+    /// from the skipped span, so the enclosing block can carry on.
+    pub fn parse_terminated<T: ParseTokens>(&mut self, error_node: fn(Span) -> T) -> T {
+        let start = self.peek_span();
+        let opener = self.peek_front().cloned();
+        let result = T::parse(self).and_then(|node| {
+            self.consume_expected(Token::Semicolon)?;
+            Ok(node)
+        });
+        match result {
+            Ok(node) => node,
+            Err(err) => error_node(self.recover(err, start, opener)),
+        }
+    }
+
+    /// Panic-mode recovery. Reports `err`, then skips tokens until just past the next `;`, or until the next `begin`, `end`
+    /// or EOF, which are left for the enclosing block.
+    /// If the failed construct opened with `if`, `for` or `procedure`, everything up to the matching
+    /// `end if`, `end for` or `end procedure` is skipped too. Returns the span of the skipped tokens.
+    pub fn recover(&mut self, err: ParserError, start: Span, opener: Option<Token>) -> Span {
+        let mut depth = usize::from(opener.as_ref().is_some_and(opens_block));
+        let err_span = err.span();
+        self.report(&err);
+
+        // The token that caused the error may have already been popped
+        if err_span == self.last_span && self.peek_span() != err_span {
+            match self.last_token.clone() {
+                Some(Token::Semicolon) if depth == 0 => return self.span_from(start),
+                Some(token @ (Token::Begin | Token::End | Token::EOF)) => self.push_front(token),
+                _ => {}
+            }
+        }
+
+        // Always make progress, or the enclosing block would fail on the same token forever
+        if self.peek_span() == start && !self.at_eof() {
+            if let Some(Token::End) = self.pop_front() {
+                if self.peek_front().is_some_and(opens_block) {
+                    self.pop_front();
+                }
+            }
+        }
+
+        while let Some(token) = self.peek_front() {
+            match token {
+                Token::EOF => break,
+                Token::End if depth == 0 => break,
+                Token::Begin if depth == 0 => break,
+                Token::End => {
+                    if !self.peek_nth(1).is_some_and(opens_block) {
+                        break; // End of the enclosing block
+                    }
+                    self.pop_front();
+                    self.pop_front();
+                    depth -= 1;
+                    if depth == 0 {
+                        self.consume_as_bool(&Token::Semicolon);
+                        break;
+                    }
+                }
+                Token::Semicolon => {
+                    self.pop_front();
+                    if depth == 0 {
+                        break;
+                    }
+                }
+                token if opens_block(token) => {
+                    depth += 1;
+                    self.pop_front();
+                }
+                _ => {
+                    self.pop_front();
+                }
+            }
+        }
+
+        self.span_from(start)
+    }
+}
+
+fn opens_block(token: &Token) -> bool {
+    matches!(token, Token::If | Token::For | Token::Procedure)
 }
@@ -1,33 +1,83 @@
 pub mod stripper;
 
-use crate::tokens::{BuildToken, Token, TokenError};
+use std::mem::discriminant;
+
+use crate::span::{LineIndex, Span};
+use crate::tokens::{BuildToken, Comment, SpannedToken, Token, TokenError};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum ScannerError {
-    #[error(transparent)]
-    StripError(#[from] stripper::StripError),
+    #[error("{0}")]
+    StripError(stripper::StripError, Span),
     #[error("Encountered invalid numeric literal.")]
-    NumLitError,
-    #[error(transparent)]
-    TokenError(#[from] TokenError),
+    NumLitError(Span),
+    #[error("{0}")]
+    TokenError(TokenError, Span),
     #[error("Invalid character.")]
-    InvalidCharacterError,
+    InvalidCharacterError(Span),
+}
+
+impl ScannerError {
+    pub fn span(&self) -> Span {
+        match self {
+            ScannerError::StripError(_, span)
+            | ScannerError::NumLitError(span)
+            | ScannerError::TokenError(_, span)
+            | ScannerError::InvalidCharacterError(span) => *span,
+        }
+    }
 }
 
 const SINGLE_CHARS: &str = "+-*/[]()&|.;,";
 const POSSIBLE_COMPOUNDS: &str = "<>=!:";
 
-pub fn scan(file_contents: String) -> Result<Vec<Token>, ScannerError> {
-    let mut line_number = 0u32;
-    let mut token_vec: Vec<Token> = vec![];
-    let cleaned_file = stripper::strip_comments(file_contents)?;
+pub fn scan(file_contents: String) -> Result<Vec<SpannedToken>, ScannerError> {
+    scan_with_comments(file_contents).map(|(tokens, _)| tokens)
+}
+
+/// Scans a file, also returning its comments, for tools that need to keep them
+pub fn scan_with_comments(
+    file_contents: String,
+) -> Result<(Vec<SpannedToken>, Vec<Comment>), ScannerError> {
+    let line_index = LineIndex::new(&file_contents);
+    let stripped = stripper::strip_comments(&file_contents).map_err(|err| {
+        let stripper::StripError::MaxCommentDepth(offset) = err;
+        ScannerError::StripError(err, line_index.span(&file_contents, offset, offset + 1))
+    })?;
+    let offsets = stripped.offsets;
+    let cleaned_chars: Vec<char> = stripped.text.chars().collect();
+    let comments = stripped
+        .comments
+        .into_iter()
+        .map(|(start, end)| Comment {
+            text: String::from(&file_contents[start..end]),
+            span: line_index.span(&file_contents, start, end),
+        })
+        .collect();
+
+    // Spans are built from inclusive indices into the cleaned characters,
+    // then mapped back to byte offsets in the original file.
+    let span = |first: usize, last: usize| {
+        let end = offsets[last] + cleaned_chars[last].len_utf8();
+        line_index.span(&file_contents, offsets[first], end)
+    };
+    let char_token = |symbol_char: char, index: usize| {
+        Token::from_char(symbol_char)
+            .map(|token| SpannedToken::new(token, span(index, index)))
+            .map_err(|err| ScannerError::TokenError(err, span(index, index)))
+    };
+
+    let mut token_vec: Vec<SpannedToken> = vec![];
     let mut current_token = BuildToken::None;
-    for (index, curr_char) in cleaned_file.chars().enumerate() {
+    // Index of the first character of the token currently being built
+    let mut token_start = 0usize;
+    for (index, &curr_char) in cleaned_chars.iter().enumerate() {
+        let previous_kind = discriminant(&current_token);
         current_token = match (curr_char, current_token) {
             (' ' | '\t' | '\n', BuildToken::None) => BuildToken::None,
             (curr_char, BuildToken::None) if SINGLE_CHARS.contains(curr_char) => {
-                token_vec.push(Token::from_char(curr_char)?);
+                token_vec.push(char_token(curr_char, index)?);
                 BuildToken::None
             }
             (curr_char, BuildToken::None) if POSSIBLE_COMPOUNDS.contains(curr_char) => {
@@ -36,23 +86,25 @@ pub fn scan(file_contents: String) -> Result<Vec<Token>, ScannerError> {
 
             ('=', BuildToken::CompoundSymbol(string)) => {
                 let compound_chars = format!("{string}=");
-                token_vec.push(Token::from_compound_identifier(compound_chars.as_str())?);
+                let token = Token::from_compound_identifier(compound_chars.as_str())
+                    .map_err(|err| ScannerError::TokenError(err, span(token_start, index)))?;
+                token_vec.push(SpannedToken::new(token, span(token_start, index)));
                 BuildToken::None
             }
 
             (' ' | '\t' | '\n', BuildToken::CompoundSymbol(string)) => {
                 let string_char = string.chars().next().unwrap();
-                token_vec.push(Token::from_char(string_char)?);
+                token_vec.push(char_token(string_char, token_start)?);
                 BuildToken::None
             }
 
-            (match_char, BuildToken::CompoundSymbol(string)) if string == String::from(":") => {
-                token_vec.push(Token::from_char(':')?);
+            (match_char, BuildToken::CompoundSymbol(string)) if string == ":" => {
+                token_vec.push(char_token(':', token_start)?);
                 match match_char {
                     '0'..='9' => BuildToken::NumberLiteral(String::from(match_char)),
                     '"' => BuildToken::StringLiteral(String::from("")),
                     'a'..='z' | 'A'..='Z' => BuildToken::Identifier(String::from(match_char)),
-                    _ => return Err(ScannerError::InvalidCharacterError),
+                    _ => return Err(ScannerError::InvalidCharacterError(span(index, index))),
                 }
             }
 
@@ -66,28 +118,40 @@ pub fn scan(file_contents: String) -> Result<Vec<Token>, ScannerError> {
                 BuildToken::NumberLiteral(updated_literal)
             }
             ('.', BuildToken::NumberLiteral(string)) if string.contains('.') => {
-                return Err(ScannerError::NumLitError);
+                return Err(ScannerError::NumLitError(span(token_start, index)));
             }
 
             (curr_char, BuildToken::NumberLiteral(string)) if SINGLE_CHARS.contains(curr_char) => {
-                token_vec.push(Token::num_literal_from_string(string));
-                token_vec.push(Token::from_char(curr_char)?);
+                token_vec.push(SpannedToken::new(
+                    Token::num_literal_from_string(string),
+                    span(token_start, index - 1),
+                ));
+                token_vec.push(char_token(curr_char, index)?);
                 BuildToken::None
             }
             (curr_char, BuildToken::NumberLiteral(string))
                 if POSSIBLE_COMPOUNDS.contains(curr_char) =>
             {
-                token_vec.push(Token::num_literal_from_string(string));
+                token_vec.push(SpannedToken::new(
+                    Token::num_literal_from_string(string),
+                    span(token_start, index - 1),
+                ));
                 BuildToken::CompoundSymbol(String::from(curr_char))
             }
             (' ' | '\t' | '\n', BuildToken::NumberLiteral(string)) => {
-                token_vec.push(Token::num_literal_from_string(string));
+                token_vec.push(SpannedToken::new(
+                    Token::num_literal_from_string(string),
+                    span(token_start, index - 1),
+                ));
                 BuildToken::None
             }
 
             ('"', BuildToken::None) => BuildToken::StringLiteral(String::from("")),
             ('"', BuildToken::StringLiteral(string)) => {
-                token_vec.push(Token::string_literal_from_string(string));
+                token_vec.push(SpannedToken::new(
+                    Token::string_literal_from_string(string),
+                    span(token_start, index),
+                ));
                 BuildToken::None
             }
             (_, BuildToken::StringLiteral(string)) => {
@@ -101,29 +165,45 @@ pub fn scan(file_contents: String) -> Result<Vec<Token>, ScannerError> {
                 BuildToken::Identifier(format!("{string}{curr_char}"))
             }
             (curr_char, BuildToken::Identifier(string)) if SINGLE_CHARS.contains(curr_char) => {
-                token_vec.push(Token::from_string(string));
-                token_vec.push(Token::from_char(curr_char)?);
+                token_vec.push(SpannedToken::new(
+                    Token::from_string(string),
+                    span(token_start, index - 1),
+                ));
+                token_vec.push(char_token(curr_char, index)?);
                 BuildToken::None
             }
             (curr_char, BuildToken::Identifier(string))
                 if POSSIBLE_COMPOUNDS.contains(curr_char) =>
             {
-                token_vec.push(Token::Identifier(string));
+                token_vec.push(SpannedToken::new(
+                    Token::from_string(string),
+                    span(token_start, index - 1),
+                ));
                 BuildToken::CompoundSymbol(String::from(curr_char))
             }
-            (' ' | '\t' | '\n', BuildToken::Identifier(string)) => {
-                token_vec.push(Token::from_string(string));
-                BuildToken::None
-            }
             (_, BuildToken::Identifier(string)) => {
-                token_vec.push(Token::from_string(string));
+                token_vec.push(SpannedToken::new(
+                    Token::from_string(string),
+                    span(token_start, index - 1),
+                ));
                 BuildToken::None
             }
             _ => BuildToken::None,
+        };
+
+        // Any change in the kind of token being built means a new token starts at this character.
+        if !matches!(current_token, BuildToken::None)
+            && discriminant(&current_token) != previous_kind
+        {
+            token_start = index;
         }
     }
-    token_vec.push(Token::EOF);
-    Ok(token_vec)
+    let eof = file_contents.len();
+    token_vec.push(SpannedToken::new(
+        Token::EOF,
+        line_index.span(&file_contents, eof, eof),
+    ));
+    Ok((token_vec, comments))
 }
 
 #[cfg(test)]
@@ -139,3 +219,36 @@ fn test_scan(#[files("tests/correct/*.src")] path: PathBuf) {
     let token_vec = scan(test_file_text).unwrap();
     println!("{:?}: {:?}\n", file_name, token_vec);
 }
+
+#[cfg(test)]
+#[rstest]
+fn test_scan_spans(#[files("tests/correct/*.src")] path: PathBuf) {
+    let test_file_text = fs::read_to_string(path.as_path()).unwrap();
+    let token_vec = scan(test_file_text.clone()).unwrap();
+    for SpannedToken { token, span } in token_vec {
+        let source_text = &test_file_text[span.start..span.end];
+        match token {
+            Token::Identifier(identifier) => assert_eq!(source_text.to_lowercase(), identifier),
+            Token::StringLiteral(literal) => assert_eq!(source_text, format!("\"{literal}\"")),
+            Token::EOF => assert!(source_text.is_empty()),
+            _ => assert!(!source_text.is_empty()),
+        }
+        let line_start = test_file_text[..span.start]
+            .rfind('\n')
+            .map_or(0, |i| i + 1);
+        let line = test_file_text[..span.start].matches('\n').count() + 1;
+        let column = test_file_text[line_start..span.start].chars().count() + 1;
+        assert_eq!((span.line, span.column), (line, column));
+    }
+}
+
+#[cfg(test)]
+#[rstest]
+#[case("true==x", Token::True)]
+#[case("not<y", Token::Not)]
+#[case("Total:=1", Token::Identifier(String::from("total")))]
+#[case("total := 1", Token::Identifier(String::from("total")))]
+fn scan_word_before_compound_operator(#[case] source: &str, #[case] expected: Token) {
+    let tokens = scan(String::from(source)).unwrap();
+    assert_eq!(tokens[0].token, expected);
+}
@@ -24,19 +24,41 @@ pub enum StripState {
 
 #[derive(Error, Debug)]
 pub enum StripError {
+    /// Holds the byte offset of the comment that went too deep.
     #[error("Reached max block comment nesting depth. Why?")]
-    MaxCommentDepth,
+    MaxCommentDepth(usize),
 }
 
-pub fn strip_comments(file_str: String) -> Result<String, StripError> {
-    let mut ret_str = String::with_capacity(file_str.capacity()); // Make a blank string with the same size as the original
+/// The result of stripping comments from a file
+#[derive(Debug)]
+pub struct Stripped {
+    pub text: String,
+    /// The byte offset in the original file of every character in `text`.
+    /// The scanner uses these offsets to map tokens back to their position in the original file.
+    pub offsets: Vec<usize>,
+    /// Byte ranges of the comments that were stripped, in order.
+    /// Line comments don't include their newline, which is left in `text`.
+    pub comments: Vec<(usize, usize)>,
+}
+
+/// Strips comments, keeping track of where each character and comment was in `file_str`.
+pub fn strip_comments(file_str: &str) -> Result<Stripped, StripError> {
+    let mut ret_str = String::with_capacity(file_str.len()); // Make a blank string with the same size as the original
+    let mut offsets = Vec::with_capacity(file_str.len());
+    let mut comments = Vec::new();
     let mut strip_state = StripState::Normal;
+    let mut slash_offset = 0;
 
-    for char in file_str.chars() {
+    for (offset, char) in file_str.char_indices() {
         strip_state = match (char, strip_state) {
-            ('/', StripState::Normal) => StripState::FirstSlash, // This essentially gives a lookahead state. The slash won't be pushed... yet.
+            ('/', StripState::Normal) => {
+                // This essentially gives a lookahead state. The slash won't be pushed... yet.
+                slash_offset = offset;
+                StripState::FirstSlash
+            }
             (_, StripState::Normal) => {
                 ret_str.push(char);
+                offsets.push(offset);
                 StripState::Normal
             }
 
@@ -45,32 +67,50 @@ pub fn strip_comments(file_str: String) -> Result<String, StripError> {
             (_, StripState::FirstSlash) => {
                 // False alarm. Add the slash to the return string, and the current character.
                 ret_str.push('/');
+                offsets.push(slash_offset);
                 ret_str.push(char);
+                offsets.push(offset);
                 StripState::Normal
             }
 
             ('\n', StripState::LineComment) => {
                 // Go back to normal once we find a newline
+                comments.push((slash_offset, offset));
                 ret_str.push(char);
+                offsets.push(offset);
                 StripState::Normal
             }
             (_, StripState::LineComment) => StripState::LineComment,
 
             ('/', StripState::BlockComment(n)) => StripState::BlockCommentSlash(n),
-            ('*', StripState::BlockComment(u8::MAX)) => return Err(StripError::MaxCommentDepth),
+            ('*', StripState::BlockComment(u8::MAX)) => {
+                return Err(StripError::MaxCommentDepth(offset))
+            }
             ('*', StripState::BlockComment(n)) => StripState::BlockCommentStar(n),
             (_, StripState::BlockComment(n)) => StripState::BlockComment(n),
 
             ('*', StripState::BlockCommentSlash(n)) => StripState::BlockComment(n + 1),
             (_, StripState::BlockCommentSlash(n)) => StripState::BlockComment(n),
 
-            ('/', StripState::BlockCommentStar(1)) => StripState::Normal,
+            ('/', StripState::BlockCommentStar(1)) => {
+                comments.push((slash_offset, offset + 1));
+                StripState::Normal
+            }
             ('/', StripState::BlockCommentStar(n)) => StripState::BlockComment(n - 1),
             (_, StripState::BlockCommentStar(n)) => StripState::BlockComment(n),
         }
     }
 
-    Ok(String::from(ret_str))
+    // A comment left open at the end of the file runs to the end of it
+    if !matches!(strip_state, StripState::Normal | StripState::FirstSlash) {
+        comments.push((slash_offset, file_str.len()));
+    }
+
+    Ok(Stripped {
+        text: ret_str,
+        offsets,
+        comments,
+    })
 }
 
 #[cfg(test)]
@@ -83,8 +123,10 @@ enum StripTestError {
 }
 #[cfg(test)]
 use rstest::rstest;
+#[cfg(test)]
+use std::fs;
+#[cfg(test)]
 use std::path::{Path, PathBuf};
-use std::{fs, io};
 #[cfg(test)]
 #[rstest]
 
@@ -98,8 +140,24 @@ fn strip_comment_test(
     let source_contents = fs::read_to_string(source_file)?;
     let stripped_contents = fs::read_to_string(stripped_file)?;
 
-    let stripped_source = strip_comments(source_contents)?;
-    assert_eq!(stripped_source, stripped_contents);
+    let stripped = strip_comments(&source_contents)?;
+    assert_eq!(stripped.text, stripped_contents);
 
     Ok(())
 }
+
+#[cfg(test)]
+#[rstest]
+#[case("a // line\nb", vec!["// line"])]
+#[case("a /* one /* two */ */ b", vec!["/* one /* two */ */"])]
+#[case("a / b // c", vec!["// c"])]
+#[case("a /* open", vec!["/* open"])]
+fn strip_keeps_comment_ranges(#[case] source: &str, #[case] expected: Vec<&str>) {
+    let stripped = strip_comments(source).unwrap();
+    let comments: Vec<&str> = stripped
+        .comments
+        .iter()
+        .map(|&(start, end)| &source[start..end])
+        .collect();
+    assert_eq!(comments, expected);
+}
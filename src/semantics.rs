@@ -2,17 +2,24 @@ use std::num::{ParseFloatError, ParseIntError};
 
 use thiserror::Error;
 
+pub mod assignment;
+pub mod bounds;
 pub mod context;
 pub mod declaration;
 pub mod expression;
+pub mod flow;
+pub mod fold;
 pub mod procedure;
 pub mod statement;
 pub mod traits;
 pub mod value;
 
+use crate::diagnostics::DiagnosticSink;
 use crate::parser::program::ProgramStruct;
+use crate::span::Span;
 
-use self::context::{Context, Scope, ScopeContext};
+use self::context::{Binding, Context, Scope, ScopeContext};
+use self::declaration::declare_procedures;
 use self::procedure::AnalyzedProcedure;
 use self::statement::AnalyzedBlock;
 use self::traits::Analyze;
@@ -21,30 +28,52 @@ use self::value::Type;
 #[derive(Debug, Error)]
 pub enum SemanticsError {
     #[error("Type mismatch. Expected: {0:?}, Got: {1:?}")]
-    TypeMismatch(Type, Type),
+    TypeMismatch(Type, Type, Span),
     #[error("Encountered invalid type {1:?}. Expected {0}.")]
-    InvalidType(String, Type),
+    InvalidType(String, Type, Span),
 
     #[error("Encountered {0} params. Expected {1}")]
-    ParamCountMismatch(usize, usize),
+    ParamCountMismatch(usize, usize, Span),
 
+    /// Holds the span of the new declaration, then the span of the original one if it has one.
     #[error("Variable {0} redeclared within local scope.")]
-    Redeclared(String),
+    Redeclared(String, Span, Option<Span>),
     #[error("Undeclared reference {0}")]
-    UndefinedRef(String),
+    UndefinedRef(String, Span),
     #[error("Reached end of scope")]
-    OutOfScope,
+    OutOfScope(Span),
     #[error("Attempted to index a non-array object {0}.")]
-    IndexOnNonArray(String),
+    IndexOnNonArray(String, Span),
     #[error("Attempted to index array {0} using non-integer index of type {1:?}")]
-    NonIntIndex(String, Type),
+    NonIntIndex(String, Type, Span),
+    #[error("Index {1} is out of bounds for array {0} of length {2}.")]
+    IndexOutOfBounds(String, i64, usize, Span),
     #[error("Encountered return when none was expected.")]
-    UnexpectedReturn,
+    UnexpectedReturn(Span),
 
-    #[error(transparent)]
-    InvalidIntLiteral(#[from] ParseIntError),
-    #[error(transparent)]
-    InvalidFloatLiteral(#[from] ParseFloatError),
+    #[error("{0}")]
+    InvalidIntLiteral(ParseIntError, Span),
+    #[error("{0}")]
+    InvalidFloatLiteral(ParseFloatError, Span),
+}
+
+impl SemanticsError {
+    pub fn span(&self) -> Span {
+        match self {
+            SemanticsError::TypeMismatch(_, _, span)
+            | SemanticsError::InvalidType(_, _, span)
+            | SemanticsError::ParamCountMismatch(_, _, span)
+            | SemanticsError::Redeclared(_, span, _)
+            | SemanticsError::UndefinedRef(_, span)
+            | SemanticsError::OutOfScope(span)
+            | SemanticsError::IndexOnNonArray(_, span)
+            | SemanticsError::NonIntIndex(_, _, span)
+            | SemanticsError::IndexOutOfBounds(_, _, _, span)
+            | SemanticsError::UnexpectedReturn(span)
+            | SemanticsError::InvalidIntLiteral(_, span)
+            | SemanticsError::InvalidFloatLiteral(_, span) => *span,
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -53,18 +82,32 @@ pub struct AnalyzedProgram {
     pub declarations: ScopeContext,
     pub procedures: Vec<AnalyzedProcedure>,
     pub block: AnalyzedBlock,
+    pub span: Span,
 }
 
 impl AnalyzedProgram {
-    pub fn analyze(program: ProgramStruct) -> Result<Self, SemanticsError> {
-        let mut context = Context::new();
+    /// Analyzes a program, reporting every error found to `sink`.
+    /// Declarations and statements with errors are left out of the analyzed program,
+    /// so it should only be used if `sink` has no errors.
+    pub fn analyze(
+        program: ProgramStruct,
+        sink: &mut DiagnosticSink,
+    ) -> Result<Self, SemanticsError> {
+        let mut context = Context::new(sink);
 
         let name = program.program_header.header_identifier;
         let mut procedures = Vec::new();
 
+        declare_procedures(
+            &program.program_body.declarations,
+            &mut context,
+            &Scope::Global,
+        );
         for declaration in program.program_body.declarations {
-            if let Some(procedure) = declaration.analyze(&mut context, &Scope::Global)? {
-                procedures.push(procedure);
+            match declaration.analyze(&mut context, &Scope::Global) {
+                Ok(Some(procedure)) => procedures.push(procedure),
+                Ok(None) => {}
+                Err(err) => context.report(err),
             }
         }
 
@@ -78,6 +121,115 @@ impl AnalyzedProgram {
             declarations: context.into_global(),
             procedures,
             block,
+            span: program.span,
         })
     }
+
+    /// The type of the variable `identifier`, where `binding` binds it from inside the `enclosing` procedures
+    pub fn variable_type<'p>(
+        &'p self,
+        identifier: &str,
+        binding: Binding,
+        enclosing: &[&'p AnalyzedProcedure],
+    ) -> Option<&'p Type> {
+        let scope = match binding.procedure(enclosing) {
+            Some(procedure) => &procedure.declarations,
+            None => &self.declarations,
+        };
+        scope.variables.get(identifier)
+    }
+}
+
+#[cfg(test)]
+use self::statement::AnalyzedStatement;
+#[cfg(test)]
+use crate::session::Session;
+
+#[cfg(test)]
+#[test]
+fn variables_resolve_through_enclosing_procedures() {
+    let source = "program scopes is
+    variable g : integer;
+
+    procedure outer : integer(variable n : integer)
+        variable total : integer;
+        procedure inner : integer(variable x : integer)
+        begin
+            total := n;
+            x := 2;
+            g := 3;
+        end procedure;
+    begin
+        total := inner(1);
+    end procedure;
+begin
+end program.
+";
+    let mut session = Session::from_text("test.src", source);
+    let program = session.analyze().unwrap();
+    let inner = &program.procedures[0].procedures[0];
+    let bindings: Vec<Binding> = inner
+        .block
+        .0
+        .iter()
+        .map(|statement| match statement {
+            AnalyzedStatement::Assignment(assignment) => assignment.destination.binding,
+            _ => unreachable!(),
+        })
+        .collect();
+    assert_eq!(
+        bindings,
+        [Binding::Local(1), Binding::Local(0), Binding::Global]
+    );
+}
+
+#[cfg(test)]
+#[test]
+fn global_procedures_do_not_see_enclosing_locals() {
+    let source = "program scopes is
+    procedure outer : integer(variable n : integer)
+        global procedure inner : integer(variable x : integer)
+        begin
+            return n;
+        end procedure;
+    begin
+        return n;
+    end procedure;
+begin
+end program.
+";
+    let mut session = Session::from_text("test.src", source);
+    assert!(session.analyze().is_err());
+    let diagnostics = session.diagnostics().diagnostics();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].message, "Undeclared reference n");
+}
+
+#[cfg(test)]
+#[test]
+fn procedures_are_declared_once_before_bodies() {
+    let source = "program twice is
+    procedure first : integer(variable n : integer)
+    begin
+        return second(n);
+    end procedure;
+    procedure second : integer(variable n : integer)
+    begin
+        return n;
+    end procedure;
+    procedure second : integer(variable n : integer)
+    begin
+        return n;
+    end procedure;
+begin
+end program.
+";
+    let mut session = Session::from_text("test.src", source);
+    assert!(session.analyze().is_err());
+    let diagnostics = session.diagnostics().diagnostics();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(
+        diagnostics[0].message,
+        "Variable second redeclared within local scope."
+    );
 }
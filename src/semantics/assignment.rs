@@ -0,0 +1,461 @@
+//! Definite assignment: warns about reads of variables that may not have been assigned yet.
+//!
+//! Each procedure is checked on its own, starting with only its parameters assigned.
+//! Variables declared outside a procedure may have been assigned by whoever called it,
+//! so its reads of them are checked where it's called instead, along with the ones it assigns on every path.
+//! Procedures can call each other, so the checks are repeated until what each procedure reads and assigns stops changing.
+
+use std::collections::{HashMap, HashSet};
+
+use thiserror::Error;
+
+use crate::span::Span;
+
+use super::context::Binding;
+use super::expression::{AnalyzedExpression, AnalyzedFactor, AnalyzedName};
+use super::procedure::{AnalyzedProcedure, AnalyzedProcedureCall, CallResolver};
+use super::statement::{AnalyzedBlock, AnalyzedDestination, AnalyzedStatement};
+use super::AnalyzedProgram;
+
+#[derive(Debug, Error)]
+pub enum AssignmentError {
+    #[error("Variable {0} may be used before it is assigned.")]
+    Unassigned(String, Span),
+    #[error("Element {1} of array {0} may be used before it is assigned.")]
+    UnassignedElement(String, i64, Span),
+    /// Holds the procedure called, then the variable it may read
+    #[error("Procedure {0} may use variable {1} before it is assigned.")]
+    UnassignedInCall(String, String, Span),
+}
+
+impl AssignmentError {
+    pub fn span(&self) -> Span {
+        match self {
+            AssignmentError::Unassigned(_, span)
+            | AssignmentError::UnassignedElement(_, _, span)
+            | AssignmentError::UnassignedInCall(_, _, span) => *span,
+        }
+    }
+}
+
+/// Checks every procedure in `program` and its body.
+/// Statements with errors are left out of analyzed programs, so this should only run on programs without any.
+pub fn check(program: &AnalyzedProgram) -> Vec<AssignmentError> {
+    let resolver = CallResolver::new(&program.procedures);
+    let mut summaries = HashMap::new();
+    loop {
+        let mut checker = Checker {
+            resolver: &resolver,
+            summaries: &mut summaries,
+            changed: false,
+            errors: Vec::new(),
+        };
+        for procedure in program.procedures.iter() {
+            checker.check_procedure(procedure, &mut Vec::new());
+        }
+        let mut walker = Walker::new(&resolver, checker.summaries, Vec::new());
+        walker.block(&program.block, Assigned::default());
+        let mut errors = checker.errors;
+        errors.append(&mut walker.errors);
+
+        if !checker.changed {
+            return errors;
+        }
+    }
+}
+
+/// A variable, or one element of an array indexed by a constant
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Slot<'p> {
+    /// The procedure the variable is declared in, or `None` if it's global
+    owner: Option<*const AnalyzedProcedure>,
+    identifier: &'p str,
+    index: Option<i64>,
+}
+
+impl Slot<'_> {
+    fn unassigned(&self, span: Span) -> AssignmentError {
+        match self.index {
+            Some(index) => {
+                AssignmentError::UnassignedElement(String::from(self.identifier), index, span)
+            }
+            None => AssignmentError::Unassigned(String::from(self.identifier), span),
+        }
+    }
+
+    fn name(&self) -> String {
+        match self.index {
+            Some(index) => format!("{}[{}]", self.identifier, index),
+            None => String::from(self.identifier),
+        }
+    }
+}
+
+/// The variables assigned on every path to a point in a procedure
+#[derive(Debug, Clone, Default)]
+struct Assigned<'p> {
+    slots: HashSet<Slot<'p>>,
+    /// No path reaches here, so every variable counts as assigned.
+    /// This is the case after a `return`, or a call to a procedure that never returns.
+    everything: bool,
+}
+
+impl<'p> Assigned<'p> {
+    fn contains(&self, slot: &Slot<'p>) -> bool {
+        let whole = Slot {
+            index: None,
+            ..*slot
+        };
+        self.everything || self.slots.contains(slot) || self.slots.contains(&whole)
+    }
+
+    /// The variables assigned on every path, where either path can be taken
+    fn join(self, other: Assigned<'p>) -> Assigned<'p> {
+        match (self.everything, other.everything) {
+            (true, _) => other,
+            (_, true) => self,
+            _ => Assigned {
+                slots: self.slots.intersection(&other.slots).copied().collect(),
+                everything: false,
+            },
+        }
+    }
+}
+
+/// What a call to a procedure does to the variables declared outside it
+#[derive(Debug, Clone, PartialEq, Default)]
+struct Summary<'p> {
+    /// The ones it may read before assigning them
+    reads: HashSet<Slot<'p>>,
+    /// The ones it assigns on every path that returns, or `None` if it never returns.
+    /// Procedures that haven't been checked yet count as never returning.
+    assigns: Option<HashSet<Slot<'p>>>,
+}
+
+struct Checker<'c, 'p> {
+    resolver: &'c CallResolver<'p>,
+    summaries: &'c mut HashMap<*const AnalyzedProcedure, Summary<'p>>,
+    /// Whether any summary has changed since the checks started
+    changed: bool,
+    errors: Vec<AssignmentError>,
+}
+
+impl<'p> Checker<'_, 'p> {
+    /// Checks `procedure` and the procedures nested in it, where `enclosing` are the procedures around it
+    fn check_procedure(
+        &mut self,
+        procedure: &'p AnalyzedProcedure,
+        enclosing: &mut Vec<&'p AnalyzedProcedure>,
+    ) {
+        enclosing.push(procedure);
+        let mut walker = Walker::new(self.resolver, self.summaries, enclosing.clone());
+        let mut assigned = Assigned::default();
+        for arg in procedure.arg_list.iter() {
+            assigned
+                .slots
+                .insert(walker.slot(&arg.0, Binding::Local(0), None));
+        }
+        let assigned = walker.block(&procedure.block, assigned);
+        let exits = match walker.exits.take() {
+            Some(exits) => exits.join(assigned),
+            None => assigned,
+        };
+        let own = Some(procedure as *const AnalyzedProcedure);
+        let summary = Summary {
+            assigns: (!exits.everything).then(|| {
+                exits
+                    .slots
+                    .into_iter()
+                    .filter(|slot| slot.owner != own)
+                    .collect()
+            }),
+            reads: walker.reads,
+        };
+        self.errors.append(&mut walker.errors);
+
+        if self.summaries.get(&(procedure as *const _)) != Some(&summary) {
+            self.summaries.insert(procedure, summary);
+            self.changed = true;
+        }
+        for nested in procedure.procedures.iter() {
+            self.check_procedure(nested, enclosing);
+        }
+        enclosing.pop();
+    }
+}
+
+/// Walks the body of one procedure, or the program body
+struct Walker<'c, 'p> {
+    resolver: &'c CallResolver<'p>,
+    summaries: &'c HashMap<*const AnalyzedProcedure, Summary<'p>>,
+    /// The procedure being walked and the ones around it, innermost last.
+    /// Empty for the program body.
+    procedures: Vec<&'p AnalyzedProcedure>,
+    /// The variables assigned at each `return` so far
+    exits: Option<Assigned<'p>>,
+    /// Variables declared outside the procedure that it may read before assigning them
+    reads: HashSet<Slot<'p>>,
+    errors: Vec<AssignmentError>,
+}
+
+impl<'c, 'p> Walker<'c, 'p> {
+    fn new(
+        resolver: &'c CallResolver<'p>,
+        summaries: &'c HashMap<*const AnalyzedProcedure, Summary<'p>>,
+        procedures: Vec<&'p AnalyzedProcedure>,
+    ) -> Self {
+        Walker {
+            resolver,
+            summaries,
+            procedures,
+            exits: None,
+            reads: HashSet::new(),
+            errors: Vec::new(),
+        }
+    }
+
+    fn slot(&self, identifier: &'p str, binding: Binding, index: Option<i64>) -> Slot<'p> {
+        Slot {
+            owner: binding
+                .procedure(&self.procedures)
+                .map(|procedure| procedure as *const _),
+            identifier,
+            index,
+        }
+    }
+
+    /// Whether `slot` is declared in the procedure being walked, or is a global in the program body
+    fn is_own(&self, slot: &Slot<'p>) -> bool {
+        slot.owner
+            == self
+                .procedures
+                .last()
+                .map(|procedure| *procedure as *const _)
+    }
+
+    fn read(
+        &mut self,
+        slot: Slot<'p>,
+        assigned: &mut Assigned<'p>,
+        error: impl FnOnce() -> AssignmentError,
+    ) {
+        if assigned.contains(&slot) {
+            return;
+        }
+        if self.is_own(&slot) {
+            self.errors.push(error());
+            // Only the first read that may see it unassigned is reported
+            assigned.slots.insert(slot);
+        } else {
+            self.reads.insert(slot);
+        }
+    }
+
+    fn block(&mut self, block: &'p AnalyzedBlock, mut assigned: Assigned<'p>) -> Assigned<'p> {
+        for statement in block.0.iter() {
+            assigned = self.statement(statement, assigned);
+        }
+        assigned
+    }
+
+    fn statement(
+        &mut self,
+        statement: &'p AnalyzedStatement,
+        mut assigned: Assigned<'p>,
+    ) -> Assigned<'p> {
+        match statement {
+            AnalyzedStatement::Assignment(assignment) => {
+                self.expression(&assignment.expression, &mut assigned);
+                self.destination(&assignment.destination, &mut assigned);
+                assigned
+            }
+            AnalyzedStatement::If(statement) => {
+                self.expression(&statement.conditional_expr, &mut assigned);
+                let then_assigned = self.block(&statement.then_block, assigned.clone());
+                let else_assigned = match &statement.else_block {
+                    Some(else_block) => self.block(else_block, assigned),
+                    None => assigned,
+                };
+                then_assigned.join(else_assigned)
+            }
+            AnalyzedStatement::Loop(statement) => {
+                self.expression(&statement.assignment.expression, &mut assigned);
+                self.destination(&statement.assignment.destination, &mut assigned);
+                self.expression(&statement.condition, &mut assigned);
+                // The body might not run at all, and it only ever adds assignments,
+                // so the first time through sees the fewest
+                self.block(&statement.loop_body, assigned.clone());
+                assigned
+            }
+            AnalyzedStatement::Return(statement) => {
+                self.expression(&statement.expression, &mut assigned);
+                self.exits = Some(match self.exits.take() {
+                    Some(exits) => exits.join(assigned),
+                    None => assigned,
+                });
+                Assigned {
+                    slots: HashSet::new(),
+                    everything: true,
+                }
+            }
+        }
+    }
+
+    fn destination(&mut self, destination: &'p AnalyzedDestination, assigned: &mut Assigned<'p>) {
+        let index = match &destination.expression {
+            Some(index) => {
+                self.expression(index, assigned);
+                match index.integer_literal() {
+                    Some(index) => Some(index),
+                    // Some element is assigned, but there's no telling which
+                    None => return,
+                }
+            }
+            None => None,
+        };
+        let slot = self.slot(&destination.identifier, destination.binding, index);
+        assigned.slots.insert(slot);
+    }
+
+    fn expression(&mut self, expression: &'p AnalyzedExpression, assigned: &mut Assigned<'p>) {
+        expression.visit_factors(&mut |factor| match factor {
+            AnalyzedFactor::Name(name) | AnalyzedFactor::NegatedName(name) => {
+                self.name(name, assigned)
+            }
+            AnalyzedFactor::ProcedureCall(call) => self.call(call, assigned),
+            _ => {}
+        });
+    }
+
+    fn name(&mut self, name: &'p AnalyzedName, assigned: &mut Assigned<'p>) {
+        let slot = match name {
+            AnalyzedName::Name(identifier, binding, _) => self.slot(identifier, *binding, None),
+            AnalyzedName::Indexed(identifier, binding, index, _) => {
+                match index.integer_literal() {
+                    Some(index) => self.slot(identifier, *binding, Some(index)),
+                    // Reading an unknown element is only safe once the whole array is assigned.
+                    // Arrays filled in a loop can't be told apart from ones that aren't, so they're let through.
+                    None => return,
+                }
+            }
+        };
+        self.read(slot, assigned, || slot.unassigned(name.span()));
+    }
+
+    fn call(&mut self, call: &'p AnalyzedProcedureCall, assigned: &mut Assigned<'p>) {
+        // Builtins don't touch any variables
+        let Some(callee) = self.resolver.resolve(call, &self.procedures) else {
+            return;
+        };
+        let Some(summary) = self.summaries.get(&(callee as *const _)) else {
+            *assigned = Assigned {
+                slots: HashSet::new(),
+                everything: true,
+            };
+            return;
+        };
+        let mut reads: Vec<&Slot> = summary.reads.iter().collect();
+        reads.sort_by_key(|slot| (slot.identifier, slot.index));
+        for slot in reads {
+            self.read(*slot, assigned, || {
+                AssignmentError::UnassignedInCall(call.identifier.clone(), slot.name(), call.span)
+            });
+        }
+        match &summary.assigns {
+            Some(assigns) => assigned.slots.extend(assigns.iter().copied()),
+            None => assigned.everything = true,
+        }
+    }
+}
+
+#[cfg(test)]
+use crate::lint::Lint;
+#[cfg(test)]
+use crate::session::{analyze_messages, Session};
+#[cfg(test)]
+use rstest::rstest;
+
+#[cfg(test)]
+#[rstest]
+#[case("x := n; n := x;", "", &[])]
+#[case("n := x; n := x;", "", &["Variable x may be used before it is assigned."])]
+#[case(
+    "if (n > 0) then x := 1; end if; n := x;",
+    "",
+    &["Variable x may be used before it is assigned."]
+)]
+#[case("if (n > 0) then x := 1; else x := 2; end if; n := x;", "", &[])]
+#[case("if (n > 0) then x := 1; else return 1; end if; n := x;", "", &[])]
+#[case(
+    "for (n := 0; n < 3) x := n; n := n + 1; end for; n := x;",
+    "",
+    &["Variable x may be used before it is assigned."]
+)]
+#[case(
+    "",
+    "a[0] := 1; g := a[0] + a[1];",
+    &["Element 1 of array a may be used before it is assigned."]
+)]
+#[case(
+    "",
+    "g := get();",
+    &["Procedure get may use variable g before it is assigned."]
+)]
+#[case("", "a[0] := set(); a[0] := get();", &[])]
+fn assignments_are_checked(#[case] procedure: &str, #[case] body: &str, #[case] messages: &[&str]) {
+    let source = format!(
+        "program init is
+    variable g : integer;
+    variable a : integer[3];
+    procedure set : integer()
+    begin
+        g := 1;
+        return 0;
+    end procedure;
+    procedure get : integer()
+    begin
+        return g;
+    end procedure;
+    procedure f : integer(variable n : integer)
+        variable x : integer;
+    begin
+        {}
+        return 0;
+    end procedure;
+begin
+    {}
+end program.
+",
+        procedure, body
+    );
+    assert_eq!(
+        analyze_messages(&source, &[Lint::UnassignedVariable]),
+        messages
+    );
+}
+
+#[cfg(test)]
+#[test]
+fn assignments_are_followed_through_recursive_calls() {
+    let source = "program init is
+    variable total : integer;
+    procedure count : integer(variable n : integer)
+    begin
+        if (n > 0) then
+            return count(n - 1);
+        end if;
+        total := n;
+        return 0;
+    end procedure;
+    procedure report : integer()
+    begin
+        return total;
+    end procedure;
+begin
+    total := count(3);
+    total := report();
+end program.
+";
+    let mut session = Session::from_text("test.src", source);
+    assert!(check(session.analyze().unwrap()).is_empty());
+}
@@ -0,0 +1,199 @@
+//! Checks array indices that are known before the program runs against the length of the array.
+//! Runs after folding, so any constant index is an integer literal by then.
+
+use super::context::Binding;
+use super::expression::{AnalyzedExpression, AnalyzedFactor, AnalyzedName};
+use super::procedure::AnalyzedProcedure;
+use super::statement::{AnalyzedAssignment, AnalyzedBlock, AnalyzedStatement};
+use super::value::Type;
+use super::{AnalyzedProgram, SemanticsError};
+
+/// Finds every constant index in `program` that is negative or past the end of its array
+pub fn check(program: &AnalyzedProgram) -> Vec<SemanticsError> {
+    let mut checker = Checker {
+        program,
+        errors: Vec::new(),
+    };
+    AnalyzedProcedure::visit_all(&program.procedures, &mut Vec::new(), &mut |enclosing| {
+        checker.block(&enclosing[enclosing.len() - 1].block, enclosing);
+    });
+    checker.block(&program.block, &[]);
+    checker.errors
+}
+
+struct Checker<'p> {
+    program: &'p AnalyzedProgram,
+    errors: Vec<SemanticsError>,
+}
+
+impl<'p> Checker<'p> {
+    /// Checks the indices in `block`, where `enclosing` are the procedures around it, innermost last
+    fn block(&mut self, block: &'p AnalyzedBlock, enclosing: &[&'p AnalyzedProcedure]) {
+        for statement in block.0.iter() {
+            match statement {
+                AnalyzedStatement::Assignment(assignment) => self.assignment(assignment, enclosing),
+                AnalyzedStatement::If(statement) => {
+                    self.expression(&statement.conditional_expr, enclosing);
+                    self.block(&statement.then_block, enclosing);
+                    if let Some(else_block) = &statement.else_block {
+                        self.block(else_block, enclosing);
+                    }
+                }
+                AnalyzedStatement::Loop(statement) => {
+                    self.assignment(&statement.assignment, enclosing);
+                    self.expression(&statement.condition, enclosing);
+                    self.block(&statement.loop_body, enclosing);
+                }
+                AnalyzedStatement::Return(statement) => {
+                    self.expression(&statement.expression, enclosing)
+                }
+            }
+        }
+    }
+
+    fn assignment(
+        &mut self,
+        assignment: &'p AnalyzedAssignment,
+        enclosing: &[&'p AnalyzedProcedure],
+    ) {
+        let destination = &assignment.destination;
+        if let Some(index) = &destination.expression {
+            self.expression(index, enclosing);
+            self.index(
+                &destination.identifier,
+                destination.binding,
+                index,
+                enclosing,
+            );
+        }
+        self.expression(&assignment.expression, enclosing);
+    }
+
+    fn expression(
+        &mut self,
+        expression: &'p AnalyzedExpression,
+        enclosing: &[&'p AnalyzedProcedure],
+    ) {
+        expression.visit_factors(&mut |factor| {
+            if let AnalyzedFactor::Name(AnalyzedName::Indexed(identifier, binding, index, _))
+            | AnalyzedFactor::NegatedName(AnalyzedName::Indexed(
+                identifier,
+                binding,
+                index,
+                _,
+            )) = factor
+            {
+                self.index(identifier, *binding, index, enclosing);
+            }
+        });
+    }
+
+    fn index(
+        &mut self,
+        identifier: &str,
+        binding: Binding,
+        index: &AnalyzedExpression,
+        enclosing: &[&'p AnalyzedProcedure],
+    ) {
+        let Some(value) = index.integer_literal() else {
+            return;
+        };
+        let Some(Type::Array(_, length)) =
+            self.program.variable_type(identifier, binding, enclosing)
+        else {
+            return;
+        };
+        if usize::try_from(value).map_or(true, |position| position >= *length) {
+            self.errors.push(SemanticsError::IndexOutOfBounds(
+                identifier.to_owned(),
+                value,
+                *length,
+                index.span(),
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+use crate::session::{analyze_messages, Session};
+#[cfg(test)]
+use rstest::rstest;
+
+#[cfg(test)]
+#[rstest]
+#[case("a[0] := a[14];", &[])]
+#[case("a[15] := 1;", &["Index 15 is out of bounds for array a of length 15."])]
+#[case("x := a[-1];", &["Index -1 is out of bounds for array a of length 15."])]
+#[case("x := a[10 + 5] + a[2 * 7];", &["Index 15 is out of bounds for array a of length 15."])]
+#[case("x := f(a[x]) + a[(0 - 1) * 1];", &["Index -1 is out of bounds for array a of length 15."])]
+#[case(
+    "a[20] := a[15];",
+    &[
+        "Index 20 is out of bounds for array a of length 15.",
+        "Index 15 is out of bounds for array a of length 15.",
+    ]
+)]
+fn constant_indices_are_checked(#[case] statements: &str, #[case] messages: &[&str]) {
+    let source = format!(
+        "program bounds is
+    variable x : integer;
+    variable a : integer[15];
+    procedure f : integer(variable n : integer)
+        variable b : integer[2];
+    begin
+        b[1] := n;
+        return b[1];
+    end procedure;
+begin
+    {}
+end program.
+",
+        statements
+    );
+    assert_eq!(analyze_messages(&source, &[]), messages);
+}
+
+#[cfg(test)]
+#[test]
+fn indices_are_checked_against_the_array_they_bind_to() {
+    let source = "program bounds is
+    variable a : integer[10];
+    procedure f : integer()
+        variable a : integer[2];
+    begin
+        return a[5];
+    end procedure;
+begin
+    a[5] := f();
+end program.
+";
+    let mut session = Session::from_text("test.src", source);
+    assert!(session.analyze().is_err());
+    let diagnostics = session.diagnostics().diagnostics();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(
+        diagnostics[0].message,
+        "Index 5 is out of bounds for array a of length 2."
+    );
+}
+
+#[cfg(test)]
+#[rstest]
+#[case("a[-1] := 1;", "-1")]
+#[case("a[- 2] := 1;", "- 2")]
+#[case("a[2 * 8] := 1;", "2 * 8")]
+fn out_of_bounds_index_is_underlined(#[case] statement: &str, #[case] underlined: &str) {
+    let source = format!(
+        "program bounds is
+    variable a : integer[15];
+begin
+    {}
+end program.
+",
+        statement
+    );
+    let mut session = Session::from_text("test.src", source.as_str());
+    assert!(session.analyze().is_err());
+    let span = session.diagnostics().diagnostics()[0].labels[0].span;
+    assert_eq!(&source[span.start..span.end], underlined);
+}
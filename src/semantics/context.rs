@@ -1,23 +1,52 @@
 use std::collections::HashMap;
 
-use thiserror::Error;
+use crate::diagnostics::DiagnosticSink;
+use crate::span::Span;
 
 use super::{
+    procedure::AnalyzedProcedure,
     value::{NamedValue, ProcedureSignature, Type},
     SemanticsError,
 };
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Scope {
     Global,
     Local,
 }
 
+/// Where a resolved variable or procedure lives, as seen from the procedure using it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Binding {
+    Global,
+    /// Declared in the procedure `depth` levels out from the one using it, where 0 is that procedure itself.
+    /// This is the number of static links to follow to reach the frame of the procedure it's declared in.
+    Local(usize),
+}
+
+impl Binding {
+    /// The procedure the variable or procedure bound is declared in, or `None` for globals.
+    /// `enclosing` are the procedures around where it's used, innermost last.
+    pub fn procedure<'p>(
+        self,
+        enclosing: &[&'p AnalyzedProcedure],
+    ) -> Option<&'p AnalyzedProcedure> {
+        match self {
+            Binding::Global => None,
+            Binding::Local(depth) => Some(enclosing[enclosing.len() - 1 - depth]),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct ScopeContext {
     pub variables: HashMap<String, Type>,
     pub procedures: HashMap<String, ProcedureSignature>,
     pub return_type: Type,
+    /// Where each variable was declared
+    pub variable_spans: HashMap<String, Span>,
+    /// Where each procedure was declared. Builtin procedures have no entry.
+    pub procedure_spans: HashMap<String, Span>,
 }
 
 impl ScopeContext {
@@ -26,6 +55,8 @@ impl ScopeContext {
             variables: HashMap::new(),
             procedures: HashMap::new(),
             return_type,
+            variable_spans: HashMap::new(),
+            procedure_spans: HashMap::new(),
         }
     }
 
@@ -90,25 +121,41 @@ impl ScopeContext {
             variables: HashMap::new(),
             procedures,
             return_type: Type::Void,
+            variable_spans: HashMap::new(),
+            procedure_spans: HashMap::new(),
         }
     }
 }
 
 #[derive(Debug)]
-pub struct Context {
+pub struct Context<'a> {
     global_scope: ScopeContext,
-    scope_stack: Vec<ScopeContext>,
+    /// The scopes enclosing the local scope, innermost last,
+    /// each with the scope it was declared in
+    scope_stack: Vec<(ScopeContext, Scope)>,
     local_scope: ScopeContext,
+    /// The scope the local scope's procedure was declared in.
+    /// Procedures in the global scope can be called from anywhere, so they can't see enclosing locals.
+    local_declared: Scope,
+    sink: &'a mut DiagnosticSink,
 }
-impl Context {
-    pub fn new() -> Self {
+impl<'a> Context<'a> {
+    pub fn new(sink: &'a mut DiagnosticSink) -> Self {
         Context {
             global_scope: ScopeContext::new_global_ctx(),
             scope_stack: Vec::new(),
             local_scope: ScopeContext::new(Type::Void),
+            // The program body only sees the global scope
+            local_declared: Scope::Global,
+            sink,
         }
     }
 
+    /// Reports an error and carries on analyzing
+    pub fn report(&mut self, err: SemanticsError) {
+        self.sink.error(&err)
+    }
+
     pub fn into_global(self) -> ScopeContext {
         self.global_scope
     }
@@ -118,16 +165,19 @@ impl Context {
         is_global: bool,
         identifier: String,
         value_type: Type,
+        span: Span,
     ) -> Result<(), SemanticsError> {
-        let variables = if is_global {
-            &mut self.global_scope.variables
+        let scope = if is_global {
+            &mut self.global_scope
         } else {
-            &mut self.local_scope.variables
+            &mut self.local_scope
         };
-        if variables.contains_key(&identifier) {
-            Err(SemanticsError::Redeclared(identifier))
+        if scope.variables.contains_key(&identifier) {
+            let previous_span = scope.variable_spans.get(&identifier).copied();
+            Err(SemanticsError::Redeclared(identifier, span, previous_span))
         } else {
-            variables.insert(identifier, value_type);
+            scope.variable_spans.insert(identifier.clone(), span);
+            scope.variables.insert(identifier, value_type);
             Ok(())
         }
     }
@@ -137,56 +187,96 @@ impl Context {
         is_global: bool,
         identifier: String,
         signature: ProcedureSignature,
+        span: Span,
     ) -> Result<(), SemanticsError> {
-        let procedures = if is_global {
-            &mut self.global_scope.procedures
+        let scope = if is_global {
+            &mut self.global_scope
         } else {
-            &mut self.local_scope.procedures
+            &mut self.local_scope
         };
 
-        if procedures.contains_key(&identifier) {
-            Err(SemanticsError::Redeclared(identifier))
+        if scope.procedures.contains_key(&identifier) {
+            let previous_span = scope.procedure_spans.get(&identifier).copied();
+            Err(SemanticsError::Redeclared(identifier, span, previous_span))
         } else {
-            procedures.insert(identifier, signature);
+            scope.procedure_spans.insert(identifier.clone(), span);
+            scope.procedures.insert(identifier, signature);
             Ok(())
         }
     }
 
-    pub fn get_variable_type(&self, identifier: &str) -> Result<&Type, SemanticsError> {
-        self.local_scope
-            .variables
-            .get(identifier)
-            .or_else(|| self.global_scope.variables.get(identifier))
-            .ok_or_else(|| SemanticsError::UndefinedRef(String::from(identifier)))
+    pub fn get_variable_type(&self, identifier: &str, span: Span) -> Result<&Type, SemanticsError> {
+        self.resolve_variable(identifier, span)
+            .map(|(value_type, _)| value_type)
+    }
+
+    pub fn resolve_variable(
+        &self,
+        identifier: &str,
+        span: Span,
+    ) -> Result<(&Type, Binding), SemanticsError> {
+        self.resolve(|scope| scope.variables.get(identifier))
+            .ok_or_else(|| SemanticsError::UndefinedRef(String::from(identifier), span))
     }
 
     pub fn get_procedure_signature(
         &self,
         identifier: &str,
+        span: Span,
     ) -> Result<&ProcedureSignature, SemanticsError> {
-        self.local_scope
-            .procedures
-            .get(identifier)
-            .or_else(|| self.global_scope.procedures.get(identifier))
-            .ok_or_else(|| SemanticsError::UndefinedRef(String::from(identifier)))
+        self.resolve_procedure(identifier, span)
+            .map(|(signature, _)| signature)
+    }
+
+    pub fn resolve_procedure(
+        &self,
+        identifier: &str,
+        span: Span,
+    ) -> Result<(&ProcedureSignature, Binding), SemanticsError> {
+        self.resolve(|scope| scope.procedures.get(identifier))
+            .ok_or_else(|| SemanticsError::UndefinedRef(String::from(identifier), span))
+    }
+
+    /// Looks something up in the local scope, then out through each enclosing procedure,
+    /// then in the global scope.
+    fn resolve<'s, T>(
+        &'s self,
+        lookup: impl Fn(&'s ScopeContext) -> Option<&'s T>,
+    ) -> Option<(&'s T, Binding)> {
+        let mut scope = &self.local_scope;
+        let mut declared = &self.local_declared;
+        let mut enclosing = self.scope_stack.iter().rev();
+        for depth in 0.. {
+            if let Some(found) = lookup(scope) {
+                return Some((found, Binding::Local(depth)));
+            }
+            match (declared, enclosing.next()) {
+                (Scope::Local, Some((next_scope, next_declared))) => {
+                    scope = next_scope;
+                    declared = next_declared;
+                }
+                _ => break,
+            }
+        }
+        lookup(&self.global_scope).map(|found| (found, Binding::Global))
     }
 
     pub fn get_return_type(&self) -> &Type {
         &self.local_scope.return_type
     }
 
-    pub fn start_stack(&mut self, return_type: Type) {
+    /// Starts the scope of a procedure declared in `declared`
+    pub fn start_stack(&mut self, return_type: Type, declared: Scope) {
         let previous_stack =
             std::mem::replace(&mut self.local_scope, ScopeContext::new(return_type));
-        self.scope_stack.push(previous_stack);
+        let previous_declared = std::mem::replace(&mut self.local_declared, declared);
+        self.scope_stack.push((previous_stack, previous_declared));
     }
 
-    pub fn end_stack(&mut self) -> Result<ScopeContext, SemanticsError> {
-        if let Some(scope) = self.scope_stack.pop() {
-            let old_scope = std::mem::replace(&mut self.local_scope, scope);
-            Ok(old_scope)
-        } else {
-            Err(SemanticsError::OutOfScope)
-        }
+    /// Returns the scope that just ended, or `None` if there was no scope to return to.
+    pub fn end_stack(&mut self) -> Option<ScopeContext> {
+        let (scope, declared) = self.scope_stack.pop()?;
+        self.local_declared = declared;
+        Some(std::mem::replace(&mut self.local_scope, scope))
     }
 }
@@ -22,12 +22,14 @@ impl Analyze<Option<AnalyzedProcedure>> for Declaration {
                         scope == &Scope::Global || is_global,
                         variable.identifier,
                         value_type,
+                        variable.span,
                     )?;
                 } else {
                     context.set_type(
                         scope == &Scope::Global || is_global,
                         variable.identifier,
                         variable.type_mark.into(),
+                        variable.span,
                     )?;
                 }
                 Ok(None)
@@ -36,6 +38,18 @@ impl Analyze<Option<AnalyzedProcedure>> for Declaration {
                 let curr_scope = if is_global { &Scope::Global } else { scope };
                 Ok(Some(proceedure.analyze(context, curr_scope)?))
             }
+            Declaration::Error(_) => Ok(None),
+        }
+    }
+}
+
+/// Declares every procedure in `declarations` up front,
+/// so procedures can call ones declared after them, including each other
+pub fn declare_procedures(declarations: &[Declaration], context: &mut Context, scope: &Scope) {
+    for declaration in declarations {
+        if let Declaration::Procedure(is_global, procedure) = declaration {
+            let scope = if *is_global { &Scope::Global } else { scope };
+            procedure.declare(context, scope);
         }
     }
 }
@@ -1,7 +1,8 @@
 use crate::parser::expression::{ArtihOp, Expression, Factor, Name, Relation, Term};
 use crate::parser::types::Number;
+use crate::span::Span;
 
-use super::context::Context;
+use super::context::{Binding, Context};
 use super::procedure::AnalyzedProcedureCall;
 use super::traits::AnalyzeExpression;
 use super::value::Type;
@@ -31,9 +32,51 @@ impl AnalyzedExpression {
             value_type => Err(SemanticsError::InvalidType(
                 String::from("Bool,Int"),
                 value_type,
+                self.span(),
             )),
         }
     }
+
+    pub fn span(&self) -> Span {
+        match self {
+            AnalyzedExpression::BitwiseAnd(expression, arith_op)
+            | AnalyzedExpression::BitwiseOr(expression, arith_op)
+            | AnalyzedExpression::LogicalAnd(expression, arith_op)
+            | AnalyzedExpression::LogicalOr(expression, arith_op) => {
+                expression.span().to(arith_op.span())
+            }
+            AnalyzedExpression::BitwiseNot(arith_op)
+            | AnalyzedExpression::LogicalNot(arith_op)
+            | AnalyzedExpression::ArithOp(arith_op) => arith_op.span(),
+            AnalyzedExpression::Cast(expression, _) => expression.span(),
+        }
+    }
+
+    /// Calls `visit` on each factor in the expression, in the order they're evaluated.
+    /// The factors inside a factor, like the arguments of a call, are visited before it.
+    pub fn visit_factors<'e>(&'e self, visit: &mut impl FnMut(&'e AnalyzedFactor)) {
+        match self {
+            AnalyzedExpression::BitwiseAnd(expression, arith_op)
+            | AnalyzedExpression::BitwiseOr(expression, arith_op)
+            | AnalyzedExpression::LogicalAnd(expression, arith_op)
+            | AnalyzedExpression::LogicalOr(expression, arith_op) => {
+                expression.visit_factors(visit);
+                arith_op.visit_factors(visit);
+            }
+            AnalyzedExpression::BitwiseNot(arith_op)
+            | AnalyzedExpression::LogicalNot(arith_op)
+            | AnalyzedExpression::ArithOp(arith_op) => arith_op.visit_factors(visit),
+            AnalyzedExpression::Cast(expression, _) => expression.visit_factors(visit),
+        }
+    }
+
+    /// The value of the expression if it's an integer literal, which constant expressions are once folded
+    pub fn integer_literal(&self) -> Option<i64> {
+        match self {
+            AnalyzedExpression::ArithOp(arith_op) => arith_op.integer_literal(),
+            _ => None,
+        }
+    }
 }
 
 impl AnalyzeExpression<Expression> for AnalyzedExpression {
@@ -41,6 +84,7 @@ impl AnalyzeExpression<Expression> for AnalyzedExpression {
         value: Expression,
         context: &mut Context,
     ) -> Result<Self, SemanticsError> {
+        let span = value.span();
         match value {
             Expression::BasicExp(expression) => Ok(AnalyzedExpression::ArithOp(
                 AnalyzedArithOp::analyze_expression(expression, context)?,
@@ -53,6 +97,7 @@ impl AnalyzeExpression<Expression> for AnalyzedExpression {
                     value_type => Err(SemanticsError::InvalidType(
                         String::from("Bool,Int"),
                         value_type,
+                        span,
                     )),
                 }
             }
@@ -70,7 +115,7 @@ impl AnalyzeExpression<Expression> for AnalyzedExpression {
                         arith_op,
                     )),
                     (arith_type, exp_type) => {
-                        Err(SemanticsError::TypeMismatch(arith_type, exp_type))
+                        Err(SemanticsError::TypeMismatch(arith_type, exp_type, span))
                     }
                 }
             }
@@ -88,7 +133,7 @@ impl AnalyzeExpression<Expression> for AnalyzedExpression {
                         arith_op,
                     )),
                     (arith_type, exp_type) => {
-                        Err(SemanticsError::TypeMismatch(arith_type, exp_type))
+                        Err(SemanticsError::TypeMismatch(arith_type, exp_type, span))
                     }
                 }
             }
@@ -129,10 +174,53 @@ impl AnalyzedArithOp {
     pub fn cast(self, value_type: Type) -> AnalyzedArithOp {
         AnalyzedArithOp::Cast(Box::new(self), value_type)
     }
+
+    pub fn span(&self) -> Span {
+        match self {
+            AnalyzedArithOp::Plus(arith_op, relation)
+            | AnalyzedArithOp::ArrayScalarPlus(arith_op, relation)
+            | AnalyzedArithOp::ScalarArrayPlus(arith_op, relation)
+            | AnalyzedArithOp::ArrayPlus(arith_op, relation)
+            | AnalyzedArithOp::Minus(arith_op, relation)
+            | AnalyzedArithOp::ArrayScalarMinus(arith_op, relation)
+            | AnalyzedArithOp::ScalarArrayMinus(arith_op, relation)
+            | AnalyzedArithOp::ArrayMinus(arith_op, relation) => {
+                arith_op.span().to(relation.span())
+            }
+            AnalyzedArithOp::Cast(arith_op, _) => arith_op.span(),
+            AnalyzedArithOp::Relation(relation) => relation.span(),
+        }
+    }
+
+    pub fn visit_factors<'e>(&'e self, visit: &mut impl FnMut(&'e AnalyzedFactor)) {
+        match self {
+            AnalyzedArithOp::Plus(arith_op, relation)
+            | AnalyzedArithOp::ArrayScalarPlus(arith_op, relation)
+            | AnalyzedArithOp::ScalarArrayPlus(arith_op, relation)
+            | AnalyzedArithOp::ArrayPlus(arith_op, relation)
+            | AnalyzedArithOp::Minus(arith_op, relation)
+            | AnalyzedArithOp::ArrayScalarMinus(arith_op, relation)
+            | AnalyzedArithOp::ScalarArrayMinus(arith_op, relation)
+            | AnalyzedArithOp::ArrayMinus(arith_op, relation) => {
+                arith_op.visit_factors(visit);
+                relation.visit_factors(visit);
+            }
+            AnalyzedArithOp::Cast(arith_op, _) => arith_op.visit_factors(visit),
+            AnalyzedArithOp::Relation(relation) => relation.visit_factors(visit),
+        }
+    }
+
+    pub fn integer_literal(&self) -> Option<i64> {
+        match self {
+            AnalyzedArithOp::Relation(relation) => relation.integer_literal(),
+            _ => None,
+        }
+    }
 }
 
 impl AnalyzeExpression<ArtihOp> for AnalyzedArithOp {
     fn analyze_expression(value: ArtihOp, context: &mut Context) -> Result<Self, SemanticsError> {
+        let span = value.span();
         match value {
             ArtihOp::AddOp(box arith_op, relation) => {
                 let arith_op = AnalyzedArithOp::analyze_expression(arith_op, context)?;
@@ -198,7 +286,7 @@ impl AnalyzeExpression<ArtihOp> for AnalyzedArithOp {
                     (Type::Float, Type::Array(box Type::Float, _)) => Ok(
                         AnalyzedArithOp::ScalarArrayPlus(Box::new(arith_op), relation),
                     ),
-                    (l_type, r_type) => Err(SemanticsError::TypeMismatch(l_type, r_type)),
+                    (l_type, r_type) => Err(SemanticsError::TypeMismatch(l_type, r_type, span)),
                 }
             }
             ArtihOp::SubOp(box arith_op, relation) => {
@@ -265,7 +353,7 @@ impl AnalyzeExpression<ArtihOp> for AnalyzedArithOp {
                     (Type::Float, Type::Array(box Type::Float, _)) => Ok(
                         AnalyzedArithOp::ScalarArrayMinus(Box::new(arith_op), relation),
                     ),
-                    (l_type, r_type) => Err(SemanticsError::TypeMismatch(l_type, r_type)),
+                    (l_type, r_type) => Err(SemanticsError::TypeMismatch(l_type, r_type, span)),
                 }
             }
             ArtihOp::Relation(relation) => Ok(AnalyzedArithOp::Relation(
@@ -307,6 +395,42 @@ impl AnalyzedRelation {
         AnalyzedRelation::Cast(Box::new(self), value_type)
     }
 
+    pub fn span(&self) -> Span {
+        match self {
+            AnalyzedRelation::LessThan(relation, term)
+            | AnalyzedRelation::LessThanEq(relation, term)
+            | AnalyzedRelation::GreaterThan(relation, term)
+            | AnalyzedRelation::GreaterThanEq(relation, term)
+            | AnalyzedRelation::Equals(relation, term)
+            | AnalyzedRelation::NotEquals(relation, term) => relation.span().to(term.span()),
+            AnalyzedRelation::Cast(relation, _) => relation.span(),
+            AnalyzedRelation::Term(term) => term.span(),
+        }
+    }
+
+    pub fn visit_factors<'e>(&'e self, visit: &mut impl FnMut(&'e AnalyzedFactor)) {
+        match self {
+            AnalyzedRelation::LessThan(relation, term)
+            | AnalyzedRelation::LessThanEq(relation, term)
+            | AnalyzedRelation::GreaterThan(relation, term)
+            | AnalyzedRelation::GreaterThanEq(relation, term)
+            | AnalyzedRelation::Equals(relation, term)
+            | AnalyzedRelation::NotEquals(relation, term) => {
+                relation.visit_factors(visit);
+                term.visit_factors(visit);
+            }
+            AnalyzedRelation::Cast(relation, _) => relation.visit_factors(visit),
+            AnalyzedRelation::Term(term) => term.visit_factors(visit),
+        }
+    }
+
+    pub fn integer_literal(&self) -> Option<i64> {
+        match self {
+            AnalyzedRelation::Term(term) => term.integer_literal(),
+            _ => None,
+        }
+    }
+
     pub fn try_compatible(
         relation: Relation,
         term: Term,
@@ -315,6 +439,7 @@ impl AnalyzedRelation {
     ) -> Result<(AnalyzedRelation, AnalyzedTerm), SemanticsError> {
         let mut relation = AnalyzedRelation::analyze_expression(relation, context)?;
         let mut term = AnalyzedTerm::analyze_expression(term, context)?;
+        let span = relation.span().to(term.span());
 
         relation = match relation.get_type(context)? {
             Type::Int | Type::Float => relation,
@@ -324,6 +449,7 @@ impl AnalyzedRelation {
                 return Err(SemanticsError::InvalidType(
                     String::from("Relation"),
                     value_type,
+                    relation.span(),
                 ))
             }
         };
@@ -336,6 +462,7 @@ impl AnalyzedRelation {
                 return Err(SemanticsError::InvalidType(
                     String::from("Relation"),
                     value_type,
+                    term.span(),
                 ))
             }
         };
@@ -349,7 +476,7 @@ impl AnalyzedRelation {
             match (relation_type, term_type) {
                 (Type::Int, Type::Float) => Ok((relation.cast(Type::Float), term)),
                 (Type::Float, Type::Int) => Ok((relation, term.cast(Type::Float))),
-                (l_type, r_type) => Err(SemanticsError::TypeMismatch(l_type, r_type)),
+                (l_type, r_type) => Err(SemanticsError::TypeMismatch(l_type, r_type, span)),
             }
         }
     }
@@ -423,10 +550,51 @@ impl AnalyzedTerm {
     pub fn cast(self, value_type: Type) -> AnalyzedTerm {
         AnalyzedTerm::Cast(Box::new(self), value_type)
     }
+
+    pub fn span(&self) -> Span {
+        match self {
+            AnalyzedTerm::Multiply(term, factor)
+            | AnalyzedTerm::ArrayScalarMultiply(term, factor)
+            | AnalyzedTerm::ScalarArrayMultiply(term, factor)
+            | AnalyzedTerm::ArrayMultiply(term, factor)
+            | AnalyzedTerm::Divide(term, factor)
+            | AnalyzedTerm::ArrayScalarDivide(term, factor)
+            | AnalyzedTerm::ScalarArrayDivide(term, factor)
+            | AnalyzedTerm::ArrayDivide(term, factor) => term.span().to(factor.span()),
+            AnalyzedTerm::Cast(term, _) => term.span(),
+            AnalyzedTerm::Factor(factor) => factor.span(),
+        }
+    }
+
+    pub fn visit_factors<'e>(&'e self, visit: &mut impl FnMut(&'e AnalyzedFactor)) {
+        match self {
+            AnalyzedTerm::Multiply(term, factor)
+            | AnalyzedTerm::ArrayScalarMultiply(term, factor)
+            | AnalyzedTerm::ScalarArrayMultiply(term, factor)
+            | AnalyzedTerm::ArrayMultiply(term, factor)
+            | AnalyzedTerm::Divide(term, factor)
+            | AnalyzedTerm::ArrayScalarDivide(term, factor)
+            | AnalyzedTerm::ScalarArrayDivide(term, factor)
+            | AnalyzedTerm::ArrayDivide(term, factor) => {
+                term.visit_factors(visit);
+                factor.visit_factors(visit);
+            }
+            AnalyzedTerm::Cast(term, _) => term.visit_factors(visit),
+            AnalyzedTerm::Factor(factor) => factor.visit_factors(visit),
+        }
+    }
+
+    pub fn integer_literal(&self) -> Option<i64> {
+        match self {
+            AnalyzedTerm::Factor(factor) => factor.integer_literal(),
+            _ => None,
+        }
+    }
 }
 
 impl AnalyzeExpression<Term> for AnalyzedTerm {
     fn analyze_expression(value: Term, context: &mut Context) -> Result<Self, SemanticsError> {
+        let span = value.span();
         match value {
             Term::Factor(factor) => Ok(AnalyzedTerm::Factor(AnalyzedFactor::analyze_expression(
                 factor, context,
@@ -437,7 +605,7 @@ impl AnalyzeExpression<Term> for AnalyzedTerm {
                 let factor = AnalyzedFactor::analyze_expression(factor, context)?;
 
                 let term_type = term.get_type(context)?;
-                let factor_type = term.get_type(context)?;
+                let factor_type = factor.get_type(context)?;
 
                 match (term_type, factor_type) {
                     (Type::Int, Type::Int) | (Type::Float, Type::Float) => {
@@ -486,7 +654,7 @@ impl AnalyzeExpression<Term> for AnalyzedTerm {
                         Ok(AnalyzedTerm::ScalarArrayMultiply(Box::new(term), factor))
                     }
 
-                    (l_type, r_type) => Err(SemanticsError::TypeMismatch(l_type, r_type)),
+                    (l_type, r_type) => Err(SemanticsError::TypeMismatch(l_type, r_type, span)),
                 }
             }
             Term::DivTerm(term, factor) => {
@@ -494,7 +662,7 @@ impl AnalyzeExpression<Term> for AnalyzedTerm {
                 let factor = AnalyzedFactor::analyze_expression(factor, context)?;
 
                 let term_type = term.get_type(context)?;
-                let factor_type = term.get_type(context)?;
+                let factor_type = factor.get_type(context)?;
 
                 match (term_type, factor_type) {
                     (Type::Int, Type::Int) | (Type::Float, Type::Float) => {
@@ -543,7 +711,7 @@ impl AnalyzeExpression<Term> for AnalyzedTerm {
                         Ok(AnalyzedTerm::ScalarArrayDivide(Box::new(term), factor))
                     }
 
-                    (l_type, r_type) => Err(SemanticsError::TypeMismatch(l_type, r_type)),
+                    (l_type, r_type) => Err(SemanticsError::TypeMismatch(l_type, r_type, span)),
                 }
             }
         }
@@ -570,11 +738,11 @@ pub enum AnalyzedFactor {
     ProcedureCall(AnalyzedProcedureCall),
     Name(AnalyzedName),
     NegatedName(AnalyzedName),
-    Number(AnalyzedNumber),
-    NegatedNumber(AnalyzedNumber),
-    String(String),
-    True,
-    False,
+    Number(AnalyzedNumber, Span),
+    NegatedNumber(AnalyzedNumber, Span),
+    String(String, Span),
+    True(Span),
+    False(Span),
 
     Cast(Box<AnalyzedFactor>, Type),
 }
@@ -583,6 +751,52 @@ impl AnalyzedFactor {
     pub fn cast(self, value_type: Type) -> AnalyzedFactor {
         AnalyzedFactor::Cast(Box::new(self), value_type)
     }
+
+    pub fn span(&self) -> Span {
+        match self {
+            AnalyzedFactor::NestedExpression(expression) => expression.span(),
+            AnalyzedFactor::ProcedureCall(proc_call) => proc_call.span,
+            AnalyzedFactor::Name(name) | AnalyzedFactor::NegatedName(name) => name.span(),
+            AnalyzedFactor::Number(_, span)
+            | AnalyzedFactor::NegatedNumber(_, span)
+            | AnalyzedFactor::String(_, span)
+            | AnalyzedFactor::True(span)
+            | AnalyzedFactor::False(span) => *span,
+            AnalyzedFactor::Cast(factor, _) => factor.span(),
+        }
+    }
+
+    pub fn visit_factors<'e>(&'e self, visit: &mut impl FnMut(&'e AnalyzedFactor)) {
+        match self {
+            AnalyzedFactor::NestedExpression(expression) => expression.visit_factors(visit),
+            AnalyzedFactor::ProcedureCall(proc_call) => {
+                for arg in proc_call.arg_list.iter() {
+                    arg.visit_factors(visit);
+                }
+            }
+            AnalyzedFactor::Name(AnalyzedName::Indexed(_, _, index, _))
+            | AnalyzedFactor::NegatedName(AnalyzedName::Indexed(_, _, index, _)) => {
+                index.visit_factors(visit)
+            }
+            AnalyzedFactor::Cast(factor, _) => {
+                // The cast itself isn't a factor of its own
+                return factor.visit_factors(visit);
+            }
+            _ => {}
+        }
+        visit(self)
+    }
+
+    pub fn integer_literal(&self) -> Option<i64> {
+        match self {
+            AnalyzedFactor::Number(AnalyzedNumber::Integer(value), _) => Some(*value),
+            AnalyzedFactor::NegatedNumber(AnalyzedNumber::Integer(value), _) => {
+                Some(value.wrapping_neg())
+            }
+            AnalyzedFactor::NestedExpression(expression) => expression.integer_literal(),
+            _ => None,
+        }
+    }
 }
 
 impl AnalyzeExpression<Factor> for AnalyzedFactor {
@@ -595,7 +809,7 @@ impl AnalyzeExpression<Factor> for AnalyzedFactor {
                 AnalyzedProcedureCall::analyze_expression(proc_call, context)?,
             )),
             Factor::Name { negate, name } => {
-                if negate {
+                if negate.is_some() {
                     let name = AnalyzedName::analyze_expression(name, context)?;
                     let value_type = name.get_type(context)?.clone();
 
@@ -605,6 +819,7 @@ impl AnalyzeExpression<Factor> for AnalyzedFactor {
                         Err(SemanticsError::InvalidType(
                             String::from("Number"),
                             value_type,
+                            name.span(),
                         ))
                     }
                 } else {
@@ -614,19 +829,22 @@ impl AnalyzeExpression<Factor> for AnalyzedFactor {
                 }
             }
             Factor::Number { negate, number } => {
-                if negate {
+                let span = number.span;
+                if let Some(minus) = negate {
                     Ok(AnalyzedFactor::NegatedNumber(
                         AnalyzedNumber::analyze_expression(number, context)?,
+                        minus.to(span),
                     ))
                 } else {
-                    Ok(AnalyzedFactor::Number(AnalyzedNumber::analyze_expression(
-                        number, context,
-                    )?))
+                    Ok(AnalyzedFactor::Number(
+                        AnalyzedNumber::analyze_expression(number, context)?,
+                        span,
+                    ))
                 }
             }
-            Factor::String(value) => Ok(AnalyzedFactor::String(value.literal_string)),
-            Factor::TrueLit => Ok(AnalyzedFactor::True),
-            Factor::FalseLit => Ok(AnalyzedFactor::False),
+            Factor::String(value) => Ok(AnalyzedFactor::String(value.literal_string, value.span)),
+            Factor::TrueLit(span) => Ok(AnalyzedFactor::True(span)),
+            Factor::FalseLit(span) => Ok(AnalyzedFactor::False(span)),
         }
     }
 
@@ -637,11 +855,11 @@ impl AnalyzeExpression<Factor> for AnalyzedFactor {
             AnalyzedFactor::Name(name) | AnalyzedFactor::NegatedName(name) => {
                 name.get_type(context)
             }
-            AnalyzedFactor::Number(number) | AnalyzedFactor::NegatedNumber(number) => {
+            AnalyzedFactor::Number(number, _) | AnalyzedFactor::NegatedNumber(number, _) => {
                 number.get_type(context)
             }
-            AnalyzedFactor::String(_) => Ok(Type::String),
-            AnalyzedFactor::True | AnalyzedFactor::False => Ok(Type::Bool),
+            AnalyzedFactor::String(..) => Ok(Type::String),
+            AnalyzedFactor::True(_) | AnalyzedFactor::False(_) => Ok(Type::Bool),
             AnalyzedFactor::Cast(_, value_type) => Ok(value_type.clone()),
         }
     }
@@ -672,12 +890,22 @@ impl AnalyzeExpression<Number> for AnalyzedNumber {
 
 #[derive(Debug)]
 pub enum AnalyzedName {
-    Name(String),
-    Indexed(String, Box<AnalyzedExpression>),
+    Name(String, Binding, Span),
+    Indexed(String, Binding, Box<AnalyzedExpression>, Span),
+}
+
+impl AnalyzedName {
+    pub fn span(&self) -> Span {
+        match self {
+            AnalyzedName::Name(_, _, span) | AnalyzedName::Indexed(_, _, _, span) => *span,
+        }
+    }
 }
 
 impl AnalyzeExpression<Name> for AnalyzedName {
     fn analyze_expression(value: Name, context: &mut Context) -> Result<Self, SemanticsError> {
+        let (_, binding) =
+            context.resolve_variable(&value.identifier.identifier_string, value.span)?;
         if let Some(box expression) = value.expression {
             let expression = AnalyzedExpression::analyze_expression(expression, context)?;
             let exp_type = expression.get_type(context)?;
@@ -686,29 +914,116 @@ impl AnalyzeExpression<Name> for AnalyzedName {
                 Err(SemanticsError::NonIntIndex(
                     value.identifier.identifier_string,
                     exp_type,
+                    expression.span(),
                 ))
             } else {
                 Ok(AnalyzedName::Indexed(
                     value.identifier.identifier_string,
+                    binding,
                     Box::new(expression),
+                    value.span,
                 ))
             }
         } else {
-            Ok(AnalyzedName::Name(value.identifier.identifier_string))
+            Ok(AnalyzedName::Name(
+                value.identifier.identifier_string,
+                binding,
+                value.span,
+            ))
         }
     }
     fn get_type(&self, context: &Context) -> Result<Type, SemanticsError> {
         match self {
-            AnalyzedName::Name(identifier) => {
-                context.get_variable_type(identifier).map(Type::clone)
+            AnalyzedName::Name(identifier, _, span) => {
+                context.get_variable_type(identifier, *span).cloned()
             }
-            AnalyzedName::Indexed(identifier, _) => {
-                let array_type = context.get_variable_type(identifier)?;
+            AnalyzedName::Indexed(identifier, _, _, span) => {
+                let array_type = context.get_variable_type(identifier, *span)?;
                 match array_type {
-                    Type::Array(_, _) => Ok(array_type.clone()),
-                    _ => Err(SemanticsError::IndexOnNonArray(identifier.to_owned())),
+                    Type::Array(box element_type, _) => Ok(element_type.clone()),
+                    _ => Err(SemanticsError::IndexOnNonArray(
+                        identifier.to_owned(),
+                        *span,
+                    )),
                 }
             }
         }
     }
 }
+
+#[cfg(test)]
+use super::statement::AnalyzedStatement;
+#[cfg(test)]
+use crate::diagnostics::Severity;
+#[cfg(test)]
+use crate::session::Session;
+#[cfg(test)]
+use rstest::rstest;
+
+/// Analyzes `statement` in a program with integer `x`, float `f` and integer array `a`,
+/// returning the errors it reports
+#[cfg(test)]
+fn analyze_statement(statement: &str) -> Vec<String> {
+    let source = format!(
+        "program types is
+    variable x : integer;
+    variable f : float;
+    variable a : integer[3];
+begin
+    {}
+end program.
+",
+        statement
+    );
+    let mut session = Session::from_text("test.src", source);
+    let _ = session.analyze();
+    session
+        .diagnostics()
+        .diagnostics()
+        .iter()
+        .filter(|diagnostic| diagnostic.severity == Severity::Error)
+        .map(|diagnostic| diagnostic.message.clone())
+        .collect()
+}
+
+#[cfg(test)]
+#[rstest]
+#[case("x := 2 * x;", &[])]
+#[case("x := 2 * true;", &["Type mismatch. Expected: Int, Got: Bool"])]
+#[case("x := 6 / \"two\";", &["Type mismatch. Expected: Int, Got: String"])]
+#[case("f := true / 2.0;", &["Type mismatch. Expected: Bool, Got: Float"])]
+fn terms_check_both_operands(#[case] statement: &str, #[case] messages: &[&str]) {
+    assert_eq!(analyze_statement(statement), messages);
+}
+
+#[cfg(test)]
+#[rstest]
+#[case("x := a[1] + 1;", &[])]
+#[case("a[0] := a[1] * a[2];", &[])]
+#[case("x := a + 1;", &["Type mismatch. Expected: Int, Got: Array(Int, 3)"])]
+fn indexed_names_have_the_element_type(#[case] statement: &str, #[case] messages: &[&str]) {
+    assert_eq!(analyze_statement(statement), messages);
+}
+
+#[cfg(test)]
+#[test]
+fn int_operand_is_cast_when_multiplied_by_float() {
+    let source = "program types is
+    variable x : integer;
+    variable f : float;
+begin
+    f := x * f;
+end program.
+";
+    let mut session = Session::from_text("test.src", source);
+    let program = session.analyze().unwrap();
+    let AnalyzedStatement::Assignment(assignment) = &program.block.0[0] else {
+        panic!("expected an assignment");
+    };
+    assert!(matches!(
+        &assignment.expression,
+        AnalyzedExpression::ArithOp(AnalyzedArithOp::Relation(AnalyzedRelation::Term(
+            AnalyzedTerm::Multiply(box AnalyzedTerm::Cast(_, Type::Float), _),
+        )))
+    ));
+}
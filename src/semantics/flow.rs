@@ -0,0 +1,155 @@
+//! Control-flow checks over an analyzed program: procedures that can reach their end without
+//! returning a value, and statements that can never run because every path before them returns.
+
+use thiserror::Error;
+
+use crate::span::Span;
+
+use super::procedure::AnalyzedProcedure;
+use super::statement::{AnalyzedBlock, AnalyzedStatement};
+use super::AnalyzedProgram;
+
+#[derive(Debug, Error)]
+pub enum FlowError {
+    #[error("Not all paths in procedure {0} return a value.")]
+    MissingReturn(String, Span),
+    /// Holds the span of the unreachable statements, then the span of the statement that returns before them.
+    #[error("Unreachable statement.")]
+    Unreachable(Span, Span),
+}
+
+impl FlowError {
+    pub fn span(&self) -> Span {
+        match self {
+            FlowError::MissingReturn(_, span) | FlowError::Unreachable(span, _) => *span,
+        }
+    }
+}
+
+/// Checks every procedure in `program`.
+/// Statements with errors are left out of analyzed programs, so this should only run on programs without any.
+pub fn check(program: &AnalyzedProgram) -> Vec<FlowError> {
+    let mut errors = Vec::new();
+    for procedure in program.procedures.iter() {
+        check_procedure(procedure, &mut errors);
+    }
+    errors
+}
+
+fn check_procedure(procedure: &AnalyzedProcedure, errors: &mut Vec<FlowError>) {
+    if !block_returns(&procedure.block, errors) {
+        errors.push(FlowError::MissingReturn(
+            procedure.identifier.clone(),
+            procedure.span,
+        ));
+    }
+    for nested in procedure.procedures.iter() {
+        check_procedure(nested, errors);
+    }
+}
+
+/// Whether every path through `block` returns, reporting any statements after one that always does
+fn block_returns(block: &AnalyzedBlock, errors: &mut Vec<FlowError>) -> bool {
+    let mut statements = block.0.iter();
+    while let Some(statement) = statements.next() {
+        if statement_returns(statement, errors) {
+            let unreachable: Vec<&AnalyzedStatement> = statements.collect();
+            if let (Some(first), Some(last)) = (unreachable.first(), unreachable.last()) {
+                errors.push(FlowError::Unreachable(
+                    first.span().to(last.span()),
+                    statement.span(),
+                ));
+            }
+            // Unreachable statements are still checked for unreachable statements of their own
+            for statement in unreachable {
+                statement_returns(statement, errors);
+            }
+            return true;
+        }
+    }
+    false
+}
+
+fn statement_returns(statement: &AnalyzedStatement, errors: &mut Vec<FlowError>) -> bool {
+    match statement {
+        AnalyzedStatement::Assignment(_) => false,
+        AnalyzedStatement::Return(_) => true,
+        AnalyzedStatement::If(statement) => {
+            let then_returns = block_returns(&statement.then_block, errors);
+            let else_returns = statement
+                .else_block
+                .as_ref()
+                .is_some_and(|block| block_returns(block, errors));
+            then_returns && else_returns
+        }
+        // The loop body might not run at all
+        AnalyzedStatement::Loop(statement) => {
+            block_returns(&statement.loop_body, errors);
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+use crate::lint::Lint;
+#[cfg(test)]
+use crate::session::{analyze_messages, Session};
+#[cfg(test)]
+use rstest::rstest;
+
+#[cfg(test)]
+#[rstest]
+#[case("return n;", &[])]
+#[case("n := 1;", &["Not all paths in procedure f return a value."])]
+#[case("if (n > 0) then return 1; end if;", &["Not all paths in procedure f return a value."])]
+#[case("if (n > 0) then return 1; else return 2; end if;", &[])]
+#[case(
+    "for (n := 0; n < 3) return n; end for;",
+    &["Not all paths in procedure f return a value."]
+)]
+#[case("return n; n := 1; n := 2;", &["Unreachable statement."])]
+#[case(
+    "if (n > 0) then return 1; n := 1; else return 2; end if;",
+    &["Unreachable statement."]
+)]
+#[case(
+    "if (n > 0) then return 1; else return 2; end if; return n;",
+    &["Unreachable statement."]
+)]
+fn flow_is_checked(#[case] body: &str, #[case] messages: &[&str]) {
+    let source = format!(
+        "program flow is
+    procedure f : integer(variable n : integer)
+    begin
+        {}
+    end procedure;
+begin
+end program.
+",
+        body
+    );
+    assert_eq!(
+        analyze_messages(&source, &[Lint::MissingReturn, Lint::UnreachableCode]),
+        messages
+    );
+}
+
+#[cfg(test)]
+#[test]
+fn nested_procedures_are_checked() {
+    let source = "program flow is
+    procedure outer : integer()
+        procedure inner : integer()
+        begin
+        end procedure;
+    begin
+        return inner();
+    end procedure;
+begin
+end program.
+";
+    let mut session = Session::from_text("test.src", source);
+    let errors = check(session.analyze().unwrap());
+    assert_eq!(errors.len(), 1);
+    assert!(matches!(&errors[0], FlowError::MissingReturn(identifier, _) if identifier == "inner"));
+}
@@ -0,0 +1,457 @@
+//! Constant folding: replaces expressions whose operands are all literals with the literal they evaluate to,
+//! reporting the ones that would divide by zero or overflow when run.
+
+use std::cmp::Ordering;
+
+use thiserror::Error;
+
+use crate::span::Span;
+
+use super::expression::{
+    AnalyzedArithOp, AnalyzedExpression, AnalyzedFactor, AnalyzedName, AnalyzedNumber,
+    AnalyzedRelation, AnalyzedTerm,
+};
+use super::procedure::AnalyzedProcedure;
+use super::statement::{AnalyzedAssignment, AnalyzedBlock, AnalyzedStatement};
+use super::value::Type;
+use super::AnalyzedProgram;
+
+#[derive(Debug, Error)]
+pub enum FoldError {
+    /// Holds the span of the division, then the span of the divisor
+    #[error("Attempted to divide by zero.")]
+    DivisionByZero(Span, Span),
+    #[error("Integer overflow in constant expression.")]
+    Overflow(Span),
+}
+
+impl FoldError {
+    pub fn span(&self) -> Span {
+        match self {
+            FoldError::DivisionByZero(span, _) | FoldError::Overflow(span) => *span,
+        }
+    }
+}
+
+/// Folds every expression in `program`.
+/// Expressions that would fail when run are reported and left as they are.
+pub fn fold(program: &mut AnalyzedProgram) -> Vec<FoldError> {
+    let mut folder = Folder::default();
+    for procedure in program.procedures.iter_mut() {
+        folder.procedure(procedure);
+    }
+    folder.block(&mut program.block);
+    folder.errors
+}
+
+/// The value of a folded expression
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Constant {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+}
+
+impl Constant {
+    /// Converts to `value_type` the way a cast does when the program runs
+    fn cast(self, value_type: &Type) -> Option<Constant> {
+        match (self, value_type) {
+            (Constant::Int(value), Type::Float) => Some(Constant::Float(value as f64)),
+            (Constant::Float(value), Type::Int) => Some(Constant::Int(value as i64)),
+            (Constant::Bool(value), Type::Int) => Some(Constant::Int(i64::from(value))),
+            (Constant::Int(value), Type::Bool) => Some(Constant::Bool(value != 0)),
+            (Constant::Bool(_), Type::Bool)
+            | (Constant::Int(_), Type::Int)
+            | (Constant::Float(_), Type::Float) => Some(self),
+            _ => None,
+        }
+    }
+
+    fn factor(self, span: Span) -> AnalyzedFactor {
+        match self {
+            Constant::Bool(true) => AnalyzedFactor::True(span),
+            Constant::Bool(false) => AnalyzedFactor::False(span),
+            Constant::Int(value) => AnalyzedFactor::Number(AnalyzedNumber::Integer(value), span),
+            Constant::Float(value) => AnalyzedFactor::Number(AnalyzedNumber::Float(value), span),
+        }
+    }
+
+    fn term(self, span: Span) -> AnalyzedTerm {
+        AnalyzedTerm::Factor(self.factor(span))
+    }
+
+    fn relation(self, span: Span) -> AnalyzedRelation {
+        AnalyzedRelation::Term(self.term(span))
+    }
+
+    fn arith_op(self, span: Span) -> AnalyzedArithOp {
+        AnalyzedArithOp::Relation(self.relation(span))
+    }
+
+    fn expression(self, span: Span) -> AnalyzedExpression {
+        AnalyzedExpression::ArithOp(self.arith_op(span))
+    }
+}
+
+/// Folds expressions bottom up. Each level returns the value of the expression if it's constant,
+/// after replacing it with a literal if it isn't one already.
+#[derive(Debug, Default)]
+struct Folder {
+    errors: Vec<FoldError>,
+}
+
+impl Folder {
+    fn procedure(&mut self, procedure: &mut AnalyzedProcedure) {
+        for nested in procedure.procedures.iter_mut() {
+            self.procedure(nested);
+        }
+        self.block(&mut procedure.block);
+    }
+
+    fn block(&mut self, block: &mut AnalyzedBlock) {
+        for statement in block.0.iter_mut() {
+            self.statement(statement);
+        }
+    }
+
+    fn statement(&mut self, statement: &mut AnalyzedStatement) {
+        match statement {
+            AnalyzedStatement::Assignment(assignment) => self.assignment(assignment),
+            AnalyzedStatement::If(statement) => {
+                self.expression(&mut statement.conditional_expr);
+                self.block(&mut statement.then_block);
+                if let Some(else_block) = &mut statement.else_block {
+                    self.block(else_block);
+                }
+            }
+            AnalyzedStatement::Loop(statement) => {
+                self.assignment(&mut statement.assignment);
+                self.expression(&mut statement.condition);
+                self.block(&mut statement.loop_body);
+            }
+            AnalyzedStatement::Return(statement) => {
+                self.expression(&mut statement.expression);
+            }
+        }
+    }
+
+    fn assignment(&mut self, assignment: &mut AnalyzedAssignment) {
+        if let Some(index) = &mut assignment.destination.expression {
+            self.expression(index);
+        }
+        self.expression(&mut assignment.expression);
+    }
+
+    fn expression(&mut self, expression: &mut AnalyzedExpression) -> Option<Constant> {
+        let span = expression.span();
+        let constant = match expression {
+            AnalyzedExpression::BitwiseAnd(lhs, rhs)
+            | AnalyzedExpression::BitwiseOr(lhs, rhs)
+            | AnalyzedExpression::LogicalAnd(lhs, rhs)
+            | AnalyzedExpression::LogicalOr(lhs, rhs) => {
+                let lhs = self.expression(lhs);
+                let rhs = self.arith_op(rhs);
+                match (&*expression, lhs?, rhs?) {
+                    (
+                        AnalyzedExpression::BitwiseAnd(..),
+                        Constant::Int(lhs),
+                        Constant::Int(rhs),
+                    ) => Constant::Int(lhs & rhs),
+                    (AnalyzedExpression::BitwiseOr(..), Constant::Int(lhs), Constant::Int(rhs)) => {
+                        Constant::Int(lhs | rhs)
+                    }
+                    (
+                        AnalyzedExpression::LogicalAnd(..),
+                        Constant::Bool(lhs),
+                        Constant::Bool(rhs),
+                    ) => Constant::Bool(lhs && rhs),
+                    (
+                        AnalyzedExpression::LogicalOr(..),
+                        Constant::Bool(lhs),
+                        Constant::Bool(rhs),
+                    ) => Constant::Bool(lhs || rhs),
+                    _ => return None,
+                }
+            }
+            AnalyzedExpression::BitwiseNot(arith_op) | AnalyzedExpression::LogicalNot(arith_op) => {
+                match self.arith_op(arith_op)? {
+                    Constant::Int(value) => Constant::Int(!value),
+                    Constant::Bool(value) => Constant::Bool(!value),
+                    Constant::Float(_) => return None,
+                }
+            }
+            AnalyzedExpression::Cast(inner, value_type) => {
+                self.expression(inner)?.cast(value_type)?
+            }
+            AnalyzedExpression::ArithOp(arith_op) => return self.arith_op(arith_op),
+        };
+        *expression = constant.expression(span);
+        Some(constant)
+    }
+
+    fn arith_op(&mut self, arith_op: &mut AnalyzedArithOp) -> Option<Constant> {
+        let span = arith_op.span();
+        let constant = match arith_op {
+            AnalyzedArithOp::Plus(lhs, rhs) => {
+                let lhs = self.arith_op(lhs);
+                let rhs = self.relation(rhs);
+                self.arith(lhs?, rhs?, span, i64::checked_add, |lhs, rhs| lhs + rhs)?
+            }
+            AnalyzedArithOp::Minus(lhs, rhs) => {
+                let lhs = self.arith_op(lhs);
+                let rhs = self.relation(rhs);
+                self.arith(lhs?, rhs?, span, i64::checked_sub, |lhs, rhs| lhs - rhs)?
+            }
+            // Arrays are never constant, but their operands may have constants in them
+            AnalyzedArithOp::ArrayScalarPlus(lhs, rhs)
+            | AnalyzedArithOp::ScalarArrayPlus(lhs, rhs)
+            | AnalyzedArithOp::ArrayPlus(lhs, rhs)
+            | AnalyzedArithOp::ArrayScalarMinus(lhs, rhs)
+            | AnalyzedArithOp::ScalarArrayMinus(lhs, rhs)
+            | AnalyzedArithOp::ArrayMinus(lhs, rhs) => {
+                self.arith_op(lhs);
+                self.relation(rhs);
+                return None;
+            }
+            AnalyzedArithOp::Cast(inner, value_type) => self.arith_op(inner)?.cast(value_type)?,
+            AnalyzedArithOp::Relation(relation) => return self.relation(relation),
+        };
+        *arith_op = constant.arith_op(span);
+        Some(constant)
+    }
+
+    fn relation(&mut self, relation: &mut AnalyzedRelation) -> Option<Constant> {
+        let span = relation.span();
+        let constant = match relation {
+            AnalyzedRelation::LessThan(lhs, rhs)
+            | AnalyzedRelation::LessThanEq(lhs, rhs)
+            | AnalyzedRelation::GreaterThan(lhs, rhs)
+            | AnalyzedRelation::GreaterThanEq(lhs, rhs)
+            | AnalyzedRelation::Equals(lhs, rhs)
+            | AnalyzedRelation::NotEquals(lhs, rhs) => {
+                let lhs = self.relation(lhs);
+                let rhs = self.term(rhs);
+                let ordering = match (lhs?, rhs?) {
+                    (Constant::Int(lhs), Constant::Int(rhs)) => lhs.partial_cmp(&rhs),
+                    (Constant::Float(lhs), Constant::Float(rhs)) => lhs.partial_cmp(&rhs),
+                    (Constant::Bool(lhs), Constant::Bool(rhs)) => lhs.partial_cmp(&rhs),
+                    _ => return None,
+                };
+                // Folds to what `Value::compare` gives at run time
+                Constant::Bool(match (&*relation, ordering) {
+                    (AnalyzedRelation::NotEquals(..), ordering) => {
+                        ordering != Some(Ordering::Equal)
+                    }
+                    (_, None) => false,
+                    (AnalyzedRelation::LessThan(..), Some(ordering)) => ordering.is_lt(),
+                    (AnalyzedRelation::LessThanEq(..), Some(ordering)) => ordering.is_le(),
+                    (AnalyzedRelation::GreaterThan(..), Some(ordering)) => ordering.is_gt(),
+                    (AnalyzedRelation::GreaterThanEq(..), Some(ordering)) => ordering.is_ge(),
+                    (_, Some(ordering)) => ordering.is_eq(),
+                })
+            }
+            AnalyzedRelation::Cast(inner, value_type) => self.relation(inner)?.cast(value_type)?,
+            AnalyzedRelation::Term(term) => return self.term(term),
+        };
+        *relation = constant.relation(span);
+        Some(constant)
+    }
+
+    fn term(&mut self, term: &mut AnalyzedTerm) -> Option<Constant> {
+        let span = term.span();
+        let constant = match term {
+            AnalyzedTerm::Multiply(lhs, rhs) => {
+                let lhs = self.term(lhs);
+                let rhs = self.factor(rhs);
+                self.arith(lhs?, rhs?, span, i64::checked_mul, |lhs, rhs| lhs * rhs)?
+            }
+            AnalyzedTerm::Divide(lhs, rhs) => {
+                let lhs = self.term(lhs);
+                let rhs = self.divisor(rhs, span)?;
+                self.arith(lhs?, rhs, span, i64::checked_div, |lhs, rhs| lhs / rhs)?
+            }
+            AnalyzedTerm::ArrayScalarDivide(lhs, rhs) => {
+                self.term(lhs);
+                self.divisor(rhs, span);
+                return None;
+            }
+            AnalyzedTerm::ArrayScalarMultiply(lhs, rhs)
+            | AnalyzedTerm::ScalarArrayMultiply(lhs, rhs)
+            | AnalyzedTerm::ArrayMultiply(lhs, rhs)
+            | AnalyzedTerm::ScalarArrayDivide(lhs, rhs)
+            | AnalyzedTerm::ArrayDivide(lhs, rhs) => {
+                self.term(lhs);
+                self.factor(rhs);
+                return None;
+            }
+            AnalyzedTerm::Cast(inner, value_type) => self.term(inner)?.cast(value_type)?,
+            AnalyzedTerm::Factor(factor) => return self.factor(factor),
+        };
+        *term = constant.term(span);
+        Some(constant)
+    }
+
+    fn factor(&mut self, factor: &mut AnalyzedFactor) -> Option<Constant> {
+        let span = factor.span();
+        let constant = match factor {
+            AnalyzedFactor::NestedExpression(expression) => self.expression(expression)?,
+            AnalyzedFactor::ProcedureCall(call) => {
+                for arg in call.arg_list.iter_mut() {
+                    self.expression(arg);
+                }
+                return None;
+            }
+            AnalyzedFactor::Name(AnalyzedName::Indexed(_, _, index, _))
+            | AnalyzedFactor::NegatedName(AnalyzedName::Indexed(_, _, index, _)) => {
+                self.expression(index);
+                return None;
+            }
+            AnalyzedFactor::Name(_) | AnalyzedFactor::NegatedName(_) => return None,
+            // Literals are left as they are, so they keep their own spans
+            AnalyzedFactor::Number(number, _) => return Some(Constant::from(&*number)),
+            AnalyzedFactor::NegatedNumber(number, _) => {
+                return Some(match number {
+                    AnalyzedNumber::Integer(value) => Constant::Int(value.wrapping_neg()),
+                    AnalyzedNumber::Float(value) => Constant::Float(-*value),
+                })
+            }
+            AnalyzedFactor::True(_) => return Some(Constant::Bool(true)),
+            AnalyzedFactor::False(_) => return Some(Constant::Bool(false)),
+            AnalyzedFactor::String(..) => return None,
+            AnalyzedFactor::Cast(inner, value_type) => self.factor(inner)?.cast(value_type)?,
+        };
+        *factor = constant.factor(span);
+        Some(constant)
+    }
+
+    /// Folds the divisor of the division at `span`, reporting it if it's an integer zero.
+    /// Floats divide by zero to infinity, so only integers are checked.
+    fn divisor(&mut self, divisor: &mut AnalyzedFactor, span: Span) -> Option<Constant> {
+        let constant = self.factor(divisor)?;
+        if constant == Constant::Int(0) {
+            self.errors
+                .push(FoldError::DivisionByZero(span, divisor.span()));
+            return None;
+        }
+        Some(constant)
+    }
+
+    /// Applies `int_op` or `float_op` to constant operands of the same type.
+    /// Integer results that don't fit are reported, and floats that aren't finite are left unfolded,
+    /// since not every backend can write them as literals.
+    fn arith(
+        &mut self,
+        lhs: Constant,
+        rhs: Constant,
+        span: Span,
+        int_op: fn(i64, i64) -> Option<i64>,
+        float_op: fn(f64, f64) -> f64,
+    ) -> Option<Constant> {
+        match (lhs, rhs) {
+            (Constant::Int(lhs), Constant::Int(rhs)) => match int_op(lhs, rhs) {
+                Some(value) => Some(Constant::Int(value)),
+                None => {
+                    self.errors.push(FoldError::Overflow(span));
+                    None
+                }
+            },
+            (Constant::Float(lhs), Constant::Float(rhs)) => Some(float_op(lhs, rhs))
+                .filter(|value| value.is_finite())
+                .map(Constant::Float),
+            _ => None,
+        }
+    }
+}
+
+impl From<&AnalyzedNumber> for Constant {
+    fn from(value: &AnalyzedNumber) -> Self {
+        match value {
+            AnalyzedNumber::Integer(value) => Constant::Int(*value),
+            AnalyzedNumber::Float(value) => Constant::Float(*value),
+        }
+    }
+}
+
+#[cfg(test)]
+use crate::session::{analyze_messages, Session};
+#[cfg(test)]
+use rstest::rstest;
+
+/// The value of an expression that has been folded into a literal
+#[cfg(test)]
+fn literal(expression: &AnalyzedExpression) -> Option<Constant> {
+    let AnalyzedExpression::ArithOp(AnalyzedArithOp::Relation(AnalyzedRelation::Term(
+        AnalyzedTerm::Factor(factor),
+    ))) = expression
+    else {
+        return None;
+    };
+    match factor {
+        AnalyzedFactor::Number(number, _) => Some(Constant::from(number)),
+        AnalyzedFactor::True(_) => Some(Constant::Bool(true)),
+        AnalyzedFactor::False(_) => Some(Constant::Bool(false)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+#[rstest]
+#[case("x", "2 * 3 + 1", Some(Constant::Int(7)))]
+#[case("x", "(1 + 2) * -3", Some(Constant::Int(-9)))]
+#[case("x", "7 / 2", Some(Constant::Int(3)))]
+#[case("x", "5 & 3 | 8", Some(Constant::Int(9)))]
+#[case("x", "not 0", Some(Constant::Int(-1)))]
+#[case("x", "2.9 * 2", Some(Constant::Int(5)))]
+#[case("y", "1 + 2", Some(Constant::Float(3.0)))]
+#[case("b", "not true", Some(Constant::Bool(false)))]
+#[case("b", "1 < 2 & 2.5 >= 3", Some(Constant::Bool(false)))]
+#[case("b", "(true == false) | 1 <= 1", Some(Constant::Bool(true)))]
+#[case("x", "x + 1 * 2", None)]
+#[case("y", "1.0 / 0.0", None)]
+fn constants_are_folded(
+    #[case] destination: &str,
+    #[case] expression: &str,
+    #[case] folded: Option<Constant>,
+) {
+    let source = format!(
+        "program fold is
+    variable x : integer;
+    variable y : float;
+    variable b : bool;
+begin
+    {} := {};
+end program.
+",
+        destination, expression
+    );
+    let mut session = Session::from_text("test.src", source);
+    let program = session.analyze().unwrap();
+    let AnalyzedStatement::Assignment(assignment) = &program.block.0[0] else {
+        unreachable!()
+    };
+    assert_eq!(literal(&assignment.expression), folded);
+}
+
+#[cfg(test)]
+#[rstest]
+#[case("x := 1 / 0;", &["Attempted to divide by zero."])]
+#[case("x := x / (2 - 2);", &["Attempted to divide by zero."])]
+#[case("a := a / 0;", &["Attempted to divide by zero."])]
+#[case("x := 9223372036854775807 + 1;", &["Integer overflow in constant expression."])]
+#[case("x := -9223372036854775807 - 2 * 1;", &["Integer overflow in constant expression."])]
+#[case("x := x + 9223372036854775807 * 2;", &["Integer overflow in constant expression."])]
+#[case("x := 9223372036854775808;", &["number too large to fit in target type"])]
+#[case("x := 9223372036854775807 - 1; a[x / 1] := 1;", &[])]
+fn constant_errors_are_reported(#[case] statements: &str, #[case] messages: &[&str]) {
+    let source = format!(
+        "program fold is
+    variable x : integer;
+    variable a : integer[3];
+begin
+    {}
+end program.
+",
+        statements
+    );
+    assert_eq!(analyze_messages(&source, &[]), messages);
+}
@@ -1,10 +1,13 @@
+use std::collections::HashMap;
+
 use thiserror::Error;
 
 use crate::parser::declaratons::ProcedureDeclaration;
 use crate::parser::procedure::{ParamList, ProcedureCall};
-use crate::parser::types::Identifier;
+use crate::span::Span;
 
-use super::context::{Context, Scope, ScopeContext};
+use super::context::{Binding, Context, Scope, ScopeContext};
+use super::declaration::declare_procedures;
 use super::expression::AnalyzedExpression;
 use super::statement::AnalyzedBlock;
 use super::traits::{Analyze, AnalyzeExpression};
@@ -14,10 +17,13 @@ use super::SemanticsError;
 #[derive(Debug)]
 pub struct AnalyzedProcedure {
     pub identifier: String,
+    /// Declared in the global scope, either at the top level or with `global`
+    pub is_global: bool,
     pub arg_list: Vec<NamedValue>,
     pub declarations: ScopeContext,
     pub procedures: Vec<Box<AnalyzedProcedure>>,
     pub block: AnalyzedBlock,
+    pub span: Span,
 }
 
 impl Analyze<AnalyzedProcedure> for ProcedureDeclaration {
@@ -26,28 +32,36 @@ impl Analyze<AnalyzedProcedure> for ProcedureDeclaration {
         context: &mut Context,
         scope: &Scope,
     ) -> Result<AnalyzedProcedure, SemanticsError> {
-        let arg_list = match self.procedure_header.param_list {
-            Some(ParamList { param_list }) => param_list
-                .into_iter()
-                .map(|param| param.try_into())
-                .collect::<Result<Vec<NamedValue>, SemanticsError>>()?,
-            None => Vec::new(),
-        };
+        let params = self
+            .procedure_header
+            .param_list
+            .map_or(Vec::new(), |ParamList { param_list }| param_list);
+        let param_spans: Vec<Span> = params
+            .iter()
+            .map(|param| param.variable_declaration.span)
+            .collect();
+        let arg_list = params
+            .into_iter()
+            .map(|param| param.try_into())
+            .collect::<Result<Vec<NamedValue>, SemanticsError>>()?;
         let identifier = self.procedure_header.identifier;
         let return_type: Type = self.procedure_header.type_mark.into();
-        let signature = ProcedureSignature(arg_list.clone(), return_type.clone());
-        context.set_procedure(scope == &Scope::Global, identifier.clone(), signature)?;
-
-        context.start_stack(return_type);
+        let is_global = scope == &Scope::Global;
+        context.start_stack(return_type, *scope);
 
-        for arg in arg_list.iter() {
-            context.set_type(false, arg.0.clone(), arg.1.clone())?;
+        for (arg, span) in arg_list.iter().zip(param_spans) {
+            if let Err(err) = context.set_type(false, arg.0.clone(), arg.1.clone(), span) {
+                context.report(err);
+            }
         }
 
+        declare_procedures(&self.procedure_body.declarations, context, &Scope::Local);
         let mut procedures = Vec::new();
         for declaration in self.procedure_body.declarations {
-            if let Some(procedure) = declaration.analyze(context, &Scope::Local)? {
-                procedures.push(Box::new(procedure))
+            match declaration.analyze(context, &Scope::Local) {
+                Ok(Some(procedure)) => procedures.push(Box::new(procedure)),
+                Ok(None) => {}
+                Err(err) => context.report(err),
             }
         }
 
@@ -58,19 +72,124 @@ impl Analyze<AnalyzedProcedure> for ProcedureDeclaration {
 
         Ok(AnalyzedProcedure {
             identifier,
+            is_global,
             arg_list,
-            declarations: context.end_stack()?,
+            declarations: context
+                .end_stack()
+                .ok_or(SemanticsError::OutOfScope(self.span))?,
             procedures,
             block,
+            span: self.span,
         })
     }
 }
 
+impl AnalyzedProcedure {
+    /// Calls `visit` on each procedure in `procedures` and the ones nested in them.
+    /// It's passed the procedure along with the ones enclosing it, innermost last.
+    pub fn visit_all<'p>(
+        procedures: impl IntoIterator<Item = &'p AnalyzedProcedure>,
+        enclosing: &mut Vec<&'p AnalyzedProcedure>,
+        visit: &mut impl FnMut(&[&'p AnalyzedProcedure]),
+    ) {
+        for procedure in procedures {
+            enclosing.push(procedure);
+            visit(enclosing);
+            AnalyzedProcedure::visit_all(
+                procedure.procedures.iter().map(Box::as_ref),
+                enclosing,
+                visit,
+            );
+            enclosing.pop();
+        }
+    }
+}
+
+/// Finds the procedure a call in an analyzed program calls
+#[derive(Debug)]
+pub struct CallResolver<'p> {
+    global_procedures: HashMap<&'p str, &'p AnalyzedProcedure>,
+}
+
+impl<'p> CallResolver<'p> {
+    /// A resolver for calls in the program with the top-level `procedures`
+    pub fn new(procedures: &'p [AnalyzedProcedure]) -> Self {
+        let mut global_procedures = HashMap::new();
+        AnalyzedProcedure::visit_all(procedures, &mut Vec::new(), &mut |enclosing| {
+            let procedure = enclosing[enclosing.len() - 1];
+            if procedure.is_global {
+                global_procedures.insert(procedure.identifier.as_str(), procedure);
+            }
+        });
+        CallResolver { global_procedures }
+    }
+
+    /// The procedure `call` calls, where `enclosing` are the procedures around the call, innermost last.
+    /// Builtins aren't declared in the program, so they resolve to `None`.
+    pub fn resolve(
+        &self,
+        call: &AnalyzedProcedureCall,
+        enclosing: &[&'p AnalyzedProcedure],
+    ) -> Option<&'p AnalyzedProcedure> {
+        match call.binding.procedure(enclosing) {
+            None => self.global(&call.identifier),
+            Some(parent) => CallResolver::nested(parent, &call.identifier),
+        }
+    }
+
+    /// The procedure named `identifier` declared in the global scope
+    pub fn global(&self, identifier: &str) -> Option<&'p AnalyzedProcedure> {
+        self.global_procedures.get(identifier).copied()
+    }
+
+    /// The procedure named `identifier` declared in `parent` without `global`
+    pub fn nested(
+        parent: &'p AnalyzedProcedure,
+        identifier: &str,
+    ) -> Option<&'p AnalyzedProcedure> {
+        parent
+            .procedures
+            .iter()
+            .find(|procedure| !procedure.is_global && procedure.identifier == identifier)
+            .map(Box::as_ref)
+    }
+}
+
+impl ProcedureDeclaration {
+    /// Declares the procedure's signature in `scope`, before any procedure bodies are analyzed.
+    /// Invalid parameters are left to be reported when the procedure itself is analyzed.
+    pub fn declare(&self, context: &mut Context, scope: &Scope) {
+        let header = &self.procedure_header;
+        let params = header
+            .param_list
+            .iter()
+            .flat_map(|ParamList { param_list }| param_list.iter().cloned());
+        let Ok(arg_list) = params
+            .map(NamedValue::try_from)
+            .collect::<Result<Vec<NamedValue>, SemanticsError>>()
+        else {
+            return;
+        };
+        let signature = ProcedureSignature(arg_list, header.type_mark.clone().into());
+        // A redeclared procedure can still have errors of its own in its body
+        if let Err(err) = context.set_procedure(
+            scope == &Scope::Global,
+            header.identifier.clone(),
+            signature,
+            header.span,
+        ) {
+            context.report(err);
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct AnalyzedProcedureCall {
     pub identifier: String,
+    pub binding: Binding,
     pub arg_list: Vec<AnalyzedExpression>,
     pub ret_type: Type,
+    pub span: Span,
 }
 
 impl AnalyzeExpression<ProcedureCall> for AnalyzedProcedureCall {
@@ -78,9 +197,9 @@ impl AnalyzeExpression<ProcedureCall> for AnalyzedProcedureCall {
         value: ProcedureCall,
         context: &mut Context,
     ) -> Result<Self, SemanticsError> {
-        let proc_sig = context
-            .get_procedure_signature(&value.identifier.identifier_string)?
-            .clone();
+        let (proc_sig, binding) = context
+            .resolve_procedure(&value.identifier.identifier_string, value.identifier.span)?;
+        let proc_sig = proc_sig.clone();
         let identifier = &value.identifier;
 
         let passed_args = value
@@ -91,16 +210,18 @@ impl AnalyzeExpression<ProcedureCall> for AnalyzedProcedureCall {
             return Err(SemanticsError::ParamCountMismatch(
                 passed_args.len(),
                 proc_sig.0.len(),
+                value.span,
             ));
         }
         let args = passed_args
             .into_iter()
-            .zip(proc_sig.0.into_iter())
+            .zip(proc_sig.0)
             .map(move |(passed_arg, sig_arg)| {
+                let arg_span = passed_arg.span();
                 let expression = AnalyzedExpression::analyze_expression(passed_arg, context)?;
                 let exp_type = expression.get_type(context)?;
                 if exp_type != sig_arg.1 {
-                    Err(SemanticsError::TypeMismatch(exp_type, sig_arg.1))
+                    Err(SemanticsError::TypeMismatch(sig_arg.1, exp_type, arg_span))
                 } else {
                     Ok(expression)
                 }
@@ -109,8 +230,10 @@ impl AnalyzeExpression<ProcedureCall> for AnalyzedProcedureCall {
 
         Ok(AnalyzedProcedureCall {
             identifier: identifier.identifier_string.clone(),
+            binding,
             arg_list: args,
             ret_type: proc_sig.1,
+            span: value.span,
         })
     }
 
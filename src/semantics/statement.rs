@@ -2,7 +2,10 @@ use crate::parser::statement::{
     AssignmentStatement, Destination, IfStatement, LoopStatement, ReturnStatement, Statement,
 };
 
-use super::expression::AnalyzedExpression;
+use crate::span::Span;
+
+use super::context::Binding;
+use super::expression::{AnalyzedExpression, AnalyzedFactor};
 use super::traits::{Analyze, AnalyzeExpression};
 use super::value::Type;
 use super::SemanticsError;
@@ -10,7 +13,17 @@ use super::SemanticsError;
 #[derive(Debug)]
 pub struct AnalyzedBlock(pub Vec<AnalyzedStatement>);
 
+impl AnalyzedBlock {
+    /// Calls `visit` on each factor of every expression in the block, including in nested blocks
+    pub fn visit_factors<'b>(&'b self, visit: &mut impl FnMut(&'b AnalyzedFactor)) {
+        for statement in self.0.iter() {
+            statement.visit_factors(visit);
+        }
+    }
+}
+
 #[derive(Debug)]
+#[allow(clippy::large_enum_variant)]
 pub enum AnalyzedStatement {
     Assignment(AnalyzedAssignment),
     If(AnalyzedIf),
@@ -18,27 +31,63 @@ pub enum AnalyzedStatement {
     Return(AnalyzedReturn),
 }
 
+impl AnalyzedStatement {
+    pub fn span(&self) -> Span {
+        match self {
+            AnalyzedStatement::Assignment(statement) => statement.span,
+            AnalyzedStatement::If(statement) => statement.span,
+            AnalyzedStatement::Loop(statement) => statement.span,
+            AnalyzedStatement::Return(statement) => statement.span,
+        }
+    }
+
+    pub fn visit_factors<'s>(&'s self, visit: &mut impl FnMut(&'s AnalyzedFactor)) {
+        match self {
+            AnalyzedStatement::Assignment(assignment) => assignment.visit_factors(visit),
+            AnalyzedStatement::If(statement) => {
+                statement.conditional_expr.visit_factors(visit);
+                statement.then_block.visit_factors(visit);
+                if let Some(else_block) = &statement.else_block {
+                    else_block.visit_factors(visit);
+                }
+            }
+            AnalyzedStatement::Loop(statement) => {
+                statement.assignment.visit_factors(visit);
+                statement.condition.visit_factors(visit);
+                statement.loop_body.visit_factors(visit);
+            }
+            AnalyzedStatement::Return(statement) => statement.expression.visit_factors(visit),
+        }
+    }
+}
+
 impl Analyze<AnalyzedBlock> for Vec<Statement> {
     fn analyze(
         self,
         context: &mut super::context::Context,
         scope: &super::context::Scope,
     ) -> Result<AnalyzedBlock, super::SemanticsError> {
-        let statements = self
-            .into_iter()
-            .map(|statment| statment.analyze(context, scope))
-            .collect::<Result<Vec<AnalyzedStatement>, SemanticsError>>()?;
+        let mut statements = Vec::new();
+        // Statements are independent of each other, so keep going after a bad one
+        for statement in self {
+            match statement.analyze(context, scope) {
+                Ok(Some(statement)) => statements.push(statement),
+                Ok(None) => {}
+                Err(err) => context.report(err),
+            }
+        }
 
         Ok(AnalyzedBlock(statements))
     }
 }
 
-impl Analyze<AnalyzedStatement> for Statement {
+/// Statements that failed to parse analyze to `None`
+impl Analyze<Option<AnalyzedStatement>> for Statement {
     fn analyze(
         self,
         context: &mut super::context::Context,
         scope: &super::context::Scope,
-    ) -> Result<AnalyzedStatement, super::SemanticsError> {
+    ) -> Result<Option<AnalyzedStatement>, super::SemanticsError> {
         let statement = match self {
             Statement::Assignment(statement) => {
                 AnalyzedStatement::Assignment(statement.analyze(context, scope)?)
@@ -50,9 +99,10 @@ impl Analyze<AnalyzedStatement> for Statement {
             Statement::Return(statement) => {
                 AnalyzedStatement::Return(statement.analyze(context, scope)?)
             }
+            Statement::Error(_) => return Ok(None),
         };
 
-        Ok(statement)
+        Ok(Some(statement))
     }
 }
 
@@ -60,7 +110,18 @@ impl Analyze<AnalyzedStatement> for Statement {
 pub struct AnalyzedAssignment {
     pub destination: AnalyzedDestination,
     pub expression: AnalyzedExpression,
+    pub span: Span,
+}
+
+impl AnalyzedAssignment {
+    pub fn visit_factors<'a>(&'a self, visit: &mut impl FnMut(&'a AnalyzedFactor)) {
+        if let Some(index) = &self.destination.expression {
+            index.visit_factors(visit);
+        }
+        self.expression.visit_factors(visit);
+    }
 }
+
 impl Analyze<AnalyzedAssignment> for AssignmentStatement {
     fn analyze(
         self,
@@ -68,22 +129,28 @@ impl Analyze<AnalyzedAssignment> for AssignmentStatement {
         scope: &super::context::Scope,
     ) -> Result<AnalyzedAssignment, SemanticsError> {
         let destination = self.destination.analyze(context, scope)?;
+        let expression_span = self.expression.span();
         let mut expression = AnalyzedExpression::analyze_expression(self.expression, context)?;
         let expression_type = expression.get_type(context)?;
 
-        if &destination.value_type != &expression_type {
+        if destination.value_type != expression_type {
             expression = match (&destination.value_type, expression_type) {
                 (Type::Int, Type::Bool | Type::Float) => expression.cast_expr(Type::Int),
                 (Type::Bool, Type::Int) => expression.cast_expr(Type::Bool),
                 (Type::Float, Type::Int) => expression.cast_expr(Type::Float),
                 (dest_type, expr_type) => {
-                    return Err(SemanticsError::TypeMismatch(dest_type.clone(), expr_type))
+                    return Err(SemanticsError::TypeMismatch(
+                        dest_type.clone(),
+                        expr_type,
+                        expression_span,
+                    ))
                 }
             }
         }
         Ok(AnalyzedAssignment {
             destination,
             expression,
+            span: self.span,
         })
     }
 }
@@ -91,8 +158,10 @@ impl Analyze<AnalyzedAssignment> for AssignmentStatement {
 #[derive(Debug)]
 pub struct AnalyzedDestination {
     pub identifier: String,
+    pub binding: Binding,
     pub expression: Option<AnalyzedExpression>,
     pub value_type: Type,
+    pub span: Span,
 }
 
 impl Analyze<AnalyzedDestination> for Destination {
@@ -101,33 +170,43 @@ impl Analyze<AnalyzedDestination> for Destination {
         context: &mut super::context::Context,
         scope: &super::context::Scope,
     ) -> Result<AnalyzedDestination, SemanticsError> {
-        let value_type = context
-            .get_variable_type(&self.identifier.identifier_string)?
-            .clone();
+        let (value_type, binding) =
+            context.resolve_variable(&self.identifier.identifier_string, self.identifier.span)?;
+        let value_type = value_type.clone();
 
         if let Some(curr_expr) = self.expression {
             if let Type::Array(arr_type, _) = value_type {
+                let index_span = curr_expr.span();
                 let analyzed_expr = AnalyzedExpression::analyze_expression(curr_expr, context)?;
                 let curr_type = analyzed_expr.get_type(context)?;
                 if curr_type != Type::Int {
-                    Err(SemanticsError::TypeMismatch(Type::Int, curr_type))
+                    Err(SemanticsError::TypeMismatch(
+                        Type::Int,
+                        curr_type,
+                        index_span,
+                    ))
                 } else {
                     Ok(AnalyzedDestination {
                         identifier: self.identifier.identifier_string,
+                        binding,
                         expression: Some(analyzed_expr),
                         value_type: *arr_type,
+                        span: self.span,
                     })
                 }
             } else {
                 Err(SemanticsError::IndexOnNonArray(
                     self.identifier.identifier_string,
+                    self.span,
                 ))
             }
         } else {
             Ok(AnalyzedDestination {
                 identifier: self.identifier.identifier_string,
+                binding,
                 expression: None,
                 value_type,
+                span: self.span,
             })
         }
     }
@@ -138,6 +217,7 @@ pub struct AnalyzedIf {
     pub conditional_expr: AnalyzedExpression,
     pub then_block: AnalyzedBlock,
     pub else_block: Option<AnalyzedBlock>,
+    pub span: Span,
 }
 
 impl Analyze<AnalyzedIf> for IfStatement {
@@ -157,6 +237,7 @@ impl Analyze<AnalyzedIf> for IfStatement {
             conditional_expr,
             then_block,
             else_block,
+            span: self.span,
         })
     }
 }
@@ -166,6 +247,7 @@ pub struct AnalyzedLoop {
     pub assignment: Box<AnalyzedAssignment>,
     pub condition: AnalyzedExpression,
     pub loop_body: AnalyzedBlock,
+    pub span: Span,
 }
 
 impl Analyze<AnalyzedLoop> for LoopStatement {
@@ -184,6 +266,7 @@ impl Analyze<AnalyzedLoop> for LoopStatement {
             assignment: Box::new(assignment),
             condition,
             loop_body,
+            span: self.span,
         })
     }
 }
@@ -191,6 +274,7 @@ impl Analyze<AnalyzedLoop> for LoopStatement {
 #[derive(Debug)]
 pub struct AnalyzedReturn {
     pub expression: AnalyzedExpression,
+    pub span: Span,
 }
 
 impl Analyze<AnalyzedReturn> for ReturnStatement {
@@ -201,9 +285,10 @@ impl Analyze<AnalyzedReturn> for ReturnStatement {
     ) -> Result<AnalyzedReturn, SemanticsError> {
         let exprected_ret_type = context.get_return_type().clone();
         if exprected_ret_type == Type::Void {
-            return Err(SemanticsError::UnexpectedReturn);
+            return Err(SemanticsError::UnexpectedReturn(self.span));
         }
 
+        let expression_span = self.expression.span();
         let mut expression = AnalyzedExpression::analyze_expression(self.expression, context)?;
         let exp_type = expression.get_type(context)?;
         if exprected_ret_type != exp_type {
@@ -212,10 +297,17 @@ impl Analyze<AnalyzedReturn> for ReturnStatement {
                 (Type::Bool, Type::Int) => expression.cast_expr(Type::Bool),
                 (Type::Float, Type::Int) => expression.cast_expr(Type::Float),
                 (ret_type, exp_type) => {
-                    return Err(SemanticsError::TypeMismatch(ret_type, exp_type))
+                    return Err(SemanticsError::TypeMismatch(
+                        ret_type,
+                        exp_type,
+                        expression_span,
+                    ))
                 }
             }
         }
-        Ok(AnalyzedReturn { expression })
+        Ok(AnalyzedReturn {
+            expression,
+            span: self.span,
+        })
     }
 }
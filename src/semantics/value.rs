@@ -1,5 +1,7 @@
 use super::SemanticsError;
 use crate::parser::types::TypeMark;
+use crate::span::Span;
+use std::fmt::Display;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Type {
@@ -12,9 +14,9 @@ pub enum Type {
 }
 
 impl Type {
-    pub fn expect_type(self, other: Type) -> Result<Self, SemanticsError> {
+    pub fn expect_type(self, other: Type, span: Span) -> Result<Self, SemanticsError> {
         if self != other {
-            Err(SemanticsError::TypeMismatch(other, self))
+            Err(SemanticsError::TypeMismatch(other, self, span))
         } else {
             Ok(self)
         }
@@ -34,6 +36,20 @@ impl Type {
     }
 }
 
+/// Types are shown as they are written in source
+impl Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Type::Bool => write!(f, "bool"),
+            Type::Int => write!(f, "integer"),
+            Type::Float => write!(f, "float"),
+            Type::String => write!(f, "string"),
+            Type::Array(element_type, bound) => write!(f, "{}[{}]", element_type, bound),
+            Type::Void => write!(f, "void"),
+        }
+    }
+}
+
 impl From<TypeMark> for Type {
     fn from(value: TypeMark) -> Self {
         match value {
@@ -0,0 +1,365 @@
+use std::collections::VecDeque;
+use std::path::Path;
+use std::{fs, io};
+
+use crate::diagnostics::{DiagnosticSink, Severity};
+use crate::lint::{self, Level, Lint, LintLevels};
+use crate::parser::program::ProgramStruct;
+use crate::semantics::{bounds, fold, AnalyzedProgram};
+use crate::span::SourceFile;
+use crate::tokens::{Comment, SpannedToken};
+use crate::{parser, scanner};
+
+/// The phases of the front end, in the order they run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Phase {
+    Scan,
+    Parse,
+    Analyze,
+}
+
+/// Compiles a single source file, one phase at a time.
+///
+/// Each phase runs the phases before it first, and runs at most once.
+/// Its result is kept, so earlier results are still available after later phases run.
+/// Errors are reported to the session's diagnostics, and the phase methods return
+/// the first phase that reported one.
+///
+/// ```
+/// use crust::session::{Phase, Session};
+///
+/// let mut session = Session::from_text("example.src", "program example is begin end program.");
+/// assert_eq!(session.analyze().unwrap().name, "example");
+/// assert!(!session.tokens().unwrap().is_empty());
+///
+/// let mut session = Session::from_text("broken.src", "program broken is");
+/// assert_eq!(session.analyze().unwrap_err(), Phase::Parse);
+/// assert!(session.diagnostics().has_errors());
+/// ```
+#[derive(Debug)]
+pub struct Session {
+    source: SourceFile,
+    sink: DiagnosticSink,
+    lint_levels: LintLevels,
+    tokens: Option<Vec<SpannedToken>>,
+    comments: Option<Vec<Comment>>,
+    ast: Option<ProgramStruct>,
+    analyzed: Option<AnalyzedProgram>,
+    /// The last phase that has run
+    last_run: Option<Phase>,
+    /// The first phase that reported an error
+    failed: Option<Phase>,
+}
+
+impl Session {
+    pub fn new(source: SourceFile) -> Self {
+        Session {
+            source,
+            sink: DiagnosticSink::default(),
+            lint_levels: LintLevels::default(),
+            tokens: None,
+            comments: None,
+            ast: None,
+            analyzed: None,
+            last_run: None,
+            failed: None,
+        }
+    }
+
+    /// Creates a session for source text, where `name` is the file name diagnostics are reported in
+    pub fn from_text(name: impl Into<String>, text: impl Into<String>) -> Self {
+        Session::new(SourceFile::new(name.into(), text.into()))
+    }
+
+    pub fn from_path(path: &Path) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        Ok(Session::from_text(path.display().to_string(), text))
+    }
+
+    /// Stops keeping diagnostics after `error_limit` errors, or never if it is `None`
+    pub fn with_error_limit(mut self, error_limit: Option<usize>) -> Self {
+        self.sink = DiagnosticSink::new(error_limit);
+        self
+    }
+
+    /// Sets the level each lint is reported at once analysis succeeds
+    pub fn with_lint_levels(mut self, lint_levels: LintLevels) -> Self {
+        self.lint_levels = lint_levels;
+        self
+    }
+
+    /// Reports missing returns and unreachable statements as errors or warnings (the default).
+    /// Shorthand for setting the level of the `missing-return` and `unreachable-code` lints.
+    pub fn with_flow_severity(mut self, flow_severity: Severity) -> Self {
+        let level = match flow_severity {
+            Severity::Error => Level::Deny,
+            Severity::Warning => Level::Warn,
+        };
+        self.lint_levels.set(Lint::MissingReturn, level);
+        self.lint_levels.set(Lint::UnreachableCode, level);
+        self
+    }
+
+    pub fn source(&self) -> &SourceFile {
+        &self.source
+    }
+
+    /// Every diagnostic reported by the phases run so far
+    pub fn diagnostics(&self) -> &DiagnosticSink {
+        &self.sink
+    }
+
+    /// The first phase that reported an error, if any
+    pub fn failed_phase(&self) -> Option<Phase> {
+        self.failed
+    }
+
+    /// Runs every phase up to and including `phase`
+    pub fn run_until(&mut self, phase: Phase) -> Result<(), Phase> {
+        match phase {
+            Phase::Scan => self.scan().map(|_| ()),
+            Phase::Parse => self.parse().map(|_| ()),
+            Phase::Analyze => self.analyze().map(|_| ()),
+        }
+    }
+
+    pub fn scan(&mut self) -> Result<&[SpannedToken], Phase> {
+        if self.last_run.is_none() {
+            self.last_run = Some(Phase::Scan);
+            match scanner::scan_with_comments(self.source.text.clone()) {
+                Ok((tokens, comments)) => {
+                    self.tokens = Some(tokens);
+                    self.comments = Some(comments);
+                }
+                Err(err) => {
+                    self.sink.error(&err);
+                    self.fail(Phase::Scan);
+                }
+            }
+        }
+        self.check(Phase::Scan)?;
+        Ok(self.tokens.as_deref().unwrap_or_default())
+    }
+
+    pub fn parse(&mut self) -> Result<&ProgramStruct, Phase> {
+        if self.last_run < Some(Phase::Parse) {
+            let tokens = self.scan()?.to_vec();
+            self.last_run = Some(Phase::Parse);
+            self.ast = parser::parse_tokens(VecDeque::from(tokens), &mut self.sink);
+            // The parser recovers from errors, so there may be a tree even if it reported some
+            if self.ast.is_none() || self.sink.has_errors() {
+                self.fail(Phase::Parse);
+            }
+        }
+        self.check(Phase::Parse)?;
+        self.ast.as_ref().ok_or(Phase::Parse)
+    }
+
+    pub fn analyze(&mut self) -> Result<&AnalyzedProgram, Phase> {
+        if self.last_run < Some(Phase::Analyze) {
+            // Analysis still runs after errors the parser recovered from, to report its own
+            let _ = self.parse();
+            let Some(ast) = self.ast.clone() else {
+                return Err(self.failed.unwrap_or(Phase::Parse));
+            };
+            self.last_run = Some(Phase::Analyze);
+            let error_count = self.sink.error_count();
+            match AnalyzedProgram::analyze(ast, &mut self.sink) {
+                Ok(mut analyzed) => {
+                    for err in fold::fold(&mut analyzed).iter() {
+                        self.sink.error(err);
+                    }
+                    // Constant indices are only known once they're folded
+                    for err in bounds::check(&analyzed).iter() {
+                        self.sink.error(err);
+                    }
+                    self.analyzed = Some(analyzed);
+                }
+                Err(err) => self.sink.error(&err),
+            }
+            // Lints only run once everything else is right, since statements with errors are left out
+            if let Some(analyzed) = self.analyzed.as_ref().filter(|_| !self.sink.has_errors()) {
+                lint::check(analyzed, &self.lint_levels, &mut self.sink);
+            }
+            if self.analyzed.is_none() || self.sink.error_count() > error_count {
+                self.fail(Phase::Analyze);
+            }
+        }
+        self.check(Phase::Analyze)?;
+        self.analyzed.as_ref().ok_or(Phase::Analyze)
+    }
+
+    /// The tokens, if scanning has run without errors
+    pub fn tokens(&self) -> Option<&[SpannedToken]> {
+        self.check(Phase::Scan).ok()?;
+        self.tokens.as_deref()
+    }
+
+    /// The comments left out of the tokens, if scanning has run without errors
+    pub fn comments(&self) -> Option<&[Comment]> {
+        self.check(Phase::Scan).ok()?;
+        self.comments.as_deref()
+    }
+
+    /// The syntax tree, if parsing has run without errors
+    pub fn ast(&self) -> Option<&ProgramStruct> {
+        self.check(Phase::Parse).ok()?;
+        self.ast.as_ref()
+    }
+
+    /// The syntax tree even if parsing reported errors, for tools that work on incomplete programs.
+    /// Declarations and statements that failed to parse are `Error` nodes in it.
+    pub fn partial_ast(&self) -> Option<&ProgramStruct> {
+        self.ast.as_ref()
+    }
+
+    /// The analyzed program, if analysis has run without errors
+    pub fn analyzed(&self) -> Option<&AnalyzedProgram> {
+        self.check(Phase::Analyze).ok()?;
+        self.analyzed.as_ref()
+    }
+
+    /// The analyzed program even if analysis reported errors, for tools that work on incomplete programs.
+    /// Declarations and statements with errors are left out of it.
+    pub fn partial_analysis(&self) -> Option<&AnalyzedProgram> {
+        self.analyzed.as_ref()
+    }
+
+    fn fail(&mut self, phase: Phase) {
+        self.failed.get_or_insert(phase);
+    }
+
+    /// Fails if `phase`, or a phase before it, reported an error
+    fn check(&self, phase: Phase) -> Result<(), Phase> {
+        match self.failed {
+            Some(failed) if failed <= phase => Err(failed),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+use rstest::rstest;
+
+/// Analyzes a test program, which must have no errors, and passes it to `f`.
+/// Backends use this to compile the programs in `tests/correct`.
+#[cfg(test)]
+pub(crate) fn with_test_program<T>(path: &str, f: impl FnOnce(&AnalyzedProgram) -> T) -> T {
+    with_test_source(&fs::read_to_string(path).unwrap(), f)
+}
+
+/// Like [`with_test_program`], for source text written in a test
+#[cfg(test)]
+pub(crate) fn with_test_source<T>(text: &str, f: impl FnOnce(&AnalyzedProgram) -> T) -> T {
+    let mut session = Session::from_text("test.src", text);
+    match session.analyze() {
+        Ok(program) => f(program),
+        Err(phase) => panic!("{:?} failed: {:?}", phase, session.diagnostics()),
+    }
+}
+
+/// Analyzes source text written in a test with only `lints` reported,
+/// returning the message of everything reported about it
+#[cfg(test)]
+pub(crate) fn analyze_messages(text: &str, lints: &[Lint]) -> Vec<String> {
+    let mut levels = LintLevels::default();
+    for lint in Lint::ALL {
+        if !lints.contains(&lint) {
+            levels.set(lint, Level::Allow);
+        }
+    }
+    let mut session = Session::from_text("test.src", text).with_lint_levels(levels);
+    let _ = session.analyze();
+    session
+        .diagnostics()
+        .diagnostics()
+        .iter()
+        .map(|diagnostic| diagnostic.message.clone())
+        .collect()
+}
+
+/// Test programs that every way of running a program is checked against,
+/// as the program's path, its input and what it should write
+#[cfg(test)]
+pub(crate) const TEST_RUNS: &[(&str, &str, &str)] = &[
+    ("tests/correct/recursiveFib.src", "5\n", "0\n1\n3\n6\n10\n"),
+    (
+        "tests/correct/iterativeFib.src",
+        "7\n",
+        "0\n1\n1\n2\n3\n5\n8\n",
+    ),
+    ("tests/correct/math.src", "", "610\n"),
+    ("tests/correct/multipleProcs.src", "", "3\n"),
+    ("tests/correct/test2.src", "", "144\n"),
+    ("tests/correct/test_program_minimal.src", "", "15\n"),
+    ("tests/correct/logicals.src", "true\nB\n", "T\nF\n"),
+    (
+        "tests/correct/mutualRecursion.src",
+        "",
+        "true\nfalse\n8\n16\n",
+    ),
+    (
+        "tests/correct/test_heap.src",
+        "a\nb\n",
+        "Enter a string:\nEnter a string:\nb\na\n",
+    ),
+];
+
+/// A test program whose nested procedures use their enclosing procedures' locals,
+/// which only some backends support, so it is kept out of `tests/correct`
+#[cfg(test)]
+pub(crate) const NESTED_RUN: (&str, &str, &str) =
+    ("tests/nested/enclosingLocals.src", "", "35\n17\n");
+
+#[cfg(test)]
+#[rstest]
+#[case("program ok is begin end program.", None)]
+#[case("program bad is begin x := 1.2.3; end program.", Some(Phase::Scan))]
+#[case("program bad is begin x := ; end program.", Some(Phase::Parse))]
+#[case("program bad is begin x := 1; end program.", Some(Phase::Analyze))]
+fn session_reports_failed_phase(#[case] text: &str, #[case] failed: Option<Phase>) {
+    let mut session = Session::from_text("test.src", text);
+    assert_eq!(session.analyze().err(), failed);
+    assert_eq!(session.failed_phase(), failed);
+    assert_eq!(session.diagnostics().has_errors(), failed.is_some());
+    assert_eq!(session.tokens().is_some(), failed != Some(Phase::Scan));
+    assert_eq!(session.analyzed().is_some(), failed.is_none());
+}
+
+#[cfg(test)]
+#[test]
+fn session_runs_each_phase_once() {
+    let mut session = Session::from_text("test.src", "program bad is begin x := ; end program.");
+    assert!(session.scan().is_ok());
+    assert_eq!(session.parse().err(), Some(Phase::Parse));
+    assert_eq!(session.analyze().err(), Some(Phase::Parse));
+    let error_count = session.diagnostics().error_count();
+    assert_eq!(session.run_until(Phase::Analyze), Err(Phase::Parse));
+    assert_eq!(session.diagnostics().error_count(), error_count);
+}
+
+#[cfg(test)]
+#[test]
+fn flow_errors_fail_analysis() {
+    let source = "program flow is
+    procedure outer : integer()
+        procedure inner : integer()
+        begin
+        end procedure;
+    begin
+        return inner();
+    end procedure;
+begin
+end program.
+";
+    let mut session = Session::from_text("test.src", source).with_flow_severity(Severity::Error);
+    assert!(session.analyze().is_err());
+    let errors: Vec<&str> = session
+        .diagnostics()
+        .diagnostics()
+        .iter()
+        .filter(|diagnostic| diagnostic.severity == Severity::Error)
+        .map(|diagnostic| diagnostic.message.as_str())
+        .collect();
+    assert_eq!(errors, ["Not all paths in procedure inner return a value."]);
+}
@@ -0,0 +1,118 @@
+/// A range of source text.
+/// `start` and `end` are byte offsets into the original file (end exclusive),
+/// `line` and `column` are the 1-based position of `start`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize, line: usize, column: usize) -> Self {
+        Span {
+            start,
+            end,
+            line,
+            column,
+        }
+    }
+
+    /// Returns a span covering everything from the start of `self` to the end of `other`.
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            start: self.start,
+            end: other.end.max(self.end),
+            line: self.line,
+            column: self.column,
+        }
+    }
+}
+
+/// Byte offsets of the start of every line in a file.
+/// Used to turn byte offsets back into line/column pairs.
+#[derive(Debug, Clone)]
+pub struct LineIndex {
+    line_starts: Vec<usize>,
+}
+
+impl LineIndex {
+    pub fn new(source: &str) -> Self {
+        let mut line_starts = vec![0];
+        line_starts.extend(
+            source
+                .char_indices()
+                .filter(|(_, curr_char)| *curr_char == '\n')
+                .map(|(index, _)| index + 1),
+        );
+        LineIndex { line_starts }
+    }
+
+    /// Returns the 1-based (line, column) of a byte offset. Columns are counted in characters.
+    pub fn locate(&self, source: &str, offset: usize) -> (usize, usize) {
+        let line = match self.line_starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(next_line) => next_line - 1,
+        };
+        let line_start = self.line_starts[line];
+        let column = source
+            .get(line_start..offset)
+            .map_or(offset - line_start, |prefix| prefix.chars().count());
+        (line + 1, column + 1)
+    }
+
+    pub fn span(&self, source: &str, start: usize, end: usize) -> Span {
+        let (line, column) = self.locate(source, start);
+        Span::new(start, end, line, column)
+    }
+
+    /// Byte range of a 1-based line, not including the line terminator.
+    pub fn line_range(&self, source: &str, line: usize) -> Option<(usize, usize)> {
+        let start = *self.line_starts.get(line.checked_sub(1)?)?;
+        let end = self
+            .line_starts
+            .get(line)
+            .map_or(source.len(), |next_start| next_start - 1);
+        let end = if source[start..end].ends_with('\r') {
+            end - 1
+        } else {
+            end
+        };
+        Some((start, end))
+    }
+}
+
+/// A source file along with its name and line index
+#[derive(Debug, Clone)]
+pub struct SourceFile {
+    pub name: String,
+    pub text: String,
+    lines: LineIndex,
+}
+
+impl SourceFile {
+    pub fn new(name: String, text: String) -> Self {
+        let lines = LineIndex::new(&text);
+        SourceFile { name, text, lines }
+    }
+
+    /// Text of a 1-based line, without the line terminator
+    pub fn line_text(&self, line: usize) -> &str {
+        self.lines
+            .line_range(&self.text, line)
+            .map_or("", |(start, end)| &self.text[start..end])
+    }
+
+    /// The 1-based line a byte offset is on
+    pub fn line_of(&self, offset: usize) -> usize {
+        self.lines.locate(&self.text, offset).0
+    }
+
+    /// Byte offset where a 1-based line starts
+    pub fn line_start(&self, line: usize) -> usize {
+        self.lines
+            .line_range(&self.text, line)
+            .map_or(self.text.len(), |(start, _)| start)
+    }
+}
@@ -0,0 +1,214 @@
+//! A lossless concrete syntax tree, for tools that rewrite source text.
+//!
+//! Every token keeps the text it was written with, along with the whitespace and comments around it,
+//! so writing the tree back out gives the original file byte for byte.
+//! Tokens are grouped into nodes following the syntax tree from the parser,
+//! and parts of the file that failed to parse are kept in `Error` nodes.
+
+use std::fmt;
+
+use crate::session::{Phase, Session};
+use crate::span::Span;
+
+pub mod build;
+pub mod token;
+
+pub use token::{SyntaxToken, Trivia, TriviaKind};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyntaxKind {
+    Program,
+    ProgramHeader,
+    VariableDeclaration,
+    ProcedureDeclaration,
+    ProcedureHeader,
+    Parameter,
+    Assignment,
+    Destination,
+    If,
+    Loop,
+    Return,
+    /// Two operands with an operator between them
+    BinaryExpression,
+    Not,
+    /// A name or number with a `-` before it
+    Negation,
+    Parenthesized,
+    Call,
+    /// A variable, which may be indexed
+    Name,
+    /// A declaration or statement that failed to parse, or the whole program if parsing failed early
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SyntaxElement {
+    Node(SyntaxNode),
+    Token(SyntaxToken),
+}
+
+impl fmt::Display for SyntaxElement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SyntaxElement::Node(node) => node.fmt(f),
+            SyntaxElement::Token(token) => token.fmt(f),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxNode {
+    pub kind: SyntaxKind,
+    pub children: Vec<SyntaxElement>,
+}
+
+impl SyntaxNode {
+    /// Every token in the node, in order
+    pub fn tokens(&self) -> Vec<&SyntaxToken> {
+        let mut tokens = Vec::new();
+        self.collect_tokens(&mut tokens);
+        tokens
+    }
+
+    fn collect_tokens<'a>(&'a self, tokens: &mut Vec<&'a SyntaxToken>) {
+        for child in self.children.iter() {
+            match child {
+                SyntaxElement::Node(node) => node.collect_tokens(tokens),
+                SyntaxElement::Token(token) => tokens.push(token),
+            }
+        }
+    }
+
+    /// Every node in the tree of `kind`, outermost first
+    pub fn descendants(&self, kind: SyntaxKind) -> Vec<&SyntaxNode> {
+        let mut nodes = Vec::new();
+        if self.kind == kind {
+            nodes.push(self);
+        }
+        for child in self.children.iter() {
+            if let SyntaxElement::Node(node) = child {
+                nodes.extend(node.descendants(kind));
+            }
+        }
+        nodes
+    }
+
+    /// The span of the node's tokens, not including their trivia, or `None` if it has no tokens
+    pub fn span(&self) -> Option<Span> {
+        let tokens = self.tokens();
+        let first = tokens.first()?.span;
+        let last = tokens.last()?.span;
+        Some(first.to(last))
+    }
+}
+
+/// Writes the node's text exactly as it was in the source
+impl fmt::Display for SyntaxNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.children.iter().try_for_each(|child| child.fmt(f))
+    }
+}
+
+/// Builds the concrete syntax tree of the session's source, which only has to scan without errors.
+/// The root is a `Program` node.
+pub fn parse(session: &mut Session) -> Result<SyntaxNode, Phase> {
+    // Syntax errors end up in `Error` nodes
+    let _ = session.parse();
+    let (Some(tokens), Some(comments)) = (session.tokens(), session.comments()) else {
+        return Err(Phase::Scan);
+    };
+    let tokens = token::lex(&session.source().text, tokens, comments);
+    Ok(build::build(tokens, session.partial_ast()))
+}
+
+#[cfg(test)]
+use rstest::rstest;
+#[cfg(test)]
+use std::path::PathBuf;
+
+#[cfg(test)]
+#[rstest]
+fn syntax_tree_round_trips(#[files("tests/**/*.src")] path: PathBuf) {
+    let source = std::fs::read_to_string(path).unwrap();
+    let mut session = Session::from_text("test.src", source.as_str());
+    let Ok(tree) = parse(&mut session) else {
+        // Files with errors scanning have no tokens
+        assert_eq!(session.failed_phase(), Some(Phase::Scan));
+        return;
+    };
+    assert_eq!(tree.to_string(), source);
+    assert_eq!(tree.kind, SyntaxKind::Program);
+}
+
+#[cfg(test)]
+#[test]
+fn trivia_is_attached_to_tokens() {
+    let source = "x := 1; // one\n  /* two /* nested */ */ y#\r\n";
+    let mut session = Session::from_text("test.src", source);
+    let tokens = session.scan().unwrap().to_vec();
+    let comments = session.comments().unwrap();
+    let tokens = token::lex(source, &tokens, comments);
+    let kinds = |trivia: &[Trivia]| -> Vec<TriviaKind> {
+        trivia.iter().map(|trivia| trivia.kind).collect()
+    };
+
+    let semicolon = &tokens[3];
+    assert_eq!(
+        kinds(&semicolon.trailing),
+        [TriviaKind::Whitespace, TriviaKind::LineComment]
+    );
+    let y = &tokens[4];
+    assert_eq!(y.text, "y");
+    assert_eq!(
+        kinds(&y.leading),
+        [
+            TriviaKind::Newline,
+            TriviaKind::Whitespace,
+            TriviaKind::BlockComment,
+            TriviaKind::Whitespace
+        ]
+    );
+    assert_eq!(y.leading[2].text, "/* two /* nested */ */");
+    assert_eq!(kinds(&y.trailing), [TriviaKind::Skipped]);
+    let eof = &tokens[5];
+    assert_eq!(kinds(&eof.leading), [TriviaKind::Newline]);
+    assert_eq!(eof.leading[0].text, "\r\n");
+}
+
+#[cfg(test)]
+#[test]
+fn syntax_tree_follows_grammar() {
+    let source = "PROGRAM p IS
+    global variable Total : integer;
+    procedure Add : integer(variable a : integer, variable b : integer)
+    begin
+        return -a + ((b) * 2);
+    end procedure;
+begin
+    Total := not Add(1, 2);
+    if (Total > 3 then Total := 0; end if;
+end program.
+";
+    let mut session = Session::from_text("test.src", source);
+    let tree = parse(&mut session).unwrap();
+    assert_eq!(tree.to_string(), source);
+
+    let count = |kind| tree.descendants(kind).len();
+    assert_eq!(count(SyntaxKind::VariableDeclaration), 1);
+    assert_eq!(count(SyntaxKind::Parameter), 2);
+    assert_eq!(count(SyntaxKind::Negation), 1);
+    assert_eq!(count(SyntaxKind::Parenthesized), 2);
+    assert_eq!(count(SyntaxKind::Call), 1);
+    // The `if` is missing a `)`
+    assert_eq!(count(SyntaxKind::Error), 1);
+
+    let declaration = tree.descendants(SyntaxKind::VariableDeclaration)[0];
+    assert_eq!(
+        declaration.to_string(),
+        "\n    global variable Total : integer"
+    );
+    let parenthesized = tree.descendants(SyntaxKind::Parenthesized)[0];
+    assert_eq!(parenthesized.to_string(), "((b) * 2)");
+    let not = &tree.descendants(SyntaxKind::Not)[0];
+    assert_eq!(not.span().map(|span| span.column), Some(14));
+}
@@ -0,0 +1,358 @@
+use crate::parser::declaratons::{Declaration, VariableDeclaration};
+use crate::parser::expression::{ArtihOp, Expression, Factor, Name, Relation, Term};
+use crate::parser::program::ProgramStruct;
+use crate::parser::statement::{AssignmentStatement, Destination, Statement};
+
+use super::{SyntaxElement, SyntaxKind, SyntaxNode, SyntaxToken};
+
+/// Groups `tokens` into nodes following `program`, the syntax tree parsed from them.
+/// Without a syntax tree, every token goes in an `Error` node.
+pub fn build(tokens: Vec<SyntaxToken>, program: Option<&ProgramStruct>) -> SyntaxNode {
+    let mut builder = Builder::new(tokens);
+    match program {
+        Some(program) => builder.program(program),
+        None => {
+            builder.open(SyntaxKind::Error, 0);
+            builder.close(usize::MAX);
+        }
+    }
+    builder.finish()
+}
+
+/// Builds nodes from the outside in.
+/// Opening a node first adds the tokens before it to its parent, and closing it adds the rest of its tokens.
+///
+/// Spans in the syntax tree leave out some of the tokens of a node, like the `(` and `)` around a
+/// parenthesized expression, so nodes are opened at the start of their first token and closed
+/// after the end of their last.
+struct Builder {
+    tokens: Vec<SyntaxToken>,
+    /// Index of the next token to add
+    next: usize,
+    /// The nodes that are open, innermost last
+    stack: Vec<SyntaxNode>,
+}
+
+impl Builder {
+    fn new(tokens: Vec<SyntaxToken>) -> Self {
+        Builder {
+            tokens,
+            next: 0,
+            stack: vec![SyntaxNode {
+                kind: SyntaxKind::Program,
+                children: Vec::new(),
+            }],
+        }
+    }
+
+    fn finish(mut self) -> SyntaxNode {
+        self.bump_until(usize::MAX);
+        let mut stack = self.stack;
+        while stack.len() > 1 {
+            let node = stack.pop().unwrap();
+            stack
+                .last_mut()
+                .unwrap()
+                .children
+                .push(SyntaxElement::Node(node));
+        }
+        stack.pop().unwrap()
+    }
+
+    /// Adds the tokens starting before `offset` to the innermost open node
+    fn bump_until(&mut self, offset: usize) {
+        while let Some(token) = self.tokens.get(self.next) {
+            if token.span.start >= offset {
+                break;
+            }
+            let node = self.stack.last_mut().unwrap();
+            node.children.push(SyntaxElement::Token(token.clone()));
+            self.next += 1;
+        }
+    }
+
+    fn open(&mut self, kind: SyntaxKind, start: usize) {
+        self.bump_until(start);
+        self.stack.push(SyntaxNode {
+            kind,
+            children: Vec::new(),
+        });
+    }
+
+    fn close(&mut self, end: usize) {
+        self.bump_until(end);
+        let node = self.stack.pop().unwrap();
+        self.stack
+            .last_mut()
+            .unwrap()
+            .children
+            .push(SyntaxElement::Node(node));
+    }
+
+    /// The start of the token before the one at `offset`, like the `-` of a negated name
+    fn token_before(&self, offset: usize) -> usize {
+        let index = self
+            .tokens
+            .partition_point(|token| token.span.start < offset);
+        index
+            .checked_sub(1)
+            .map_or(offset, |index| self.tokens[index].span.start)
+    }
+
+    fn program(&mut self, program: &ProgramStruct) {
+        let header = &program.program_header;
+        self.open(SyntaxKind::ProgramHeader, header.span.start);
+        self.close(header.span.end);
+        let body = &program.program_body;
+        self.declarations(&body.declarations);
+        self.statements(&body.statements);
+    }
+
+    fn declarations(&mut self, declarations: &[Declaration]) {
+        for declaration in declarations {
+            let span = declaration.span();
+            // Spans start after `global`
+            let start = match declaration {
+                Declaration::Procedure(true, _) | Declaration::Variable(true, _) => {
+                    self.token_before(span.start)
+                }
+                _ => span.start,
+            };
+            match declaration {
+                Declaration::Variable(_, variable) => {
+                    self.open(SyntaxKind::VariableDeclaration, start);
+                    self.close(variable.span.end);
+                }
+                Declaration::Procedure(_, procedure) => {
+                    self.open(SyntaxKind::ProcedureDeclaration, start);
+                    let header = &procedure.procedure_header;
+                    self.open(SyntaxKind::ProcedureHeader, span.start);
+                    let params = header.param_list.iter().flat_map(|list| &list.param_list);
+                    for param in params {
+                        self.parameter(&param.variable_declaration);
+                    }
+                    self.close(header.span.end);
+                    let body = &procedure.procedure_body;
+                    self.declarations(&body.declarations);
+                    self.statements(&body.statements);
+                    self.close(span.end);
+                }
+                Declaration::Error(_) => {
+                    self.open(SyntaxKind::Error, start);
+                    self.close(span.end);
+                }
+            }
+        }
+    }
+
+    fn parameter(&mut self, variable: &VariableDeclaration) {
+        self.open(SyntaxKind::Parameter, variable.span.start);
+        self.close(variable.span.end);
+    }
+
+    fn statements(&mut self, statements: &[Statement]) {
+        for statement in statements {
+            let span = statement.span();
+            match statement {
+                Statement::Assignment(assignment) => self.assignment(assignment),
+                Statement::If(statement) => {
+                    self.open(SyntaxKind::If, span.start);
+                    self.expression(&statement.condition);
+                    self.statements(&statement.then_statement);
+                    if let Some(else_statement) = &statement.else_statement {
+                        self.statements(else_statement);
+                    }
+                    self.close(span.end);
+                }
+                Statement::Loop(statement) => {
+                    self.open(SyntaxKind::Loop, span.start);
+                    self.assignment(&statement.assignment_statement);
+                    self.expression(&statement.condition);
+                    self.statements(&statement.loop_body);
+                    self.close(span.end);
+                }
+                Statement::Return(statement) => {
+                    self.open(SyntaxKind::Return, span.start);
+                    self.expression(&statement.expression);
+                    self.close(span.end);
+                }
+                Statement::Error(_) => {
+                    self.open(SyntaxKind::Error, span.start);
+                    self.close(span.end);
+                }
+            }
+        }
+    }
+
+    fn assignment(&mut self, assignment: &AssignmentStatement) {
+        self.open(SyntaxKind::Assignment, assignment.span.start);
+        self.destination(&assignment.destination);
+        self.expression(&assignment.expression);
+        self.close(assignment.span.end);
+    }
+
+    fn destination(&mut self, destination: &Destination) {
+        self.open(SyntaxKind::Destination, destination.span.start);
+        if let Some(index) = &destination.expression {
+            self.expression(index);
+        }
+        self.close(destination.span.end);
+    }
+
+    fn expression(&mut self, expression: &Expression) {
+        match expression {
+            Expression::AndExp(left, right) | Expression::OrExp(left, right) => {
+                self.open(SyntaxKind::BinaryExpression, self.expression_start(left));
+                self.expression(left);
+                self.arith_op(right);
+                self.close(right.span().end);
+            }
+            Expression::NotExp(arith_op) => {
+                self.open(
+                    SyntaxKind::Not,
+                    self.token_before(self.arith_op_start(arith_op)),
+                );
+                self.arith_op(arith_op);
+                self.close(arith_op.span().end);
+            }
+            Expression::BasicExp(arith_op) => self.arith_op(arith_op),
+        }
+    }
+
+    fn arith_op(&mut self, arith_op: &ArtihOp) {
+        match arith_op {
+            ArtihOp::AddOp(left, right) | ArtihOp::SubOp(left, right) => {
+                self.open(SyntaxKind::BinaryExpression, self.arith_op_start(left));
+                self.arith_op(left);
+                self.relation(right);
+                self.close(right.span().end);
+            }
+            ArtihOp::Relation(relation) => self.relation(relation),
+        }
+    }
+
+    fn relation(&mut self, relation: &Relation) {
+        match relation {
+            Relation::LessThan(left, right)
+            | Relation::LessThanEq(left, right)
+            | Relation::GreaterThan(left, right)
+            | Relation::GreaterThanEq(left, right)
+            | Relation::Equals(left, right)
+            | Relation::NotEquals(left, right) => {
+                self.open(SyntaxKind::BinaryExpression, self.relation_start(left));
+                self.relation(left);
+                self.term(right);
+                self.close(right.span().end);
+            }
+            Relation::Term(term) => self.term(term),
+        }
+    }
+
+    fn term(&mut self, term: &Term) {
+        match term {
+            Term::MultTerm(left, right) | Term::DivTerm(left, right) => {
+                self.open(SyntaxKind::BinaryExpression, self.term_start(left));
+                self.term(left);
+                self.factor(right);
+                self.close(right.span().end);
+            }
+            Term::Factor(factor) => self.factor(factor),
+        }
+    }
+
+    fn factor(&mut self, factor: &Factor) {
+        let span = factor.span();
+        match factor {
+            Factor::NestedExpression(expression) => {
+                self.open(SyntaxKind::Parenthesized, self.factor_start(factor));
+                self.expression(expression);
+                // The span of the expression leaves out the `)`, which is the next token
+                let close_paren = self
+                    .tokens
+                    .get(self.next)
+                    .map_or(span.end, |token| token.span.end);
+                self.close(close_paren);
+            }
+            Factor::ProcedureCall(call) => {
+                self.open(SyntaxKind::Call, span.start);
+                for arg in call.arg_list.iter().flat_map(|args| &args.expr_list) {
+                    self.expression(arg);
+                }
+                self.close(span.end);
+            }
+            Factor::Name { negate, name } => {
+                if negate.is_some() {
+                    self.open(SyntaxKind::Negation, span.start);
+                }
+                self.name(name);
+                if negate.is_some() {
+                    self.close(span.end);
+                }
+            }
+            Factor::Number {
+                negate: Some(_), ..
+            } => {
+                self.open(SyntaxKind::Negation, span.start);
+                self.close(span.end);
+            }
+            // Literals are single tokens, which end up in their parent
+            Factor::Number { .. }
+            | Factor::String(_)
+            | Factor::TrueLit(_)
+            | Factor::FalseLit(_) => {}
+        }
+    }
+
+    fn name(&mut self, name: &Name) {
+        self.open(SyntaxKind::Name, name.span.start);
+        if let Some(index) = &name.expression {
+            self.expression(index);
+        }
+        self.close(name.span.end);
+    }
+
+    // The start of each kind of expression's first token, which may be before the start of its span
+
+    fn expression_start(&self, expression: &Expression) -> usize {
+        match expression {
+            Expression::AndExp(left, _) | Expression::OrExp(left, _) => self.expression_start(left),
+            Expression::NotExp(arith_op) => self.token_before(self.arith_op_start(arith_op)),
+            Expression::BasicExp(arith_op) => self.arith_op_start(arith_op),
+        }
+    }
+
+    fn arith_op_start(&self, arith_op: &ArtihOp) -> usize {
+        match arith_op {
+            ArtihOp::AddOp(left, _) | ArtihOp::SubOp(left, _) => self.arith_op_start(left),
+            ArtihOp::Relation(relation) => self.relation_start(relation),
+        }
+    }
+
+    fn relation_start(&self, relation: &Relation) -> usize {
+        match relation {
+            Relation::LessThan(left, _)
+            | Relation::LessThanEq(left, _)
+            | Relation::GreaterThan(left, _)
+            | Relation::GreaterThanEq(left, _)
+            | Relation::Equals(left, _)
+            | Relation::NotEquals(left, _) => self.relation_start(left),
+            Relation::Term(term) => self.term_start(term),
+        }
+    }
+
+    fn term_start(&self, term: &Term) -> usize {
+        match term {
+            Term::MultTerm(left, _) | Term::DivTerm(left, _) => self.term_start(left),
+            Term::Factor(factor) => self.factor_start(factor),
+        }
+    }
+
+    fn factor_start(&self, factor: &Factor) -> usize {
+        match factor {
+            Factor::NestedExpression(expression) => {
+                self.token_before(self.expression_start(expression))
+            }
+            _ => factor.span().start,
+        }
+    }
+}
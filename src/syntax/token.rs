@@ -0,0 +1,130 @@
+use std::fmt;
+
+use crate::span::{LineIndex, Span};
+use crate::tokens::{Comment, SpannedToken, Token};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriviaKind {
+    /// Spaces and tabs
+    Whitespace,
+    /// A single line terminator, `\n` or `\r\n`
+    Newline,
+    LineComment,
+    /// A block comment, along with any comments nested in it
+    BlockComment,
+    /// Text the scanner skipped without making a token of it
+    Skipped,
+}
+
+/// Source text between tokens, which the parser ignores
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trivia {
+    pub kind: TriviaKind,
+    pub text: String,
+    pub span: Span,
+}
+
+/// A token along with the exact text it was scanned from and the trivia around it.
+/// Writing out every token in order gives back the original file.
+///
+/// A token's trailing trivia is everything after it on the same line, up to the line terminator.
+/// Everything else between it and the previous token is its leading trivia.
+/// The EOF token leads with whatever is left at the end of the file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxToken {
+    pub token: Token,
+    /// The token as written, since the scanner lowercases identifiers
+    pub text: String,
+    pub span: Span,
+    pub leading: Vec<Trivia>,
+    pub trailing: Vec<Trivia>,
+}
+
+impl fmt::Display for SyntaxToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for trivia in self.leading.iter() {
+            f.write_str(&trivia.text)?;
+        }
+        f.write_str(&self.text)?;
+        for trivia in self.trailing.iter() {
+            f.write_str(&trivia.text)?;
+        }
+        Ok(())
+    }
+}
+
+/// Attaches the text between `tokens` to them as trivia.
+/// `tokens` and `comments` are what the scanner returned for `source`.
+pub fn lex(source: &str, tokens: &[SpannedToken], comments: &[Comment]) -> Vec<SyntaxToken> {
+    let lines = LineIndex::new(source);
+    let mut comments = comments.iter().peekable();
+    let mut syntax_tokens: Vec<SyntaxToken> = Vec::with_capacity(tokens.len());
+    let mut offset = 0;
+    for spanned in tokens {
+        let mut trivia = Vec::new();
+        while offset < spanned.span.start {
+            let (kind, end) = match comments.next_if(|comment| comment.span.start == offset) {
+                Some(comment) => {
+                    let kind = match comment.text.starts_with("//") {
+                        true => TriviaKind::LineComment,
+                        false => TriviaKind::BlockComment,
+                    };
+                    (kind, comment.span.end)
+                }
+                None => {
+                    let next_comment = comments
+                        .peek()
+                        .map_or(spanned.span.start, |comment| comment.span.start);
+                    uncommented_trivia(source, offset, next_comment.min(spanned.span.start))
+                }
+            };
+            trivia.push(Trivia {
+                kind,
+                text: String::from(&source[offset..end]),
+                span: lines.span(source, offset, end),
+            });
+            offset = end;
+        }
+
+        // The trivia up to the first line terminator trails the previous token
+        if let Some(previous) = syntax_tokens.last_mut() {
+            let same_line = trivia
+                .iter()
+                .position(|trivia| trivia.kind == TriviaKind::Newline)
+                .unwrap_or(trivia.len());
+            previous.trailing = trivia.drain(..same_line).collect();
+        }
+        syntax_tokens.push(SyntaxToken {
+            token: spanned.token.clone(),
+            text: String::from(&source[spanned.span.start..spanned.span.end]),
+            span: spanned.span,
+            leading: trivia,
+            trailing: Vec::new(),
+        });
+        offset = spanned.span.end;
+    }
+    syntax_tokens
+}
+
+/// The kind and end of the trivia at `start`, which isn't a comment and ends by `end`
+fn uncommented_trivia(source: &str, start: usize, end: usize) -> (TriviaKind, usize) {
+    let text = &source[start..end];
+    for newline in ["\r\n", "\n"] {
+        if text.starts_with(newline) {
+            return (TriviaKind::Newline, start + newline.len());
+        }
+    }
+    let is_whitespace = |character: char| matches!(character, ' ' | '\t' | '\r');
+    let kind = match text.starts_with(is_whitespace) {
+        true => TriviaKind::Whitespace,
+        false => TriviaKind::Skipped,
+    };
+    let length = text
+        .char_indices()
+        .find(|&(index, character)| {
+            let at_newline = text[index..].starts_with('\n') || text[index..].starts_with("\r\n");
+            at_newline || is_whitespace(character) != (kind == TriviaKind::Whitespace)
+        })
+        .map_or(text.len(), |(index, _)| index);
+    (kind, start + length)
+}
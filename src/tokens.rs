@@ -1,5 +1,7 @@
 use thiserror::Error;
 
+use crate::span::Span;
+
 #[derive(Debug, PartialEq, Clone)]
 /// Terminals from the EBNF Grammar Provided
 pub enum Token {
@@ -130,6 +132,27 @@ impl Token {
     }
 }
 
+/// A token along with the span of source text it was scanned from
+#[derive(Debug, PartialEq, Clone)]
+pub struct SpannedToken {
+    pub token: Token,
+    pub span: Span,
+}
+
+impl SpannedToken {
+    pub fn new(token: Token, span: Span) -> Self {
+        SpannedToken { token, span }
+    }
+}
+
+/// A comment, which the scanner leaves out of the tokens
+#[derive(Debug, PartialEq, Clone)]
+pub struct Comment {
+    /// The whole comment, including its delimiters
+    pub text: String,
+    pub span: Span,
+}
+
 #[derive(Error, Debug)]
 pub enum TokenError {
     #[error("Unrecognized token {0}")]